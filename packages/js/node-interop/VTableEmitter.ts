@@ -1,5 +1,9 @@
 import { EventEmitter } from 'events';
-import { DisplayInformation, EstablishSessionStatus } from './index';
+import {
+    DisplayInformation,
+    DisplayType,
+    EstablishSessionStatus,
+} from './index';
 import { VTable } from './index.node';
 
 export enum VTableEvent {
@@ -13,11 +17,27 @@ export enum VTableEvent {
     WpsskaClientPasswordPrompt = 'wpsska_client_password_prompt',
     WpsskaClientAuthenticationSuccessful = 'wpsska_client_authentication_successful',
     WpsskaClientAuthenticationFailed = 'wpsska_client_authentication_failed',
+    WpsskaClientHostKeyChanged = 'wpsska_client_host_key_changed',
     WpsskaHostAuthenticationSuccessful = 'wpsska_host_authentication_successful',
     RvdClientHandshakeComplete = 'rvd_client_handshake_complete',
+    RvdClientControlChanged = 'rvd_client_control_changed',
     RvdDisplayUpdate = 'rvd_display_update',
     RvdFrameData = 'rvd_frame_data',
+    RvdCursorShape = 'rvd_cursor_shape',
+    RvdDisplayGeometry = 'rvd_display_geometry',
     RvdHostHandshakeComplete = 'rvd_host_handshake_complete',
+    RvdHostInputRejected = 'rvd_host_input_rejected',
+    RvdFileOffered = 'rvd_file_offered',
+    RvdFileProgress = 'rvd_file_progress',
+    RvdFileComplete = 'rvd_file_complete',
+    RvdFileFailed = 'rvd_file_failed',
+    HostClientConnected = 'host_client_connected',
+    HostClientDisconnected = 'host_client_disconnected',
+    HostClientControlRequested = 'host_client_control_requested',
+    HostClientInputRejected = 'host_client_input_rejected',
+    HostDisplaysChanged = 'host_displays_changed',
+    HostDisplayLost = 'host_display_lost',
+    HostDisplayRestored = 'host_display_restored',
 }
 
 export declare interface VTableEmitter extends EventEmitter {
@@ -31,6 +51,15 @@ export declare interface VTableEmitter extends EventEmitter {
         listener: (status: EstablishSessionStatus) => void
     ): this;
 
+    on(
+        event: VTableEvent.WpsskaClientHostKeyChanged,
+        listener: (
+            host: string,
+            previousFingerprint: string,
+            fingerprint: string
+        ) => void
+    ): this;
+
     on(
         event: VTableEvent.RvdDisplayUpdate,
         listener: (
@@ -39,6 +68,67 @@ export declare interface VTableEmitter extends EventEmitter {
         ) => void
     ): this;
 
+    on(
+        event:
+            | VTableEvent.HostClientConnected
+            | VTableEvent.HostClientDisconnected
+            | VTableEvent.HostClientControlRequested
+            | VTableEvent.HostClientInputRejected,
+        listener: (clientId: number) => void
+    ): this;
+
+    on(
+        event: VTableEvent.RvdClientControlChanged,
+        listener: (hasControl: boolean) => void
+    ): this;
+
+    on(
+        event: VTableEvent.RvdDisplayGeometry,
+        listener: (
+            displayId: number,
+            width: number,
+            height: number,
+            scale: number,
+            rotation: number
+        ) => void
+    ): this;
+
+    on(
+        event: VTableEvent.RvdCursorShape,
+        listener: (
+            width: number,
+            height: number,
+            hotspotX: number,
+            hotspotY: number,
+            data: ArrayBuffer
+        ) => void
+    ): this;
+
+    on(
+        event: VTableEvent.RvdFileOffered,
+        listener: (fileId: number, name: string, size: number) => void
+    ): this;
+
+    on(
+        event: VTableEvent.RvdFileProgress,
+        listener: (fileId: number, transferred: number, size: number) => void
+    ): this;
+
+    on(
+        event: VTableEvent.RvdFileComplete,
+        listener: (fileId: number, path: string) => void
+    ): this;
+
+    on(
+        event: VTableEvent.RvdFileFailed,
+        listener: (fileId: number, reason: string) => void
+    ): this;
+
+    on(
+        event: VTableEvent.HostDisplayLost | VTableEvent.HostDisplayRestored,
+        listener: (displayType: DisplayType, nativeId: number) => void
+    ): this;
+
     on(event: VTableEvent, listener: () => void): this;
 }
 
@@ -92,6 +182,19 @@ export class VTableEmitter extends EventEmitter implements VTable {
         this.emit(VTableEvent.WpsskaClientAuthenticationFailed);
     }
 
+    wpskka_client_host_key_changed(
+        host: string,
+        previousFingerprint: string,
+        fingerprint: string
+    ) {
+        this.emit(
+            VTableEvent.WpsskaClientHostKeyChanged,
+            host,
+            previousFingerprint,
+            fingerprint
+        );
+    }
+
     /* wpskka - host */
     wpskka_host_authentication_successful() {
         this.emit(VTableEvent.WpsskaHostAuthenticationSuccessful);
@@ -102,10 +205,48 @@ export class VTableEmitter extends EventEmitter implements VTable {
         this.emit(VTableEvent.RvdClientHandshakeComplete);
     }
 
+    rvd_client_control_changed(hasControl: boolean) {
+        this.emit(VTableEvent.RvdClientControlChanged, hasControl);
+    }
+
     rvd_frame_data(displayId: number, data: ArrayBuffer) {
         this.emit(VTableEvent.RvdFrameData, displayId, data);
     }
 
+    rvd_display_geometry(
+        displayId: number,
+        width: number,
+        height: number,
+        scale: number,
+        rotation: number
+    ) {
+        this.emit(
+            VTableEvent.RvdDisplayGeometry,
+            displayId,
+            width,
+            height,
+            scale,
+            rotation
+        );
+    }
+
+    rvd_cursor_shape(
+        width: number,
+        height: number,
+        hotspotX: number,
+        hotspotY: number,
+        data: ArrayBuffer
+    ) {
+        this.emit(
+            VTableEvent.RvdCursorShape,
+            width,
+            height,
+            hotspotX,
+            hotspotY,
+            data
+        );
+    }
+
     rvd_display_update(
         clipboardReadable: boolean,
         displays: DisplayInformation[]
@@ -116,6 +257,57 @@ export class VTableEmitter extends EventEmitter implements VTable {
     rvd_host_handshake_complete() {
         this.emit(VTableEvent.RvdHostHandshakeComplete);
     }
+
+    rvd_host_input_rejected() {
+        this.emit(VTableEvent.RvdHostInputRejected);
+    }
+
+    /* rvd - files */
+    rvd_file_offered(fileId: number, name: string, size: number) {
+        this.emit(VTableEvent.RvdFileOffered, fileId, name, size);
+    }
+
+    rvd_file_progress(fileId: number, transferred: number, size: number) {
+        this.emit(VTableEvent.RvdFileProgress, fileId, transferred, size);
+    }
+
+    rvd_file_complete(fileId: number, path: string) {
+        this.emit(VTableEvent.RvdFileComplete, fileId, path);
+    }
+
+    rvd_file_failed(fileId: number, reason: string) {
+        this.emit(VTableEvent.RvdFileFailed, fileId, reason);
+    }
+
+    /* direct host clients */
+    host_client_connected(clientId: number) {
+        this.emit(VTableEvent.HostClientConnected, clientId);
+    }
+
+    host_client_disconnected(clientId: number) {
+        this.emit(VTableEvent.HostClientDisconnected, clientId);
+    }
+
+    host_client_control_requested(clientId: number) {
+        this.emit(VTableEvent.HostClientControlRequested, clientId);
+    }
+
+    host_client_input_rejected(clientId: number) {
+        this.emit(VTableEvent.HostClientInputRejected, clientId);
+    }
+
+    /* host displays */
+    host_displays_changed() {
+        this.emit(VTableEvent.HostDisplaysChanged);
+    }
+
+    host_display_lost(displayType: DisplayType, nativeId: number) {
+        this.emit(VTableEvent.HostDisplayLost, displayType, nativeId);
+    }
+
+    host_display_restored(displayType: DisplayType, nativeId: number) {
+        this.emit(VTableEvent.HostDisplayRestored, displayType, nativeId);
+    }
 }
 
 export default VTableEmitter;
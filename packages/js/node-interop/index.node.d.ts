@@ -7,6 +7,10 @@ import type {
     InstanceConnectionType,
     NativeThumbnail,
     DisplayInformation,
+    KnownHost,
+    PermissionMask,
+    AccessMask,
+    DisplayType,
 } from './index';
 
 export declare type ClientDirectInstance = JSBox<
@@ -79,6 +83,12 @@ export declare function keyboard_input(
     down: boolean
 ): Promise<undefined>;
 
+// For text from an IME or with characters the Host's keyboard layout may not have
+export declare function text_input(
+    handle: ClientInstance,
+    text: string
+): Promise<undefined>;
+
 export declare function lease_request(
     handle: HostSignalInstance
 ): Promise<undefined>;
@@ -89,9 +99,10 @@ export declare function update_static_password(
 ): Promise<undefined>;
 
 // export  declare function preview_displays<T extends InstanceType.Host>(handle: JSBox<Instance<T>>): Promise<{ monitors: [], windows: []}>;
+// Either every shared display is controllable or none are, or only the listed ones are
 export declare function set_controllable(
     handle: HostInstance,
-    is_controllable: boolean
+    controllable: boolean | Display[]
 ): Promise<undefined>;
 
 export declare function set_clipboard_readable(
@@ -103,7 +114,76 @@ export declare function set_clipboard_readable(
 export declare function share_displays(
     handle: HostInstance,
     displays: Display[],
-    controllable: boolean
+    controllable: boolean | Display[]
+): Promise<undefined>;
+
+// Host identity keys are pinned to the known hosts file at path on first use, passing null disables this
+export declare function set_known_hosts(
+    handle: ClientInstance,
+    path: string | null
+): Promise<undefined>;
+
+// The identity is a PKCS#8 document from identity_generate, passing null stops public key authentication
+export declare function set_identity(
+    handle: AnyInstance,
+    pkcs8: ArrayBuffer | null
+): Promise<undefined>;
+
+// Clients with these hex encoded public keys may authenticate with their identity
+export declare function set_authorized_clients(
+    handle: HostInstance,
+    public_keys: string[]
+): Promise<undefined>;
+
+// Direct hosts can have more than one client, each with its own permissions and access
+export declare function set_client_access(
+    handle: HostDirectInstance,
+    client_id: number,
+    permissions: PermissionMask,
+    access: AccessMask
+): Promise<undefined>;
+
+export declare function disconnect_client(
+    handle: HostDirectInstance,
+    client_id: number
+): Promise<undefined>;
+
+export declare function grant_control(
+    handle: HostDirectInstance,
+    client_id: number
+): Promise<undefined>;
+
+export declare function revoke_control(
+    handle: HostDirectInstance
+): Promise<undefined>;
+
+export declare function request_control(
+    handle: ClientInstance
+): Promise<undefined>;
+
+// Resolves with the id used for the transfer in the rvd_file_* callbacks, direct hosts have to
+// pass the client to send to
+export declare function send_file(
+    handle: AnyInstance,
+    path: string,
+    client_id: number | null
+): Promise<number>;
+
+// A partially received copy of the file in directory is resumed from where it stopped
+export declare function accept_file(
+    handle: AnyInstance,
+    file_id: number,
+    directory: string
+): Promise<undefined>;
+
+export declare function reject_file(
+    handle: AnyInstance,
+    file_id: number
+): Promise<undefined>;
+
+export declare function cancel_file(
+    handle: AnyInstance,
+    file_id: number
 ): Promise<undefined>;
 
 export declare function thumbnails(
@@ -114,6 +194,23 @@ export declare function close_thumbnails(handle: ThumbnailHandle): void;
 
 export declare function available_displays(): Array<Display>;
 
+export declare function known_hosts_list(path: string): KnownHost[];
+
+export declare function known_hosts_pin(
+    path: string,
+    host: string,
+    public_key: string
+): void;
+
+// Returns false if host wasn't pinned
+export declare function known_hosts_revoke(path: string, host: string): boolean;
+
+// Returns a new identity as a PKCS#8 document, it should be stored somewhere safe
+export declare function identity_generate(): ArrayBuffer;
+
+// Returns the hex encoded public key Hosts authorize the identity by
+export declare function identity_public_key(pkcs8: ArrayBuffer): string;
+
 /* macos only */
 export declare function macos_accessibility_permission(
     prompt: boolean
@@ -166,6 +263,13 @@ export interface VTable {
 
     wpskka_client_authentication_failed(): void;
 
+    // authentication_failed is called after this
+    wpskka_client_host_key_changed(
+        host: string,
+        previous_fingerprint: string,
+        fingerprint: string
+    ): void;
+
     /* wpskka - host */
     wpskka_host_authentication_successful(): void;
 
@@ -177,8 +281,60 @@ export interface VTable {
 
     rvd_client_handshake_complete(): void;
 
+    rvd_client_control_changed(has_control: boolean): void;
+
+    // data is a whole VP9 frame, frames are handed out in order once they're due to be shown
     rvd_frame_data(display_id: number, data: ArrayBuffer);
 
+    // width and height are in pixels, scale is pixels per point and rotation is in degrees clockwise
+    rvd_display_geometry(
+        display_id: number,
+        width: number,
+        height: number,
+        scale: number,
+        rotation: number
+    ): void;
+
+    // data is width * height RGBA pixels, not premultiplied
+    rvd_cursor_shape(
+        width: number,
+        height: number,
+        hotspot_x: number,
+        hotspot_y: number,
+        data: ArrayBuffer
+    ): void;
+
+    /* rvd - files */
+    rvd_file_offered(file_id: number, name: string, size: number): void;
+
+    rvd_file_progress(file_id: number, transferred: number, size: number): void;
+
+    // path is where a received file was saved, it's empty for files we sent
+    rvd_file_complete(file_id: number, path: string): void;
+
+    rvd_file_failed(file_id: number, reason: string): void;
+
     /* rvd - host */
     rvd_host_handshake_complete(): void;
+
+    // input from a client without the controls was dropped
+    rvd_host_input_rejected(): void;
+
+    /* direct host clients */
+    host_client_connected(client_id: number): void;
+
+    host_client_disconnected(client_id: number): void;
+
+    host_client_control_requested(client_id: number): void;
+
+    host_client_input_rejected(client_id: number): void;
+
+    /* host displays */
+    // the monitors or windows available to share may have changed
+    host_displays_changed(): void;
+
+    // a shared display went away and was unshared, monitors are shared again when they come back
+    host_display_lost(display_type: DisplayType, native_id: number): void;
+
+    host_display_restored(display_type: DisplayType, native_id: number): void;
 }
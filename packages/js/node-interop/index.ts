@@ -27,6 +27,15 @@ export enum ButtonMask {
     SCROLL_RIGHT = 1 << 6,
 }
 
+export enum PermissionMask {
+    CLIPBOARD_READ = 1 << 0,
+    CLIPBOARD_WRITE = 1 << 1,
+}
+
+export enum AccessMask {
+    CONTROLLABLE = 1 << 0,
+}
+
 export enum DisplayType {
     Monitor = 'monitor',
     Window = 'window',
@@ -59,3 +68,9 @@ export interface DisplayInformation {
     width: number;
     height: number;
 }
+
+export interface KnownHost {
+    host: string; // "lease:<lease id>" or "addr:<address>"
+    public_key: string;
+    fingerprint: string;
+}
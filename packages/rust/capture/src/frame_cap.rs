@@ -46,6 +46,7 @@ impl<P: ProcessFrame> FrameCapture<P> {
             sender: request_sender,
             receiver: response_receiver,
             handle: None,
+            control: ProcessorControl::default(),
         });
 
         let new_handle = match old_state {
@@ -67,6 +68,7 @@ impl<P: ProcessFrame> FrameCapture<P> {
                 sender
                     .send(WorkerRequest::UpdateFrame(
                         Box::new(CaptureResources::new()),
+                        ProcessorControl::default(),
                     ))
                     .expect(BROKEN_PIPE_MSG);
                 *handle = Some(new_handle);
@@ -94,7 +96,9 @@ impl<P: ProcessFrame> FrameCapture<P> {
 
     pub fn deactivate(&mut self) {
         let (native_api, waker) = match &mut self.state {
-            FrameCaptureState::Active { handle, .. } => {
+            FrameCaptureState::Active { sender, handle, .. } => {
+                // The worker only exits once it's told to, an UpdateFrame that's in flight is dropped
+                let _ = sender.send(WorkerRequest::Stop);
                 handle
                     .take()
                     .expect("frame capture thread handle not present")
@@ -108,11 +112,13 @@ impl<P: ProcessFrame> FrameCapture<P> {
         self.state = FrameCaptureState::Inactive { native_api, waker };
     }
 
-    pub fn update(&self, resources: Box<CaptureResources<P>>) {
-        match &self.state {
-            FrameCaptureState::Active { sender, .. } => {
+    pub fn update(&mut self, resources: Box<CaptureResources<P>>) {
+        match &mut self.state {
+            FrameCaptureState::Active {
+                sender, control, ..
+            } => {
                 sender
-                    .send(WorkerRequest::UpdateFrame(resources))
+                    .send(WorkerRequest::UpdateFrame(resources, mem::take(control)))
                     .expect(BROKEN_PIPE_MSG);
             }
             FrameCaptureState::Inactive { .. } =>
@@ -120,6 +126,26 @@ impl<P: ProcessFrame> FrameCapture<P> {
         }
     }
 
+    /// Makes the next frame captured a keyframe. The worker only hears about it with the next
+    /// update, asking while it still holds the resources would block.
+    pub fn request_keyframe(&mut self) {
+        match &mut self.state {
+            FrameCaptureState::Active { control, .. } => control.keyframe = true,
+            FrameCaptureState::Inactive { .. } =>
+                panic!("Cannot request a keyframe while in inactive state"),
+        }
+    }
+
+    /// Changes the bitrate frames are encoded at, in kbit/s. Like keyframes, the worker hears about
+    /// it with the next update.
+    pub fn set_bitrate(&mut self, kbps: u32) {
+        match &mut self.state {
+            FrameCaptureState::Active { control, .. } => control.bitrate = Some(kbps),
+            FrameCaptureState::Inactive { .. } =>
+                panic!("Cannot set the bitrate while in inactive state"),
+        }
+    }
+
     pub fn next_update(&mut self) -> Option<FrameUpdateResult<P>> {
         match &mut self.state {
             FrameCaptureState::Active {
@@ -166,7 +192,15 @@ impl<P: ProcessFrame> FrameCapture<P> {
             let start = Instant::now();
 
             let mut resources = match receiver.recv().expect(BROKEN_PIPE_MSG) {
-                WorkerRequest::UpdateFrame(resources) => resources,
+                WorkerRequest::UpdateFrame(resources, control) => {
+                    if let Some(kbps) = control.bitrate {
+                        frame_processor.set_bitrate(kbps);
+                    }
+                    if control.keyframe {
+                        frame_processor.request_keyframe();
+                    }
+                    resources
+                }
                 WorkerRequest::Stop => break,
             };
 
@@ -220,11 +254,22 @@ enum FrameCaptureState<P: ProcessFrame> {
         sender: Sender<WorkerRequest<P>>,
         receiver: Receiver<CaptureReply<P>>,
         handle: Option<JoinHandle<(NativeApi, ThreadWaker)>>,
+        // Passed to the worker with the next update
+        control: ProcessorControl,
     },
 }
 
+// What the frame processor should change before processing the next frame
+#[derive(Default)]
+struct ProcessorControl {
+    // Whether the frame should be a keyframe
+    keyframe: bool,
+    // The bitrate to encode at from now on, in kbit/s
+    bitrate: Option<u32>,
+}
+
 enum WorkerRequest<P: ProcessFrame> {
-    UpdateFrame(Box<CaptureResources<P>>),
+    UpdateFrame(Box<CaptureResources<P>>, ProcessorControl),
     Stop,
 }
 
@@ -38,6 +38,53 @@ impl<P: ProcessFrame> CapturePool<P> {
         ret
     }
 
+    /// Stops capturing the display, returning whether it was being captured
+    pub fn deactivate(&mut self, display_id: DisplayId) -> bool {
+        let index = match self.captures[.. self.next_inactive]
+            .iter()
+            .position(|capture| capture.is_capturing(display_id))
+        {
+            Some(index) => index,
+            None => return false,
+        };
+
+        self.captures[index].deactivate();
+        // Keep the active captures in front of the inactive ones
+        self.next_inactive -= 1;
+        self.captures.swap(index, self.next_inactive);
+        true
+    }
+
+    /// Makes the next frame captured from the display a keyframe, returning whether it's being
+    /// captured
+    pub fn request_keyframe(&mut self, display_id: DisplayId) -> bool {
+        match self.captures[.. self.next_inactive]
+            .iter_mut()
+            .find(|capture| capture.is_capturing(display_id))
+        {
+            Some(capture) => {
+                capture.request_keyframe();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Changes the bitrate frames captured from the display are encoded at, in kbit/s, returning
+    /// whether it's being captured
+    pub fn set_bitrate(&mut self, display_id: DisplayId, kbps: u32) -> bool {
+        match self.captures[.. self.next_inactive]
+            .iter_mut()
+            .find(|capture| capture.is_capturing(display_id))
+        {
+            Some(capture) => {
+                capture.set_bitrate(kbps);
+                true
+            }
+            None => false,
+        }
+    }
+
     pub fn active_captures(
         &mut self,
     ) -> impl Iterator<Item = (DisplayId, &'_ mut FrameCapture<P>)> {
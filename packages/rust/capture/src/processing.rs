@@ -13,6 +13,13 @@ pub trait ProcessFrame: 'static {
         frame: &mut BGRAFrame,
         resources: &mut Self::Resources,
     ) -> FrameProcessResult;
+
+    /// Makes the next frame processed decodable without the ones before it, for processors that
+    /// encode frames relative to each other
+    fn request_keyframe(&mut self) {}
+
+    /// Changes the bitrate frames are encoded at, in kbit/s, for processors that encode them
+    fn set_bitrate(&mut self, _kbps: u32) {}
 }
 
 pub trait ViewResources<'a> {
@@ -3,7 +3,9 @@ use hmac::SimpleHmac;
 use srp::groups::G_2048;
 
 pub const SVSC_VERSION: &str = "SVSC 001.000";
-pub const RVD_VERSION: &str = "RVD 001.000";
+// Peers on earlier versions can't read reliable messages larger than 64 KiB or the messages added
+// since, so they're turned away
+pub const RVD_VERSION: &str = "RVD 001.001";
 pub static SRP_PARAM: &G_2048 = &G_2048;
 
 pub type HashAlgo = blake3::Hasher;
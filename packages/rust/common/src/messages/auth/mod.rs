@@ -1 +1,2 @@
+pub mod public_key;
 pub mod srp;
@@ -0,0 +1,27 @@
+use parser::{message_id, MessageComponent};
+
+use crate::messages::Message;
+
+#[derive(Debug, MessageComponent)]
+#[message_id(1)]
+pub struct HostIdentity {
+    pub public_key: [u8; 32],
+    pub signature: [u8; 64],
+}
+
+#[derive(Debug, MessageComponent)]
+#[message_id(2)]
+pub struct ClientIdentity {
+    pub public_key: [u8; 32],
+    pub signature: [u8; 64],
+}
+
+#[derive(Debug, MessageComponent)]
+pub enum PublicKeyMessage {
+    HostIdentity(HostIdentity),
+    ClientIdentity(ClientIdentity),
+}
+
+impl Message for PublicKeyMessage {
+    const LEN_PREFIX_WIDTH: usize = 0;
+}
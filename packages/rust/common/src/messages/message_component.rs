@@ -43,8 +43,41 @@ pub trait Message {
             Ok(data)
         }
     }
+
+    /// Like [`Message::to_bytes`], except a message too long for the length prefix is written as
+    /// an extended frame: a length prefix of zero followed by the length as a u32. Only Peers that
+    /// negotiated extended frames can read these.
+    fn to_bytes_extended<'a>(&'a self) -> Result<Vec<u8>, Error>
+    where Self: MessageComponent<'a> {
+        if Self::LEN_PREFIX_WIDTH == 0 {
+            return self.to_bytes();
+        }
+
+        let mut cursor = Cursor::new(vec![0u8; Self::LEN_PREFIX_WIDTH]);
+        cursor.set_position(u64::try_from(Self::LEN_PREFIX_WIDTH)?);
+        self.write(&mut cursor)?;
+        let len = cursor.get_ref().len() - Self::LEN_PREFIX_WIDTH;
+        let mut data = cursor.into_inner();
+
+        if len < 1usize << (8 * Self::LEN_PREFIX_WIDTH) {
+            let len_bytes = len.to_le_bytes();
+            data[.. Self::LEN_PREFIX_WIDTH].copy_from_slice(&len_bytes[.. Self::LEN_PREFIX_WIDTH]);
+        } else {
+            // The length prefix is left as zero and the real length goes after it
+            let len_bytes = u32::try_from(len)?.to_le_bytes();
+            data.splice(
+                Self::LEN_PREFIX_WIDTH .. Self::LEN_PREFIX_WIDTH,
+                len_bytes.iter().copied(),
+            );
+        }
+
+        Ok(data)
+    }
 }
 
+/// The width of the length that follows a zero length prefix in an extended frame
+pub const EXTENDED_LENGTH_WIDTH: usize = 4;
+
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[error("io error: {0}")]
@@ -94,6 +94,48 @@ pub struct DisplayUnshare {
     pub display_id: DisplayId,
 }
 
+// Clockwise, how far a display is turned from its natural orientation. Frames are always sent the
+// way the display is shown.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(u8)]
+pub enum DisplayRotation {
+    None = 0,
+    Clockwise90 = 1,
+    Clockwise180 = 2,
+    Clockwise270 = 3,
+}
+
+impl MessageComponent<'_> for DisplayRotation {
+    fn read(cursor: &mut Cursor<&[u8]>) -> Result<Self, Error> {
+        match cursor.read_u8()? {
+            0 => Ok(Self::None),
+            1 => Ok(Self::Clockwise90),
+            2 => Ok(Self::Clockwise180),
+            3 => Ok(Self::Clockwise270),
+            value => Err(Error::InvalidEnumValue {
+                name: "DisplayRotation",
+                value: u16::from(value),
+            }),
+        }
+    }
+
+    fn write(&self, cursor: &mut Cursor<Vec<u8>>) -> Result<(), Error> {
+        cursor.write_u8(*self as u8).map_err(Into::into)
+    }
+}
+
+// width and height are in pixels, MouseInput positions are relative to them. scale is how many
+// pixels make up one of the Host's points in hundredths, 200 on a typical HiDPI display.
+#[derive(MessageComponent, Debug, Clone, PartialEq, Eq)]
+#[message_id(33)]
+pub struct DisplayGeometry {
+    pub display_id: DisplayId,
+    pub width: u16,
+    pub height: u16,
+    pub scale: u16,
+    pub rotation: DisplayRotation,
+}
+
 
 #[derive(MessageComponent, Debug, Clone, PartialEq, Eq)]
 #[message_id(10)]
@@ -155,12 +197,22 @@ pub struct KeyInput {
     pub key: u32, // keysym
 }
 
-#[derive(PartialEq, Eq, Debug, Clone)]
+// Text typed on the Client that can't be sent as key presses, e.g. from an IME or a character that
+// isn't on the Host's keyboard layout
+#[derive(MessageComponent, Debug, PartialEq, Eq, Clone)]
+#[message_id(34)]
+pub struct TextInput {
+    #[parse(len_prefixed(2))]
+    pub text: String,
+}
+
+#[derive(PartialEq, Eq, Hash, Debug, Clone)]
 pub enum ClipboardType {
     Text,
     Rtf,
     Html,
     FilePointer,
+    Image, // PNG encoded
     Custom(String),
 }
 
@@ -173,6 +225,7 @@ impl TryFrom<u8> for ClipboardType {
             2 => Ok(Self::Rtf),
             3 => Ok(Self::Html),
             4 => Ok(Self::FilePointer),
+            5 => Ok(Self::Image),
             _ => Err(Error::InvalidEnumValue {
                 name: "ClipboardType",
                 value: u16::from(clipboard_type),
@@ -189,6 +242,7 @@ impl From<&ClipboardType> for u8 {
             ClipboardType::Rtf => 2,
             ClipboardType::Html => 3,
             ClipboardType::FilePointer => 4,
+            ClipboardType::Image => 5,
         }
     }
 }
@@ -325,6 +379,163 @@ pub struct FrameData<'a> {
     pub data: Data<'a>,
 }
 
+// Sent by the Client when FrameData for a display went missing and it can't decode what follows
+// until the Host sends a keyframe
+#[derive(MessageComponent, Debug, Clone, PartialEq, Eq)]
+#[message_id(35)]
+pub struct PictureLoss {
+    pub display_id: DisplayId,
+}
+
+// Sent by the Client for FrameData packets that went missing, sequences are their RTP sequence
+// numbers
+#[derive(MessageComponent, Debug, Clone, PartialEq, Eq)]
+#[message_id(36)]
+pub struct FrameNack {
+    pub display_id: DisplayId,
+    #[parse(len_prefixed(1))]
+    pub sequences: Vec<u16>,
+}
+
+// Sent by the Client every so often for each display it receives, along the lines of an RTCP
+// receiver report
+#[derive(MessageComponent, Debug, Clone, PartialEq, Eq)]
+#[message_id(37)]
+pub struct ReceiverReport {
+    pub display_id: DisplayId,
+    pub fraction_lost: u8, // out of 256, of the FrameData expected since the last report
+    pub lost: u32,         // FrameData packets lost in total
+    pub jitter: u32,       // interarrival jitter in RTP timestamp units
+    pub received_bytes: u64, // FrameData payload received in total
+}
+
+// Only one Client holds the controls at a time. Others send a ControlRequest and the Host decides
+// whether to hand the controls over.
+#[derive(MessageComponent, Debug)]
+#[message_id(17)]
+pub struct ControlRequest {}
+
+#[derive(MessageComponent, Debug)]
+#[message_id(18)]
+pub struct ControlGrant {}
+
+#[derive(MessageComponent, Debug)]
+#[message_id(19)]
+pub struct ControlRevoke {}
+
+pub type TransferId = u32;
+
+// Either Peer can offer a file. Transfer IDs are chosen by the Peer that offers the file, so a
+// Host and a Client may use the same ID for different transfers.
+#[derive(MessageComponent, Debug, Clone, PartialEq, Eq)]
+#[message_id(20)]
+pub struct FileOffer {
+    pub transfer_id: TransferId,
+    pub size: u64,
+    pub hash: [u8; 32], // BLAKE3 of the whole file
+    #[parse(len_prefixed(2))]
+    pub name: String,
+}
+
+#[derive(MessageComponent, Debug, Clone, PartialEq, Eq)]
+#[message_id(21)]
+pub struct FileAccept {
+    pub transfer_id: TransferId,
+    pub offset: u64, // non-zero when resuming a partially received file
+}
+
+#[derive(MessageComponent, Debug, Clone, PartialEq, Eq)]
+#[message_id(22)]
+pub struct FileReject {
+    pub transfer_id: TransferId,
+}
+
+#[derive(MessageComponent, Debug)]
+#[message_id(23)]
+#[lifetime('a)]
+pub struct FileChunk<'a> {
+    pub transfer_id: TransferId,
+    pub offset: u64,
+    pub data: Data<'a>,
+}
+
+// Acknowledges every byte up to offset, the sender waits for these before sending more
+#[derive(MessageComponent, Debug, Clone, PartialEq, Eq)]
+#[message_id(24)]
+pub struct FileAck {
+    pub transfer_id: TransferId,
+    pub offset: u64,
+}
+
+#[derive(MessageComponent, Debug, Clone, PartialEq, Eq)]
+#[message_id(25)]
+pub struct FileCancel {
+    pub transfer_id: TransferId,
+    pub outgoing: bool, // whether the sender of this message offered the file
+}
+
+#[derive(MessageComponent, Debug, Clone, PartialEq, Eq)]
+#[message_id(26)]
+pub struct FileComplete {
+    pub transfer_id: TransferId,
+    pub hash_ok: bool,
+}
+
+// Clipboard content too large for a single ClipboardNotification is sent in chunks. Each Peer
+// announces the largest content it accepts with a ClipboardLimit.
+#[derive(MessageComponent, Debug, Clone, PartialEq, Eq)]
+#[message_id(27)]
+pub struct ClipboardLimit {
+    pub max_size: u32,
+}
+
+#[derive(MessageComponent, Debug, Clone)]
+#[message_id(28)]
+pub struct ClipboardStart {
+    pub transfer_id: TransferId,
+    pub info: ClipboardMeta,
+    pub size: u32,
+}
+
+#[derive(MessageComponent, Debug)]
+#[message_id(29)]
+#[lifetime('a)]
+pub struct ClipboardChunk<'a> {
+    pub transfer_id: TransferId,
+    pub sequence: u32,
+    pub data: Data<'a>,
+}
+
+// received is how many chunks have arrived, it's 0 in reply to a ClipboardStart
+#[derive(MessageComponent, Debug, Clone, PartialEq, Eq)]
+#[message_id(30)]
+pub struct ClipboardAck {
+    pub transfer_id: TransferId,
+    pub received: u32,
+}
+
+// Identifies a cursor shape by a hash of it, so a shape the Client already has isn't sent again
+pub type CursorId = u64;
+
+// data is the cursor image as width * height RGBA pixels, not premultiplied
+#[derive(MessageComponent, Debug)]
+#[message_id(31)]
+#[lifetime('a)]
+pub struct CursorShape<'a> {
+    pub cursor_id: CursorId,
+    pub width: u16,
+    pub height: u16,
+    pub hotspot_x: u16,
+    pub hotspot_y: u16,
+    pub data: Data<'a>,
+}
+
+#[derive(MessageComponent, Debug, Clone, PartialEq, Eq)]
+#[message_id(32)]
+pub struct CursorShapeCached {
+    pub cursor_id: CursorId,
+}
+
 #[derive(MessageComponent, Debug)]
 #[lifetime('a)]
 pub enum RvdMessage<'a> {
@@ -345,6 +556,27 @@ pub enum RvdMessage<'a> {
     ClipboardRequest(ClipboardRequest),
     ClipboardNotification(ClipboardNotification),
     FrameData(FrameData<'a>),
+    ControlRequest(ControlRequest),
+    ControlGrant(ControlGrant),
+    ControlRevoke(ControlRevoke),
+    FileOffer(FileOffer),
+    FileAccept(FileAccept),
+    FileReject(FileReject),
+    FileChunk(FileChunk<'a>),
+    FileAck(FileAck),
+    FileCancel(FileCancel),
+    FileComplete(FileComplete),
+    ClipboardLimit(ClipboardLimit),
+    ClipboardStart(ClipboardStart),
+    ClipboardChunk(ClipboardChunk<'a>),
+    ClipboardAck(ClipboardAck),
+    CursorShape(CursorShape<'a>),
+    CursorShapeCached(CursorShapeCached),
+    DisplayGeometry(DisplayGeometry),
+    TextInput(TextInput),
+    PictureLoss(PictureLoss),
+    FrameNack(FrameNack),
+    ReceiverReport(ReceiverReport),
 }
 
 impl Message for RvdMessage<'_> {
@@ -88,6 +88,20 @@ pub struct TransportDataMessageUnreliable<'a> {
     pub data: Data<'a>,
 }
 
+#[derive(Debug, MessageComponent)]
+#[message_id(8)]
+pub struct Rekey {
+    pub epoch: u64,
+    pub mac: [u8; 32],
+}
+
+#[derive(Debug, MessageComponent)]
+#[message_id(9)]
+pub struct RekeyAck {
+    pub epoch: u64,
+    pub mac: [u8; 32],
+}
+
 #[derive(MessageComponent, Debug)]
 #[lifetime('a)]
 pub enum WpskkaMessage<'a> {
@@ -98,6 +112,8 @@ pub enum WpskkaMessage<'a> {
     AuthResult(AuthResult),
     TransportDataMessageReliable(TransportDataMessageReliable<'a>),
     TransportDataMessageUnreliable(TransportDataMessageUnreliable<'a>),
+    Rekey(Rekey),
+    RekeyAck(RekeyAck),
 }
 
 impl<'a> Message for WpskkaMessage<'a> {
@@ -0,0 +1,26 @@
+#[macro_use]
+mod helper;
+use crate::helper::*;
+use common::messages::{
+    auth::public_key::{ClientIdentity, HostIdentity},
+    MessageComponent,
+};
+use std::io::Cursor;
+
+#[test]
+fn test_host_identity() {
+    let bytes = include_bytes!("binary/wpskka/public_key/host_identity.bin");
+    let message: HostIdentity = HostIdentity::read(&mut Cursor::new(bytes)).unwrap();
+    assert_eq!(message.public_key, bytes[.. 32]);
+    assert_eq!(message.signature, bytes[32 ..]);
+    test_write(&message, bytes);
+}
+
+#[test]
+fn test_client_identity() {
+    let bytes = include_bytes!("binary/wpskka/public_key/client_identity.bin");
+    let message: ClientIdentity = ClientIdentity::read(&mut Cursor::new(bytes)).unwrap();
+    assert_eq!(message.public_key, bytes[.. 32]);
+    assert_eq!(message.signature, bytes[32 ..]);
+    test_write(&message, bytes);
+}
@@ -125,6 +125,14 @@ fn test_key_input() {
     test_write(&message, bytes);
 }
 
+#[test]
+fn test_text_input() {
+    let bytes = include_bytes!("binary/rvd/text_input.bin");
+    let message = TextInput::read(&mut Cursor::new(bytes)).unwrap();
+    assert_eq!(message.text, "日本");
+    test_write(&message, bytes);
+}
+
 #[test]
 fn test_clipboard_request_default() {
     let bytes = include_bytes!("binary/rvd/clipboard_request_default.bin");
@@ -146,6 +154,15 @@ fn test_clipboard_request_custom() {
     test_write(&message, bytes);
 }
 
+#[test]
+fn test_clipboard_request_image() {
+    let bytes = include_bytes!("binary/rvd/clipboard_request_image.bin");
+    let message: ClipboardRequest = ClipboardRequest::read(&mut Cursor::new(bytes)).unwrap();
+    assert_eq!(message.info.clipboard_type, ClipboardType::Image);
+    assert!(!message.info.content_request);
+    test_write(&message, bytes);
+}
+
 #[test]
 #[should_panic]
 fn test_clipboard_request_bad() {
@@ -188,3 +205,32 @@ fn frame_data() {
     assert_eq!(message.data.0.as_ref(), b"abc");
     test_write(&message, bytes);
 }
+
+#[test]
+fn test_picture_loss() {
+    let bytes = include_bytes!("binary/rvd/picture_loss.bin");
+    let message = PictureLoss::read(&mut Cursor::new(bytes)).unwrap();
+    assert_eq!(message.display_id, 3);
+    test_write(&message, bytes);
+}
+
+#[test]
+fn test_frame_nack() {
+    let bytes = include_bytes!("binary/rvd/frame_nack.bin");
+    let message = FrameNack::read(&mut Cursor::new(bytes)).unwrap();
+    assert_eq!(message.display_id, 2);
+    assert_eq!(message.sequences, [7, 65535, 0]);
+    test_write(&message, bytes);
+}
+
+#[test]
+fn test_receiver_report() {
+    let bytes = include_bytes!("binary/rvd/receiver_report.bin");
+    let message = ReceiverReport::read(&mut Cursor::new(bytes)).unwrap();
+    assert_eq!(message.display_id, 1);
+    assert_eq!(message.fraction_lost, 64);
+    assert_eq!(message.lost, 5);
+    assert_eq!(message.jitter, 900);
+    assert_eq!(message.received_bytes, 123456);
+    test_write(&message, bytes);
+}
@@ -1,8 +1,8 @@
 #[macro_use]
 mod helper;
 use crate::helper::*;
-use common::messages::{wpskka::*, MessageComponent};
-use std::io::Cursor;
+use common::messages::{wpskka::*, Data, Message, MessageComponent, EXTENDED_LENGTH_WIDTH};
+use std::{borrow::Cow, io::Cursor};
 
 #[test]
 fn test_key_exchange() {
@@ -63,3 +63,52 @@ fn test_transport_data_reliable() {
     assert_eq!(message.data.0.as_ref(), b"YELLOW SUBMARINE");
     test_write(&message, bytes);
 }
+
+#[test]
+fn test_rekey() {
+    let bytes = include_bytes!("binary/wpskka/rekey.bin");
+    let message: Rekey = Rekey::read(&mut Cursor::new(bytes)).unwrap();
+    assert_eq!(&message.epoch, b_to_u64!(b"EPOCHNUM"));
+    assert_eq!(&message.mac, b"YELLOW SUBMARINEYELLOW SUBMARINE");
+    test_write(&message, bytes);
+}
+
+#[test]
+fn test_rekey_ack() {
+    let bytes = include_bytes!("binary/wpskka/rekey_ack.bin");
+    let message: RekeyAck = RekeyAck::read(&mut Cursor::new(bytes)).unwrap();
+    assert_eq!(&message.epoch, b_to_u64!(b"EPOCHNUM"));
+    assert_eq!(&message.mac, b"YELLOW SUBMARINEYELLOW SUBMARINE");
+    test_write(&message, bytes);
+}
+
+fn transport_data_reliable(len: usize) -> WpskkaMessage<'static> {
+    WpskkaMessage::TransportDataMessageReliable(TransportDataMessageReliable {
+        data: Data(Cow::Owned((0 .. len).map(|i| i as u8).collect())),
+    })
+}
+
+#[test]
+fn test_extended_frame() {
+    // Messages that fit the length prefix are framed as usual
+    let message = transport_data_reliable(100);
+    assert_eq!(
+        message.to_bytes_extended().unwrap(),
+        message.to_bytes().unwrap()
+    );
+
+    let message = transport_data_reliable(70000);
+    assert!(message.to_bytes().is_err());
+    let bytes = message.to_bytes_extended().unwrap();
+    let header = WpskkaMessage::LEN_PREFIX_WIDTH + EXTENDED_LENGTH_WIDTH;
+    assert_eq!(&bytes[.. WpskkaMessage::LEN_PREFIX_WIDTH], &[0, 0]);
+    assert_eq!(
+        &bytes[WpskkaMessage::LEN_PREFIX_WIDTH .. header],
+        &70001u32.to_le_bytes()
+    );
+    match WpskkaMessage::read(&mut Cursor::new(&bytes[header ..])).unwrap() {
+        WpskkaMessage::TransportDataMessageReliable(message) =>
+            assert_eq!(message.data.0.len(), 70000),
+        _ => panic!("expected TransportDataMessageReliable"),
+    }
+}
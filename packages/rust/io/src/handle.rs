@@ -214,6 +214,14 @@ impl<R, U: Unreliable> IoHandle<R, U> {
         Ok(())
     }
 
+    /// Terminates any existing unreliable channel and replaces it using the given closure.
+    pub fn bind_unreliable_with<F>(&mut self, f: F)
+    where F: FnOnce(Sender<TransportResult>) -> U {
+        self.disconnect_unreliable();
+        let handle = f(self.result_sender.clone());
+        self.unreliable = Some(handle);
+    }
+
     pub fn connect_unreliable<A: ToSocketAddrs>(&mut self, addr: A) -> Result<(), io::Error> {
         self.unreliable
             .as_ref()
@@ -9,6 +9,11 @@ pub use tcp::*;
 pub use udp::*;
 
 pub(crate) const LENGTH_FIELD_WIDTH: usize = 2;
+// A reliable message too long for the length field has a length field of zero, followed by its
+// length in this many bytes
+pub(crate) const EXTENDED_LENGTH_FIELD_WIDTH: usize = 4;
+/// The largest reliable message that will be read, so a bad length can't exhaust our memory
+pub const MAX_RELIABLE_MESSAGE_SIZE: usize = 32 * 1024 * 1024;
 const INIT_BUFFER_CAPACITY: usize = 4096;
 const UDP_READ_SIZE: usize = 65507;
 const UDP_TIMEOUT: u64 = 50;
@@ -29,3 +34,12 @@ fn parse_length_field(message: &[u8]) -> usize {
     length_bytes.copy_from_slice(&message[0 .. LENGTH_FIELD_WIDTH]);
     usize::from(u16::from_le_bytes(length_bytes))
 }
+
+#[inline]
+fn parse_extended_length_field(message: &[u8]) -> usize {
+    let mut length_bytes = [0u8; EXTENDED_LENGTH_FIELD_WIDTH];
+    length_bytes.copy_from_slice(
+        &message[LENGTH_FIELD_WIDTH .. LENGTH_FIELD_WIDTH + EXTENDED_LENGTH_FIELD_WIDTH],
+    );
+    u32::from_le_bytes(length_bytes) as usize
+}
@@ -1,4 +1,5 @@
 use super::{
+    parse_extended_length_field,
     parse_length_field,
     Reliable,
     SendError,
@@ -6,8 +7,10 @@ use super::{
     TransportError,
     TransportResponse,
     TransportResult,
+    EXTENDED_LENGTH_FIELD_WIDTH,
     INIT_BUFFER_CAPACITY,
     LENGTH_FIELD_WIDTH,
+    MAX_RELIABLE_MESSAGE_SIZE,
 };
 use crate::return_if_err;
 use common::messages::Error;
@@ -125,11 +128,17 @@ fn read_reliable(stream: Arc<TcpStream>, sender: Sender<TransportResult>, waker:
 
         // Collect and parse the message
         let data_parsed = match collect_and_parse_reliable(&*stream, &mut buffer, &mut data_end) {
-            Ok(message) => {
-                let message_len = message.len();
-                return_if_err!(sender.send(Ok(TransportResponse::ReliableMessage(message))));
+            Ok((message, frame_len)) => {
+                let result = match message {
+                    Some(message) => Ok(TransportResponse::ReliableMessage(message)),
+                    None => Err(TransportError::Recoverable {
+                        source: Source::ReadReliable,
+                        error: Error::BadTransportMessage,
+                    }),
+                };
+                return_if_err!(sender.send(result));
 
-                message_len + LENGTH_FIELD_WIDTH
+                frame_len
             }
             Err(error) => {
                 let res = sender.send(Err(TransportError::Recoverable {
@@ -157,25 +166,40 @@ fn read_reliable(stream: Arc<TcpStream>, sender: Sender<TransportResult>, waker:
     }
 }
 
+// Returns the message along with the number of bytes of the buffer its frame took up. Messages
+// that are too large are skipped, in which case there is no message.
 fn collect_and_parse_reliable(
-    mut stream: &TcpStream,
+    stream: &TcpStream,
     buffer: &mut Vec<u8>,
     data_end: &mut usize,
-) -> Result<Vec<u8>, Error> {
-    if *data_end < LENGTH_FIELD_WIDTH {
-        // We need to read the length field but there isn't enough data in the buffer to do so
-        Read::read_exact(&mut stream, &mut buffer[*data_end .. LENGTH_FIELD_WIDTH])?;
-    }
+) -> Result<(Option<Vec<u8>>, usize), Error> {
+    // We need to read the length field but there may not be enough data in the buffer to do so
+    collect_reliable(stream, buffer, data_end, LENGTH_FIELD_WIDTH)?;
 
-    // The length field indicates the remaining length of the message so we add on the length
-    // field width to get the total length
-    let length = match parse_length_field(buffer).checked_add(LENGTH_FIELD_WIDTH) {
-        Some(len) => len,
-        None => return Err(Error::BadTransportMessage),
+    // The length field indicates the remaining length of the message, unless it's zero in which
+    // case this is an extended frame and the length follows it
+    let (header_len, message_len) = match parse_length_field(buffer) {
+        0 => {
+            let header_len = LENGTH_FIELD_WIDTH + EXTENDED_LENGTH_FIELD_WIDTH;
+            collect_reliable(stream, buffer, data_end, header_len)?;
+            (header_len, parse_extended_length_field(buffer))
+        }
+        message_len => (LENGTH_FIELD_WIDTH, message_len),
     };
 
+    let length = header_len + message_len;
+    if message_len > MAX_RELIABLE_MESSAGE_SIZE {
+        // Whatever isn't buffered yet is read past so the next frame is read from its start
+        let buffered = length.min(*data_end);
+        io::copy(
+            &mut Read::take(stream, (length - buffered) as u64),
+            &mut io::sink(),
+        )?;
+        return Ok((None, buffered));
+    }
+
     collect_reliable(stream, buffer, data_end, length)?;
-    Ok(buffer[LENGTH_FIELD_WIDTH .. length].to_vec())
+    Ok((Some(buffer[header_len .. length].to_vec()), length))
 }
 
 #[inline]
@@ -215,3 +239,117 @@ fn write_reliable(
         waker.wake();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use event_loop::event_loop::ThreadWakerCore;
+    use std::{net::TcpListener, time::Duration};
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    fn connect() -> (TcpStream, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (other, _) = listener.accept().unwrap();
+        (stream, other)
+    }
+
+    fn handle(stream: TcpStream) -> (TcpHandle, Receiver<TransportResult>) {
+        let (result_sender, results) = unbounded();
+        let waker = ThreadWakerCore::new_current_thread().make_waker(0);
+        (TcpHandle::new_from(stream, result_sender, waker), results)
+    }
+
+    fn frame(message: &[u8]) -> Vec<u8> {
+        let mut frame = match u16::try_from(message.len()) {
+            Ok(len) => len.to_le_bytes().to_vec(),
+            Err(_) => {
+                let mut frame = vec![0u8; LENGTH_FIELD_WIDTH];
+                frame.extend_from_slice(&(message.len() as u32).to_le_bytes());
+                frame
+            }
+        };
+        frame.extend_from_slice(message);
+        frame
+    }
+
+    fn recv(results: &Receiver<TransportResult>) -> Vec<u8> {
+        match results.recv_timeout(TIMEOUT).expect("timed out") {
+            Ok(TransportResponse::ReliableMessage(message)) => message,
+            _ => panic!("expected a reliable message"),
+        }
+    }
+
+    fn message(len: usize) -> Vec<u8> {
+        (0 .. len).map(|i| (i % 251) as u8).collect()
+    }
+
+    #[test]
+    fn test_tcp_round_trip() {
+        let (a, b) = connect();
+        let (mut a, _a_results) = handle(a);
+        let (_b, b_results) = handle(b);
+
+        let messages = [
+            message(10),
+            message(200_000),
+            message(u16::MAX as usize),
+            message(u16::MAX as usize + 1),
+            message(1),
+        ];
+        for message in &messages {
+            a.send(frame(message)).unwrap();
+        }
+        for message in &messages {
+            assert_eq!(&recv(&b_results), message);
+        }
+    }
+
+    #[test]
+    fn test_tcp_split_header() {
+        let (mut stream, other) = connect();
+        let (_handle, results) = handle(other);
+
+        // The reader has to wait for the rest of both the length field and the extended length
+        let large = message(100_000);
+        let large_frame = frame(&large);
+        for part in [&large_frame[.. 1], &large_frame[1 .. 4], &large_frame[4 ..]] {
+            stream.write_all(part).unwrap();
+            thread::sleep(Duration::from_millis(20));
+        }
+        let small = message(300);
+        let small_frame = frame(&small);
+        stream.write_all(&small_frame[.. 1]).unwrap();
+        thread::sleep(Duration::from_millis(20));
+        stream.write_all(&small_frame[1 ..]).unwrap();
+
+        assert_eq!(recv(&results), large);
+        assert_eq!(recv(&results), small);
+    }
+
+    #[test]
+    fn test_tcp_too_large() {
+        let (mut stream, other) = connect();
+        let (_handle, results) = handle(other);
+
+        // The payload of the rejected message mustn't be mistaken for the frames that follow
+        let too_large = frame(&message(MAX_RELIABLE_MESSAGE_SIZE + 1));
+        let small = message(300);
+        let writer = thread::spawn(move || {
+            stream.write_all(&too_large).unwrap();
+            stream.write_all(&frame(&small)).unwrap();
+            stream
+        });
+
+        assert!(matches!(
+            results.recv_timeout(TIMEOUT).expect("timed out"),
+            Err(TransportError::Recoverable {
+                source: Source::ReadReliable,
+                error: Error::BadTransportMessage,
+            })
+        ));
+        assert_eq!(recv(&results), message(300));
+        let _stream = writer.join().unwrap();
+    }
+}
@@ -10,8 +10,9 @@ use crate::{
 use crossbeam_channel::{unbounded, Receiver, Sender};
 use event_loop::{event_loop::ThreadWaker, JoinOnDrop};
 use std::{
+    cell::Cell,
     io::{self, ErrorKind},
-    net::{ToSocketAddrs, UdpSocket},
+    net::{SocketAddr, ToSocketAddrs, UdpSocket},
     sync::{
         atomic::{AtomicU8, Ordering},
         Arc,
@@ -102,6 +103,134 @@ impl Drop for UdpHandle {
     }
 }
 
+/// An unconnected UDP socket which can receive from and send to any address. This is used by the
+/// signal server, which relays unreliable messages between many peers over a single socket.
+pub struct UdpServer {
+    socket: Arc<UdpSocket>,
+    state: Arc<AtomicU8>,
+    _handle: JoinOnDrop<()>,
+}
+
+impl UdpServer {
+    pub fn new<A: ToSocketAddrs>(
+        addr: A,
+        result_sender: Sender<TransportResult>,
+        waker: ThreadWaker,
+    ) -> Result<Self, io::Error> {
+        let socket = Arc::new(UdpSocket::bind(addr)?);
+        socket.set_read_timeout(Some(Duration::from_millis(UDP_TIMEOUT)))?;
+        let state = Arc::new(AtomicU8::new(BOUND));
+
+        let read_handle = thread::spawn({
+            let socket = Arc::clone(&socket);
+            let state = Arc::clone(&state);
+            move || {
+                read_unreliable(socket, result_sender, &*state, &waker);
+                waker.wake();
+            }
+        });
+
+        Ok(Self {
+            socket,
+            state,
+            _handle: JoinOnDrop::new(read_handle),
+        })
+    }
+
+    /// Sends a message to the given address. Unlike [`UdpHandle`] this is done on the calling
+    /// thread, since sending a datagram does not block.
+    pub fn send_to(&self, message: &[u8], addr: SocketAddr) -> Result<(), io::Error> {
+        self.socket.send_to(message, addr).map(|_| ())
+    }
+
+    pub fn close(&mut self) {
+        self.state.store(SHUTDOWN, Ordering::Relaxed);
+    }
+}
+
+impl Drop for UdpServer {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+/// A single peer's view of a [`UdpServer`] which is shared by many peers, such as the clients of a
+/// direct host. Datagrams received on a shared server go to the server's result sender rather than
+/// the peer's, so the owner of the server has to route them to the right peer.
+pub struct UdpPeer {
+    server: Arc<UdpServer>,
+    addr: Cell<Option<SocketAddr>>,
+    result_sender: Sender<TransportResult>,
+}
+
+impl UdpPeer {
+    pub fn new_shared(server: Arc<UdpServer>, result_sender: Sender<TransportResult>) -> Self {
+        Self {
+            server,
+            addr: Cell::new(None),
+            result_sender,
+        }
+    }
+
+    /// The address datagrams are sent to, if it's known yet
+    pub fn addr(&self) -> Option<SocketAddr> {
+        self.addr.get()
+    }
+}
+
+impl Unreliable for UdpPeer {
+    /// Binds a server which is only used by this peer
+    fn new<A: ToSocketAddrs>(
+        addr: A,
+        result_sender: Sender<TransportResult>,
+        waker: ThreadWaker,
+    ) -> Result<Self, io::Error> {
+        let server = UdpServer::new(addr, result_sender.clone(), waker)?;
+        Ok(Self::new_shared(Arc::new(server), result_sender))
+    }
+
+    fn connect<A: ToSocketAddrs>(&self, addr: A) -> Result<(), io::Error> {
+        let addr = addr
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(ErrorKind::InvalidInput, "no addresses to connect to"))?;
+        self.addr.set(Some(addr));
+        Ok(())
+    }
+
+    fn state(&self) -> UnreliableState {
+        match self.addr.get() {
+            None => UnreliableState::Bound,
+            Some(_) => UnreliableState::Connected,
+        }
+    }
+
+    fn send(&mut self, message: Vec<u8>, max_len: usize) -> Result<(), SendError> {
+        if message.len() > max_len {
+            return self
+                .result_sender
+                .send(Err(TransportError::TooLarge(message.len(), max_len)))
+                .map_err(|_| SendError(Source::WriteUnreliable));
+        }
+
+        // Until the peer's first datagram arrives we don't know where to send, which is no
+        // different from the datagram being lost
+        let addr = match self.addr.get() {
+            Some(addr) => addr,
+            None => return Ok(()),
+        };
+
+        self.server
+            .send_to(&message, addr)
+            .map_err(|_| SendError(Source::WriteUnreliable))
+    }
+
+    /// Forgets the peer's address. The server is shut down once every peer sharing it is dropped.
+    fn close(&mut self) {
+        self.addr.set(None);
+    }
+}
+
 fn read_unreliable(
     socket: Arc<UdpSocket>,
     sender: Sender<TransportResult>,
@@ -1,4 +1,7 @@
-use std::fmt::{Debug, Display, Formatter};
+use std::{
+    fmt::{Debug, Display, Formatter},
+    time::Duration,
+};
 
 #[derive(Clone, PartialEq)]
 pub enum NativeId {
@@ -9,12 +12,25 @@ pub enum NativeId {
 pub type MonitorId = u32;
 pub type WindowId = u32;
 
+/// Clockwise, how far a monitor is turned from its natural orientation
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rotation {
+    None,
+    Clockwise90,
+    Clockwise180,
+    Clockwise270,
+}
+
 #[derive(Debug, Clone)]
 pub struct Monitor {
     pub id: MonitorId,
     pub name: String,
+    /// In the units pointer positions are given in
     pub width: u32,
     pub height: u32,
+    /// How many captured pixels there are to each unit of width and height
+    pub scale_factor: f64,
+    pub rotation: Rotation,
 }
 
 #[derive(Debug, Clone)]
@@ -58,13 +74,19 @@ pub type Key = u32; // keysym
 #[derive(Clone, Debug)]
 pub enum ClipboardType {
     Text,
-    Custom(String), // TODO other variants will be added later
+    Html,
+    Rtf,
+    Image, // PNG encoded
+    Custom(String),
 }
 
 impl Display for ClipboardType {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         let str = match self {
             ClipboardType::Text => "ClipboardType::Text",
+            ClipboardType::Html => "ClipboardType::Html",
+            ClipboardType::Rtf => "ClipboardType::Rtf",
+            ClipboardType::Image => "ClipboardType::Image",
             ClipboardType::Custom(custom) => return write!(f, "ClipboardType::Custom({})", custom),
         };
         write!(f, "{}", str)
@@ -77,11 +99,25 @@ pub struct BGRAFrame {
     pub height: u32,
 }
 
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CursorImage {
+    pub width: u32,
+    pub height: u32,
+    pub hotspot_x: u32,
+    pub hotspot_y: u32,
+    /// width * height RGBA pixels, not premultiplied
+    pub data: Vec<u8>,
+}
+
 pub trait NativeApiTemplate {
     type Error: Debug;
 
     fn key_toggle(&mut self, key: Key, down: bool) -> Result<(), Self::Error>;
 
+    /// Types the text as if it came from a keyboard, including characters that aren't on the
+    /// current layout
+    fn type_text(&mut self, text: &str) -> Result<(), Self::Error>;
+
     /// Returns current MousePosition and all Window's the mouse intersect. Intuitively, this should only be one but because Windows can be layered it can be multiple.
     fn pointer_position(&mut self, windows: &[WindowId]) -> Result<MousePosition, Self::Error>;
 
@@ -119,6 +155,10 @@ pub trait NativeApiTemplate {
         content: &[u8],
     ) -> Result<(), Self::Error>;
 
+    /// Returns the image of the cursor as it's currently shown, None if it can't be read on this
+    /// platform.
+    fn cursor_image(&mut self) -> Result<Option<CursorImage>, Self::Error>;
+
     fn monitors(&mut self) -> Result<Vec<Monitor>, Self::Error>;
 
     fn windows(&mut self) -> Result<Vec<Window>, Self::Error>;
@@ -146,6 +186,39 @@ pub trait NativeApiTemplate {
     }
 }
 
+/// Watches something so it doesn't have to be polled. The ClipboardWatcher reports changes to the
+/// system clipboard, including ones made by this process, it's up to the caller to tell them apart.
+/// The DisplayWatcher reports monitors and windows being added, removed or reconfigured. The
+/// CursorWatcher reports the cursor's image changing, so it's only read when it may look different.
+pub trait WatcherTemplate {
+    type Error: Debug;
+
+    /// Blocks until what's watched changes or the timeout elapses. Returns whether it changed.
+    fn wait_for_change(&mut self, timeout: Duration) -> Result<bool, Self::Error>;
+}
+
+/// The monitors and windows as far as watchers that have to poll for changes are concerned
+#[cfg(all(any(windows, target_os = "macos"), not(dummy_native)))]
+#[derive(PartialEq, Eq)]
+pub(crate) struct DisplaySnapshot {
+    monitors: Vec<(MonitorId, u32, u32, Rotation)>,
+    windows: Vec<WindowId>,
+}
+
+#[cfg(all(any(windows, target_os = "macos"), not(dummy_native)))]
+impl DisplaySnapshot {
+    pub(crate) fn take<T: NativeApiTemplate>(api: &mut T) -> Result<Self, T::Error> {
+        Ok(Self {
+            monitors: api
+                .monitors()?
+                .into_iter()
+                .map(|monitor| (monitor.id, monitor.width, monitor.height, monitor.rotation))
+                .collect(),
+            windows: api.windows()?.into_iter().map(|window| window.id).collect(),
+        })
+    }
+}
+
 #[cfg(dummy_native)]
 pub(crate) mod dummy {
     use super::*;
@@ -166,6 +239,10 @@ pub(crate) mod dummy {
             unimplemented!()
         }
 
+        fn type_text(&mut self, _text: &str) -> Result<(), Self::Error> {
+            unimplemented!()
+        }
+
         fn pointer_position(
             &mut self,
             _windows: &[WindowId],
@@ -215,6 +292,10 @@ pub(crate) mod dummy {
             unimplemented!()
         }
 
+        fn cursor_image(&mut self) -> Result<Option<CursorImage>, Self::Error> {
+            unimplemented!()
+        }
+
         fn monitors(&mut self) -> Result<Vec<Monitor>, Self::Error> {
             unimplemented!()
         }
@@ -234,4 +315,52 @@ pub(crate) mod dummy {
             unimplemented!()
         }
     }
+
+    pub enum DummyClipboardWatcher {}
+
+    impl DummyClipboardWatcher {
+        pub fn new() -> Result<Self, Infallible> {
+            unimplemented!()
+        }
+    }
+
+    impl WatcherTemplate for DummyClipboardWatcher {
+        type Error = Infallible;
+
+        fn wait_for_change(&mut self, _timeout: Duration) -> Result<bool, Self::Error> {
+            unimplemented!()
+        }
+    }
+
+    pub enum DummyDisplayWatcher {}
+
+    impl DummyDisplayWatcher {
+        pub fn new() -> Result<Self, Infallible> {
+            unimplemented!()
+        }
+    }
+
+    impl WatcherTemplate for DummyDisplayWatcher {
+        type Error = Infallible;
+
+        fn wait_for_change(&mut self, _timeout: Duration) -> Result<bool, Self::Error> {
+            unimplemented!()
+        }
+    }
+
+    pub enum DummyCursorWatcher {}
+
+    impl DummyCursorWatcher {
+        pub fn new() -> Result<Self, Infallible> {
+            unimplemented!()
+        }
+    }
+
+    impl WatcherTemplate for DummyCursorWatcher {
+        type Error = Infallible;
+
+        fn wait_for_change(&mut self, _timeout: Duration) -> Result<bool, Self::Error> {
+            unimplemented!()
+        }
+    }
 }
@@ -6,18 +6,30 @@ mod keymaps;
 cfg_if! {
     if #[cfg(dummy_native)] {
         pub use api::dummy::DummyApi as NativeApi;
+        pub use api::dummy::DummyClipboardWatcher as ClipboardWatcher;
+        pub use api::dummy::DummyDisplayWatcher as DisplayWatcher;
+        pub use api::dummy::DummyCursorWatcher as CursorWatcher;
         pub use std::convert::Infallible as NativeApiError;
     } else if #[cfg(target_os="linux")] {
         mod linux_x11;
         pub use linux_x11::X11Api as NativeApi;
+        pub use linux_x11::X11ClipboardWatcher as ClipboardWatcher;
+        pub use linux_x11::X11DisplayWatcher as DisplayWatcher;
+        pub use linux_x11::X11CursorWatcher as CursorWatcher;
         pub use linux_x11::Error as NativeApiError;
     } else if #[cfg(windows)] {
         mod windows;
         pub use crate::windows::WindowsApi as NativeApi;
+        pub use crate::windows::WindowsClipboardWatcher as ClipboardWatcher;
+        pub use crate::windows::WindowsDisplayWatcher as DisplayWatcher;
+        pub use crate::windows::WindowsCursorWatcher as CursorWatcher;
         pub use crate::windows::Error as NativeApiError;
     } else if #[cfg(target_os="macos")] {
         mod mac;
         pub use mac::MacApi as NativeApi;
+        pub use mac::MacClipboardWatcher as ClipboardWatcher;
+        pub use mac::MacDisplayWatcher as DisplayWatcher;
+        pub use mac::MacCursorWatcher as CursorWatcher;
         pub use mac::Error as NativeApiError;
     } else {
         compile_error!("Unknown target operating system");
@@ -1,11 +1,26 @@
 use errno::{errno, Errno};
-use libc::{c_int, shmat, shmctl, shmdt, shmget, size_t, IPC_CREAT, IPC_PRIVATE, IPC_RMID};
+use libc::{
+    c_int,
+    poll,
+    pollfd,
+    shmat,
+    shmctl,
+    shmdt,
+    shmget,
+    size_t,
+    EINTR,
+    IPC_CREAT,
+    IPC_PRIVATE,
+    IPC_RMID,
+    POLLIN,
+};
 use std::{
     error::Error as StdError,
     fmt::{self, Debug, Formatter},
+    os::unix::io::AsRawFd,
     ptr,
     str,
-    time::Duration,
+    time::{Duration, Instant},
 };
 use x11::{
     xlib::{XDefaultRootWindow, XKeysymToKeycode, XOpenDisplay, XRaiseWindow, XSync},
@@ -13,14 +28,24 @@ use x11::{
 };
 use x11_clipboard::{error::Error as X11ClipboardError, Clipboard};
 use xcb::{
-    randr::GetMonitors,
+    randr::{self, GetCrtcInfo, GetMonitors, GetOutputInfo, Output, Rotation as RandrRotation},
     shm::{Attach, Detach, GetImage, Seg},
     x::{
+        self,
+        Atom,
+        ChangeKeyboardMapping,
+        ChangeWindowAttributes,
+        Cw,
         Drawable,
+        EventMask,
         GetAtomName,
         GetGeometry,
+        GetKeyboardMapping,
         GetProperty,
         GetWindowAttributes,
+        InternAtom,
+        Keycode,
+        Keysym,
         MapState,
         QueryPointer,
         QueryTree,
@@ -28,9 +53,20 @@ use xcb::{
         Window,
         ATOM_STRING,
         ATOM_WM_NAME,
+        CURRENT_TIME,
+    },
+    xfixes::{
+        self,
+        CursorNotifyMask,
+        GetCursorImage,
+        QueryVersion,
+        SelectCursorInput,
+        SelectSelectionInput,
+        SelectionEventMask,
     },
     ConnError,
     Connection,
+    Extension,
     ProtocolError,
     Xid,
     XidNew,
@@ -45,6 +81,7 @@ struct X11MonitorInfo {
     y: u32,
     width: u32,
     height: u32,
+    rotation: Rotation,
 }
 
 impl MouseButton {
@@ -77,6 +114,12 @@ pub struct X11Api {
 
     // Clipboard API
     clipboard: Clipboard,
+
+    // Whether we've told the server which XFixes version we speak
+    xfixes_ready: bool,
+
+    // A keycode without any keysyms, remapped to type characters that have no key
+    spare_keycode: Option<Keycode>,
 }
 
 unsafe impl Send for X11Api {}
@@ -96,10 +139,66 @@ impl X11Api {
             capture_info: None,
             monitors: Vec::new(),
             clipboard: Clipboard::new()?,
+            xfixes_ready: false,
+            spare_keycode: None,
         })
     }
 }
 
+impl X11Api {
+    fn spare_keycode(&mut self) -> Result<Keycode, Error> {
+        if let Some(keycode) = self.spare_keycode {
+            return Ok(keycode);
+        }
+
+        let setup = self.conn.get_setup();
+        let (min_keycode, max_keycode) = (setup.min_keycode(), setup.max_keycode());
+        let reply = self
+            .conn
+            .wait_for_reply(self.conn.send_request(&GetKeyboardMapping {
+                first_keycode: min_keycode,
+                count: max_keycode - min_keycode + 1,
+            }))?;
+        let per_keycode = reply.keysyms_per_keycode() as usize;
+        // Take the highest, the low ones are where the keys of real keyboards end up
+        let keycode = reply
+            .keysyms()
+            .chunks(per_keycode)
+            .zip(min_keycode ..= max_keycode)
+            .rev()
+            .find(|(keysyms, _)| keysyms.iter().all(|&keysym| keysym == 0))
+            .map(|(_, keycode)| keycode)
+            .ok_or(Error::NoSpareKeycode)?;
+
+        self.spare_keycode = Some(keycode);
+        Ok(keycode)
+    }
+
+    // Maps both the plain and shifted keysyms so Shift being held doesn't change the character
+    fn remap_keycode(&mut self, keycode: Keycode, keysym: Keysym) -> Result<(), Error> {
+        self.conn
+            .check_request(self.conn.send_request_checked(&ChangeKeyboardMapping {
+                keycode_count: 1,
+                first_keycode: keycode,
+                keysyms_per_keycode: 2,
+                keysyms: &[keysym, keysym],
+            }))
+            .map_err(Into::into)
+    }
+}
+
+// Printable Latin-1 characters have the same keysym as their code point, everything else in
+// Unicode is 0x01000000 + its code point
+fn char_to_keysym(c: char) -> Option<Keysym> {
+    match c {
+        '\n' => Some(0xff0d), // Return
+        '\t' => Some(0xff09), // Tab
+        c if c.is_control() => None,
+        ' ' ..= '~' | '\u{a0}' ..= '\u{ff}' => Some(c as Keysym),
+        c => Some(0x0100_0000 | c as Keysym),
+    }
+}
+
 impl NativeApiTemplate for X11Api {
     type Error = Error;
 
@@ -116,6 +215,24 @@ impl NativeApiTemplate for X11Api {
         Ok(())
     }
 
+    fn type_text(&mut self, text: &str) -> Result<(), Error> {
+        let keycode = self.spare_keycode()?;
+        let dpy = self.conn.get_raw_dpy();
+
+        for keysym in text.chars().filter_map(char_to_keysym) {
+            // Clients look keycodes up when they get the event, and the MappingNotify for each
+            // remap is queued behind the key events before it, so every press sees its own keysym
+            self.remap_keycode(keycode, keysym)?;
+            unsafe {
+                XTestFakeKeyEvent(dpy, keycode as _, 1, 0);
+                XTestFakeKeyEvent(dpy, keycode as _, 0, 0);
+                XSync(dpy, 0);
+            }
+        }
+
+        self.remap_keycode(keycode, 0)
+    }
+
     fn pointer_position(&mut self, windows: &[WindowId]) -> Result<MousePosition, Error> {
         let reply = self
             .conn
@@ -239,12 +356,8 @@ impl NativeApiTemplate for X11Api {
     }
 
     fn clipboard_content(&mut self, type_name: &ClipboardType) -> Result<Option<Vec<u8>>, Error> {
+        let target = self.clipboard_target(type_name)?;
         let atoms = &self.clipboard.setter.atoms;
-        let target = match type_name {
-            ClipboardType::Text => atoms.utf8_string,
-            #[allow(unreachable_patterns)]
-            _ => return Err(Error::UnsupportedClipboardType(type_name.clone())),
-        };
         self.clipboard
             .load(
                 atoms.clipboard,
@@ -261,17 +374,49 @@ impl NativeApiTemplate for X11Api {
         type_name: &ClipboardType,
         content: &[u8],
     ) -> Result<(), Error> {
-        let atoms = &self.clipboard.setter.atoms;
-        let target = match type_name {
-            ClipboardType::Text => atoms.utf8_string,
-            #[allow(unreachable_patterns)]
-            _ => return Err(Error::UnsupportedClipboardType(type_name.clone())),
-        };
+        let target = self.clipboard_target(type_name)?;
         self.clipboard
-            .store(atoms.clipboard, target, content)
+            .store(self.clipboard.setter.atoms.clipboard, target, content)
             .map_err(Into::into)
     }
 
+    fn cursor_image(&mut self) -> Result<Option<CursorImage>, Error> {
+        if !self.xfixes_ready {
+            self.conn
+                .wait_for_reply(self.conn.send_request(&QueryVersion {
+                    client_major_version: 5,
+                    client_minor_version: 0,
+                }))?;
+            self.xfixes_ready = true;
+        }
+
+        let reply = self
+            .conn
+            .wait_for_reply(self.conn.send_request(&GetCursorImage {}))?;
+
+        // Pixels are premultiplied ARGB
+        let data = reply
+            .cursor_image()
+            .iter()
+            .flat_map(|&pixel| {
+                let [b, g, r, a] = pixel.to_le_bytes();
+                let unpremultiply = |channel: u8| match a {
+                    0 => 0,
+                    a => ((channel as u32 * 255 + a as u32 / 2) / a as u32).min(255) as u8,
+                };
+                [unpremultiply(r), unpremultiply(g), unpremultiply(b), a]
+            })
+            .collect();
+
+        Ok(Some(CursorImage {
+            width: reply.width() as u32,
+            height: reply.height() as u32,
+            hotspot_x: reply.xhot() as u32,
+            hotspot_y: reply.yhot() as u32,
+            data,
+        }))
+    }
+
     fn monitors(&mut self) -> Result<Vec<Monitor>, Error> {
         self.update_monitors()?;
         Ok(self
@@ -282,6 +427,9 @@ impl NativeApiTemplate for X11Api {
                 name: info.name.clone(),
                 width: info.width,
                 height: info.height,
+                // X11 positions the pointer in pixels
+                scale_factor: 1.0,
+                rotation: info.rotation,
             })
             .collect())
     }
@@ -606,6 +754,7 @@ impl X11Api {
                 y: monitor_info.y() as u32,
                 width: monitor_info.width() as u32,
                 height: monitor_info.height() as u32,
+                rotation: self.monitor_rotation(monitor_info.outputs())?,
             });
         }
 
@@ -613,6 +762,41 @@ impl X11Api {
         Ok(())
     }
 
+    // The rotation of the CRTC driving the monitor's first output. RandR measures it anticlockwise.
+    fn monitor_rotation(&self, outputs: &[Output]) -> Result<Rotation, Error> {
+        let output = match outputs.first() {
+            Some(&output) => output,
+            None => return Ok(Rotation::None),
+        };
+        let crtc = self
+            .conn
+            .wait_for_reply(self.conn.send_request(&GetOutputInfo {
+                output,
+                config_timestamp: CURRENT_TIME,
+            }))?
+            .crtc();
+        if crtc.is_none() {
+            return Ok(Rotation::None);
+        }
+        let rotation = self
+            .conn
+            .wait_for_reply(self.conn.send_request(&GetCrtcInfo {
+                crtc,
+                config_timestamp: CURRENT_TIME,
+            }))?
+            .rotation();
+
+        Ok(if rotation.contains(RandrRotation::ROTATE_90) {
+            Rotation::Clockwise270
+        } else if rotation.contains(RandrRotation::ROTATE_180) {
+            Rotation::Clockwise180
+        } else if rotation.contains(RandrRotation::ROTATE_270) {
+            Rotation::Clockwise90
+        } else {
+            Rotation::None
+        })
+    }
+
     fn get_monitor(&mut self, id: MonitorId) -> Result<&X11MonitorInfo, Error> {
         // We have to use indices here because for once the programmer is smarter than the borrow
         // checker
@@ -631,6 +815,19 @@ impl X11Api {
         self.monitors.iter().position(|monitor| monitor.id == id)
     }
 
+    fn clipboard_target(&self, type_name: &ClipboardType) -> Result<Atom, Error> {
+        let context = &self.clipboard.setter;
+        let target = match type_name {
+            ClipboardType::Text => return Ok(context.atoms.utf8_string),
+            ClipboardType::Html => "text/html",
+            ClipboardType::Rtf => "text/rtf",
+            ClipboardType::Image => "image/png",
+            ClipboardType::Custom(_) =>
+                return Err(Error::UnsupportedClipboardType(type_name.clone())),
+        };
+        context.get_atom(target).map_err(Into::into)
+    }
+
     fn list_windows(&self, window: Window, windows: &mut Vec<Window>) -> Result<(), Error> {
         let wininfo = self
             .conn
@@ -686,6 +883,200 @@ impl Drop for X11Api {
     }
 }
 
+/// Watches the CLIPBOARD selection using XFixes, which tells us whenever its owner changes. This
+/// uses its own connection so it can block without holding up the X11Api.
+pub struct X11ClipboardWatcher {
+    conn: Connection,
+    clipboard: Atom,
+}
+
+unsafe impl Send for X11ClipboardWatcher {}
+
+impl X11ClipboardWatcher {
+    pub fn new() -> Result<Self, Error> {
+        let (conn, screen_num) =
+            Connection::connect_with_extensions(None, &[Extension::XFixes], &[])?;
+        let root = conn
+            .get_setup()
+            .roots()
+            .nth(screen_num as usize)
+            .ok_or(Error::DisplayOpenFailed)?
+            .root();
+
+        // The server won't send XFixes events until we've told it which version we speak
+        conn.wait_for_reply(conn.send_request(&QueryVersion {
+            client_major_version: 5,
+            client_minor_version: 0,
+        }))?;
+
+        let clipboard = conn
+            .wait_for_reply(conn.send_request(&InternAtom {
+                only_if_exists: false,
+                name: b"CLIPBOARD",
+            }))?
+            .atom();
+
+        conn.check_request(conn.send_request_checked(&SelectSelectionInput {
+            window: root,
+            selection: clipboard,
+            event_mask: SelectionEventMask::SET_SELECTION_OWNER
+                | SelectionEventMask::SELECTION_WINDOW_DESTROY
+                | SelectionEventMask::SELECTION_CLIENT_CLOSE,
+        }))?;
+
+        Ok(Self { conn, clipboard })
+    }
+}
+
+impl WatcherTemplate for X11ClipboardWatcher {
+    type Error = Error;
+
+    fn wait_for_change(&mut self, timeout: Duration) -> Result<bool, Error> {
+        let clipboard = self.clipboard;
+        wait_for_event(&self.conn, timeout, |event| {
+            matches!(event, xcb::Event::XFixes(xfixes::Event::SelectionNotify(event))
+                if event.selection() == clipboard)
+        })
+    }
+}
+
+pub struct X11DisplayWatcher {
+    conn: Connection,
+}
+
+unsafe impl Send for X11DisplayWatcher {}
+
+impl X11DisplayWatcher {
+    pub fn new() -> Result<Self, Error> {
+        let (conn, screen_num) =
+            Connection::connect_with_extensions(None, &[Extension::RandR], &[])?;
+        let root = conn
+            .get_setup()
+            .roots()
+            .nth(screen_num as usize)
+            .ok_or(Error::DisplayOpenFailed)?
+            .root();
+
+        // The server won't send RandR events until we've told it which version we speak
+        conn.wait_for_reply(conn.send_request(&randr::QueryVersion {
+            major_version: 1,
+            minor_version: 5,
+        }))?;
+
+        conn.check_request(conn.send_request_checked(&randr::SelectInput {
+            window: root,
+            enable: randr::NotifyMask::SCREEN_CHANGE
+                | randr::NotifyMask::CRTC_CHANGE
+                | randr::NotifyMask::OUTPUT_CHANGE,
+        }))?;
+
+        // Shared windows are often children of the top-level windows the window manager creates,
+        // those come and go with them so watching the root's children is enough
+        conn.check_request(conn.send_request_checked(&ChangeWindowAttributes {
+            window: root,
+            value_list: &[Cw::EventMask(EventMask::SUBSTRUCTURE_NOTIFY)],
+        }))?;
+
+        Ok(Self { conn })
+    }
+}
+
+impl WatcherTemplate for X11DisplayWatcher {
+    type Error = Error;
+
+    fn wait_for_change(&mut self, timeout: Duration) -> Result<bool, Error> {
+        wait_for_event(&self.conn, timeout, |event| {
+            matches!(
+                event,
+                xcb::Event::RandR(_)
+                    | xcb::Event::X(
+                        x::Event::MapNotify(_)
+                            | x::Event::UnmapNotify(_)
+                            | x::Event::DestroyNotify(_)
+                    )
+            )
+        })
+    }
+}
+
+/// Watches the cursor using XFixes, which tells us whenever the cursor shown changes. This uses its
+/// own connection so it can block without holding up the X11Api.
+pub struct X11CursorWatcher {
+    conn: Connection,
+}
+
+unsafe impl Send for X11CursorWatcher {}
+
+impl X11CursorWatcher {
+    pub fn new() -> Result<Self, Error> {
+        let (conn, screen_num) =
+            Connection::connect_with_extensions(None, &[Extension::XFixes], &[])?;
+        let root = conn
+            .get_setup()
+            .roots()
+            .nth(screen_num as usize)
+            .ok_or(Error::DisplayOpenFailed)?
+            .root();
+
+        // The server won't send XFixes events until we've told it which version we speak
+        conn.wait_for_reply(conn.send_request(&QueryVersion {
+            client_major_version: 5,
+            client_minor_version: 0,
+        }))?;
+
+        conn.check_request(conn.send_request_checked(&SelectCursorInput {
+            window: root,
+            event_mask: CursorNotifyMask::DISPLAY_CURSOR,
+        }))?;
+
+        Ok(Self { conn })
+    }
+}
+
+impl WatcherTemplate for X11CursorWatcher {
+    type Error = Error;
+
+    fn wait_for_change(&mut self, timeout: Duration) -> Result<bool, Error> {
+        wait_for_event(&self.conn, timeout, |event| {
+            matches!(event, xcb::Event::XFixes(xfixes::Event::CursorNotify(_)))
+        })
+    }
+}
+
+// Waits until the server sends an event that matches or the timeout elapses, returning whether one
+// did. Other events are dropped.
+fn wait_for_event<F>(conn: &Connection, timeout: Duration, mut matches: F) -> Result<bool, Error>
+where F: FnMut(&xcb::Event) -> bool {
+    let deadline = Instant::now() + timeout;
+
+    loop {
+        if let Some(event) = conn.poll_for_event()? {
+            if matches(&event) {
+                return Ok(true);
+            }
+            continue;
+        }
+
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining == Duration::ZERO {
+            return Ok(false);
+        }
+
+        // Nothing is queued so wait for the server to send us something
+        let mut fd = pollfd {
+            fd: conn.as_raw_fd(),
+            events: POLLIN,
+            revents: 0,
+        };
+        if unsafe { poll(&mut fd, 1, remaining.as_millis() as c_int) } < 0 {
+            let error = errno();
+            if error.0 != EINTR {
+                return Err(Error::Poll(error));
+            }
+        }
+    }
+}
+
 #[derive(Clone, Copy)]
 struct CaptureInfo {
     width: u16,
@@ -715,6 +1106,10 @@ pub enum Error {
     Clipboard(#[from] X11ClipboardError),
     #[error("clipboard type {0:?} not supported")]
     UnsupportedClipboardType(ClipboardType),
+    #[error("failed to wait for X events: error code {0}")]
+    Poll(Errno),
+    #[error("no spare keycode to type characters with")]
+    NoSpareKeycode,
 }
 
 // TODO: get this sorted out
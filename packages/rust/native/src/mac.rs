@@ -17,6 +17,8 @@ use std::{
     ptr,
     ptr::copy_nonoverlapping,
     slice::from_raw_parts,
+    thread,
+    time::{Duration, Instant},
 };
 
 use cocoa::{
@@ -26,6 +28,9 @@ use cocoa::{
         NSColorSpace,
         NSEvent,
         NSPasteboard,
+        NSPasteboardTypeHTML,
+        NSPasteboardTypePNG,
+        NSPasteboardTypeRTF,
         NSPasteboardTypeString,
         NSRunningApplication,
         NSScreen,
@@ -37,6 +42,7 @@ use cocoa::{
         NSDate,
         NSDefaultRunLoopMode,
         NSDictionary,
+        NSInteger,
         NSPoint,
         NSRect,
         NSRunLoop,
@@ -105,6 +111,7 @@ struct MacMonitor {
     id: u32,
     name: String,
     rect: NSRect,
+    scale_factor: CGFloat,
 }
 
 #[derive(Debug)]
@@ -153,6 +160,13 @@ impl From<MacMonitor> for Monitor {
             name: m.name,
             width: m.rect.size.width as u32,
             height: m.rect.size.height as u32,
+            scale_factor: m.scale_factor,
+            rotation: match CGDisplay::new(m.id).rotation() as u32 {
+                90 => Rotation::Clockwise90,
+                180 => Rotation::Clockwise180,
+                270 => Rotation::Clockwise270,
+                _ => Rotation::None,
+            },
         }
     }
 }
@@ -265,6 +279,19 @@ impl MacApi {
         })
     }
 
+    fn pasteboard_type(type_name: &ClipboardType) -> id {
+        unsafe {
+            match type_name {
+                ClipboardType::Text => NSPasteboardTypeString,
+                ClipboardType::Html => NSPasteboardTypeHTML,
+                ClipboardType::Rtf => NSPasteboardTypeRTF,
+                ClipboardType::Image => NSPasteboardTypePNG,
+                ClipboardType::Custom(type_name) =>
+                    NSString::alloc(nil).init_str(type_name.as_str()),
+            }
+        }
+    }
+
     fn set_clipboard_content_impl(type_name: id, content: &[u8]) -> Result<(), Error> {
         let paste_board = unsafe { NSPasteboard::generalPasteboard(nil) };
         unsafe { NSPasteboard::clearContents(paste_board) };
@@ -317,6 +344,7 @@ impl MacApi {
                 id: number,
                 name,
                 rect: nsrect,
+                scale_factor: unsafe { NSScreen::backingScaleFactor(nsscreen) },
             });
         }
         Ok(monitors)
@@ -626,6 +654,32 @@ impl NativeApiTemplate for MacApi {
         Ok(())
     }
 
+    fn type_text(&mut self, text: &str) -> Result<(), Error> {
+        // Apps only read the first 20 UTF-16 units of the string an event carries
+        const MAX_UNITS: usize = 20;
+        let mut chunks = vec![String::new()];
+        for c in text.chars() {
+            let chunk = chunks.last_mut().unwrap();
+            if chunk.encode_utf16().count() + c.len_utf16() > MAX_UNITS {
+                chunks.push(String::new());
+            }
+            chunks.last_mut().unwrap().push(c);
+        }
+
+        // The key code is ignored once the event carries a string, apps are only given the string
+        for chunk in chunks.iter().filter(|chunk| !chunk.is_empty()) {
+            for down in [true, false] {
+                let source = CGEventSource::new(CGEventSourceStateID::CombinedSessionState)
+                    .map_err(|_| UnableToCreateCGSource)?;
+                let key_event =
+                    CGEvent::new_keyboard_event(source, 0, down).map_err(|_| CGEventError)?;
+                key_event.set_string(chunk);
+                key_event.post(CGEventTapLocation::Session);
+            }
+        }
+        Ok(())
+    }
+
     fn pointer_position(&mut self, windows: &[WindowId]) -> Result<MousePosition, Error> {
         let position = Self::pointer_position_impl()?;
         let normalized_position = NSPoint::new(
@@ -801,17 +855,8 @@ impl NativeApiTemplate for MacApi {
         if paste_board == nil {
             return Err(NSPasteboardError);
         }
-        let data = match type_name {
-            ClipboardType::Text => unsafe {
-                NSPasteboard::dataForType(paste_board, NSPasteboardTypeString)
-            },
-            ClipboardType::Custom(type_name) => unsafe {
-                NSPasteboard::dataForType(
-                    paste_board,
-                    NSString::alloc(nil).init_str(type_name.as_str()),
-                )
-            },
-        };
+        let data =
+            unsafe { NSPasteboard::dataForType(paste_board, Self::pasteboard_type(type_name)) };
         if data == nil {
             return Ok(None);
         }
@@ -823,14 +868,12 @@ impl NativeApiTemplate for MacApi {
         type_name: &ClipboardType,
         content: &[u8],
     ) -> Result<(), Error> {
-        let type_name = unsafe {
-            match type_name {
-                ClipboardType::Text => NSPasteboardTypeString,
-                ClipboardType::Custom(type_name) =>
-                    NSString::alloc(nil).init_str(type_name.as_str()),
-            }
-        };
-        Self::set_clipboard_content_impl(type_name, content)
+        Self::set_clipboard_content_impl(Self::pasteboard_type(type_name), content)
+    }
+
+    fn cursor_image(&mut self) -> Result<Option<CursorImage>, Error> {
+        // TODO: read the cursor image, until then Clients draw their own
+        Ok(None)
     }
 
     fn monitors(&mut self) -> Result<Vec<Monitor>, Error> {
@@ -856,6 +899,107 @@ impl NativeApiTemplate for MacApi {
     }
 }
 
+/// NSPasteboard has no change notifications, so this polls its change count instead
+pub struct MacClipboardWatcher {
+    change_count: NSInteger,
+}
+
+unsafe impl Send for MacClipboardWatcher {}
+
+impl MacClipboardWatcher {
+    const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+    pub fn new() -> Result<Self, Error> {
+        Ok(Self {
+            change_count: Self::change_count(),
+        })
+    }
+
+    fn change_count() -> NSInteger {
+        unsafe { NSPasteboard::generalPasteboard(nil).changeCount() }
+    }
+}
+
+impl WatcherTemplate for MacClipboardWatcher {
+    type Error = Error;
+
+    fn wait_for_change(&mut self, timeout: Duration) -> Result<bool, Error> {
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            let change_count = Self::change_count();
+            if change_count != self.change_count {
+                self.change_count = change_count;
+                return Ok(true);
+            }
+
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining == Duration::ZERO {
+                return Ok(false);
+            }
+            thread::sleep(remaining.min(Self::POLL_INTERVAL));
+        }
+    }
+}
+
+/// Polls the monitors and windows, there's no notification of them changing we can wait on
+pub struct MacDisplayWatcher {
+    api: MacApi,
+    snapshot: DisplaySnapshot,
+}
+
+unsafe impl Send for MacDisplayWatcher {}
+
+impl MacDisplayWatcher {
+    const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+    pub fn new() -> Result<Self, Error> {
+        let mut api = MacApi::new()?;
+        let snapshot = DisplaySnapshot::take(&mut api)?;
+        Ok(Self { api, snapshot })
+    }
+}
+
+impl WatcherTemplate for MacDisplayWatcher {
+    type Error = Error;
+
+    fn wait_for_change(&mut self, timeout: Duration) -> Result<bool, Error> {
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            let snapshot = DisplaySnapshot::take(&mut self.api)?;
+            if snapshot != self.snapshot {
+                self.snapshot = snapshot;
+                return Ok(true);
+            }
+
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining == Duration::ZERO {
+                return Ok(false);
+            }
+            thread::sleep(remaining.min(Self::POLL_INTERVAL));
+        }
+    }
+}
+
+/// The cursor image can't be read yet, so there are no changes to report
+pub struct MacCursorWatcher {}
+
+impl MacCursorWatcher {
+    pub fn new() -> Result<Self, Error> {
+        Ok(Self {})
+    }
+}
+
+impl WatcherTemplate for MacCursorWatcher {
+    type Error = Error;
+
+    fn wait_for_change(&mut self, timeout: Duration) -> Result<bool, Error> {
+        thread::sleep(timeout);
+        Ok(false)
+    }
+}
+
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
     #[error("Could not get Window Array")]
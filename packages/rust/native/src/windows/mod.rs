@@ -12,7 +12,12 @@ use crate::{
         window_captuer_win_gdi::WindowCapturerWinGdi,
     },
 };
-use std::{collections::HashMap, string::FromUtf16Error};
+use std::{
+    collections::HashMap,
+    string::FromUtf16Error,
+    thread,
+    time::{Duration, Instant},
+};
 use windows::{
     core::{PCWSTR, PSTR},
     Win32::{
@@ -26,6 +31,9 @@ use windows::{
             DEVMODEW,
             DISPLAY_DEVICEW,
             DISPLAY_DEVICE_ACTIVE,
+            DMDO_180,
+            DMDO_270,
+            DMDO_90,
             ENUM_CURRENT_SETTINGS,
             HDC,
             HMONITOR,
@@ -37,6 +45,7 @@ use windows::{
                 CloseClipboard,
                 EmptyClipboard,
                 GetClipboardData,
+                GetClipboardSequenceNumber,
                 OpenClipboard,
                 SetClipboardData,
             },
@@ -55,6 +64,7 @@ use windows::{
                 KEYBD_EVENT_FLAGS,
                 KEYEVENTF_KEYUP,
                 KEYEVENTF_SCANCODE,
+                KEYEVENTF_UNICODE,
                 MOUSEEVENTF_ABSOLUTE,
                 MOUSEEVENTF_HWHEEL,
                 MOUSEEVENTF_LEFTDOWN,
@@ -68,6 +78,7 @@ use windows::{
                 MOUSEEVENTF_WHEEL,
                 MOUSEEVENTF_XDOWN,
                 MOUSEEVENTF_XUP,
+                VIRTUAL_KEY,
                 VK_SPACE,
             },
             WindowsAndMessaging::{
@@ -377,6 +388,32 @@ impl NativeApiTemplate for WindowsApi {
         Ok(())
     }
 
+    fn type_text(&mut self, text: &str) -> Result<(), Self::Error> {
+        // Each UTF-16 unit is pressed and released, Windows puts surrogate pairs back together
+        let inputs: Vec<_> = text
+            .encode_utf16()
+            .flat_map(|unit| {
+                [KEYBD_EVENT_FLAGS(0), KEYEVENTF_KEYUP].map(|flags| INPUT {
+                    r#type: INPUT_KEYBOARD,
+                    Anonymous: INPUT_0 {
+                        ki: KEYBDINPUT {
+                            wVk: VIRTUAL_KEY(0),
+                            wScan: unit,
+                            dwFlags: KEYEVENTF_UNICODE | flags,
+                            time: 0,
+                            dwExtraInfo: 0,
+                        },
+                    },
+                })
+            })
+            .collect();
+        if inputs.is_empty() {
+            return Ok(());
+        }
+        unsafe { SendInput(&inputs, std::mem::size_of::<INPUT>() as i32) };
+        Ok(())
+    }
+
     fn pointer_position(&mut self, windows: &[WindowId]) -> Result<MousePosition, Self::Error> {
         let mut point = POINT { x: 0, y: 0 };
         if unsafe { GetCursorPos(&mut point) } == FALSE {
@@ -542,7 +579,8 @@ impl NativeApiTemplate for WindowsApi {
         &mut self,
         type_name: &ClipboardType,
     ) -> Result<Option<Vec<u8>>, Self::Error> {
-        if let ClipboardType::Custom(_) = type_name {
+        // TODO: register formats for html, rtf and png
+        if !matches!(type_name, ClipboardType::Text) {
             return Ok(None);
         }
 
@@ -586,8 +624,8 @@ impl NativeApiTemplate for WindowsApi {
         type_name: &ClipboardType,
         content: &[u8],
     ) -> Result<(), Self::Error> {
-        if let ClipboardType::Custom(_) = type_name {
-            // windows doesn't support custom clipboard types by string
+        if !matches!(type_name, ClipboardType::Text) {
+            // windows doesn't support custom clipboard types by string, the others aren't mapped yet
             return Ok(());
         }
 
@@ -628,6 +666,11 @@ impl NativeApiTemplate for WindowsApi {
     }
 
     /// Note: The device id returned by this method is not guaranteed to be consistant
+    fn cursor_image(&mut self) -> Result<Option<CursorImage>, Self::Error> {
+        // TODO: read the cursor image, until then Clients draw their own
+        Ok(None)
+    }
+
     fn monitors(&mut self) -> Result<Vec<Monitor>, Self::Error> {
         Ok(self
             .monitors_impl()?
@@ -637,6 +680,15 @@ impl NativeApiTemplate for WindowsApi {
                 name: m.name,
                 width: m.device_mode.dmPelsWidth,
                 height: m.device_mode.dmPelsHeight,
+                // The display mode is measured in pixels, the same as captured frames
+                scale_factor: 1.0,
+                rotation: match unsafe { m.device_mode.Anonymous1.Anonymous2.dmDisplayOrientation }
+                {
+                    DMDO_90 => Rotation::Clockwise90,
+                    DMDO_180 => Rotation::Clockwise180,
+                    DMDO_270 => Rotation::Clockwise270,
+                    _ => Rotation::None,
+                },
             })
             .collect())
     }
@@ -724,6 +776,99 @@ unsafe extern "system" fn window_callback(window_handle: HWND, data_ptr: LPARAM)
     TRUE
 }
 
+/// Polls the clipboard sequence number, which Windows bumps every time the clipboard changes
+pub struct WindowsClipboardWatcher {
+    sequence_number: u32,
+}
+
+impl WindowsClipboardWatcher {
+    const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+    pub fn new() -> Result<Self, Error> {
+        Ok(Self {
+            sequence_number: unsafe { GetClipboardSequenceNumber() },
+        })
+    }
+}
+
+impl WatcherTemplate for WindowsClipboardWatcher {
+    type Error = Error;
+
+    fn wait_for_change(&mut self, timeout: Duration) -> Result<bool, Error> {
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            let sequence_number = unsafe { GetClipboardSequenceNumber() };
+            if sequence_number != self.sequence_number {
+                self.sequence_number = sequence_number;
+                return Ok(true);
+            }
+
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining == Duration::ZERO {
+                return Ok(false);
+            }
+            thread::sleep(remaining.min(Self::POLL_INTERVAL));
+        }
+    }
+}
+
+/// Polls the monitors and windows, there's no notification of them changing we can wait on
+pub struct WindowsDisplayWatcher {
+    api: WindowsApi,
+    snapshot: DisplaySnapshot,
+}
+
+impl WindowsDisplayWatcher {
+    const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+    pub fn new() -> Result<Self, Error> {
+        let mut api = WindowsApi::new()?;
+        let snapshot = DisplaySnapshot::take(&mut api)?;
+        Ok(Self { api, snapshot })
+    }
+}
+
+impl WatcherTemplate for WindowsDisplayWatcher {
+    type Error = Error;
+
+    fn wait_for_change(&mut self, timeout: Duration) -> Result<bool, Error> {
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            let snapshot = DisplaySnapshot::take(&mut self.api)?;
+            if snapshot != self.snapshot {
+                self.snapshot = snapshot;
+                return Ok(true);
+            }
+
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining == Duration::ZERO {
+                return Ok(false);
+            }
+            thread::sleep(remaining.min(Self::POLL_INTERVAL));
+        }
+    }
+}
+
+/// The cursor image can't be read yet, so there are no changes to report
+pub struct WindowsCursorWatcher {}
+
+impl WindowsCursorWatcher {
+    pub fn new() -> Result<Self, Error> {
+        Ok(Self {})
+    }
+}
+
+impl WatcherTemplate for WindowsCursorWatcher {
+    type Error = Error;
+
+    fn wait_for_change(&mut self, timeout: Duration) -> Result<bool, Error> {
+        thread::sleep(timeout);
+        Ok(false)
+    }
+}
+
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
     #[error("windows api error occured when calling {0}")]
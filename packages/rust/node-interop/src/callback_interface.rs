@@ -60,6 +60,8 @@ impl_try_into_js_type!(
     i32 => |cx, me| Ok(JsNumber::new(cx, me).upcast()),
     u8 => |cx, me| Ok(JsNumber::new(cx, me).upcast()),
     u16 => |cx, me| Ok(JsNumber::new(cx, me).upcast()),
+    u32 => |cx, me| Ok(JsNumber::new(cx, me).upcast()),
+    f64 => |cx, me| Ok(JsNumber::new(cx, me).upcast()),
     String => |cx, me| Ok(JsString::new(cx, me).upcast()),
     EstablishSessionStatus => |cx, me| Ok(JsNumber::new(cx, me as u8).upcast()),
     /*DisplayInformation => |cx, me| Ok(js_object!(cx,
@@ -134,11 +136,30 @@ vtable_methods!(
     wpskka_client_password_prompt(),
     wpskka_client_authentication_successful(),
     wpskka_client_authentication_failed(),
+    wpskka_client_host_key_changed(host: String, previous_fingerprint: String, fingerprint: String),
     /* wpskka - host */
     wpskka_host_authentication_successful(),
     /* rvd - client */
     rvd_frame_data(display_id: u8, data: Vec<u8>),
     rvd_client_handshake_complete(),
+    rvd_client_control_changed(has_control: bool),
+    rvd_display_geometry(display_id: u8, width: u32, height: u32, scale: f64, rotation: u32), // rotation in degrees clockwise
+    rvd_cursor_shape(width: u32, height: u32, hotspot_x: u32, hotspot_y: u32, data: Vec<u8>),
+    /* rvd - files, sizes are f64 as they can be larger than a u32 */
+    rvd_file_offered(file_id: u32, name: String, size: f64),
+    rvd_file_progress(file_id: u32, transferred: f64, size: f64),
+    rvd_file_complete(file_id: u32, path: String),
+    rvd_file_failed(file_id: u32, reason: String),
     /* rvd - host */
     rvd_host_handshake_complete(),
+    rvd_host_input_rejected(), // input from a client without the controls was dropped
+    /* direct host clients */
+    host_client_connected(client_id: u32),
+    host_client_disconnected(client_id: u32),
+    host_client_control_requested(client_id: u32),
+    host_client_input_rejected(client_id: u32),
+    /* host displays */
+    host_displays_changed(), // the monitors or windows available to share may have changed
+    host_display_lost(display_type: String, native_id: u32),
+    host_display_restored(display_type: String, native_id: u32),
 );
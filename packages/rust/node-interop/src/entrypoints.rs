@@ -4,11 +4,11 @@
 use crate::{
     callback_interface::NodeInterface,
     instance_handler::InstanceHandle,
-    protocol::{ConnectionType, Message, RequestContent},
+    protocol::{ConnectionType, ControllableDisplays, Message, RequestContent},
     thumbnail_driver::ThumbnailHandle,
 };
 use common::messages::{
-    rvd::ButtonsMask,
+    rvd::{AccessMask, ButtonsMask, PermissionMask},
     svsc::{Cookie, LeaseId},
 };
 use io::Unreliable;
@@ -18,6 +18,10 @@ use native::{
 };
 use neon::{prelude::*, types::buffer::TypedArray};
 use num_traits::FromPrimitive;
+use peer::{
+    helpers::{crypto::IdentityKeyPair, secret::SecretVec},
+    wpskka::known_hosts::{decode_public_key, encode_public_key, HostKey, KnownHosts},
+};
 use std::{any::type_name, cell::RefCell, convert::TryFrom, num::FpCategory, sync::Mutex};
 
 #[macro_export]
@@ -211,6 +215,14 @@ pub fn keyboard_input(mut cx: FunctionContext<'_>) -> JsResult<'_, JsPromise> {
     })
 }
 
+// Text from an IME or with characters the Host's keyboard layout may not have
+pub fn text_input(mut cx: FunctionContext<'_>) -> JsResult<'_, JsPromise> {
+    let handle = cx.argument::<InstanceWrapper>(0)?;
+    let text = cx.argument::<JsString>(1)?.value(&mut cx);
+
+    send_request(&mut cx, handle, RequestContent::TextInput { text })
+}
+
 pub fn lease_request(mut cx: FunctionContext<'_>) -> JsResult<'_, JsPromise> {
     let handle = cx.argument::<InstanceWrapper>(0)?;
     let cookie: Option<Cookie> = match cx
@@ -233,19 +245,57 @@ pub fn update_static_password(mut cx: FunctionContext<'_>) -> JsResult<'_, JsPro
         .argument::<JsValue>(1)?
         .downcast::<JsString, _>(&mut cx)
         .ok()
-        .map(|string| string.value(&mut cx).into_bytes());
+        .map(|string| SecretVec::from(string.value(&mut cx).into_bytes()));
 
     send_request(&mut cx, handle, RequestContent::UpdateStaticPassword {
         password,
     })
 }
 
+// Displays are passed as an array of { native_id, type } objects
+fn displays_arg(cx: &mut FunctionContext<'_>, index: i32) -> NeonResult<Vec<NativeId>> {
+    let js_displays = cx.argument::<JsArray>(index)?;
+
+    let len = js_displays.len(cx);
+    let mut displays = Vec::with_capacity(usize::try_from(len).unwrap());
+
+    for i in 0 .. len {
+        let obj = js_displays.get::<JsObject, _, _>(cx, i)?;
+        let native_id = obj.get::<JsNumber, _, _>(cx, "native_id")?.value(cx);
+        let native_id: u32 = checked_int_cast(cx, native_id)?;
+        let display_type = obj.get::<JsString, _, _>(cx, "type")?.value(cx);
+
+        let display = match display_type.as_str() {
+            "monitor" => NativeId::Monitor(native_id),
+            "window" => NativeId::Window(native_id),
+            _ => return throw!(*cx, "invalid display type"),
+        };
+
+        displays.push(display);
+    }
+
+    Ok(displays)
+}
+
+// Either a boolean which applies to every display or an array of the displays which are controllable
+fn controllable_arg(cx: &mut FunctionContext<'_>, index: i32) -> NeonResult<ControllableDisplays> {
+    let value = cx.argument::<JsValue>(index)?;
+    if let Ok(controllable) = value.downcast::<JsBoolean, _>(cx) {
+        return Ok(if controllable.value(cx) {
+            ControllableDisplays::All
+        } else {
+            ControllableDisplays::None
+        });
+    }
+    displays_arg(cx, index).map(ControllableDisplays::Only)
+}
+
 pub fn set_controllable(mut cx: FunctionContext<'_>) -> JsResult<'_, JsPromise> {
     let handle = cx.argument::<InstanceWrapper>(0)?;
-    let is_controllable = cx.argument::<JsBoolean>(1)?.value(&mut cx);
+    let controllable = controllable_arg(&mut cx, 1)?;
 
     send_request(&mut cx, handle, RequestContent::SetControllable {
-        is_controllable,
+        controllable,
     })
 }
 
@@ -260,35 +310,152 @@ pub fn set_clipboard_readable(mut cx: FunctionContext<'_>) -> JsResult<'_, JsPro
 
 pub fn share_displays(mut cx: FunctionContext<'_>) -> JsResult<'_, JsPromise> {
     let handle = cx.argument::<InstanceWrapper>(0)?;
-    let js_displays = cx.argument::<JsArray>(1)?;
-    let controllable = cx.argument::<JsBoolean>(2)?.value(&mut cx);
+    let displays = displays_arg(&mut cx, 1)?;
+    let controllable = controllable_arg(&mut cx, 2)?;
 
-    let len = js_displays.len(&mut cx);
-    let mut displays = Vec::with_capacity(usize::try_from(len).unwrap());
+    send_request(&mut cx, handle, RequestContent::ShareDisplays {
+        displays,
+        controllable,
+    })
+}
 
-    for i in 0 .. len {
-        let obj = js_displays.get::<JsObject, _, _>(&mut cx, i)?;
-        let native_id = obj
-            .get::<JsNumber, _, _>(&mut cx, "native_id")?
-            .value(&mut cx);
-        let native_id: u32 = checked_int_cast(&mut cx, native_id)?;
-        let display_type = obj.get::<JsString, _, _>(&mut cx, "type")?.value(&mut cx);
 
-        let display = match display_type.as_str() {
-            "monitor" => NativeId::Monitor(native_id),
-            "window" => NativeId::Window(native_id),
-            _ => return throw!(cx, "invalid display type"),
-        };
+pub fn set_known_hosts(mut cx: FunctionContext<'_>) -> JsResult<'_, JsPromise> {
+    let handle = cx.argument::<InstanceWrapper>(0)?;
+    let path = cx
+        .argument::<JsValue>(1)?
+        .downcast::<JsString, _>(&mut cx)
+        .ok()
+        .map(|string| string.value(&mut cx));
 
-        displays.push(display);
+    send_request(&mut cx, handle, RequestContent::SetKnownHosts { path })
+}
+
+// The identity is a PKCS#8 document from identity_generate, null stops public key authentication
+pub fn set_identity(mut cx: FunctionContext<'_>) -> JsResult<'_, JsPromise> {
+    let handle = cx.argument::<InstanceWrapper>(0)?;
+    let pkcs8 = cx
+        .argument::<JsValue>(1)?
+        .downcast::<JsArrayBuffer, _>(&mut cx)
+        .ok()
+        .map(|array_buf| SecretVec::from(array_buf.as_slice(&cx)));
+
+    send_request(&mut cx, handle, RequestContent::SetIdentity { pkcs8 })
+}
+
+fn public_key_arg(
+    cx: &mut FunctionContext<'_>,
+    value: Handle<'_, JsValue>,
+) -> NeonResult<[u8; 32]> {
+    let public_key = value.downcast_or_throw::<JsString, _>(cx)?.value(cx);
+    match decode_public_key(&public_key) {
+        Some(public_key) => Ok(public_key),
+        None => throw!(*cx, "Public key must be 32 hex encoded bytes"),
     }
+}
 
-    send_request(&mut cx, handle, RequestContent::ShareDisplays {
-        displays,
-        controllable,
+// Replaces the public keys of the Clients that may authenticate with their identity
+pub fn set_authorized_clients(mut cx: FunctionContext<'_>) -> JsResult<'_, JsPromise> {
+    let handle = cx.argument::<InstanceWrapper>(0)?;
+    let js_keys = cx.argument::<JsArray>(1)?.to_vec(&mut cx)?;
+    let public_keys = js_keys
+        .into_iter()
+        .map(|value| public_key_arg(&mut cx, value))
+        .collect::<NeonResult<Vec<_>>>()?;
+
+    send_request(&mut cx, handle, RequestContent::SetAuthorizedClients {
+        public_keys,
+    })
+}
+
+pub fn set_client_access(mut cx: FunctionContext<'_>) -> JsResult<'_, JsPromise> {
+    let handle = cx.argument::<InstanceWrapper>(0)?;
+    let client_id = integer_arg::<u32>(&mut cx, 1)?;
+    let permissions = integer_arg::<u8>(&mut cx, 2)?;
+    let access = integer_arg::<u8>(&mut cx, 3)?;
+    let (permissions, access) = match (
+        PermissionMask::from_bits(permissions),
+        AccessMask::from_bits(access),
+    ) {
+        (Some(permissions), Some(access)) => (permissions, access),
+        _ => return throw!(cx, "Invalid permission or access mask: invalid bit pattern"),
+    };
+
+    send_request(&mut cx, handle, RequestContent::SetClientAccess {
+        client_id,
+        permissions,
+        access,
+    })
+}
+
+pub fn disconnect_client(mut cx: FunctionContext<'_>) -> JsResult<'_, JsPromise> {
+    let handle = cx.argument::<InstanceWrapper>(0)?;
+    let client_id = integer_arg::<u32>(&mut cx, 1)?;
+
+    send_request(&mut cx, handle, RequestContent::DisconnectClient {
+        client_id,
+    })
+}
+
+pub fn grant_control(mut cx: FunctionContext<'_>) -> JsResult<'_, JsPromise> {
+    let handle = cx.argument::<InstanceWrapper>(0)?;
+    let client_id = integer_arg::<u32>(&mut cx, 1)?;
+
+    send_request(&mut cx, handle, RequestContent::GrantControl { client_id })
+}
+
+pub fn revoke_control(mut cx: FunctionContext<'_>) -> JsResult<'_, JsPromise> {
+    let handle = cx.argument::<InstanceWrapper>(0)?;
+    send_request(&mut cx, handle, RequestContent::RevokeControl)
+}
+
+pub fn request_control(mut cx: FunctionContext<'_>) -> JsResult<'_, JsPromise> {
+    let handle = cx.argument::<InstanceWrapper>(0)?;
+    send_request(&mut cx, handle, RequestContent::RequestControl)
+}
+
+// client_id is only used by direct hosts, which have to say which client to send the file to
+pub fn send_file(mut cx: FunctionContext<'_>) -> JsResult<'_, JsPromise> {
+    let handle = cx.argument::<InstanceWrapper>(0)?;
+    let path = cx.argument::<JsString>(1)?.value(&mut cx);
+    let client_id = match cx.argument::<JsValue>(2)?.downcast::<JsNumber, _>(&mut cx) {
+        Ok(client_id) => {
+            let client_id = client_id.value(&mut cx);
+            Some(checked_int_cast::<u32>(&mut cx, client_id)?)
+        }
+        Err(_) => None,
+    };
+
+    send_request(&mut cx, handle, RequestContent::SendFile {
+        path,
+        client_id,
+    })
+}
+
+pub fn accept_file(mut cx: FunctionContext<'_>) -> JsResult<'_, JsPromise> {
+    let handle = cx.argument::<InstanceWrapper>(0)?;
+    let file_id = integer_arg::<u32>(&mut cx, 1)?;
+    let directory = cx.argument::<JsString>(2)?.value(&mut cx);
+
+    send_request(&mut cx, handle, RequestContent::AcceptFile {
+        file_id,
+        directory,
     })
 }
 
+pub fn reject_file(mut cx: FunctionContext<'_>) -> JsResult<'_, JsPromise> {
+    let handle = cx.argument::<InstanceWrapper>(0)?;
+    let file_id = integer_arg::<u32>(&mut cx, 1)?;
+
+    send_request(&mut cx, handle, RequestContent::RejectFile { file_id })
+}
+
+pub fn cancel_file(mut cx: FunctionContext<'_>) -> JsResult<'_, JsPromise> {
+    let handle = cx.argument::<InstanceWrapper>(0)?;
+    let file_id = integer_arg::<u32>(&mut cx, 1)?;
+
+    send_request(&mut cx, handle, RequestContent::CancelFile { file_id })
+}
 
 pub fn thumbnails(mut cx: FunctionContext<'_>) -> JsResult<'_, JsBox<ThumbnailHandle>> {
     let callback = cx.argument::<JsFunction>(0)?.root(&mut cx);
@@ -352,6 +519,89 @@ pub fn available_displays(mut cx: FunctionContext<'_>) -> JsResult<'_, JsArray>
     Ok(js_displays)
 }
 
+fn open_known_hosts(cx: &mut FunctionContext<'_>) -> NeonResult<KnownHosts> {
+    let path = cx.argument::<JsString>(0)?.value(cx);
+    match KnownHosts::open(path) {
+        Ok(known_hosts) => Ok(known_hosts),
+        Err(error) => throw!(*cx, error),
+    }
+}
+
+fn host_arg(cx: &mut FunctionContext<'_>, index: i32) -> NeonResult<HostKey> {
+    let host = cx.argument::<JsString>(index)?.value(cx);
+    match host.parse() {
+        Ok(host) => Ok(host),
+        Err(error) => throw!(*cx, error),
+    }
+}
+
+pub fn known_hosts_list(mut cx: FunctionContext<'_>) -> JsResult<'_, JsArray> {
+    let known_hosts = open_known_hosts(&mut cx)?;
+    let js_hosts = cx.empty_array();
+
+    for (i, known_host) in known_hosts.list().into_iter().enumerate() {
+        let js_host = JsObject::new(&mut cx);
+        let host = cx.string(known_host.host.to_string());
+        js_host.set(&mut cx, "host", host)?;
+        let public_key = cx.string(encode_public_key(&known_host.public_key));
+        js_host.set(&mut cx, "public_key", public_key)?;
+        let fingerprint = cx.string(known_host.fingerprint());
+        js_host.set(&mut cx, "fingerprint", fingerprint)?;
+        js_hosts.set(&mut cx, i as u32, js_host)?;
+    }
+
+    Ok(js_hosts)
+}
+
+pub fn known_hosts_pin(mut cx: FunctionContext<'_>) -> JsResult<'_, JsUndefined> {
+    let mut known_hosts = open_known_hosts(&mut cx)?;
+    let host = host_arg(&mut cx, 1)?;
+    let public_key = cx.argument::<JsValue>(2)?;
+    let public_key = public_key_arg(&mut cx, public_key)?;
+
+    known_hosts.pin(host, public_key);
+    match known_hosts.save() {
+        Ok(()) => Ok(cx.undefined()),
+        Err(error) => throw!(cx, error),
+    }
+}
+
+// Returns whether the host was pinned
+pub fn known_hosts_revoke(mut cx: FunctionContext<'_>) -> JsResult<'_, JsBoolean> {
+    let mut known_hosts = open_known_hosts(&mut cx)?;
+    let host = host_arg(&mut cx, 1)?;
+
+    if known_hosts.revoke(&host).is_none() {
+        return Ok(cx.boolean(false));
+    }
+    match known_hosts.save() {
+        Ok(()) => Ok(cx.boolean(true)),
+        Err(error) => throw!(cx, error),
+    }
+}
+
+// Returns a new identity as a PKCS#8 document, it should be stored somewhere safe
+pub fn identity_generate(mut cx: FunctionContext<'_>) -> JsResult<'_, JsArrayBuffer> {
+    let identity = match IdentityKeyPair::generate() {
+        Ok(identity) => identity,
+        Err(_) => return throw!(cx, "Failed to generate identity"),
+    };
+    let pkcs8 = identity.pkcs8();
+    let mut array_buf = cx.array_buffer(pkcs8.len())?;
+    array_buf.as_mut_slice(&mut cx).copy_from_slice(pkcs8);
+
+    Ok(array_buf)
+}
+
+// The public key Hosts authorize the identity by
+pub fn identity_public_key(mut cx: FunctionContext<'_>) -> JsResult<'_, JsString> {
+    let pkcs8 = SecretVec::from(cx.argument::<JsArrayBuffer>(0)?.as_slice(&cx));
+    match IdentityKeyPair::from_pkcs8(&pkcs8) {
+        Ok(identity) => Ok(cx.string(encode_public_key(&identity.public_key()))),
+        Err(error) => throw!(cx, error),
+    }
+}
+
 pub fn macos_accessibility_permission(mut cx: FunctionContext<'_>) -> JsResult<'_, JsBoolean> {
     cfg_if::cfg_if! {
         if #[cfg(target_os = "macos")] {
@@ -1,13 +1,17 @@
 // when peer emits an event it ends up here for us to handle it or forward it to node via callback_interface
 
-use crate::{forward, instance::Instance};
+use crate::{forward, instance::Instance, screenview_handler::ScreenViewHandler};
+use common::messages::rvd::DisplayRotation;
 use peer::{
     rvd::{RvdClientInform, RvdHostInform},
     svsc_handler::SvscInform,
-    wpskka::{WpskkaClientInform, WpskkaHostInform},
+    wpskka::{known_hosts::fingerprint, WpskkaClientInform, WpskkaHostInform},
     InformEvent,
 };
-use peer_util::rvd_native_helper::{rvd_client_native_helper, rvd_host_native_helper};
+use peer_util::{
+    handler_stack::HandlerError,
+    rvd_native_helper::{rvd_client_native_helper, rvd_host_native_helper},
+};
 
 pub fn handle_event(instance: &mut Instance, event: InformEvent) -> Result<(), ()> {
     match event {
@@ -26,9 +30,15 @@ pub fn handle_event(instance: &mut Instance, event: InformEvent) -> Result<(), (
             SvscInform::SessionUpdate => instance
                 .callback_interface
                 .svsc_session_update(&instance.channel),
-            SvscInform::SessionEnd => instance
-                .callback_interface
-                .svsc_session_end(&instance.channel),
+            SvscInform::SessionEnd => {
+                // The Client is gone, and with it whatever it was holding down
+                if let ScreenViewHandler::HostSignal(stack) = &mut instance.sv_handler {
+                    stack.release_input();
+                }
+                instance
+                    .callback_interface
+                    .svsc_session_end(&instance.channel)
+            }
             SvscInform::LeaseRequestRejected => instance
                 .callback_interface
                 .svsc_session_end(&instance.channel),
@@ -40,6 +50,11 @@ pub fn handle_event(instance: &mut Instance, event: InformEvent) -> Result<(), (
                 .svsc_error_lease_extension_request_rejected(&instance.channel),
         },
         InformEvent::RvdClientInform(event) => {
+            if let RvdClientInform::ClipboardNotification(content, clipboard_type) = &event {
+                instance
+                    .clipboard_sync
+                    .remote_change(clipboard_type, content);
+            }
             let event = match rvd_client_native_helper(event, &mut instance.native)
                 .expect("rvd_client_native_helper failed")
             {
@@ -53,31 +68,100 @@ pub fn handle_event(instance: &mut Instance, event: InformEvent) -> Result<(), (
                         .callback_interface
                         .rvd_client_handshake_complete(&instance.channel);
                 }
-                RvdClientInform::FrameData(data) => instance.callback_interface.rvd_frame_data(
-                    &instance.channel,
-                    data.display_id,
-                    data.data.0.into_owned(),
-                ),
+                RvdClientInform::FrameData(data) => {
+                    // Packets that can't be depacketized are passed on all the same
+                    let feedback = instance
+                        .frame_receiver
+                        .receive(data.display_id, &data.data.0)
+                        .unwrap_or_default();
+                    // If these don't get through we give up on the packets and ask again
+                    let _ = forward!(instance.sv_handler, [ClientSignal, ClientDirect], |stack| {
+                        if !feedback.nack.is_empty() {
+                            stack.frame_nack(data.display_id, feedback.nack)?;
+                        }
+                        if feedback.picture_loss {
+                            stack.picture_loss(data.display_id)?;
+                        }
+                        if let Some(report) = feedback.report {
+                            stack.receiver_report(report)?;
+                        }
+                        Ok::<_, HandlerError>(())
+                    });
+                    // The packet may have completed a frame that's due already
+                    instance.play_frames();
+                }
+                // A display shared again is a new stream
+                RvdClientInform::DisplayShare(share) =>
+                    instance.frame_receiver.remove(share.display_id),
+                RvdClientInform::DisplayUnshare(display_id) =>
+                    instance.frame_receiver.remove(display_id),
+                RvdClientInform::ControlChanged(has_control) => instance
+                    .callback_interface
+                    .rvd_client_control_changed(&instance.channel, has_control),
+                RvdClientInform::DisplayGeometry(geometry) =>
+                    instance.callback_interface.rvd_display_geometry(
+                        &instance.channel,
+                        geometry.display_id,
+                        geometry.width as u32,
+                        geometry.height as u32,
+                        geometry.scale as f64 / 100.0,
+                        match geometry.rotation {
+                            DisplayRotation::None => 0,
+                            DisplayRotation::Clockwise90 => 90,
+                            DisplayRotation::Clockwise180 => 180,
+                            DisplayRotation::Clockwise270 => 270,
+                        },
+                    ),
+                RvdClientInform::CursorShape(cursor) =>
+                    instance.callback_interface.rvd_cursor_shape(
+                        &instance.channel,
+                        cursor.width as u32,
+                        cursor.height as u32,
+                        cursor.hotspot_x as u32,
+                        cursor.hotspot_y as u32,
+                        cursor.data,
+                    ),
+                RvdClientInform::FileTransfer(inform) =>
+                    instance.handle_file_transfer(None, inform),
                 _ => {}
             }
         }
         InformEvent::RvdHostInform(event) => {
-            let (inform, msg) =
+            // Writing this to our clipboard makes the watcher fire, it mustn't be sent back
+            if let RvdHostInform::ClipboardNotification(content, clipboard_type) = &event {
+                instance
+                    .clipboard_sync
+                    .remote_change(clipboard_type, content);
+            }
+            let inform =
                 rvd_host_native_helper(event, &mut instance.native, &instance.shared_displays)
                     .expect("rvd_host_native_helper failed");
             if let Some(inform) = inform {
                 match inform {
                     RvdHostInform::HandshakeComplete => {
+                        // The cursor is otherwise only sent when it changes
+                        instance.handle_cursor_change();
                         instance
                             .callback_interface
                             .rvd_host_handshake_complete(&instance.channel);
                     }
+                    RvdHostInform::FileTransfer(inform) =>
+                        instance.handle_file_transfer(None, inform),
+                    RvdHostInform::PictureLoss(display_id) => {
+                        instance.capture_pool.request_keyframe(display_id);
+                    }
+                    RvdHostInform::FrameNack(display_id, sequences) =>
+                        instance.handle_frame_nack(None, display_id, &sequences),
+                    RvdHostInform::ReceiverReport(report) =>
+                        instance.handle_receiver_report(None, report),
+                    RvdHostInform::InputRejected => instance
+                        .callback_interface
+                        .rvd_host_input_rejected(&instance.channel),
+                    RvdHostInform::ClipboardRequest(is_content, clipboard_type) =>
+                        instance.handle_clipboard_request(None, is_content, clipboard_type),
                     _ => {}
                 }
             }
-            if let Some(_msg) = msg {
-                // TODO
-            }
         }
         InformEvent::WpskkaClientInform(event) => match event {
             WpskkaClientInform::AuthScheme(auths) => {
@@ -94,13 +178,33 @@ pub fn handle_event(instance: &mut Instance, event: InformEvent) -> Result<(), (
             WpskkaClientInform::PasswordPrompt =>
                 if let Some(password) = &instance.password {
                     forward!(instance.sv_handler, [ClientSignal, ClientDirect], |stack| {
-                        stack.process_password(password.as_bytes())
+                        stack.process_password(password)
                     });
                 } else {
                     instance
                         .callback_interface
                         .wpskka_client_password_prompt(&instance.channel)
                 },
+            // An AuthFailed event always follows this
+            WpskkaClientInform::UntrustedHost(_) => {}
+            // Only emitted after AuthSuccessful, so a Host that turned us away is never pinned
+            WpskkaClientInform::HostKeyPinned(known_host) =>
+                if let Some(known_hosts) = &mut instance.known_hosts {
+                    known_hosts.pin(known_host.host, known_host.public_key);
+                    // If this fails we'll trust the Host on first use again next time
+                    let _ = known_hosts.save();
+                },
+            // An AuthFailed event always follows this
+            WpskkaClientInform::HostKeyChanged {
+                host,
+                previous,
+                public_key,
+            } => instance.callback_interface.wpskka_client_host_key_changed(
+                &instance.channel,
+                host.to_string(),
+                fingerprint(&previous),
+                fingerprint(&public_key),
+            ),
             WpskkaClientInform::AuthFailed => instance
                 .callback_interface
                 .wpskka_client_authentication_failed(&instance.channel),
@@ -0,0 +1,433 @@
+// Files sent and received over RVD. Transfer IDs on the wire are only unique per connection and
+// direction, so node gets IDs of its own which map to the connection (the Client, for direct Hosts)
+// and the transfer on it. The disk side lives in peer_util::file_transfer.
+
+use crate::{host_clients::ClientId, instance::Instance, screenview_handler::ScreenViewHandler};
+use common::messages::rvd::{FileOffer, TransferId};
+use peer::rvd::{FileTransferInform, FILE_CHUNK_SIZE};
+use peer_util::{
+    file_transfer::{FileError, IncomingFile, OutgoingFile},
+    handler_stack::HandlerError,
+};
+use std::collections::HashMap;
+
+pub type FileId = u32;
+
+// Node is told about progress each time this much more of a file has been transferred
+const PROGRESS_INTERVAL: u64 = 1024 * 1024;
+
+// Runs $op with the stack a transfer belongs to, direct Hosts keep one per Client
+macro_rules! with_file_stack {
+    ($handler:expr, $client_id:expr, |$stack:ident| $op:expr) => {
+        match (&mut $handler, $client_id) {
+            (ScreenViewHandler::HostDirect(clients, _), Some(client_id)) =>
+                clients.with_client(client_id, |$stack| $op),
+            (ScreenViewHandler::HostDirect(..), None) => Err(anyhow::anyhow!(
+                "direct hosts need the id of the client to transfer files with"
+            )),
+            (ScreenViewHandler::HostSignal($stack), _) => $op.map_err(anyhow::Error::from),
+            (ScreenViewHandler::ClientSignal($stack), _) => $op.map_err(anyhow::Error::from),
+            (ScreenViewHandler::ClientDirect($stack), _) => $op.map_err(anyhow::Error::from),
+        }
+    };
+}
+
+enum FileState {
+    // Offered to us, waiting for node to accept or reject it
+    Offered(FileOffer),
+    Receiving(IncomingFile),
+    Sending(OutgoingFile),
+}
+
+struct FileTransfer {
+    client_id: Option<ClientId>,
+    transfer_id: TransferId,
+    state: FileState,
+}
+
+impl FileTransfer {
+    fn outgoing(&self) -> bool {
+        matches!(self.state, FileState::Sending(_))
+    }
+}
+
+#[derive(Default)]
+pub struct FileTransfers {
+    transfers: HashMap<FileId, FileTransfer>,
+    next_file_id: FileId,
+}
+
+impl FileTransfers {
+    fn insert(
+        &mut self,
+        client_id: Option<ClientId>,
+        transfer_id: TransferId,
+        state: FileState,
+    ) -> FileId {
+        let file_id = self.next_file_id;
+        self.next_file_id = self.next_file_id.wrapping_add(1);
+        self.transfers.insert(file_id, FileTransfer {
+            client_id,
+            transfer_id,
+            state,
+        });
+        file_id
+    }
+
+    fn find(
+        &self,
+        client_id: Option<ClientId>,
+        transfer_id: TransferId,
+        outgoing: bool,
+    ) -> Option<FileId> {
+        self.transfers
+            .iter()
+            .find(|(_, transfer)| {
+                transfer.client_id == client_id
+                    && transfer.transfer_id == transfer_id
+                    && transfer.outgoing() == outgoing
+            })
+            .map(|(&file_id, _)| file_id)
+    }
+
+    fn get_mut(&mut self, file_id: FileId) -> Result<&mut FileTransfer, anyhow::Error> {
+        self.transfers
+            .get_mut(&file_id)
+            .ok_or_else(|| anyhow::anyhow!("no file transfer with id {}", file_id))
+    }
+}
+
+impl Instance {
+    /// Offers a file to the other Peer, direct Hosts offer it to one of their Clients
+    pub(crate) fn send_file(
+        &mut self,
+        path: &str,
+        client_id: Option<ClientId>,
+    ) -> Result<FileId, anyhow::Error> {
+        let file = OutgoingFile::open(path)?;
+        let transfer_id = with_file_stack!(self.sv_handler, client_id, |stack| stack.offer_file(
+            file.name().to_owned(),
+            file.size(),
+            file.hash()
+        ))?;
+        Ok(self
+            .file_transfers
+            .insert(client_id, transfer_id, FileState::Sending(file)))
+    }
+
+    /// Accepts a file offered to us, it's saved to directory. A partial copy left there by an
+    /// earlier transfer is picked up from where it stopped.
+    pub(crate) fn accept_file(
+        &mut self,
+        file_id: FileId,
+        directory: &str,
+    ) -> Result<(), anyhow::Error> {
+        let transfer = self.file_transfers.get_mut(file_id)?;
+        let offer = match &transfer.state {
+            FileState::Offered(offer) => offer,
+            _ =>
+                return Err(anyhow::anyhow!(
+                    "file {} isn't waiting to be accepted",
+                    file_id
+                )),
+        };
+        let file = IncomingFile::create(directory, &offer.name, offer.size, offer.hash)?;
+        let (client_id, transfer_id, offset) =
+            (transfer.client_id, transfer.transfer_id, file.offset());
+        with_file_stack!(self.sv_handler, client_id, |stack| stack
+            .accept_file(transfer_id, offset))?;
+
+        let done = offset == file.size();
+        transfer.state = FileState::Receiving(file);
+        // We already have all of it, there's just the hash to check
+        if done {
+            self.finish_incoming_file(file_id);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn reject_file(&mut self, file_id: FileId) -> Result<(), anyhow::Error> {
+        let transfer = self.file_transfers.get_mut(file_id)?;
+        if !matches!(transfer.state, FileState::Offered(_)) {
+            return Err(anyhow::anyhow!(
+                "file {} isn't waiting to be accepted",
+                file_id
+            ));
+        }
+        let (client_id, transfer_id) = (transfer.client_id, transfer.transfer_id);
+        self.file_transfers.transfers.remove(&file_id);
+        with_file_stack!(self.sv_handler, client_id, |stack| stack
+            .reject_file(transfer_id))
+    }
+
+    /// Cancels a transfer in either direction, a partially received file is kept so it can be
+    /// resumed
+    pub(crate) fn cancel_file(&mut self, file_id: FileId) -> Result<(), anyhow::Error> {
+        let transfer = self.file_transfers.get_mut(file_id)?;
+        if matches!(transfer.state, FileState::Offered(_)) {
+            return Err(anyhow::anyhow!(
+                "file {} hasn't been accepted, reject it instead",
+                file_id
+            ));
+        }
+        let (client_id, transfer_id, outgoing) = (
+            transfer.client_id,
+            transfer.transfer_id,
+            transfer.outgoing(),
+        );
+        self.file_transfers.transfers.remove(&file_id);
+        with_file_stack!(self.sv_handler, client_id, |stack| stack
+            .cancel_file(transfer_id, outgoing))
+    }
+
+    /// Forgets the transfers of a Client that went away
+    pub(crate) fn client_file_transfers_ended(&mut self, client_id: ClientId) {
+        let ended: Vec<FileId> = self
+            .file_transfers
+            .transfers
+            .iter()
+            .filter(|(_, transfer)| transfer.client_id == Some(client_id))
+            .map(|(&file_id, _)| file_id)
+            .collect();
+        for file_id in ended {
+            self.file_transfers.transfers.remove(&file_id);
+            self.callback_interface.rvd_file_failed(
+                &self.channel,
+                file_id,
+                "disconnected".to_string(),
+            );
+        }
+    }
+
+    pub(crate) fn handle_file_transfer(
+        &mut self,
+        client_id: Option<ClientId>,
+        inform: FileTransferInform,
+    ) {
+        match inform {
+            FileTransferInform::Offered(offer) => {
+                let (name, size) = (offer.name.clone(), offer.size);
+                let file_id = self.file_transfers.insert(
+                    client_id,
+                    offer.transfer_id,
+                    FileState::Offered(offer),
+                );
+                self.callback_interface
+                    .rvd_file_offered(&self.channel, file_id, name, size as f64);
+            }
+            FileTransferInform::Accepted {
+                transfer_id,
+                offset,
+            } => {
+                let file_id = match self.file_transfers.find(client_id, transfer_id, true) {
+                    Some(file_id) => file_id,
+                    None => return,
+                };
+                if let Some(FileState::Sending(file)) = self
+                    .file_transfers
+                    .transfers
+                    .get_mut(&file_id)
+                    .map(|transfer| &mut transfer.state)
+                {
+                    if let Err(error) = file.seek(offset) {
+                        self.abort_file(file_id, error.to_string());
+                        return;
+                    }
+                }
+                self.send_file_chunks(file_id);
+            }
+            FileTransferInform::Acked {
+                transfer_id,
+                offset,
+            } => {
+                let file_id = match self.file_transfers.find(client_id, transfer_id, true) {
+                    Some(file_id) => file_id,
+                    None => return,
+                };
+                if let Some(FileState::Sending(file)) = self
+                    .file_transfers
+                    .transfers
+                    .get(&file_id)
+                    .map(|transfer| &transfer.state)
+                {
+                    let size = file.size();
+                    // Acks come a chunk at a time
+                    if crossed_progress_interval(
+                        offset.saturating_sub(FILE_CHUNK_SIZE as u64),
+                        offset,
+                        size,
+                    ) {
+                        self.callback_interface.rvd_file_progress(
+                            &self.channel,
+                            file_id,
+                            offset as f64,
+                            size as f64,
+                        );
+                    }
+                }
+                self.send_file_chunks(file_id);
+            }
+            FileTransferInform::Rejected(transfer_id) =>
+                self.file_ended(client_id, transfer_id, true, "rejected"),
+            FileTransferInform::Cancelled {
+                transfer_id,
+                outgoing,
+            } => self.file_ended(client_id, transfer_id, outgoing, "cancelled"),
+            FileTransferInform::Completed {
+                transfer_id,
+                hash_ok,
+            } => {
+                let file_id = match self.file_transfers.find(client_id, transfer_id, true) {
+                    Some(file_id) => file_id,
+                    None => return,
+                };
+                self.file_transfers.transfers.remove(&file_id);
+                if hash_ok {
+                    self.callback_interface.rvd_file_complete(
+                        &self.channel,
+                        file_id,
+                        String::new(),
+                    );
+                } else {
+                    self.callback_interface.rvd_file_failed(
+                        &self.channel,
+                        file_id,
+                        FileError::HashMismatch.to_string(),
+                    );
+                }
+            }
+            FileTransferInform::Chunk {
+                transfer_id,
+                offset,
+                data,
+                done,
+            } => {
+                let file_id = match self.file_transfers.find(client_id, transfer_id, false) {
+                    Some(file_id) => file_id,
+                    None => return,
+                };
+                let file = match self
+                    .file_transfers
+                    .transfers
+                    .get_mut(&file_id)
+                    .map(|transfer| &mut transfer.state)
+                {
+                    Some(FileState::Receiving(file)) => file,
+                    _ => return,
+                };
+                if let Err(error) = file.write_chunk(offset, &data) {
+                    self.abort_file(file_id, error.to_string());
+                    return;
+                }
+                let (end, size) = (file.offset(), file.size());
+                if crossed_progress_interval(offset, end, size) {
+                    self.callback_interface.rvd_file_progress(
+                        &self.channel,
+                        file_id,
+                        end as f64,
+                        size as f64,
+                    );
+                }
+                if done {
+                    self.finish_incoming_file(file_id);
+                }
+            }
+        }
+    }
+
+    // Sends as much of an accepted file as the window allows
+    fn send_file_chunks(&mut self, file_id: FileId) {
+        let transfer = match self.file_transfers.transfers.get_mut(&file_id) {
+            Some(transfer) => transfer,
+            None => return,
+        };
+        let (client_id, transfer_id) = (transfer.client_id, transfer.transfer_id);
+        let file = match &mut transfer.state {
+            FileState::Sending(file) => file,
+            _ => return,
+        };
+
+        loop {
+            match with_file_stack!(self.sv_handler, client_id, |stack| Ok::<_, HandlerError>(
+                stack.file_window_open(transfer_id)
+            )) {
+                Ok(true) => {}
+                Ok(false) => return,
+                // The connection is gone, whatever dropped it cleans up
+                Err(_) => return,
+            }
+            let chunk = match file.next_chunk() {
+                Ok(chunk) => chunk,
+                Err(error) => {
+                    self.abort_file(file_id, error.to_string());
+                    return;
+                }
+            };
+            if with_file_stack!(self.sv_handler, client_id, |stack| stack
+                .send_file_chunk(transfer_id, chunk))
+            .is_err()
+            {
+                return;
+            }
+        }
+    }
+
+    // Checks the hash of a fully received file and lets the sender know how it went
+    fn finish_incoming_file(&mut self, file_id: FileId) {
+        let transfer = match self.file_transfers.transfers.remove(&file_id) {
+            Some(transfer) => transfer,
+            None => return,
+        };
+        let file = match transfer.state {
+            FileState::Receiving(file) => file,
+            _ => return,
+        };
+        let result = file.finish();
+        let hash_ok = result.is_ok();
+        // The sender only cares whether the file made it, not what went wrong
+        let _ = with_file_stack!(self.sv_handler, transfer.client_id, |stack| stack
+            .complete_file(transfer.transfer_id, hash_ok));
+        match result {
+            Ok(path) => self.callback_interface.rvd_file_complete(
+                &self.channel,
+                file_id,
+                path.display().to_string(),
+            ),
+            Err(error) =>
+                self.callback_interface
+                    .rvd_file_failed(&self.channel, file_id, error.to_string()),
+        }
+    }
+
+    // Gives up on a transfer because of a local error, the other Peer sees it as cancelled
+    fn abort_file(&mut self, file_id: FileId, error: String) {
+        let transfer = match self.file_transfers.transfers.remove(&file_id) {
+            Some(transfer) => transfer,
+            None => return,
+        };
+        let _ = with_file_stack!(self.sv_handler, transfer.client_id, |stack| stack
+            .cancel_file(transfer.transfer_id, transfer.outgoing()));
+        self.callback_interface
+            .rvd_file_failed(&self.channel, file_id, error);
+    }
+
+    // The other Peer ended a transfer before it completed
+    fn file_ended(
+        &mut self,
+        client_id: Option<ClientId>,
+        transfer_id: TransferId,
+        outgoing: bool,
+        reason: &str,
+    ) {
+        if let Some(file_id) = self.file_transfers.find(client_id, transfer_id, outgoing) {
+            self.file_transfers.transfers.remove(&file_id);
+            self.callback_interface
+                .rvd_file_failed(&self.channel, file_id, reason.to_string());
+        }
+    }
+}
+
+// Whether going from offset to end passed a progress interval or finished the file
+fn crossed_progress_interval(offset: u64, end: u64, size: u64) -> bool {
+    end == size || offset / PROGRESS_INTERVAL != end / PROGRESS_INTERVAL
+}
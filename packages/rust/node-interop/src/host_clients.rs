@@ -0,0 +1,640 @@
+// A direct Host can be viewed by many Clients at once. Every Client gets its own HandlerStack, so
+// its own keys, RVD state, PermissionMask and AccessMask, while the capture pipeline and the UDP
+// socket are shared between them. Displays are only shared with a Client once its RVD handshake
+// completes, and the Client's AccessMask is applied on top of each display's.
+//
+// At most one Client holds the controls. The first Client to connect gets them, everyone after
+// views only until the Host grants them the controls, which takes them away from the last holder.
+
+use common::messages::rvd::{
+    AccessMask,
+    ClipboardType,
+    DisplayGeometry,
+    DisplayId,
+    PermissionMask,
+    ReceiverReport,
+};
+use crossbeam_channel::{unbounded, Receiver, Sender};
+use event_loop::event_loop::ThreadWaker;
+use io::{
+    IoHandle,
+    TcpHandle,
+    TransportError,
+    TransportResponse,
+    TransportResult,
+    UdpPeer,
+    UdpServer,
+};
+use peer::{
+    helpers::{crypto::IdentityKeyPair, secret::SecretVec},
+    higher_handler::{HigherError, HigherHandlerHost},
+    lower::LowerHandlerDirect,
+    rvd::{CursorImage, FileTransferInform, RvdError, RvdHostError, RvdHostInform},
+    wpskka::{auth::TrustedKeys, WpskkaHostInform},
+    InformEvent,
+};
+use peer_util::handler_stack::{HandlerError, HandlerStack};
+use rtp::packet::Packet;
+use std::{
+    collections::{BTreeMap, HashMap},
+    io,
+    mem,
+    net::{SocketAddr, TcpStream},
+    sync::Arc,
+};
+
+pub type ClientId = u32;
+
+pub type ClientStack = HandlerStack<HigherHandlerHost, LowerHandlerDirect, TcpHandle, UdpPeer>;
+
+// The most messages handled per Client at a time, so one busy Client can't starve the others
+const MAX_TO_HANDLE: usize = 8;
+
+pub enum ClientEvent {
+    /// The Client finished the RVD handshake and is now receiving displays
+    Connected(ClientId),
+    /// A Client that was connected has gone away
+    Disconnected(ClientId),
+    /// A Client that doesn't hold the controls asked for them
+    ControlRequested(ClientId),
+    /// Input from a Client that doesn't hold the controls was dropped
+    InputRejected(ClientId),
+    /// A Client asked what the clipboard holds of a type, the reply only goes to it
+    ClipboardRequest(ClientId, bool, ClipboardType),
+    /// Something happened to a file being transferred to or from a Client
+    FileTransfer(ClientId, FileTransferInform),
+    /// A Client is missing these FrameData packets for a display
+    FrameNack(ClientId, DisplayId, Vec<u16>),
+    /// How the FrameData for a display is reaching a Client
+    ReceiverReport(ClientId, ReceiverReport),
+}
+
+struct SharedDisplay {
+    name: String,
+    access: AccessMask,
+    geometry: Option<DisplayGeometry>,
+}
+
+struct HostClient {
+    stack: ClientStack,
+    permissions: PermissionMask,
+    access: AccessMask,
+    // The address we receive this Client's datagrams from, learned from its first one
+    addr: Option<SocketAddr>,
+    // Datagrams can only be handled once the unreliable keys have been derived
+    authenticated: bool,
+    ready: bool,
+}
+
+pub struct HostClients {
+    clients: BTreeMap<ClientId, HostClient>,
+    next_client_id: ClientId,
+    addrs: HashMap<SocketAddr, ClientId>,
+    unreliable: Option<Arc<UdpServer>>,
+    unreliable_sender: Sender<TransportResult>,
+    unreliable_results: Receiver<TransportResult>,
+    displays: BTreeMap<DisplayId, SharedDisplay>,
+    static_password: Option<SecretVec>,
+    identity: Option<IdentityKeyPair>,
+    authorized_clients: TrustedKeys,
+    controller: Option<ClientId>,
+    client_events: Vec<ClientEvent>,
+    // Releases for what Clients that have gone away were holding down
+    releases: Vec<RvdHostInform>,
+}
+
+impl Default for HostClients {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HostClients {
+    pub fn new() -> Self {
+        let (unreliable_sender, unreliable_results) = unbounded();
+        Self {
+            clients: BTreeMap::new(),
+            next_client_id: 0,
+            addrs: HashMap::new(),
+            unreliable: None,
+            unreliable_sender,
+            unreliable_results,
+            displays: BTreeMap::new(),
+            static_password: None,
+            identity: None,
+            authorized_clients: TrustedKeys::new(),
+            controller: None,
+            client_events: Vec::new(),
+            releases: Vec::new(),
+        }
+    }
+
+    /// Binds the UDP socket shared by every Client
+    pub fn bind_unreliable(&mut self, addr: &str, waker: ThreadWaker) -> Result<(), io::Error> {
+        let server = UdpServer::new(addr, self.unreliable_sender.clone(), waker)?;
+        self.unreliable = Some(Arc::new(server));
+        Ok(())
+    }
+
+    /// Adds a Client for a newly accepted stream and starts the key exchange with it
+    pub fn accept(
+        &mut self,
+        stream: TcpStream,
+        waker: ThreadWaker,
+    ) -> Result<ClientId, HandlerError> {
+        let mut io_handle = IoHandle::<TcpHandle, UdpPeer>::new();
+        io_handle.connect_reliable_with(move |result_sender| {
+            TcpHandle::new_from(stream, result_sender, waker)
+        });
+        if let Some(server) = &self.unreliable {
+            let server = Arc::clone(server);
+            io_handle.bind_unreliable_with(move |result_sender| {
+                UdpPeer::new_shared(server, result_sender)
+            });
+        }
+
+        let mut stack = HandlerStack::new(
+            HigherHandlerHost::new(),
+            LowerHandlerDirect::new(),
+            io_handle,
+        );
+        stack.set_static_password(self.static_password.clone());
+        stack.set_identity(self.identity.clone());
+        *stack.authorized_clients_mut() = self.authorized_clients.clone();
+        stack.key_exchange()?;
+
+        let id = self.next_client_id;
+        self.next_client_id = self.next_client_id.wrapping_add(1);
+        self.clients.insert(id, HostClient {
+            stack,
+            permissions: PermissionMask::empty(),
+            access: AccessMask::all(),
+            addr: None,
+            authenticated: false,
+            ready: false,
+        });
+        Ok(id)
+    }
+
+    /// Disconnects a Client, returning whether it existed
+    pub fn disconnect(&mut self, id: ClientId) -> bool {
+        // Dropping the stack closes the connection
+        let mut client = match self.clients.remove(&id) {
+            Some(client) => client,
+            None => return false,
+        };
+        client.stack.release_input();
+        self.releases.extend(client.stack.take_input_releases());
+        if let Some(addr) = client.addr {
+            self.addrs.remove(&addr);
+        }
+        if self.controller == Some(id) {
+            self.controller = None;
+        }
+        if client.ready {
+            self.client_events.push(ClientEvent::Disconnected(id));
+        }
+        true
+    }
+
+    pub fn take_client_events(&mut self) -> Vec<ClientEvent> {
+        mem::take(&mut self.client_events)
+    }
+
+    /// Takes the releases for input Clients can no longer hold, see
+    /// HandlerStack::take_input_releases
+    pub fn take_input_releases(&mut self) -> Vec<RvdHostInform> {
+        let mut releases = mem::take(&mut self.releases);
+        for client in self.clients.values_mut() {
+            releases.extend(client.stack.take_input_releases());
+        }
+        releases
+    }
+
+    /// Handles messages from every Client. Returns the events produced and whether there may be
+    /// more messages waiting.
+    pub fn handle_messages(&mut self) -> (Vec<InformEvent>, bool) {
+        let mut events = Vec::new();
+        let mut more = false;
+        let mut closed = Vec::new();
+
+        for (&id, client) in &mut self.clients {
+            let mut handled = 0;
+            while handled < MAX_TO_HANDLE {
+                let result = match client.stack.io_handle.recv() {
+                    Some(result) => result,
+                    None => break,
+                };
+                handled += 1;
+
+                let response = match result {
+                    Ok(TransportResponse::Shutdown(_)) | Err(TransportError::Fatal { .. }) => {
+                        closed.push(id);
+                        break;
+                    }
+                    Err(_) => continue,
+                    Ok(response) => response,
+                };
+
+                match client.stack.handle_transport_result(Ok(response)) {
+                    Ok(informs) => {
+                        client.authenticated |= informs.iter().any(|event| {
+                            matches!(
+                                event,
+                                InformEvent::WpskkaHostInform(WpskkaHostInform::AuthSuccessful)
+                            )
+                        });
+                        events.extend(informs.into_iter().map(|event| (id, event)));
+                    }
+                    // A Client that misbehaves is dropped, the others carry on
+                    Err(_) => {
+                        closed.push(id);
+                        break;
+                    }
+                }
+            }
+            more |= handled == MAX_TO_HANDLE;
+        }
+
+        let mut handled = 0;
+        while handled < MAX_TO_HANDLE {
+            let (wire, addr) = match self.unreliable_results.try_recv() {
+                Ok(Ok(TransportResponse::UnreliableMessage(wire, addr))) => (wire, addr),
+                Ok(_) => continue,
+                Err(_) => break,
+            };
+            handled += 1;
+            self.handle_unreliable(wire, addr, &mut events);
+        }
+        more |= handled == MAX_TO_HANDLE;
+
+        for id in closed {
+            self.disconnect(id);
+        }
+
+        let ready: Vec<ClientId> = events
+            .iter()
+            .filter(|(_, event)| {
+                matches!(
+                    event,
+                    InformEvent::RvdHostInform(RvdHostInform::HandshakeComplete)
+                )
+            })
+            .map(|(id, _)| *id)
+            .collect();
+        for id in ready {
+            self.client_ready(id);
+        }
+
+        // Control requests and input from a Client without the controls are for the Host to
+        // arbitrate, clipboard replies and file transfers belong to one Client, only the Client
+        // that missed FrameData should get it again and each Client's link is adapted to on its
+        // own, so they're surfaced with the Client's ID
+        let events = events
+            .into_iter()
+            .filter_map(|(id, event)| match event {
+                InformEvent::RvdHostInform(RvdHostInform::ControlRequest) => {
+                    self.client_events.push(ClientEvent::ControlRequested(id));
+                    None
+                }
+                InformEvent::RvdHostInform(RvdHostInform::InputRejected) => {
+                    self.client_events.push(ClientEvent::InputRejected(id));
+                    None
+                }
+                InformEvent::RvdHostInform(RvdHostInform::ClipboardRequest(
+                    is_content,
+                    clipboard_type,
+                )) => {
+                    self.client_events.push(ClientEvent::ClipboardRequest(
+                        id,
+                        is_content,
+                        clipboard_type,
+                    ));
+                    None
+                }
+                InformEvent::RvdHostInform(RvdHostInform::FileTransfer(inform)) => {
+                    self.client_events
+                        .push(ClientEvent::FileTransfer(id, inform));
+                    None
+                }
+                InformEvent::RvdHostInform(RvdHostInform::FrameNack(display_id, sequences)) => {
+                    self.client_events
+                        .push(ClientEvent::FrameNack(id, display_id, sequences));
+                    None
+                }
+                InformEvent::RvdHostInform(RvdHostInform::ReceiverReport(report)) => {
+                    self.client_events
+                        .push(ClientEvent::ReceiverReport(id, report));
+                    None
+                }
+                event => Some(event),
+            })
+            .collect();
+
+        (events, more)
+    }
+
+    fn handle_unreliable(
+        &mut self,
+        wire: Vec<u8>,
+        addr: SocketAddr,
+        events: &mut Vec<(ClientId, InformEvent)>,
+    ) {
+        if let Some(&id) = self.addrs.get(&addr) {
+            if let Some(client) = self.clients.get_mut(&id) {
+                // Bad datagrams are dropped, they don't affect the Client's connection
+                if let Ok(informs) = client
+                    .stack
+                    .handle_transport_result(Ok(TransportResponse::UnreliableMessage(wire, addr)))
+                {
+                    events.extend(informs.into_iter().map(|event| (id, event)));
+                }
+            }
+            return;
+        }
+
+        // We haven't heard from this address before, so it belongs to whichever Client has the
+        // keys to authenticate it
+        for (&id, client) in &mut self.clients {
+            if client.addr.is_some() || !client.authenticated {
+                continue;
+            }
+            if let Ok(informs) =
+                client
+                    .stack
+                    .handle_transport_result(Ok(TransportResponse::UnreliableMessage(
+                        wire.clone(),
+                        addr,
+                    )))
+            {
+                client.addr = Some(addr);
+                self.addrs.insert(addr, id);
+                events.extend(informs.into_iter().map(|event| (id, event)));
+                return;
+            }
+        }
+    }
+
+    fn client_ready(&mut self, id: ClientId) {
+        let client = match self.clients.get_mut(&id) {
+            Some(client) => client,
+            None => return,
+        };
+        client.ready = true;
+
+        let control = self.controller.is_none();
+        if control {
+            self.controller = Some(id);
+        }
+
+        let result = client
+            .stack
+            .set_permissions(client.permissions)
+            .and_then(|_| share_displays(client, &self.displays))
+            .and_then(|_| {
+                if control {
+                    client.stack.grant_control()
+                } else {
+                    client.stack.revoke_control()
+                }
+            });
+        match result {
+            Ok(()) => self.client_events.push(ClientEvent::Connected(id)),
+            Err(_) => {
+                self.disconnect(id);
+            }
+        }
+    }
+
+    /// Sets the PermissionMask and AccessMask for one Client. Displays are reshared with the
+    /// Client if its AccessMask changed.
+    pub fn set_client_access(
+        &mut self,
+        id: ClientId,
+        permissions: PermissionMask,
+        access: AccessMask,
+    ) -> Result<(), anyhow::Error> {
+        let client = self
+            .clients
+            .get_mut(&id)
+            .ok_or_else(|| anyhow::anyhow!("no client with id {}", id))?;
+        let reshare = client.access != access;
+        client.permissions = permissions;
+        client.access = access;
+
+        // Clients that aren't ready get everything once they are
+        if !client.ready {
+            return Ok(());
+        }
+
+        let result = client.stack.set_permissions(permissions).and_then(|_| {
+            if !reshare {
+                return Ok(());
+            }
+            for &display_id in self.displays.keys() {
+                client.stack.unshare_display(display_id)?;
+            }
+            share_displays(client, &self.displays)
+        });
+        if let Err(error) = result {
+            self.disconnect(id);
+            return Err(error.into());
+        }
+        Ok(())
+    }
+
+    /// Hands the controls to a Client, revoking them from the Client that held them
+    pub fn grant_control(&mut self, id: ClientId) -> Result<(), anyhow::Error> {
+        match self.clients.get(&id) {
+            Some(client) if client.ready => {}
+            _ => return Err(anyhow::anyhow!("no connected client with id {}", id)),
+        }
+        if self.controller == Some(id) {
+            return Ok(());
+        }
+
+        self.revoke_control();
+        let client = self.clients.get_mut(&id).unwrap();
+        if let Err(error) = client.stack.grant_control() {
+            self.disconnect(id);
+            return Err(error.into());
+        }
+        self.controller = Some(id);
+        Ok(())
+    }
+
+    /// Takes the controls from whichever Client holds them, leaving every Client view only
+    pub fn revoke_control(&mut self) {
+        let id = match self.controller.take() {
+            Some(id) => id,
+            None => return,
+        };
+        if let Some(client) = self.clients.get_mut(&id) {
+            if client.stack.revoke_control().is_err() {
+                self.disconnect(id);
+            }
+        }
+    }
+
+    /// Runs f with the stack of a ready Client. The Client is disconnected if f fails.
+    pub fn with_client<T, F>(&mut self, id: ClientId, f: F) -> Result<T, anyhow::Error>
+    where F: FnOnce(&mut ClientStack) -> Result<T, HandlerError> {
+        let client = match self.clients.get_mut(&id) {
+            Some(client) if client.ready => client,
+            _ => return Err(anyhow::anyhow!("no connected client with id {}", id)),
+        };
+        match f(&mut client.stack) {
+            Ok(value) => Ok(value),
+            Err(error) => {
+                self.disconnect(id);
+                Err(error.into())
+            }
+        }
+    }
+
+    // Runs f for every Client that's ready. Clients which fail are disconnected since they're no
+    // longer in sync with the rest.
+    fn for_each_ready<F>(&mut self, mut f: F)
+    where F: FnMut(&mut HostClient) -> Result<(), HandlerError> {
+        let failed: Vec<ClientId> = self
+            .clients
+            .iter_mut()
+            .filter(|(_, client)| client.ready)
+            .filter_map(|(&id, client)| f(client).err().map(|_| id))
+            .collect();
+        for id in failed {
+            self.disconnect(id);
+        }
+    }
+
+    pub fn set_static_password(&mut self, static_password: Option<SecretVec>) {
+        for client in self.clients.values_mut() {
+            client.stack.set_static_password(static_password.clone());
+        }
+        self.static_password = static_password;
+    }
+
+    pub fn set_identity(&mut self, identity: Option<IdentityKeyPair>) {
+        for client in self.clients.values_mut() {
+            client.stack.set_identity(identity.clone());
+        }
+        self.identity = identity;
+    }
+
+    pub fn set_authorized_clients(&mut self, authorized_clients: TrustedKeys) {
+        for client in self.clients.values_mut() {
+            *client.stack.authorized_clients_mut() = authorized_clients.clone();
+        }
+        self.authorized_clients = authorized_clients;
+    }
+
+    pub fn share_display(
+        &mut self,
+        name: String,
+        access: AccessMask,
+    ) -> Result<DisplayId, HandlerError> {
+        let display_id = (0 .. u8::MAX)
+            .find(|display_id| !self.displays.contains_key(display_id))
+            .ok_or_else(|| HigherError::from(RvdError::from(RvdHostError::RanOutOfDisplayIds)))?;
+
+        self.for_each_ready(|client| {
+            client
+                .stack
+                .share_display_with_id(display_id, name.clone(), access & client.access)
+        });
+        self.displays.insert(display_id, SharedDisplay {
+            name,
+            access,
+            geometry: None,
+        });
+        Ok(display_id)
+    }
+
+    pub fn unshare_display(&mut self, display_id: DisplayId) -> Result<(), HandlerError> {
+        if self.displays.remove(&display_id).is_none() {
+            return Err(
+                HigherError::from(RvdError::from(RvdHostError::DisplayNotFound(display_id))).into(),
+            );
+        }
+        self.for_each_ready(|client| client.stack.unshare_display(display_id));
+        Ok(())
+    }
+
+    pub fn set_display_access(
+        &mut self,
+        display_id: DisplayId,
+        access: AccessMask,
+    ) -> Result<(), HandlerError> {
+        let display = self.displays.get_mut(&display_id).ok_or_else(|| {
+            HigherError::from(RvdError::from(RvdHostError::DisplayNotFound(display_id)))
+        })?;
+        display.access = access;
+        self.for_each_ready(|client| {
+            client
+                .stack
+                .set_display_access(display_id, access & client.access)
+        });
+        Ok(())
+    }
+
+    pub fn set_display_geometry(&mut self, geometry: DisplayGeometry) -> Result<(), HandlerError> {
+        let display = self.displays.get_mut(&geometry.display_id).ok_or_else(|| {
+            HigherError::from(RvdError::from(RvdHostError::DisplayNotFound(
+                geometry.display_id,
+            )))
+        })?;
+        display.geometry = Some(geometry.clone());
+        self.for_each_ready(|client| client.stack.set_display_geometry(geometry.clone()));
+        Ok(())
+    }
+
+    pub fn clipboard_changed(
+        &mut self,
+        clipboard_type: ClipboardType,
+        content: &[u8],
+    ) -> Result<(), HandlerError> {
+        self.for_each_ready(|client| {
+            client
+                .stack
+                .clipboard_changed(clipboard_type.clone(), content)
+        });
+        Ok(())
+    }
+
+    pub fn cursor_changed(&mut self, cursor: &CursorImage) -> Result<(), HandlerError> {
+        self.for_each_ready(|client| client.stack.cursor_changed(cursor));
+        Ok(())
+    }
+
+    /// Sends a frame update to every ready Client. The update is only encoded once.
+    pub fn send_frame_update(
+        &mut self,
+        display_id: DisplayId,
+        fragments: impl Iterator<Item = Packet>,
+    ) -> Result<(), HandlerError> {
+        let packets: Vec<Packet> = fragments.collect();
+        self.for_each_ready(|client| {
+            client
+                .stack
+                .send_frame_update(display_id, packets.iter().cloned())
+        });
+        Ok(())
+    }
+}
+
+fn share_displays(
+    client: &mut HostClient,
+    displays: &BTreeMap<DisplayId, SharedDisplay>,
+) -> Result<(), HandlerError> {
+    for (&display_id, display) in displays {
+        client.stack.share_display_with_id(
+            display_id,
+            display.name.clone(),
+            display.access & client.access,
+        )?;
+        if let Some(geometry) = &display.geometry {
+            client.stack.set_display_geometry(geometry.clone())?;
+        }
+    }
+    Ok(())
+}
@@ -3,15 +3,17 @@
 
 use crate::{
     callback_interface::NodeInterface,
+    file_transfers::{FileId, FileTransfers},
     forward,
+    host_clients::{ClientEvent, ClientId},
     instance_main::Events,
-    protocol::{ConnectionType, Message, RequestContent},
+    protocol::{ConnectionType, ControllableDisplays, Message, RequestContent},
     screenview_handler::ScreenViewHandler,
     throw,
 };
 use capture::CapturePool;
 use common::messages::{
-    rvd::{AccessMask, ButtonsMask, DisplayId},
+    rvd::{AccessMask, ButtonsMask, ClipboardType, DisplayId, PermissionMask, ReceiverReport},
     svsc::{Cookie, LeaseId},
     wpskka::AuthSchemeType,
 };
@@ -22,9 +24,9 @@ use event_loop::{
     oneshot::channel,
     JoinOnDrop,
 };
-use io::{DirectServer, TcpHandle};
+use io::DirectServer;
 use native::{
-    api::{NativeApiTemplate, NativeId},
+    api::{ClipboardType as NativeClipboardType, Monitor, NativeApiTemplate, NativeId, Window},
     NativeApi,
     NativeApiError,
 };
@@ -33,31 +35,80 @@ use neon::{
     types::Deferred,
 };
 use peer::{
-    rvd::{RvdClientInform, RvdHostInform},
+    helpers::{crypto::IdentityKeyPair, secret::SecretVec},
+    rvd::{CursorImage, RvdClientInform, RvdHostInform},
     svsc_handler::SvscInform,
-    wpskka::{WpskkaClientInform, WpskkaHostInform},
+    wpskka::{
+        auth::TrustedKeys,
+        known_hosts::{HostKey, KnownHosts},
+        WpskkaClientInform,
+        WpskkaHostInform,
+    },
     InformEvent,
 };
 use peer_util::{
+    bitrate_control::BitrateControl,
+    clipboard_sync::ClipboardSync,
     frame_data_mtu::frame_data_mtu,
+    frame_fec::FrameFec,
+    frame_history::FrameHistory,
     frame_processor::FrameProcessor,
-    rvd_native_helper::{rvd_client_native_helper, rvd_host_native_helper},
+    frame_receiver::FrameReceiver,
+    playout_timer::PlayoutTimer,
+    rvd_native_helper::{
+        clipboard_content,
+        monitor_geometry,
+        rvd_client_native_helper,
+        rvd_host_native_helper,
+        HostDisplay,
+    },
+    watcher_thread::WatcherThread,
 };
 use std::{
     collections::HashMap,
     net::TcpStream,
     thread::{self, JoinHandle},
+    time::{Duration, Instant},
 };
 
+/// How often the shared monitors are checked for resolution, scaling and rotation changes
+const GEOMETRY_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
 pub struct Instance {
     pub(crate) native: NativeApi,
     pub(crate) sv_handler: ScreenViewHandler,
     pub(crate) callback_interface: NodeInterface,
     pub(crate) capture_pool: CapturePool<FrameProcessor>,
     pub(crate) channel: Channel,
-    pub(crate) shared_displays: HashMap<DisplayId, NativeId>,
+    pub(crate) shared_displays: HashMap<DisplayId, HostDisplay>,
+    // Monitors node asked us to share that aren't plugged in, they're shared once they are
+    pub(crate) unplugged: Vec<NativeId>,
+    pub(crate) controllable: ControllableDisplays,
+    // When the geometry of the shared monitors was last checked for changes
+    pub(crate) geometry_checked: Instant,
     pub(crate) auth_schemes: Vec<AuthSchemeType>,
-    pub(crate) password: Option<String>,
+    pub(crate) password: Option<SecretVec>,
+    pub(crate) known_hosts: Option<KnownHosts>,
+    // Clients only try public key authentication once they've been given an identity
+    pub(crate) has_identity: bool,
+    // The clipboard is pushed to the peer(s) whenever it changes, None if it can't be watched
+    pub(crate) _clipboard_watcher: Option<WatcherThread>,
+    // Hosts follow monitors and windows coming and going, None if they can't be watched
+    pub(crate) _display_watcher: Option<WatcherThread>,
+    // Hosts only read the cursor when it may have changed, None if it can't be watched
+    pub(crate) _cursor_watcher: Option<WatcherThread>,
+    pub(crate) clipboard_sync: ClipboardSync,
+    pub(crate) file_transfers: FileTransfers,
+    // Hosts send FrameData again from this when a Client misses it
+    pub(crate) frame_history: FrameHistory,
+    // Hosts send repair packets from this when Clients miss enough FrameData
+    pub(crate) frame_fec: FrameFec,
+    // Hosts adapt the bitrate of each display to the ReceiverReports Clients send with this
+    pub(crate) bitrate_control: BitrateControl,
+    // Clients notice FrameData going missing and put it back together into frames with this
+    pub(crate) frame_receiver: FrameReceiver,
+    // Clients are woken by this when the next frame is due
+    pub(crate) playout_timer: PlayoutTimer,
 }
 
 impl Instance {
@@ -112,17 +163,43 @@ impl Instance {
             ),
             RequestContent::KeyboardInput { keycode, down } =>
                 self.handle_keyboard_input(promise, keycode, down),
+            RequestContent::TextInput { text } => self.handle_text_input(promise, text),
             RequestContent::LeaseRequest { cookie } => self.handle_lease_request(promise, cookie),
             RequestContent::UpdateStaticPassword { password } =>
                 self.handle_update_static_password(promise, password),
-            RequestContent::SetControllable { is_controllable } =>
-                self.handle_set_controllable(promise, is_controllable),
+            RequestContent::SetControllable { controllable } =>
+                self.handle_set_controllable(promise, controllable),
             RequestContent::SetClipboardReadable { is_readable } =>
                 self.handle_set_clipboard_readable(promise, is_readable),
             RequestContent::ShareDisplays {
                 displays,
                 controllable,
             } => self.handle_share_displays(promise, displays, controllable),
+            RequestContent::SetKnownHosts { path } => self.handle_set_known_hosts(promise, path),
+            RequestContent::SetIdentity { pkcs8 } => self.handle_set_identity(promise, pkcs8),
+            RequestContent::SetAuthorizedClients { public_keys } =>
+                self.handle_set_authorized_clients(promise, public_keys),
+            RequestContent::SetClientAccess {
+                client_id,
+                permissions,
+                access,
+            } => self.handle_set_client_access(promise, client_id, permissions, access),
+            RequestContent::DisconnectClient { client_id } =>
+                self.handle_disconnect_client(promise, client_id),
+            RequestContent::GrantControl { client_id } =>
+                self.handle_grant_control(promise, client_id),
+            RequestContent::RevokeControl => self.handle_revoke_control(promise),
+            RequestContent::RequestControl => self.handle_request_control(promise),
+            RequestContent::SendFile {
+                ref path,
+                client_id,
+            } => self.handle_send_file(promise, path, client_id),
+            RequestContent::AcceptFile {
+                file_id,
+                ref directory,
+            } => self.handle_accept_file(promise, file_id, directory),
+            RequestContent::RejectFile { file_id } => self.handle_reject_file(promise, file_id),
+            RequestContent::CancelFile { file_id } => self.handle_cancel_file(promise, file_id),
         }
     }
 
@@ -133,8 +210,20 @@ impl Instance {
         addr: &str,
         connection_type: ConnectionType,
     ) -> Result<(), anyhow::Error> {
-        let io_handle = self.sv_handler.io_handle();
+        if let ScreenViewHandler::HostDirect(..) = self.sv_handler {
+            let result = Err("direct hosts accept connections from start_server instead");
+            self.settle_with_result(promise, result, Self::undefined);
+            return Ok(());
+        }
 
+        // Signal Hosts are identified by their lease ID when we establish a session instead
+        if let (ConnectionType::Reliable, ScreenViewHandler::ClientDirect(..)) =
+            (&connection_type, &self.sv_handler)
+        {
+            self.set_known_host(HostKey::Address(addr.to_string()));
+        }
+
+        let io_handle = self.sv_handler.io_handle();
         let waker = waker_core.make_waker(Events::RemoteMessage as u32);
         let result = match connection_type {
             ConnectionType::Reliable => io_handle.connect_reliable(addr, waker),
@@ -160,23 +249,15 @@ impl Instance {
         waker_core: &ThreadWakerCore,
         stream: TcpStream,
     ) -> Result<(), anyhow::Error> {
-        let io_handle = self.sv_handler.io_handle();
-
-        // Terminate the connection
-        if io_handle.is_reliable_connected() {
-            drop(stream);
-            return Ok(());
-        }
+        let clients = match &mut self.sv_handler {
+            ScreenViewHandler::HostDirect(clients, _) => clients,
+            _ => unreachable!(),
+        };
 
+        // A Client we can't start the key exchange with never makes it into the pool, dropping
+        // the stream terminates the connection
         let waker = waker_core.make_waker(Events::RemoteMessage as u32);
-        io_handle.connect_reliable_with(move |result_sender| {
-            TcpHandle::new_from(stream, result_sender, waker)
-        });
-
-        forward!(self.sv_handler, [HostDirect], |stack| {
-            stack.key_exchange()
-        })
-        .expect("unable to produce key exchange"); // TODO error handling
+        let _ = clients.accept(stream, waker);
 
         Ok(())
     }
@@ -188,8 +269,8 @@ impl Instance {
         reliable_addr: &str,
         unreliable_addr: &str,
     ) -> Result<(), anyhow::Error> {
-        let (io_handle, server) = match &mut self.sv_handler {
-            ScreenViewHandler::HostDirect(stack, server) => (&mut stack.io_handle, server),
+        let (clients, server) = match &mut self.sv_handler {
+            ScreenViewHandler::HostDirect(clients, server) => (clients, server),
             _ => unreachable!(),
         };
 
@@ -209,7 +290,7 @@ impl Instance {
         ) {
             Ok(new_server) => {
                 *server = Some(new_server);
-                io_handle.bind_unreliable(
+                clients.bind_unreliable(
                     unreliable_addr,
                     waker_core.make_waker(Events::RemoteMessage as u32),
                 )
@@ -227,6 +308,7 @@ impl Instance {
         promise: Deferred,
         lease_id: LeaseId,
     ) -> Result<(), anyhow::Error> {
+        self.set_known_host(HostKey::Lease(lease_id));
         let result = forward!(self.sv_handler, [HostSignal, ClientSignal], |stack| stack
             .establish_session_request(lease_id));
         self.settle_with_result(promise, result, Self::undefined);
@@ -238,6 +320,7 @@ impl Instance {
         promise: Deferred,
         password: Vec<u8>,
     ) -> Result<(), anyhow::Error> {
+        let password = SecretVec::from(password);
         let result = forward!(self.sv_handler, [ClientSignal, ClientDirect], |stack| stack
             .process_password(&password));
         self.settle_with_result(promise, result, Self::undefined);
@@ -272,6 +355,18 @@ impl Instance {
         Ok(())
     }
 
+    fn handle_text_input(&mut self, promise: Deferred, text: String) -> Result<(), anyhow::Error> {
+        let result = match &mut self.sv_handler {
+            ScreenViewHandler::ClientSignal(stack) =>
+                stack.text_input(text).map_err(anyhow::Error::from),
+            ScreenViewHandler::ClientDirect(stack) =>
+                stack.text_input(text).map_err(anyhow::Error::from),
+            _ => Err(anyhow::anyhow!("only clients can send input")),
+        };
+        self.settle_with_result(promise, result, Self::undefined);
+        Ok(())
+    }
+
     fn handle_lease_request(
         &mut self,
         promise: Deferred,
@@ -286,7 +381,7 @@ impl Instance {
     fn handle_update_static_password(
         &mut self,
         promise: Deferred,
-        password: Option<Vec<u8>>,
+        password: Option<SecretVec>,
     ) -> Result<(), anyhow::Error> {
         forward!(self.sv_handler, [HostSignal, HostDirect], |stack| stack
             .set_static_password(password));
@@ -297,12 +392,19 @@ impl Instance {
     fn handle_set_controllable(
         &mut self,
         promise: Deferred,
-        is_controllable: bool,
+        controllable: ControllableDisplays,
     ) -> Result<(), anyhow::Error> {
-        // TODO: implement
-
-        promise.settle_with(&self.channel, move |mut cx| Ok(cx.undefined()));
-
+        let mut result = Ok(());
+        for (&display_id, display) in &self.shared_displays {
+            let access = controllable.access(&display.native_id);
+            result = forward!(self.sv_handler, [HostSignal, HostDirect], |stack| stack
+                .set_display_access(display_id, access));
+            if result.is_err() {
+                break;
+            }
+        }
+        self.controllable = controllable;
+        self.settle_with_result(promise, result, Self::undefined);
         Ok(())
     }
 
@@ -322,22 +424,32 @@ impl Instance {
         &mut self,
         promise: Deferred,
         displays: Vec<NativeId>,
-        controllable: bool,
+        controllable: ControllableDisplays,
     ) -> Result<(), anyhow::Error> {
         // Get stuff to unsshare
         let to_unshare: Vec<_> = self
             .shared_displays
             .iter()
-            .filter(|(_, native)| !displays.iter().any(|native1| native1 == *native))
+            .filter(|(_, display)| {
+                !displays
+                    .iter()
+                    .any(|native_id| *native_id == display.native_id)
+            })
             .map(|(display_id, _)| *display_id)
             .collect();
 
         // Unshare them
         for display_id in to_unshare {
-            self.shared_displays.remove(&display_id);
-            forward!(self.sv_handler, [HostSignal, HostDirect], |stack| stack
-                .unshare_display(display_id));
+            self.stop_sharing(display_id);
+        }
+
+        // Update the access of the ones we keep, skip errors
+        for (&display_id, display) in &self.shared_displays {
+            let access = controllable.access(&display.native_id);
+            let _ = forward!(self.sv_handler, [HostSignal, HostDirect], |stack| stack
+                .set_display_access(display_id, access));
         }
+        self.controllable = controllable;
 
         // Get stuff to share
         let to_share: Vec<NativeId> = displays
@@ -346,7 +458,7 @@ impl Instance {
                 !self
                     .shared_displays
                     .iter()
-                    .any(|(_, native_id)| native_id == native)
+                    .any(|(_, display)| display.native_id == *native)
             })
             .collect::<Vec<_>>();
 
@@ -354,55 +466,595 @@ impl Instance {
         let windows = self.native.windows()?;
         let monitors = self.native.monitors()?;
 
-        // Share them, skip errors
+        // Share them, skip errors. Monitors that aren't plugged in are shared once they are.
+        self.unplugged.clear();
         for native_id in to_share {
-            let name = match match native_id {
-                NativeId::Monitor(m) => monitors
-                    .iter()
-                    .find(|m1| m1.id == m)
-                    .map(|m| m.name.clone()),
-                NativeId::Window(w) => windows.iter().find(|w1| w1.id == w).map(|w| w.name.clone()),
-            } {
-                None => continue, // TODO If we can't find it then just skip I guess
-                Some(n) => n,
-            };
-            let display_id =
-                match forward!(self.sv_handler, [HostSignal, HostDirect], |stack| stack
-                    .share_display(
-                        name,
-                        if controllable {
-                            AccessMask::CONTROLLABLE
-                        } else {
-                            AccessMask::empty()
-                        }
-                    )) {
-                    Err(_) => continue, // TODO
-                    Ok(display_id) => display_id,
-                };
-            self.shared_displays.insert(display_id, native_id.clone());
-            let capture = match self.capture_pool.get_or_create_inactive() {
-                Ok(capture) => capture,
-                Err(_error) => todo!("tell node that we couldn't create a new capture"),
-            };
+            if !self.share_display(&native_id, &monitors, &windows) {
+                if let NativeId::Monitor(_) = native_id {
+                    self.unplugged.push(native_id);
+                }
+            }
+        }
 
-            let computed_mtu = frame_data_mtu(
-                io::DEFAULT_UNRELIABLE_MESSAGE_SIZE,
-                matches!(self.sv_handler, ScreenViewHandler::HostSignal(..)),
-            );
+        promise.settle_with(&self.channel, move |mut cx| Ok(cx.undefined()));
+        Ok(())
+    }
 
-            capture.activate(computed_mtu, native_id, display_id);
+    // Shares the display and starts capturing it, returns false if it couldn't be found or shared
+    fn share_display(
+        &mut self,
+        native_id: &NativeId,
+        monitors: &[Monitor],
+        windows: &[Window],
+    ) -> bool {
+        let name = match match native_id {
+            NativeId::Monitor(m) => monitors
+                .iter()
+                .find(|m1| m1.id == *m)
+                .map(|m| m.name.clone()),
+            NativeId::Window(w) => windows
+                .iter()
+                .find(|w1| w1.id == *w)
+                .map(|w| w.name.clone()),
+        } {
+            None => return false,
+            Some(n) => n,
+        };
+        let access = self.controllable.access(native_id);
+        let display_id = match forward!(self.sv_handler, [HostSignal, HostDirect], |stack| stack
+            .share_display(name, access))
+        {
+            Err(_) => return false, // TODO
+            Ok(display_id) => display_id,
+        };
+        // Monitors have a geometry, windows are sized by their frames
+        let geometry = match native_id {
+            NativeId::Monitor(m) => monitors
+                .iter()
+                .find(|m1| m1.id == *m)
+                .map(|monitor| monitor_geometry(display_id, monitor)),
+            NativeId::Window(_) => None,
+        };
+        if let Some(geometry) = &geometry {
+            let _ = forward!(self.sv_handler, [HostSignal, HostDirect], |stack| stack
+                .set_display_geometry(geometry.clone()));
         }
+        self.shared_displays.insert(display_id, HostDisplay {
+            native_id: native_id.clone(),
+            geometry,
+        });
+        let capture = match self.capture_pool.get_or_create_inactive() {
+            Ok(capture) => capture,
+            Err(_error) => todo!("tell node that we couldn't create a new capture"),
+        };
+
+        let computed_mtu = frame_data_mtu(
+            io::DEFAULT_UNRELIABLE_MESSAGE_SIZE,
+            matches!(self.sv_handler, ScreenViewHandler::HostSignal(..)),
+        );
 
+        capture.activate(computed_mtu, native_id.clone(), display_id);
+        true
+    }
+
+    // Unshares the display and stops capturing it
+    fn stop_sharing(&mut self, display_id: DisplayId) {
+        self.shared_displays.remove(&display_id);
+        send_best_effort(forward!(
+            self.sv_handler,
+            [HostSignal, HostDirect],
+            |stack| stack.unshare_display(display_id)
+        ));
+        self.capture_pool.deactivate(display_id);
+        self.frame_history.remove(display_id);
+        self.frame_fec.remove(display_id);
+        self.bitrate_control.remove(display_id);
+    }
+
+    fn handle_set_known_hosts(
+        &mut self,
+        promise: Deferred,
+        path: Option<String>,
+    ) -> Result<(), anyhow::Error> {
+        let result = match path {
+            Some(path) => KnownHosts::open(path).map(|known_hosts| {
+                self.known_hosts = Some(known_hosts);
+            }),
+            None => {
+                self.known_hosts = None;
+                Ok(())
+            }
+        };
+        self.settle_with_result(promise, result, Self::undefined);
+        Ok(())
+    }
+
+    fn handle_set_identity(
+        &mut self,
+        promise: Deferred,
+        pkcs8: Option<SecretVec>,
+    ) -> Result<(), anyhow::Error> {
+        let identity = match pkcs8
+            .as_deref()
+            .map(IdentityKeyPair::from_pkcs8)
+            .transpose()
+        {
+            Ok(identity) => identity,
+            Err(error) => {
+                self.settle_with_result(promise, Err(error), Self::undefined);
+                return Ok(());
+            }
+        };
+        self.has_identity = identity.is_some();
+        match &mut self.sv_handler {
+            ScreenViewHandler::HostSignal(stack) => stack.set_identity(identity),
+            ScreenViewHandler::HostDirect(clients, _) => clients.set_identity(identity),
+            ScreenViewHandler::ClientSignal(stack) => stack.set_identity(identity),
+            ScreenViewHandler::ClientDirect(stack) => stack.set_identity(identity),
+        }
         promise.settle_with(&self.channel, move |mut cx| Ok(cx.undefined()));
         Ok(())
     }
 
+    fn handle_set_authorized_clients(
+        &mut self,
+        promise: Deferred,
+        public_keys: Vec<[u8; 32]>,
+    ) -> Result<(), anyhow::Error> {
+        let authorized_clients: TrustedKeys = public_keys.into_iter().collect();
+        let result = match &mut self.sv_handler {
+            ScreenViewHandler::HostSignal(stack) => {
+                *stack.authorized_clients_mut() = authorized_clients;
+                Ok(())
+            }
+            ScreenViewHandler::HostDirect(clients, _) => {
+                clients.set_authorized_clients(authorized_clients);
+                Ok(())
+            }
+            _ => Err(anyhow::anyhow!("only hosts authorize clients")),
+        };
+        self.settle_with_result(promise, result, Self::undefined);
+        Ok(())
+    }
+
+    fn handle_set_client_access(
+        &mut self,
+        promise: Deferred,
+        client_id: ClientId,
+        permissions: PermissionMask,
+        access: AccessMask,
+    ) -> Result<(), anyhow::Error> {
+        let result = match &mut self.sv_handler {
+            ScreenViewHandler::HostDirect(clients, _) =>
+                clients.set_client_access(client_id, permissions, access),
+            _ => Err(anyhow::anyhow!(
+                "only direct hosts have more than one client"
+            )),
+        };
+        self.settle_with_result(promise, result, Self::undefined);
+        Ok(())
+    }
+
+    fn handle_disconnect_client(
+        &mut self,
+        promise: Deferred,
+        client_id: ClientId,
+    ) -> Result<(), anyhow::Error> {
+        let result = match &mut self.sv_handler {
+            ScreenViewHandler::HostDirect(clients, _) =>
+                if clients.disconnect(client_id) {
+                    Ok(())
+                } else {
+                    Err(anyhow::anyhow!("no client with id {}", client_id))
+                },
+            _ => Err(anyhow::anyhow!(
+                "only direct hosts have more than one client"
+            )),
+        };
+        self.settle_with_result(promise, result, Self::undefined);
+        Ok(())
+    }
+
+    fn handle_grant_control(
+        &mut self,
+        promise: Deferred,
+        client_id: ClientId,
+    ) -> Result<(), anyhow::Error> {
+        let result = match &mut self.sv_handler {
+            ScreenViewHandler::HostDirect(clients, _) => clients.grant_control(client_id),
+            _ => Err(anyhow::anyhow!(
+                "only direct hosts have more than one client"
+            )),
+        };
+        self.settle_with_result(promise, result, Self::undefined);
+        Ok(())
+    }
+
+    fn handle_revoke_control(&mut self, promise: Deferred) -> Result<(), anyhow::Error> {
+        let result = match &mut self.sv_handler {
+            ScreenViewHandler::HostDirect(clients, _) => {
+                clients.revoke_control();
+                Ok(())
+            }
+            _ => Err(anyhow::anyhow!(
+                "only direct hosts have more than one client"
+            )),
+        };
+        self.settle_with_result(promise, result, Self::undefined);
+        Ok(())
+    }
+
+    fn handle_request_control(&mut self, promise: Deferred) -> Result<(), anyhow::Error> {
+        let result = match &mut self.sv_handler {
+            ScreenViewHandler::ClientSignal(stack) =>
+                stack.request_control().map_err(anyhow::Error::from),
+            ScreenViewHandler::ClientDirect(stack) =>
+                stack.request_control().map_err(anyhow::Error::from),
+            _ => Err(anyhow::anyhow!("only clients can request control")),
+        };
+        self.settle_with_result(promise, result, Self::undefined);
+        Ok(())
+    }
+
+    // Resolves with the ID node uses for the transfer from then on
+    fn handle_send_file(
+        &mut self,
+        promise: Deferred,
+        path: &str,
+        client_id: Option<ClientId>,
+    ) -> Result<(), anyhow::Error> {
+        match self.send_file(path, client_id) {
+            Ok(file_id) => promise.settle_with(&self.channel, move |mut cx| Ok(cx.number(file_id))),
+            Err(error) => self.settle_with_result(promise, Err(error), Self::undefined),
+        }
+        Ok(())
+    }
+
+    fn handle_accept_file(
+        &mut self,
+        promise: Deferred,
+        file_id: FileId,
+        directory: &str,
+    ) -> Result<(), anyhow::Error> {
+        let result = self.accept_file(file_id, directory);
+        self.settle_with_result(promise, result, Self::undefined);
+        Ok(())
+    }
+
+    fn handle_reject_file(
+        &mut self,
+        promise: Deferred,
+        file_id: FileId,
+    ) -> Result<(), anyhow::Error> {
+        let result = self.reject_file(file_id);
+        self.settle_with_result(promise, result, Self::undefined);
+        Ok(())
+    }
+
+    fn handle_cancel_file(
+        &mut self,
+        promise: Deferred,
+        file_id: FileId,
+    ) -> Result<(), anyhow::Error> {
+        let result = self.cancel_file(file_id);
+        self.settle_with_result(promise, result, Self::undefined);
+        Ok(())
+    }
+
+    // Sends the new clipboard content to the peer(s) allowed to have it. Text is preferred, images
+    // (e.g. screenshots) are sent when there's no text. Other types are only sent when requested.
+    pub(crate) fn handle_clipboard_change(&mut self) {
+        let synced = [
+            (NativeClipboardType::Text, ClipboardType::Text),
+            (NativeClipboardType::Image, ClipboardType::Image),
+        ];
+        let (clipboard_type, content) = match synced.into_iter().find_map(|(native, network)| {
+            match self.native.clipboard_content(&native) {
+                Ok(Some(content)) if !content.is_empty() => Some((network, content)),
+                _ => None,
+            }
+        }) {
+            Some(found) => found,
+            None => return,
+        };
+        if !self.clipboard_sync.local_change(&clipboard_type, &content) {
+            return;
+        }
+        send_best_effort(forward!(
+            self.sv_handler,
+            [HostSignal, HostDirect, ClientSignal, ClientDirect],
+            |stack| stack.clipboard_changed(clipboard_type.clone(), &content)
+        ));
+    }
+
+    // Answers a Client asking what the clipboard holds, a clipboard that can't be read holds nothing
+    pub(crate) fn handle_clipboard_request(
+        &mut self,
+        client_id: Option<ClientId>,
+        is_content: bool,
+        clipboard_type: ClipboardType,
+    ) {
+        let content = clipboard_content(&mut self.native, &clipboard_type).unwrap_or(None);
+        send_best_effort(match (&mut self.sv_handler, client_id) {
+            (ScreenViewHandler::HostDirect(clients, _), Some(client_id)) => clients
+                .with_client(client_id, |stack| {
+                    stack.clipboard_reply(clipboard_type, is_content, content)
+                }),
+            (ScreenViewHandler::HostSignal(stack), None) => stack
+                .clipboard_reply(clipboard_type, is_content, content)
+                .map_err(anyhow::Error::from),
+            _ => Ok(()),
+        });
+    }
+
+    // Tells the Client(s) when a shared monitor's resolution, scaling or rotation changes. Checked at
+    // most every GEOMETRY_CHECK_INTERVAL as listing the monitors isn't free.
+    pub(crate) fn handle_geometry_change(&mut self) {
+        if self.geometry_checked.elapsed() < GEOMETRY_CHECK_INTERVAL {
+            return;
+        }
+        if let Ok(monitors) = self.native.monitors() {
+            self.update_geometry(&monitors);
+        }
+    }
+
+    fn update_geometry(&mut self, monitors: &[Monitor]) {
+        self.geometry_checked = Instant::now();
+        for (&display_id, display) in &mut self.shared_displays {
+            let monitor_id = match display.native_id {
+                NativeId::Monitor(monitor_id) => monitor_id,
+                NativeId::Window(_) => continue,
+            };
+            let geometry = match monitors.iter().find(|monitor| monitor.id == monitor_id) {
+                Some(monitor) => monitor_geometry(display_id, monitor),
+                None => continue,
+            };
+            if display.geometry.as_ref() == Some(&geometry) {
+                continue;
+            }
+            display.geometry = Some(geometry.clone());
+            send_best_effort(forward!(
+                self.sv_handler,
+                [HostSignal, HostDirect],
+                |stack| stack.set_display_geometry(geometry.clone())
+            ));
+        }
+    }
+
+    // Called when monitors or windows may have come or gone, and when capturing a display fails.
+    // Displays that are gone are unshared, monitors that were unplugged are shared again once
+    // they're back.
+    pub(crate) fn refresh_displays(&mut self) {
+        let monitors = match self.native.monitors() {
+            Ok(monitors) => monitors,
+            Err(_) => return,
+        };
+        // Listing windows is slow on some platforms, only do it when one is shared
+        let windows = if self
+            .shared_displays
+            .values()
+            .any(|display| matches!(display.native_id, NativeId::Window(_)))
+        {
+            match self.native.windows() {
+                Ok(windows) => windows,
+                Err(_) => return,
+            }
+        } else {
+            Vec::new()
+        };
+
+        let lost: Vec<_> = self
+            .shared_displays
+            .iter()
+            .filter(|(_, display)| match display.native_id {
+                NativeId::Monitor(id) => !monitors.iter().any(|monitor| monitor.id == id),
+                NativeId::Window(id) => !windows.iter().any(|window| window.id == id),
+            })
+            .map(|(&display_id, display)| (display_id, display.native_id.clone()))
+            .collect();
+        for (display_id, native_id) in lost {
+            self.stop_sharing(display_id);
+            let (id, display_type) = match native_id {
+                NativeId::Monitor(id) => (id, "monitor"),
+                NativeId::Window(id) => (id, "window"),
+            };
+            // Closed windows don't come back, a new window gets a new id
+            if let NativeId::Monitor(_) = native_id {
+                self.unplugged.push(native_id);
+            }
+            self.callback_interface
+                .host_display_lost(&self.channel, display_type.to_string(), id);
+        }
+
+        let plugged: Vec<_> = self
+            .unplugged
+            .iter()
+            .filter(|native_id| match native_id {
+                NativeId::Monitor(id) => monitors.iter().any(|monitor| monitor.id == *id),
+                NativeId::Window(_) => false,
+            })
+            .cloned()
+            .collect();
+        for native_id in plugged {
+            if !self.share_display(&native_id, &monitors, &windows) {
+                continue;
+            }
+            self.unplugged.retain(|unplugged| *unplugged != native_id);
+            if let NativeId::Monitor(id) = native_id {
+                self.callback_interface.host_display_restored(
+                    &self.channel,
+                    "monitor".to_string(),
+                    id,
+                );
+            }
+        }
+
+        self.update_geometry(&monitors);
+    }
+
+    // Sends the cursor to the Client(s) if it looks different. The stack only sends a shape the
+    // Client hasn't seen, otherwise it just says which one to show.
+    pub(crate) fn handle_cursor_change(&mut self) {
+        let cursor = match self.native.cursor_image() {
+            Ok(Some(cursor)) => cursor,
+            _ => return,
+        };
+        let cursor = match (
+            u16::try_from(cursor.width),
+            u16::try_from(cursor.height),
+            u16::try_from(cursor.hotspot_x),
+            u16::try_from(cursor.hotspot_y),
+        ) {
+            (Ok(width), Ok(height), Ok(hotspot_x), Ok(hotspot_y)) => CursorImage {
+                width,
+                height,
+                hotspot_x,
+                hotspot_y,
+                data: cursor.data,
+            },
+            _ => return,
+        };
+        send_best_effort(forward!(
+            self.sv_handler,
+            [HostSignal, HostDirect],
+            |stack| stack.cursor_changed(&cursor)
+        ));
+    }
+
+    // Lets go of what Clients were holding down when they went away, lost the controls or lost
+    // access to a display
+    pub(crate) fn handle_input_releases(&mut self) {
+        let releases = match &mut self.sv_handler {
+            ScreenViewHandler::HostSignal(stack) => stack.take_input_releases(),
+            ScreenViewHandler::HostDirect(clients, _) => clients.take_input_releases(),
+            _ => return,
+        };
+        for release in releases {
+            // There's nothing else to try if the native API fails
+            let _ = rvd_host_native_helper(release, &mut self.native, &self.shared_displays);
+        }
+    }
+
+    // Tells node about Clients of a direct Host coming and going, and passes on what they do
+    pub(crate) fn handle_client_events(&mut self) {
+        let events = match &mut self.sv_handler {
+            ScreenViewHandler::HostDirect(clients, _) => clients.take_client_events(),
+            _ => return,
+        };
+        for event in events {
+            match event {
+                ClientEvent::Connected(client_id) => {
+                    // Only the new Client hasn't been sent the cursor yet
+                    self.handle_cursor_change();
+                    self.callback_interface
+                        .host_client_connected(&self.channel, client_id)
+                }
+                ClientEvent::Disconnected(client_id) => {
+                    self.client_file_transfers_ended(client_id);
+                    // A slow Client that left no longer holds the others back
+                    for (display_id, bitrate) in self.bitrate_control.remove_client(client_id) {
+                        self.capture_pool.set_bitrate(display_id, bitrate);
+                    }
+                    self.callback_interface
+                        .host_client_disconnected(&self.channel, client_id)
+                }
+                ClientEvent::ControlRequested(client_id) => self
+                    .callback_interface
+                    .host_client_control_requested(&self.channel, client_id),
+                ClientEvent::InputRejected(client_id) => self
+                    .callback_interface
+                    .host_client_input_rejected(&self.channel, client_id),
+                ClientEvent::ClipboardRequest(client_id, is_content, clipboard_type) =>
+                    self.handle_clipboard_request(Some(client_id), is_content, clipboard_type),
+                ClientEvent::FileTransfer(client_id, inform) =>
+                    self.handle_file_transfer(Some(client_id), inform),
+                ClientEvent::FrameNack(client_id, display_id, sequences) =>
+                    self.handle_frame_nack(Some(client_id), display_id, &sequences),
+                ClientEvent::ReceiverReport(client_id, report) =>
+                    self.handle_receiver_report(Some(client_id), report),
+            }
+        }
+    }
+
+    // Hands the frames that are due to node and waits for the next one
+    pub(crate) fn play_frames(&mut self) {
+        for (display_id, frame) in self.frame_receiver.frames(Instant::now()) {
+            self.callback_interface
+                .rvd_frame_data(&self.channel, display_id, frame.data);
+        }
+        if let Some(due) = self.frame_receiver.next_due() {
+            self.playout_timer.wake_at(due);
+        }
+    }
+
+    /// Adapts the bitrate a display is encoded at to how its FrameData is reaching a Client
+    pub(crate) fn handle_receiver_report(
+        &mut self,
+        client_id: Option<ClientId>,
+        report: ReceiverReport,
+    ) {
+        // A signal Host only has the one Client
+        let client_id = client_id.unwrap_or(0);
+        if let Some(bitrate) = self
+            .bitrate_control
+            .report(client_id, &report, Instant::now())
+        {
+            self.capture_pool.set_bitrate(report.display_id, bitrate);
+        }
+    }
+
+    /// Sends a Client the FrameData it missed, or a keyframe if it's too late for that
+    pub(crate) fn handle_frame_nack(
+        &mut self,
+        client_id: Option<ClientId>,
+        display_id: DisplayId,
+        sequences: &[u16],
+    ) {
+        self.frame_fec.lost(display_id, sequences.len());
+        let (packets, complete) = self.frame_history.retransmit(display_id, sequences);
+        if !complete {
+            self.capture_pool.request_keyframe(display_id);
+        }
+        if packets.is_empty() {
+            return;
+        }
+        send_best_effort(match (&mut self.sv_handler, client_id) {
+            (ScreenViewHandler::HostDirect(clients, _), Some(client_id)) => clients
+                .with_client(client_id, |stack| {
+                    stack.send_frame_update(display_id, packets.into_iter())
+                }),
+            (ScreenViewHandler::HostSignal(stack), None) => stack
+                .send_frame_update(display_id, packets.into_iter())
+                .map_err(anyhow::Error::from),
+            _ => Ok(()),
+        });
+    }
+
+    // Tells the client which host it's about to authenticate so the pinned key (if any) is checked
+    fn set_known_host(&mut self, host: HostKey) {
+        let known_host = self.known_hosts.as_ref().map(|known_hosts| {
+            let pinned = known_hosts.get(&host).copied();
+            (host, pinned)
+        });
+        if matches!(
+            self.sv_handler,
+            ScreenViewHandler::ClientSignal(..) | ScreenViewHandler::ClientDirect(..)
+        ) {
+            forward!(self.sv_handler, [ClientSignal, ClientDirect], |stack| stack
+                .set_known_host(known_host));
+        }
+    }
+
     pub(crate) fn next_auth_scheme(&mut self) -> Result<(), ()> {
         for scheme in [
+            AuthSchemeType::PublicKey,
             AuthSchemeType::None,
             AuthSchemeType::SrpDynamic,
             AuthSchemeType::SrpStatic,
         ] {
+            if scheme == AuthSchemeType::PublicKey && !self.has_identity {
+                continue;
+            }
             if self.auth_schemes.contains(&scheme) {
                 self.auth_schemes.retain(|&x| x != scheme);
                 forward!(self.sv_handler, [ClientSignal, ClientDirect], |stack| stack
@@ -414,4 +1066,10 @@ impl Instance {
     }
 }
 
+// For sends nothing is waiting on, their errors are dropped as a broken connection is noticed when
+// we next read from it
+fn send_best_effort<E>(result: Result<(), E>) {
+    let _ = result;
+}
+
 impl Finalize for Instance {}
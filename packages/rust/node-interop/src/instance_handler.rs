@@ -3,15 +3,24 @@ use crate::{
     callback_interface::NodeInterface,
     instance::Instance,
     instance_main::{start_instance_main, Events},
-    protocol::Message,
+    protocol::{ControllableDisplays, Message},
     screenview_handler::ScreenViewHandler,
 };
 use capture::CapturePool;
 use crossbeam_channel::{unbounded, Receiver, Sender, TryRecvError};
 use event_loop::{event_loop::ThreadWaker, oneshot, JoinOnDrop};
-use native::{NativeApi, NativeApiError};
+use native::{ClipboardWatcher, CursorWatcher, DisplayWatcher, NativeApi, NativeApiError};
 use neon::prelude::*;
-use std::thread::JoinHandle;
+use peer_util::{
+    bitrate_control::BitrateControl,
+    clipboard_sync::ClipboardSync,
+    frame_fec::FrameFec,
+    frame_history::FrameHistory,
+    frame_receiver::FrameReceiver,
+    playout_timer::PlayoutTimer,
+    watcher_thread::WatcherThread,
+};
+use std::{thread::JoinHandle, time::Instant};
 
 pub struct InstanceHandle {
     sender: Sender<Message>,
@@ -42,6 +51,10 @@ impl InstanceHandle {
         let (waker_tx, waker_rx) = oneshot::channel();
         let (message_tx, message_rx) = unbounded();
         let native = NativeApi::new()?;
+        let is_host = matches!(
+            sv_handler,
+            ScreenViewHandler::HostSignal(..) | ScreenViewHandler::HostDirect(..)
+        );
 
         let thread_handle = start_instance_main(
             move |waker_core| {
@@ -54,8 +67,48 @@ impl InstanceHandle {
                     ),
                     channel,
                     shared_displays: Default::default(),
+                    unplugged: Vec::new(),
+                    controllable: ControllableDisplays::All,
+                    geometry_checked: Instant::now(),
                     auth_schemes: Default::default(),
                     password: None,
+                    known_hosts: None,
+                    has_identity: false,
+                    _clipboard_watcher: ClipboardWatcher::new().ok().map(|watcher| {
+                        WatcherThread::new(
+                            watcher,
+                            waker_core.make_waker(Events::ClipboardChange as u32),
+                        )
+                    }),
+                    _display_watcher: if is_host {
+                        DisplayWatcher::new().ok().map(|watcher| {
+                            WatcherThread::new(
+                                watcher,
+                                waker_core.make_waker(Events::DisplayChange as u32),
+                            )
+                        })
+                    } else {
+                        None
+                    },
+                    _cursor_watcher: if is_host {
+                        CursorWatcher::new().ok().map(|watcher| {
+                            WatcherThread::new(
+                                watcher,
+                                waker_core.make_waker(Events::CursorChange as u32),
+                            )
+                        })
+                    } else {
+                        None
+                    },
+                    clipboard_sync: ClipboardSync::new(),
+                    file_transfers: Default::default(),
+                    frame_history: FrameHistory::new(),
+                    frame_fec: FrameFec::new(),
+                    bitrate_control: BitrateControl::new(),
+                    frame_receiver: FrameReceiver::new(),
+                    playout_timer: PlayoutTimer::new(
+                        waker_core.make_waker(Events::FramePlayout as u32),
+                    ),
                 };
 
                 waker_tx
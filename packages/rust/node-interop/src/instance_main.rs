@@ -7,6 +7,7 @@ use crate::{
 };
 use crossbeam_channel::{Receiver, TryRecvError};
 use event_loop::event_loop::{event_loop, EventLoopState, ThreadWakerCore};
+use rtp::packet::Packet;
 use std::{thread, thread::JoinHandle};
 
 #[repr(u32)]
@@ -15,6 +16,10 @@ pub enum Events {
     InteropMessage,
     DirectServerConnection,
     FrameUpdate,
+    ClipboardChange,
+    DisplayChange,
+    CursorChange,
+    FramePlayout,
 }
 
 pub fn start_instance_main<F>(
@@ -60,29 +65,17 @@ fn instance_main(
 
         // Handle messages from remote party
         if waker_core.check_and_unset(Events::RemoteMessage as u32) {
-            const MAX_TO_HANDLE: usize = 8;
-            let mut handled = 0;
-
-            while handled < MAX_TO_HANDLE {
-                match instance.sv_handler.handle_next_message() {
-                    Some(Ok(events)) =>
-                        for event in events {
-                            handle_event(&mut instance, event);
-                        },
-                    Some(Err(error)) => {
-                        todo!("Handle this error properly: {}", error)
-                    }
-                    None => break,
+            if let ScreenViewHandler::HostDirect(clients, _) = &mut instance.sv_handler {
+                let (events, more) = clients.handle_messages();
+                for event in events {
+                    handle_event(&mut instance, event);
                 }
 
-                handled += 1;
-            }
-
-            // We're receiving more remote messages than we can handle, but we don't want to block
-            // the event loop too long, so change our state such that we continue handling remote
-            // messages on the next iteration without parking
-            if handled == MAX_TO_HANDLE {
-                waker_core.wake_self(Events::RemoteMessage as u32);
+                if more {
+                    waker_core.wake_self(Events::RemoteMessage as u32);
+                }
+            } else {
+                handle_remote_messages(&mut instance, waker_core);
             }
         }
 
@@ -112,25 +105,92 @@ fn instance_main(
             // We don't need to double-loop here because the channels for frame capturing have a
             // capacity of 1, so we won't fall behind when processing frames, the capture threads
             // will just block and wait for us
+            let mut failed = false;
             for (display_id, capture) in instance.capture_pool.active_captures() {
                 let mut frame_update = match capture.next_update() {
                     Some(update) => update,
                     None => continue,
                 };
 
-                if let Err(_error) = frame_update.result {
-                    todo!("Handle frame update errors properly");
+                // Usually the display has gone away, which is dealt with below
+                if frame_update.result.is_err() {
+                    failed = true;
+                    capture.update(frame_update.resources);
+                    continue;
                 }
 
+                // Kept so they can be sent again to Clients that miss them
+                let mut packets: Vec<Packet> = frame_update.frame_update().collect();
+                instance.frame_history.record(display_id, &packets);
+                let repairs = instance.frame_fec.protect(display_id, &packets);
+                packets.extend(repairs);
+
                 let result = forward!(instance.sv_handler, [HostSignal, HostDirect], |stack| stack
-                    .send_frame_update(display_id, frame_update.frame_update()));
+                    .send_frame_update(display_id, packets.into_iter()));
 
                 result.expect("handle errors from sending frame updates properly");
 
                 capture.update(frame_update.resources);
             }
+
+            // A change in resolution always comes with new frames
+            if failed {
+                instance.refresh_displays();
+            } else {
+                instance.handle_geometry_change();
+            }
+        }
+
+        if waker_core.check_and_unset(Events::DisplayChange as u32) {
+            instance.refresh_displays();
+            instance
+                .callback_interface
+                .host_displays_changed(&instance.channel);
+        }
+
+        if waker_core.check_and_unset(Events::ClipboardChange as u32) {
+            instance.handle_clipboard_change();
         }
 
+        if waker_core.check_and_unset(Events::CursorChange as u32) {
+            instance.handle_cursor_change();
+        }
+
+        if waker_core.check_and_unset(Events::FramePlayout as u32) {
+            instance.play_frames();
+        }
+
+        // Anything above may have connected or disconnected a Client
+        instance.handle_client_events();
+        instance.handle_input_releases();
+
         EventLoopState::Working
     });
 }
+
+fn handle_remote_messages(instance: &mut Instance, waker_core: &ThreadWakerCore) {
+    const MAX_TO_HANDLE: usize = 8;
+    let mut handled = 0;
+
+    while handled < MAX_TO_HANDLE {
+        match instance.sv_handler.handle_next_message() {
+            Some(Ok(events)) =>
+                for event in events {
+                    handle_event(instance, event);
+                },
+            Some(Err(error)) => {
+                todo!("Handle this error properly: {}", error)
+            }
+            None => break,
+        }
+
+        handled += 1;
+    }
+
+    // We're receiving more remote messages than we can handle, but we don't want to block
+    // the event loop too long, so change our state such that we continue handling remote
+    // messages on the next iteration without parking
+    if handled == MAX_TO_HANDLE {
+        waker_core.wake_self(Events::RemoteMessage as u32);
+    }
+}
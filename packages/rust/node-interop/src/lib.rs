@@ -3,6 +3,8 @@
 mod callback_interface;
 mod entrypoints;
 mod event_handler;
+mod file_transfers;
+mod host_clients;
 mod instance;
 mod instance_handler;
 mod instance_main;
@@ -34,14 +36,32 @@ fn main(mut cx: ModuleContext<'_>) -> NeonResult<()> {
         process_password,
         mouse_input,
         keyboard_input,
+        text_input,
         lease_request,
         update_static_password,
         set_controllable,
         set_clipboard_readable,
         share_displays,
+        set_known_hosts,
+        set_identity,
+        set_authorized_clients,
+        set_client_access,
+        disconnect_client,
+        grant_control,
+        revoke_control,
+        request_control,
+        send_file,
+        accept_file,
+        reject_file,
+        cancel_file,
         thumbnails,
         close_thumbnails,
         available_displays,
+        known_hosts_list,
+        known_hosts_pin,
+        known_hosts_revoke,
+        identity_generate,
+        identity_public_key,
         macos_accessibility_permission,
         macos_screen_capture_permission,
         macos_screen_capture_permission_prompt
@@ -1,12 +1,14 @@
 // this is for communicating between the JS interface side of things and the actual node-interop rust codey things
 // Parsing is done on the JS side of things into rust objects then passed to RequestContent for consumption when sent to the event loop
 
+use crate::{file_transfers::FileId, host_clients::ClientId};
 use common::messages::{
-    rvd::ButtonsMask,
+    rvd::{AccessMask, ButtonsMask, PermissionMask},
     svsc::{Cookie, LeaseId},
 };
 use native::api::NativeId;
 use neon::types::Deferred;
+use peer::helpers::secret::SecretVec;
 use std::{convert::TryFrom, fmt::Debug};
 
 pub enum Message {
@@ -48,22 +50,84 @@ pub enum RequestContent {
         keycode: u32,
         down: bool,
     },
+    TextInput {
+        text: String,
+    },
     LeaseRequest {
         cookie: Option<Cookie>,
     },
     UpdateStaticPassword {
-        password: Option<Vec<u8>>,
+        password: Option<SecretVec>,
     },
     SetControllable {
-        is_controllable: bool,
+        controllable: ControllableDisplays,
     },
     SetClipboardReadable {
         is_readable: bool,
     },
     ShareDisplays {
         displays: Vec<NativeId>,
-        controllable: bool,
+        controllable: ControllableDisplays,
+    },
+    SetKnownHosts {
+        path: Option<String>,
+    },
+    SetIdentity {
+        pkcs8: Option<SecretVec>,
+    },
+    SetAuthorizedClients {
+        public_keys: Vec<[u8; 32]>,
+    },
+    SetClientAccess {
+        client_id: ClientId,
+        permissions: PermissionMask,
+        access: AccessMask,
+    },
+    DisconnectClient {
+        client_id: ClientId,
+    },
+    GrantControl {
+        client_id: ClientId,
+    },
+    RevokeControl,
+    RequestControl,
+    SendFile {
+        path: String,
+        // Direct Hosts send files to one Client at a time
+        client_id: Option<ClientId>,
+    },
+    AcceptFile {
+        file_id: FileId,
+        directory: String,
     },
+    RejectFile {
+        file_id: FileId,
+    },
+    CancelFile {
+        file_id: FileId,
+    },
+}
+
+// Node passes either a boolean for every display or the list of displays that are controllable
+pub enum ControllableDisplays {
+    All,
+    None,
+    Only(Vec<NativeId>),
+}
+
+impl ControllableDisplays {
+    pub fn access(&self, native_id: &NativeId) -> AccessMask {
+        let controllable = match self {
+            Self::All => true,
+            Self::None => false,
+            Self::Only(displays) => displays.contains(native_id),
+        };
+        if controllable {
+            AccessMask::CONTROLLABLE
+        } else {
+            AccessMask::empty()
+        }
+    }
 }
 
 #[repr(u8)]
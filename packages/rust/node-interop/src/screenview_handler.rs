@@ -1,3 +1,4 @@
+use crate::host_clients::HostClients;
 use common::messages::svsc::LeaseId;
 use io::{DirectServer, IoHandle, TcpHandle, UdpHandle};
 use peer::{
@@ -11,7 +12,6 @@ use peer_util::handler_stack::{HandlerError, HandlerStack};
 
 type HStack<W, R, L> = HandlerStack<HigherHandler<W, R>, L, TcpHandle, UdpHandle>;
 type HostSignalStack = HStack<WpskkaHostHandler, RvdHostHandler, LowerHandlerSignal>;
-type HostDirectStack = HStack<WpskkaHostHandler, RvdHostHandler, LowerHandlerDirect>;
 type ClientSignalStack = HStack<WpskkaClientHandler, RvdClientHandler, LowerHandlerSignal>;
 type ClientDirectStack = HStack<WpskkaClientHandler, RvdClientHandler, LowerHandlerDirect>;
 
@@ -42,7 +42,8 @@ macro_rules! forward {
 
 pub enum ScreenViewHandler {
     HostSignal(HostSignalStack),
-    HostDirect(HostDirectStack, Option<DirectServer>),
+    // Direct Hosts can have many Clients, see host_clients.rs
+    HostDirect(HostClients, Option<DirectServer>),
     ClientSignal(ClientSignalStack),
     ClientDirect(ClientDirectStack),
 }
@@ -57,14 +58,7 @@ impl ScreenViewHandler {
     }
 
     pub fn new_host_direct() -> Self {
-        Self::HostDirect(
-            HandlerStack::new(
-                HigherHandler::<WpskkaHostHandler, _>::new(),
-                LowerHandlerDirect::new(),
-                IoHandle::new(),
-            ),
-            None,
-        )
+        Self::HostDirect(HostClients::new(), None)
     }
 
     pub fn new_client_signal() -> Self {
@@ -84,18 +78,14 @@ impl ScreenViewHandler {
     }
 
     pub fn io_handle(&mut self) -> &mut IoHandle<TcpHandle, UdpHandle> {
-        forward!(
-            *self,
-            [HostSignal, HostDirect, ClientSignal, ClientDirect],
-            |stack| &mut stack.io_handle
-        )
+        forward!(*self, [HostSignal, ClientSignal, ClientDirect], |stack| {
+            &mut stack.io_handle
+        })
     }
 
     pub fn handle_next_message(&mut self) -> Option<Result<Vec<InformEvent>, HandlerError>> {
-        forward!(
-            *self,
-            [HostSignal, HostDirect, ClientSignal, ClientDirect],
-            |stack| stack.handle_next_message()
-        )
+        forward!(*self, [HostSignal, ClientSignal, ClientDirect], |stack| {
+            stack.handle_next_message()
+        })
     }
 }
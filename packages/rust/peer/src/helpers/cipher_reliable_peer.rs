@@ -1,19 +1,27 @@
+use crate::helpers::{
+    crypto::{ratchet, rekey_mac, verify_rekey_mac},
+    secret::SecretVec,
+};
 use common::sel_cipher;
 
 pub struct CipherReliablePeer {
-    send_key: Vec<u8>,
+    send_key: SecretVec,
     send_nonce: u64,
-    receive_key: Vec<u8>,
+    send_epoch: u64,
+    receive_key: SecretVec,
     receive_nonce: u64,
+    receive_epoch: u64,
 }
 
 impl CipherReliablePeer {
     pub fn new(send_key: Vec<u8>, receive_key: Vec<u8>) -> Self {
         Self {
-            send_key,
+            send_key: send_key.into(),
             send_nonce: 0,
-            receive_key,
+            send_epoch: 0,
+            receive_key: receive_key.into(),
             receive_nonce: 0,
+            receive_epoch: 0,
         }
     }
 
@@ -36,6 +44,52 @@ impl CipherReliablePeer {
             .ok_or(CipherError::MaximumNonceExceeded("receives_nonce"))?;
         Ok(plainbytes)
     }
+
+    /// The number of messages sent with the current send key
+    pub fn sent(&self) -> u64 {
+        self.send_nonce
+    }
+
+    pub fn send_epoch(&self) -> u64 {
+        self.send_epoch
+    }
+
+    pub fn receive_epoch(&self) -> u64 {
+        self.receive_epoch
+    }
+
+    /// Ratchets the send key. Returns the new epoch and a MAC over it made with the previous key
+    /// which the peer uses to authenticate the rekey.
+    pub fn rekey_send(&mut self) -> Result<(u64, [u8; 32]), CipherError> {
+        let epoch = self
+            .send_epoch
+            .checked_add(1)
+            .ok_or(CipherError::MaximumNonceExceeded("send_epoch"))?;
+        let mac = rekey_mac(&self.send_key, epoch);
+        self.send_key = SecretVec::from(&ratchet(&self.send_key)[..]);
+        self.send_nonce = 0;
+        self.send_epoch = epoch;
+        Ok((epoch, mac))
+    }
+
+    /// Ratchets the receive key if `mac` authenticates `epoch`. Returns a MAC over the epoch made
+    /// with the new key so the peer knows we've followed.
+    pub fn rekey_receive(&mut self, epoch: u64, mac: &[u8; 32]) -> Result<[u8; 32], CipherError> {
+        if Some(epoch) != self.receive_epoch.checked_add(1)
+            || !verify_rekey_mac(&self.receive_key, epoch, mac)
+        {
+            return Err(CipherError::BadRekey(epoch));
+        }
+        self.receive_key = SecretVec::from(&ratchet(&self.receive_key)[..]);
+        self.receive_nonce = 0;
+        self.receive_epoch = epoch;
+        Ok(rekey_mac(&self.receive_key, epoch))
+    }
+
+    /// Checks the peer's acknowledgement of a [`rekey_send`](Self::rekey_send)
+    pub fn verify_rekey_ack(&self, epoch: u64, mac: &[u8; 32]) -> bool {
+        epoch == self.send_epoch && verify_rekey_mac(&self.send_key, epoch, mac)
+    }
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -46,4 +100,6 @@ pub enum CipherError {
     MaximumNonceExceeded(&'static str),
     #[error("the counter ({0}) is too old ")]
     MessageTooOld(u64),
+    #[error("invalid rekey to epoch {0}")]
+    BadRekey(u64),
 }
@@ -1,22 +1,35 @@
 use super::anti_replay::AntiReplay;
-use crate::helpers::cipher_reliable_peer::CipherError;
+use crate::helpers::{cipher_reliable_peer::CipherError, crypto::ratchet, secret::SecretVec};
 use common::sel_cipher;
+use std::time::{Duration, Instant};
+
+/// How long packets encrypted with the previous receive key are accepted after a rekey
+pub const REKEY_GRACE_WINDOW: Duration = Duration::from_secs(5);
 
 pub struct CipherUnreliablePeer {
-    send_key: Vec<u8>,
+    send_key: SecretVec,
     send_nonce: u64,
-    receive_key: Vec<u8>,
+    receive_key: SecretVec,
+    anti_replay: AntiReplay,
+    previous: Option<PreviousKey>,
+}
+
+// The receive key from before the last rekey, kept around for packets that were in flight
+struct PreviousKey {
+    receive_key: SecretVec,
     anti_replay: AntiReplay,
+    expires: Instant,
 }
 
 impl CipherUnreliablePeer {
     pub fn new(send_key: Vec<u8>, receive_key: Vec<u8>) -> Self {
         // TODO ensure keys are long enough
         Self {
-            send_key,
+            send_key: send_key.into(),
             send_nonce: 0,
-            receive_key,
+            receive_key: receive_key.into(),
             anti_replay: AntiReplay::new(),
+            previous: None,
         }
     }
 
@@ -34,16 +47,52 @@ impl CipherUnreliablePeer {
     }
 
     pub fn decrypt(&mut self, cipherbytes: &[u8], counter: u64) -> Result<Vec<u8>, CipherError> {
-        let is_valid = self.anti_replay.update(counter);
+        // Only authenticated packets may move the replay window, otherwise anyone could push it
+        // forward and get our real packets dropped
+        if let Ok(plainbytes) = sel_cipher::decrypt(cipherbytes, &self.receive_key, counter) {
+            return if self.anti_replay.update(counter) {
+                Ok(plainbytes)
+            } else {
+                Err(CipherError::MessageTooOld(counter))
+            };
+        }
 
-        if !is_valid {
-            return Err(CipherError::MessageTooOld(counter));
+        if let Some(previous) = &mut self.previous {
+            if previous.expires < Instant::now() {
+                self.previous = None;
+            } else if let Ok(plainbytes) =
+                sel_cipher::decrypt(cipherbytes, &previous.receive_key, counter)
+            {
+                return if previous.anti_replay.update(counter) {
+                    Ok(plainbytes)
+                } else {
+                    Err(CipherError::MessageTooOld(counter))
+                };
+            }
         }
 
-        // TODO counter validation https://github.com/WireGuard/wireguard-rs/blob/7d84ef9064559a29b23ab86036f7ef62b450f90c/src/wireguard/router/anti_replay.rs
-        let plainbytes = sel_cipher::decrypt(cipherbytes, &self.receive_key, counter)
-            .map_err(|_| CipherError::CipherError)?;
-        // TODO self.receive_nonce change
-        Ok(plainbytes)
+        Err(CipherError::CipherError)
+    }
+
+    /// The number of packets sent with the current send key
+    pub fn sent(&self) -> u64 {
+        self.send_nonce
+    }
+
+    /// Ratchets the send key. This should only be called once the peer has ratcheted its receive
+    /// key, otherwise it will drop everything we send.
+    pub fn rekey_send(&mut self) {
+        self.send_key = SecretVec::from(&ratchet(&self.send_key)[..]);
+        self.send_nonce = 0;
+    }
+
+    /// Ratchets the receive key. The previous key is still accepted for [`REKEY_GRACE_WINDOW`].
+    pub fn rekey_receive(&mut self) {
+        let receive_key = SecretVec::from(&ratchet(&self.receive_key)[..]);
+        self.previous = Some(PreviousKey {
+            receive_key: std::mem::replace(&mut self.receive_key, receive_key),
+            anti_replay: std::mem::take(&mut self.anti_replay),
+            expires: Instant::now() + REKEY_GRACE_WINDOW,
+        });
     }
 }
@@ -1,11 +1,14 @@
+use crate::helpers::secret::{zeroize, SecretKey, SecretVec};
 use common::constants::{Hkdf, Hmac, Mac, SRP_PARAM};
 use ring::{
     agreement,
     agreement::{EphemeralPrivateKey, PublicKey, UnparsedPublicKey},
     error,
-    error::Unspecified,
+    error::{KeyRejected, Unspecified},
     rand,
     rand::{SecureRandom, SystemRandom},
+    signature,
+    signature::{Ed25519KeyPair, KeyPair as _},
 };
 
 pub struct KeyPair {
@@ -13,6 +16,68 @@ pub struct KeyPair {
     pub ephemeral_private_key: EphemeralPrivateKey,
 }
 
+/// A long-lived Ed25519 key used to identify a peer across sessions. The key is stored by the
+/// caller as PKCS#8.
+pub struct IdentityKeyPair {
+    pkcs8: SecretVec,
+    key_pair: Ed25519KeyPair,
+}
+
+impl IdentityKeyPair {
+    pub fn generate() -> Result<Self, Unspecified> {
+        let rng = SystemRandom::new();
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&rng)?;
+        Self::from_pkcs8(pkcs8.as_ref()).map_err(|_| Unspecified)
+    }
+
+    pub fn from_pkcs8(pkcs8: &[u8]) -> Result<Self, KeyRejected> {
+        Ok(Self {
+            pkcs8: SecretVec::from(pkcs8),
+            key_pair: Ed25519KeyPair::from_pkcs8(pkcs8)?,
+        })
+    }
+
+    pub fn pkcs8(&self) -> &[u8] {
+        &self.pkcs8
+    }
+
+    pub fn public_key(&self) -> [u8; 32] {
+        self.key_pair
+            .public_key()
+            .as_ref()
+            .try_into()
+            .expect("ed25519 public key was not 32 bytes")
+    }
+
+    pub fn sign(&self, msg: &[u8]) -> [u8; 64] {
+        self.key_pair
+            .sign(msg)
+            .as_ref()
+            .try_into()
+            .expect("ed25519 signature was not 64 bytes")
+    }
+}
+
+impl Clone for IdentityKeyPair {
+    fn clone(&self) -> Self {
+        Self::from_pkcs8(&self.pkcs8).expect("identity key pair was accepted once already")
+    }
+}
+
+impl std::fmt::Debug for IdentityKeyPair {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("IdentityKeyPair")
+            .field("public_key", &self.public_key())
+            .finish_non_exhaustive()
+    }
+}
+
+pub fn verify_signature(public_key: &[u8; 32], msg: &[u8], signature: &[u8; 64]) -> bool {
+    signature::UnparsedPublicKey::new(&signature::ED25519, public_key)
+        .verify(msg, signature)
+        .is_ok()
+}
+
 pub fn random_bytes(bytes: usize) -> Vec<u8> {
     let mut vec = vec![0u8; bytes];
     let rng = SystemRandom::new();
@@ -27,8 +92,8 @@ pub fn random_bytes_const<const BYTES: usize>() -> [u8; BYTES] {
     arr
 }
 
-pub fn random_srp_private_value() -> Vec<u8> {
-    random_bytes((SRP_PARAM.n.bits() / 8) as usize)
+pub fn random_srp_private_value() -> SecretVec {
+    SecretVec::new(random_bytes((SRP_PARAM.n.bits() / 8) as usize))
 }
 
 pub fn keypair() -> Result<KeyPair, error::Unspecified> {
@@ -45,40 +110,71 @@ pub fn parse_foreign_public(public_key: &[u8; 32]) -> UnparsedPublicKey<&[u8; 32
     agreement::UnparsedPublicKey::new(&agreement::X25519, public_key)
 }
 
-pub fn kdf1(ikm: &[u8]) -> [u8; 32] {
+pub fn kdf1(ikm: &[u8]) -> SecretKey<32> {
     let kdf = Hkdf::new(None, ikm);
-    let mut key = [0u8; 32];
-    kdf.expand(&[], &mut key).unwrap();
+    let mut key = SecretKey::default();
+    kdf.expand(&[], &mut *key).unwrap();
     key
 }
 
-pub fn kdf2(ikm: &[u8]) -> ([u8; 32], [u8; 32]) {
+fn key_from(bytes: &[u8]) -> SecretKey<32> {
+    let mut key = SecretKey::default();
+    key.copy_from_slice(bytes);
+    key
+}
+
+pub fn kdf2(ikm: &[u8]) -> (SecretKey<32>, SecretKey<32>) {
     let kdf = Hkdf::new(None, ikm);
-    let mut key = [0u8; 64];
-    kdf.expand(&[], &mut key).unwrap();
-    (
-        (&key[0 .. 32]).try_into().unwrap(),
-        (&key[32 .. 64]).try_into().unwrap(),
-    )
+    let mut output = [0u8; 64];
+    kdf.expand(&[], &mut output).unwrap();
+    let keys = (key_from(&output[0 .. 32]), key_from(&output[32 .. 64]));
+    zeroize(&mut output);
+    keys
 }
 
-pub fn kdf4(ikm: &[u8]) -> ([u8; 32], [u8; 32], [u8; 32], [u8; 32]) {
+pub fn kdf4(ikm: &[u8]) -> (SecretKey<32>, SecretKey<32>, SecretKey<32>, SecretKey<32>) {
     let kdf = Hkdf::new(None, ikm);
-    let mut key = [0u8; 128];
-    kdf.expand(&[], &mut key).unwrap();
-    (
-        (&key[0 .. 32]).try_into().unwrap(),
-        (&key[32 .. 64]).try_into().unwrap(),
-        (&key[64 .. 96]).try_into().unwrap(),
-        (&key[96 .. 128]).try_into().unwrap(),
-    )
+    let mut output = [0u8; 128];
+    kdf.expand(&[], &mut output).unwrap();
+    let keys = (
+        key_from(&output[0 .. 32]),
+        key_from(&output[32 .. 64]),
+        key_from(&output[64 .. 96]),
+        key_from(&output[96 .. 128]),
+    );
+    zeroize(&mut output);
+    keys
+}
+
+/// Derives the next key in a rekey chain. The old key should be discarded once it's no longer
+/// needed.
+pub fn ratchet(key: &[u8]) -> SecretKey<32> {
+    let kdf = Hkdf::new(None, key);
+    let mut next = SecretKey::default();
+    kdf.expand(b"screenview wpskka rekey", &mut *next).unwrap();
+    next
+}
+
+/// Authenticates a Rekey or RekeyAck for `epoch` with `key`
+pub fn rekey_mac(key: &[u8], epoch: u64) -> [u8; 32] {
+    let mut hmac = Hmac::new_from_slice(key).unwrap();
+    hmac.update(b"screenview wpskka rekey mac");
+    hmac.update(&epoch.to_le_bytes());
+    hmac.finalize().into_bytes().into()
+}
+
+pub fn verify_rekey_mac(key: &[u8], epoch: u64, mac: &[u8; 32]) -> bool {
+    let mut hmac = Hmac::new_from_slice(key).unwrap();
+    hmac.update(b"screenview wpskka rekey mac");
+    hmac.update(&epoch.to_le_bytes());
+    hmac.verify_slice(mac).is_ok()
 }
 
 #[allow(clippy::type_complexity)]
 pub fn diffie_hellman(
     my_private_key: EphemeralPrivateKey,
     peer_public_key: UnparsedPublicKey<&[u8; 32]>,
-) -> Result<([u8; 32], [u8; 32], [u8; 32], [u8; 32]), Unspecified> {
+) -> Result<(SecretKey<32>, SecretKey<32>, SecretKey<32>, SecretKey<32>), Unspecified> {
     agreement::agree_ephemeral(
         my_private_key,
         &peer_public_key,
@@ -3,6 +3,7 @@ pub mod cipher_reliable_peer;
 pub mod cipher_unreliable_peer;
 pub mod crypto;
 pub(crate) mod left_pad;
+pub mod secret;
 
 // Differs from the spec currently, but we give ourselves a larger margin because of
 // multithreading consistency concerns
@@ -0,0 +1,126 @@
+use std::{
+    fmt::{Debug, Formatter},
+    ops::{Deref, DerefMut},
+    ptr,
+    sync::atomic::{compiler_fence, Ordering},
+};
+
+/// Overwrites `bytes` with zeros. Unlike a plain fill the compiler can't optimize this away when
+/// the buffer is about to be freed.
+pub fn zeroize(bytes: &mut [u8]) {
+    for byte in bytes.iter_mut() {
+        // Safety: byte is a valid and aligned reference
+        unsafe { ptr::write_volatile(byte, 0) };
+    }
+    compiler_fence(Ordering::SeqCst);
+}
+
+/// A variable length secret such as a password or SRP private value. The buffer is zeroed when
+/// dropped.
+#[derive(Clone, Default)]
+pub struct SecretVec(Vec<u8>);
+
+impl SecretVec {
+    pub fn new(bytes: Vec<u8>) -> Self {
+        Self(bytes)
+    }
+
+    /// Zeros the buffer, including any spare capacity left over from before it was a secret
+    pub fn zeroize(&mut self) {
+        let len = self.0.len();
+        // This can't reallocate so the spare capacity is zeroed in place
+        self.0.resize(self.0.capacity(), 0);
+        zeroize(&mut self.0);
+        self.0.truncate(len);
+    }
+}
+
+impl From<Vec<u8>> for SecretVec {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self(bytes)
+    }
+}
+
+impl From<&[u8]> for SecretVec {
+    fn from(bytes: &[u8]) -> Self {
+        Self(bytes.to_vec())
+    }
+}
+
+impl Deref for SecretVec {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for SecretVec {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl Drop for SecretVec {
+    fn drop(&mut self) {
+        self.zeroize();
+    }
+}
+
+impl Debug for SecretVec {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "SecretVec([REDACTED; {}])", self.0.len())
+    }
+}
+
+/// A fixed length secret such as a cipher key. The key is zeroed when dropped.
+#[derive(Clone)]
+pub struct SecretKey<const N: usize>([u8; N]);
+
+impl<const N: usize> SecretKey<N> {
+    pub fn new(key: [u8; N]) -> Self {
+        Self(key)
+    }
+
+    pub fn zeroize(&mut self) {
+        zeroize(&mut self.0);
+    }
+}
+
+impl<const N: usize> Default for SecretKey<N> {
+    fn default() -> Self {
+        Self([0; N])
+    }
+}
+
+impl<const N: usize> From<[u8; N]> for SecretKey<N> {
+    fn from(key: [u8; N]) -> Self {
+        Self(key)
+    }
+}
+
+impl<const N: usize> Deref for SecretKey<N> {
+    type Target = [u8; N];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<const N: usize> DerefMut for SecretKey<N> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl<const N: usize> Drop for SecretKey<N> {
+    fn drop(&mut self) {
+        self.zeroize();
+    }
+}
+
+impl<const N: usize> Debug for SecretKey<N> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "SecretKey([REDACTED; {}])", N)
+    }
+}
@@ -1,11 +1,37 @@
 use crate::{
-    helpers::cipher_reliable_peer::CipherError,
-    rvd::{RvdClientHandler, RvdError, RvdHandlerTrait, RvdHostError, RvdHostHandler},
-    wpskka::{WpskkaClientHandler, WpskkaError, WpskkaHandlerTrait, WpskkaHostHandler},
+    helpers::{cipher_reliable_peer::CipherError, crypto::IdentityKeyPair, secret::SecretVec},
+    rvd::{
+        CursorImage,
+        FileTransfers,
+        RvdClientHandler,
+        RvdError,
+        RvdHandlerTrait,
+        RvdHostError,
+        RvdHostHandler,
+        RvdHostInform,
+        MAX_SHORT_FRAME_CURSOR_DATA,
+    },
+    wpskka::{
+        auth::TrustedKeys,
+        known_hosts::HostKey,
+        RekeyPolicy,
+        WpskkaClientHandler,
+        WpskkaError,
+        WpskkaHandlerTrait,
+        WpskkaHostHandler,
+    },
     InformEvent,
 };
 use common::messages::{
-    rvd::{AccessMask, DisplayId, RvdMessage},
+    rvd::{
+        AccessMask,
+        ClipboardType,
+        DisplayGeometry,
+        DisplayId,
+        PermissionMask,
+        ReceiverReport,
+        RvdMessage,
+    },
     wpskka::{AuthSchemeType, TransportDataMessageUnreliable, WpskkaMessage},
     ChanneledMessage,
     Error as MessageComponentError,
@@ -27,6 +53,17 @@ pub trait HigherHandlerTrait {
         &mut self,
         message: M,
     ) -> Result<ChanneledMessage<HigherOutput>, HigherError>;
+
+    // outputs a Rekey message if the rekey policy says our keys are due to be rotated
+    fn rekey_if_due(&mut self) -> Result<Option<ChanneledMessage<HigherOutput>>, HigherError>;
+
+    fn set_rekey_policy(&mut self, policy: RekeyPolicy);
+
+    // whether the transport below can carry reliable messages too long for the length prefix
+    fn set_extended_frames(&mut self, extended_frames: bool);
+
+    // File transfers work the same in both directions
+    fn file_transfers(&mut self) -> &mut FileTransfers;
 }
 
 pub(crate) mod sealed {
@@ -58,6 +95,7 @@ pub type HigherHandlerClient = HigherHandler<WpskkaClientHandler, RvdClientHandl
 pub struct HigherHandler<Wpskka, Rvd> {
     wpskka: Wpskka,
     rvd: Rvd,
+    extended_frames: bool,
 }
 
 impl HigherHandlerHost {
@@ -65,6 +103,7 @@ impl HigherHandlerHost {
         HigherHandler {
             wpskka: WpskkaHostHandler::new(),
             rvd: RvdHostHandler::new(),
+            extended_frames: false,
         }
     }
 
@@ -72,10 +111,18 @@ impl HigherHandlerHost {
         Ok(self.wpskka.key_exchange().map_err(WpskkaError::Host)?)
     }
 
-    pub fn set_static_password(&mut self, static_password: Option<Vec<u8>>) {
+    pub fn set_static_password(&mut self, static_password: Option<SecretVec>) {
         self.wpskka.set_static_password(static_password)
     }
 
+    pub fn set_identity(&mut self, identity: Option<IdentityKeyPair>) {
+        self.wpskka.set_identity(identity)
+    }
+
+    pub fn authorized_clients_mut(&mut self) -> &mut TrustedKeys {
+        self.wpskka.authorized_clients_mut()
+    }
+
     pub fn share_display(
         &mut self,
         name: String,
@@ -87,6 +134,18 @@ impl HigherHandlerHost {
             .map_err(RvdError::Host)?)
     }
 
+    pub fn share_display_with_id(
+        &mut self,
+        display_id: DisplayId,
+        name: String,
+        access: AccessMask,
+    ) -> Result<RvdMessage<'static>, HigherError> {
+        Ok(self
+            .rvd
+            .share_display_with_id(display_id, name, access)
+            .map_err(RvdError::Host)?)
+    }
+
     pub fn unshare_display(
         &mut self,
         display_id: DisplayId,
@@ -97,6 +156,77 @@ impl HigherHandlerHost {
             .map_err(RvdError::Host)?)
     }
 
+    pub fn set_display_access(
+        &mut self,
+        display_id: DisplayId,
+        access: AccessMask,
+    ) -> Result<Vec<RvdMessage<'static>>, HigherError> {
+        Ok(self
+            .rvd
+            .set_display_access(display_id, access)
+            .map_err(RvdError::Host)?)
+    }
+
+    pub fn set_display_geometry(
+        &mut self,
+        geometry: DisplayGeometry,
+    ) -> Result<Option<RvdMessage<'static>>, HigherError> {
+        Ok(self
+            .rvd
+            .set_display_geometry(geometry)
+            .map_err(RvdError::Host)?)
+    }
+
+    pub fn set_permissions(&mut self, permissions: PermissionMask) -> RvdMessage<'static> {
+        self.rvd.set_permissions(permissions)
+    }
+
+    pub fn grant_control(&mut self) -> RvdMessage<'static> {
+        self.rvd.grant_control()
+    }
+
+    pub fn revoke_control(&mut self) -> RvdMessage<'static> {
+        self.rvd.revoke_control()
+    }
+
+    pub fn release_input(&mut self) {
+        self.rvd.release_input()
+    }
+
+    pub fn take_input_releases(&mut self) -> Vec<RvdHostInform> {
+        self.rvd.take_input_releases()
+    }
+
+    pub fn clipboard_changed(
+        &mut self,
+        clipboard_type: ClipboardType,
+        content: Vec<u8>,
+    ) -> Option<RvdMessage<'static>> {
+        self.rvd.clipboard_changed(clipboard_type, content)
+    }
+
+    pub fn clipboard_reply(
+        &mut self,
+        clipboard_type: ClipboardType,
+        is_content: bool,
+        content: Option<Vec<u8>>,
+    ) -> Option<RvdMessage<'static>> {
+        self.rvd
+            .clipboard_reply(clipboard_type, is_content, content)
+    }
+
+    pub fn cursor_changed(&mut self, cursor: &CursorImage) -> Option<RvdMessage<'static>> {
+        // Without extended frames a shape that doesn't fit in one frame isn't sent at all
+        if !self.extended_frames && cursor.data.len() > MAX_SHORT_FRAME_CURSOR_DATA {
+            return None;
+        }
+        self.rvd.cursor_changed(cursor)
+    }
+
+    pub fn cursor_sent(&mut self, cursor: &CursorImage) {
+        self.rvd.cursor_sent(cursor)
+    }
+
     pub fn frame_update(display_id: DisplayId, data: &[u8]) -> RvdMessage<'_> {
         RvdHostHandler::frame_update(display_id, data)
     }
@@ -107,6 +237,7 @@ impl HigherHandlerClient {
         HigherHandler {
             wpskka: WpskkaClientHandler::new(),
             rvd: RvdClientHandler::new(),
+            extended_frames: false,
         }
     }
 
@@ -114,6 +245,34 @@ impl HigherHandlerClient {
         RvdClientHandler::protocol_version()
     }
 
+    pub fn control_request(&self) -> RvdMessage<'static> {
+        RvdClientHandler::control_request()
+    }
+
+    pub fn clipboard_changed(
+        &mut self,
+        clipboard_type: ClipboardType,
+        content: Vec<u8>,
+    ) -> Option<RvdMessage<'static>> {
+        self.rvd.clipboard_changed(clipboard_type, content)
+    }
+
+    pub fn text_input(&self, text: String) -> RvdMessage<'static> {
+        RvdClientHandler::text_input(text)
+    }
+
+    pub fn picture_loss(&self, display_id: DisplayId) -> RvdMessage<'static> {
+        RvdClientHandler::picture_loss(display_id)
+    }
+
+    pub fn frame_nack(&self, display_id: DisplayId, sequences: Vec<u16>) -> RvdMessage<'static> {
+        RvdClientHandler::frame_nack(display_id, sequences)
+    }
+
+    pub fn receiver_report(&self, report: ReceiverReport) -> RvdMessage<'static> {
+        RvdClientHandler::receiver_report(report)
+    }
+
     pub fn process_password(
         &mut self,
         password: &[u8],
@@ -126,6 +285,18 @@ impl HigherHandlerClient {
     pub fn try_auth(&mut self, scheme: AuthSchemeType) -> WpskkaMessage<'static> {
         self.wpskka.try_auth(scheme)
     }
+
+    pub fn set_identity(&mut self, identity: Option<IdentityKeyPair>) {
+        self.wpskka.set_identity(identity)
+    }
+
+    pub fn trusted_hosts_mut(&mut self) -> &mut TrustedKeys {
+        self.wpskka.trusted_hosts_mut()
+    }
+
+    pub fn set_known_host(&mut self, host: Option<(HostKey, Option<[u8; 32]>)>) {
+        self.wpskka.set_known_host(host)
+    }
 }
 
 impl<Wpskka: WpskkaHandlerTrait, Rvd: RvdHandlerTrait> HigherHandler<Wpskka, Rvd> {
@@ -160,13 +331,19 @@ impl<Wpskka: WpskkaHandlerTrait, Rvd: RvdHandlerTrait> HigherHandler<Wpskka, Rvd
         &mut self,
         message: WpskkaMessage<'_>,
     ) -> Result<ChanneledMessage<HigherOutput>, HigherSendError> {
-        let output = HigherOutput(message.to_bytes()?);
-
         match message {
             // TransportDataMessageUnreliable is the only type of unreliable message
-            WpskkaMessage::TransportDataMessageUnreliable(..) =>
-                Ok(ChanneledMessage::Unreliable(output)),
-            _ => Ok(ChanneledMessage::Reliable(output)),
+            WpskkaMessage::TransportDataMessageUnreliable(..) => Ok(ChanneledMessage::Unreliable(
+                HigherOutput(message.to_bytes()?),
+            )),
+            // Reliable messages can only be longer than the length prefix allows on a direct
+            // connection, through a signal server the SEL framing limits them to 64 KiB
+            _ if self.extended_frames => Ok(ChanneledMessage::Reliable(HigherOutput(
+                message.to_bytes_extended()?,
+            ))),
+            _ => Ok(ChanneledMessage::Reliable(HigherOutput(
+                message.to_bytes()?,
+            ))),
         }
     }
 
@@ -202,6 +379,15 @@ impl<Wpskka: WpskkaHandlerTrait, Rvd: RvdHandlerTrait> HigherHandler<Wpskka, Rvd
         Ok(events)
     }
 
+    fn rekey_if_due_internal(
+        &mut self,
+    ) -> Result<Option<ChanneledMessage<HigherOutput>>, HigherError> {
+        match self.wpskka.rekey_if_due().map_err(HigherSendError::from)? {
+            Some(rekey) => Ok(Some(self.send_wpskka(rekey)?)),
+            None => Ok(None),
+        }
+    }
+
     fn send_internal<'a, M: Into<sealed::HigherMessage<'a>>>(
         &mut self,
         message: M,
@@ -228,6 +414,22 @@ impl HigherHandlerTrait for HigherHandlerHost {
     ) -> Result<ChanneledMessage<HigherOutput>, HigherError> {
         self.send_internal(message)
     }
+
+    fn rekey_if_due(&mut self) -> Result<Option<ChanneledMessage<HigherOutput>>, HigherError> {
+        self.rekey_if_due_internal()
+    }
+
+    fn set_rekey_policy(&mut self, policy: RekeyPolicy) {
+        self.wpskka.set_rekey_policy(policy)
+    }
+
+    fn set_extended_frames(&mut self, extended_frames: bool) {
+        self.extended_frames = extended_frames
+    }
+
+    fn file_transfers(&mut self) -> &mut FileTransfers {
+        self.rvd.file_transfers()
+    }
 }
 
 impl HigherHandlerTrait for HigherHandlerClient {
@@ -245,6 +447,22 @@ impl HigherHandlerTrait for HigherHandlerClient {
     ) -> Result<ChanneledMessage<HigherOutput>, HigherError> {
         self.send_internal(message)
     }
+
+    fn rekey_if_due(&mut self) -> Result<Option<ChanneledMessage<HigherOutput>>, HigherError> {
+        self.rekey_if_due_internal()
+    }
+
+    fn set_rekey_policy(&mut self, policy: RekeyPolicy) {
+        self.wpskka.set_rekey_policy(policy)
+    }
+
+    fn set_extended_frames(&mut self, extended_frames: bool) {
+        self.extended_frames = extended_frames
+    }
+
+    fn file_transfers(&mut self) -> &mut FileTransfers {
+        self.rvd.file_transfers()
+    }
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -17,6 +17,8 @@ impl LowerHandlerDirect {
 }
 
 impl LowerHandlerTrait for LowerHandlerDirect {
+    const EXTENDED_FRAMES: bool = true;
+
     fn handle(
         &mut self,
         wire: &[u8],
@@ -2,7 +2,7 @@ use super::sealed::LowerMessage;
 use crate::{
     lower::{LowerError, LowerHandlerTrait, LowerSendError},
     sel_handler::SelHandler,
-    svsc_handler::SvscHandler,
+    svsc_handler::{SvscHandler, SvscInform},
     InformEvent,
 };
 use common::messages::{
@@ -78,6 +78,9 @@ impl LowerHandlerSignal {
 }
 
 impl LowerHandlerTrait for LowerHandlerSignal {
+    // SEL framing limits reliable messages to 64 KiB
+    const EXTENDED_FRAMES: bool = false;
+
     fn handle(
         &mut self,
         wire: &[u8],
@@ -101,6 +104,20 @@ impl LowerHandlerTrait for LowerHandlerSignal {
             None => {}
         };
 
+        // The server relays unreliable messages using keys derived from the session data
+        if events
+            .iter()
+            .any(|event| matches!(event, InformEvent::SvscInform(SvscInform::SessionUpdate)))
+        {
+            if let Some(session) = self.svsc.session() {
+                self.sel.derive_unreliable(
+                    &session.session_id,
+                    &session.peer_id,
+                    &session.peer_key,
+                );
+            }
+        }
+
         for message in send_svsc {
             // TODO check if reliable
             send.push(self.send_svsc(ChanneledMessage::Reliable(message))?);
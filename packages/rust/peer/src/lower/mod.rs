@@ -19,6 +19,9 @@ pub use lower_handler_direct::*;
 pub use lower_handler_signal::*;
 
 pub trait LowerHandlerTrait {
+    // whether reliable messages too long for the length prefix can be sent as extended frames
+    const EXTENDED_FRAMES: bool;
+
     // takes messages from the wire and outputs wpskka bytes, send is bytes to put back on the wire
     fn handle(
         &mut self,
@@ -3,7 +3,20 @@ use std::borrow::Cow;
 use crate::{
     debug,
     helpers::crypto::{random_bytes, random_bytes_const},
-    rvd::{HostState, RvdError, RvdHandlerTrait, RvdHostError},
+    rvd::{
+        ClipboardTransferError,
+        ClipboardTransfers,
+        CursorError,
+        CursorImage,
+        CursorReceiver,
+        FileTransferError,
+        FileTransferInform,
+        FileTransfers,
+        HostState,
+        RvdError,
+        RvdHandlerTrait,
+        RvdHostError,
+    },
     InformEvent,
     RvdHostInform,
 };
@@ -11,15 +24,23 @@ use common::{
     constants::RVD_VERSION,
     messages::{
         rvd::{
+            ClipboardMeta,
             ClipboardType,
+            ControlRequest,
+            DisplayGeometry,
             DisplayId,
             DisplayShare,
             DisplayShareAck,
             FrameData,
+            FrameNack,
             MouseLocation,
+            PermissionMask,
+            PictureLoss,
             ProtocolVersion,
             ProtocolVersionResponse,
+            ReceiverReport,
             RvdMessage,
+            TextInput,
             UnreliableAuthFinal,
             UnreliableAuthInitial,
             UnreliableAuthInter,
@@ -39,6 +60,13 @@ pub enum ClientState {
 
 pub struct RvdClientHandler {
     state: ClientState,
+    // Hosts hand the controls to their only Client, so we assume we hold them until told otherwise
+    control: bool,
+    // What the Host lets us do, we don't send it clipboard content it would refuse
+    permissions: PermissionMask,
+    file_transfers: FileTransfers,
+    clipboard_transfers: ClipboardTransfers,
+    cursors: CursorReceiver,
 }
 
 impl Default for RvdClientHandler {
@@ -51,9 +79,63 @@ impl RvdClientHandler {
     pub fn new() -> Self {
         Self {
             state: ClientState::ProtocolVersion,
+            control: true,
+            permissions: PermissionMask::empty(),
+            file_transfers: FileTransfers::new(),
+            clipboard_transfers: ClipboardTransfers::new(),
+            cursors: CursorReceiver::new(),
         }
     }
 
+    pub fn has_control(&self) -> bool {
+        self.control
+    }
+
+    pub fn control_request() -> RvdMessage<'static> {
+        RvdMessage::ControlRequest(ControlRequest {})
+    }
+
+    /// Tells the Host about new content on the local clipboard, large content is sent in chunks
+    /// as the Host acknowledges them. Returns None if we aren't allowed to write to the Host's
+    /// clipboard or the content is larger than it accepts.
+    pub fn clipboard_changed(
+        &mut self,
+        clipboard_type: ClipboardType,
+        content: Vec<u8>,
+    ) -> Option<RvdMessage<'static>> {
+        if !matches!(self.state, ClientState::Ready)
+            || !self.permissions.contains(PermissionMask::CLIPBOARD_WRITE)
+        {
+            return None;
+        }
+        self.clipboard_transfers.send(
+            ClipboardMeta {
+                clipboard_type,
+                content_request: true,
+            },
+            content,
+        )
+    }
+
+    pub fn text_input(text: String) -> RvdMessage<'static> {
+        RvdMessage::TextInput(TextInput { text })
+    }
+
+    pub fn picture_loss(display_id: DisplayId) -> RvdMessage<'static> {
+        RvdMessage::PictureLoss(PictureLoss { display_id })
+    }
+
+    pub fn frame_nack(display_id: DisplayId, sequences: Vec<u16>) -> RvdMessage<'static> {
+        RvdMessage::FrameNack(FrameNack {
+            display_id,
+            sequences,
+        })
+    }
+
+    pub fn receiver_report(report: ReceiverReport) -> RvdMessage<'static> {
+        RvdMessage::ReceiverReport(report)
+    }
+
     pub fn protocol_version() -> RvdMessage<'static> {
         RvdMessage::ProtocolVersion(ProtocolVersion {
             version: RVD_VERSION.to_string(),
@@ -109,6 +191,22 @@ impl RvdClientHandler {
                     self.state = ClientState::UnreliableAuth(challenge, true);
                     Ok(())
                 }
+                // The Host sends this straight after HandshakeComplete, so it can beat
+                // UnreliableAuthInter here too
+                msg @ RvdMessage::ClipboardLimit(_) if complete => {
+                    self.clipboard_transfers.handle(msg, write)?;
+                    Ok(())
+                }
+                // So can the cursor, which is sent as soon as the Host is ready
+                msg @ (RvdMessage::CursorShape(_) | RvdMessage::CursorShapeCached(_))
+                    if complete =>
+                {
+                    let cursor = self.cursors.handle(msg)?;
+                    events.push(InformEvent::RvdClientInform(RvdClientInform::CursorShape(
+                        cursor,
+                    )));
+                    Ok(())
+                }
                 _ => Err(RvdClientError::WrongMessageForState(
                     debug(&msg),
                     self.state,
@@ -152,6 +250,12 @@ impl RvdClientHandler {
                     ));
                     Ok(())
                 }
+                RvdMessage::DisplayGeometry(msg) => {
+                    events.push(InformEvent::RvdClientInform(
+                        RvdClientInform::DisplayGeometry(msg),
+                    ));
+                    Ok(())
+                }
                 RvdMessage::MouseHidden(msg) => {
                     events.push(InformEvent::RvdClientInform(RvdClientInform::MouseHidden(
                         msg.display_id,
@@ -164,6 +268,38 @@ impl RvdClientHandler {
                     ));
                     Ok(())
                 }
+                RvdMessage::PermissionsUpdate(msg) => {
+                    self.permissions = msg.permission_mask;
+                    Ok(())
+                }
+                RvdMessage::ControlGrant(_) => {
+                    self.control = true;
+                    events.push(InformEvent::RvdClientInform(
+                        RvdClientInform::ControlChanged(true),
+                    ));
+                    Ok(())
+                }
+                RvdMessage::ControlRevoke(_) => {
+                    self.control = false;
+                    events.push(InformEvent::RvdClientInform(
+                        RvdClientInform::ControlChanged(false),
+                    ));
+                    Ok(())
+                }
+                msg @ (RvdMessage::FileOffer(_)
+                | RvdMessage::FileAccept(_)
+                | RvdMessage::FileReject(_)
+                | RvdMessage::FileChunk(_)
+                | RvdMessage::FileAck(_)
+                | RvdMessage::FileCancel(_)
+                | RvdMessage::FileComplete(_)) => {
+                    if let Some(inform) = self.file_transfers.handle(msg, write)? {
+                        events.push(InformEvent::RvdClientInform(RvdClientInform::FileTransfer(
+                            inform,
+                        )));
+                    }
+                    Ok(())
+                }
                 RvdMessage::ClipboardNotification(msg) => {
                     if let Some(content) = msg.content {
                         events.push(InformEvent::RvdClientInform(
@@ -175,6 +311,26 @@ impl RvdClientHandler {
                     }
                     Ok(())
                 }
+                msg @ (RvdMessage::ClipboardLimit(_)
+                | RvdMessage::ClipboardStart(_)
+                | RvdMessage::ClipboardChunk(_)
+                | RvdMessage::ClipboardAck(_)) => {
+                    if let Some((content, clipboard_type)) =
+                        self.clipboard_transfers.handle(msg, write)?
+                    {
+                        events.push(InformEvent::RvdClientInform(
+                            RvdClientInform::ClipboardNotification(content, clipboard_type),
+                        ));
+                    }
+                    Ok(())
+                }
+                msg @ (RvdMessage::CursorShape(_) | RvdMessage::CursorShapeCached(_)) => {
+                    let cursor = self.cursors.handle(msg)?;
+                    events.push(InformEvent::RvdClientInform(RvdClientInform::CursorShape(
+                        cursor,
+                    )));
+                    Ok(())
+                }
                 _ => Err(RvdClientError::WrongMessageForState(
                     debug(&msg),
                     self.state,
@@ -193,6 +349,10 @@ impl RvdHandlerTrait for RvdClientHandler {
     ) -> Result<(), RvdError> {
         Ok(self._handle(msg, write, events)?)
     }
+
+    fn file_transfers(&mut self) -> &mut FileTransfers {
+        &mut self.file_transfers
+    }
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -203,6 +363,12 @@ pub enum RvdClientError {
     PermissionsError(String),
     #[error("unreliable auth failed")]
     UnreliableAuthFailed,
+    #[error("file transfer error: {0}")]
+    FileTransfer(#[from] FileTransferError),
+    #[error("clipboard transfer error: {0}")]
+    ClipboardTransfer(#[from] ClipboardTransferError),
+    #[error("cursor error: {0}")]
+    Cursor(#[from] CursorError),
 }
 
 #[derive(Debug)]
@@ -216,5 +382,9 @@ pub enum RvdClientInform {
     MouseLocation(MouseLocation),
     DisplayShare(DisplayShare),
     DisplayUnshare(DisplayId),
+    DisplayGeometry(DisplayGeometry),
+    ControlChanged(bool), // whether we now hold the controls
     ClipboardNotification(Vec<u8>, ClipboardType), // for now we only care when receive a clipboard notification with content
+    FileTransfer(FileTransferInform),
+    CursorShape(CursorImage),
 }
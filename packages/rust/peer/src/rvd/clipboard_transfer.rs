@@ -0,0 +1,278 @@
+// Clipboard content that doesn't fit in a single ClipboardNotification is sent in chunks. Through
+// a signal server the framing can't carry a message larger than 64 KiB, and sending a large
+// clipboard in one go would hold up the input queued behind it on the same stream.
+//
+// A transfer goes: ClipboardStart -> ClipboardAck(0) -> ClipboardChunk... with every chunk
+// answered by a ClipboardAck. The sender stops once CLIPBOARD_WINDOW chunks are unacknowledged, so
+// at most that much clipboard data is ever queued ahead of input. Each Peer announces the largest
+// content it accepts with a ClipboardLimit, nothing larger than a single chunk is sent until the
+// other Peer's limit is known.
+//
+// The clipboard only holds one thing at a time, so there's at most one transfer in each direction.
+// Newer content replaces a transfer that's still going, and a transfer that makes no progress for
+// CLIPBOARD_TIMEOUT is dropped by both Peers.
+
+use crate::debug;
+use common::messages::{
+    rvd::{
+        ClipboardAck,
+        ClipboardChunk,
+        ClipboardLimit,
+        ClipboardMeta,
+        ClipboardNotification,
+        ClipboardStart,
+        ClipboardType,
+        RvdMessage,
+        TransferId,
+    },
+    Data,
+};
+use std::{
+    borrow::Cow,
+    time::{Duration, Instant},
+};
+
+/// The most clipboard data sent in a single message
+pub const CLIPBOARD_CHUNK_SIZE: usize = 16 * 1024;
+/// The most chunks sent without being acknowledged
+pub const CLIPBOARD_WINDOW: u32 = 4;
+/// How long a transfer can go without progress before it's dropped
+pub const CLIPBOARD_TIMEOUT: Duration = Duration::from_secs(10);
+/// The largest clipboard content accepted unless another limit is given
+pub const DEFAULT_CLIPBOARD_LIMIT: u32 = 64 * 1024 * 1024;
+
+struct OutgoingClipboard {
+    transfer_id: TransferId,
+    content: Vec<u8>,
+    // Chunks sent and acknowledged so far
+    sent: u32,
+    acked: u32,
+    last_progress: Instant,
+}
+
+impl OutgoingClipboard {
+    fn chunks(&self) -> u32 {
+        ((self.content.len() + CLIPBOARD_CHUNK_SIZE - 1) / CLIPBOARD_CHUNK_SIZE) as u32
+    }
+
+    // Sends as many chunks as the window allows
+    fn send_chunks(&mut self, write: &mut Vec<RvdMessage<'_>>) {
+        while self.sent < self.chunks() && self.sent - self.acked < CLIPBOARD_WINDOW {
+            let start = self.sent as usize * CLIPBOARD_CHUNK_SIZE;
+            let end = (start + CLIPBOARD_CHUNK_SIZE).min(self.content.len());
+            write.push(RvdMessage::ClipboardChunk(ClipboardChunk {
+                transfer_id: self.transfer_id,
+                sequence: self.sent,
+                data: Data(Cow::Owned(self.content[start .. end].to_vec())),
+            }));
+            self.sent += 1;
+        }
+    }
+}
+
+struct IncomingClipboard {
+    transfer_id: TransferId,
+    clipboard_type: ClipboardType,
+    size: u32,
+    content: Vec<u8>,
+    received: u32,
+    last_progress: Instant,
+}
+
+pub struct ClipboardTransfers {
+    // The largest content we accept, and the largest the other Peer accepts once it's told us
+    limit: u32,
+    peer_limit: Option<u32>,
+    limit_sent: bool,
+    next_transfer_id: TransferId,
+    outgoing: Option<OutgoingClipboard>,
+    incoming: Option<IncomingClipboard>,
+}
+
+impl Default for ClipboardTransfers {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ClipboardTransfers {
+    pub fn new() -> Self {
+        Self::with_limit(DEFAULT_CLIPBOARD_LIMIT)
+    }
+
+    pub fn with_limit(limit: u32) -> Self {
+        Self {
+            limit,
+            peer_limit: None,
+            limit_sent: false,
+            next_transfer_id: 0,
+            outgoing: None,
+            incoming: None,
+        }
+    }
+
+    /// The largest content the other Peer accepts, None until it's told us
+    pub fn peer_limit(&self) -> Option<u32> {
+        self.peer_limit
+    }
+
+    /// Tells the other Peer the largest content we accept
+    pub fn announce_limit(&mut self) -> RvdMessage<'static> {
+        self.limit_sent = true;
+        RvdMessage::ClipboardLimit(ClipboardLimit {
+            max_size: self.limit,
+        })
+    }
+
+    /// Starts sending clipboard content, replacing anything still being sent. Content that fits in
+    /// one message is sent as a ClipboardNotification. Returns None if the content is larger than
+    /// the other Peer accepts.
+    pub fn send(&mut self, info: ClipboardMeta, content: Vec<u8>) -> Option<RvdMessage<'static>> {
+        if content.len() <= CLIPBOARD_CHUNK_SIZE {
+            return Some(RvdMessage::ClipboardNotification(ClipboardNotification {
+                info,
+                type_exists: true,
+                content: Some(content),
+            }));
+        }
+        match self.peer_limit {
+            Some(peer_limit) if content.len() <= peer_limit as usize => {}
+            _ => return None,
+        }
+
+        let transfer_id = self.next_transfer_id;
+        self.next_transfer_id = self.next_transfer_id.wrapping_add(1);
+        let size = content.len() as u32;
+        self.outgoing = Some(OutgoingClipboard {
+            transfer_id,
+            content,
+            sent: 0,
+            acked: 0,
+            last_progress: Instant::now(),
+        });
+        Some(RvdMessage::ClipboardStart(ClipboardStart {
+            transfer_id,
+            info,
+            size,
+        }))
+    }
+
+    /// Handles a clipboard transfer message. Once all of the incoming content has arrived it's
+    /// returned along with its type. Messages for transfers that were replaced or timed out are
+    /// ignored.
+    pub fn handle(
+        &mut self,
+        msg: RvdMessage<'_>,
+        write: &mut Vec<RvdMessage<'_>>,
+    ) -> Result<Option<(Vec<u8>, ClipboardType)>, ClipboardTransferError> {
+        self.expire();
+        match msg {
+            RvdMessage::ClipboardLimit(msg) => {
+                self.peer_limit = Some(msg.max_size);
+                if !self.limit_sent {
+                    write.push(self.announce_limit());
+                }
+                Ok(None)
+            }
+            RvdMessage::ClipboardStart(msg) => {
+                if msg.size > self.limit {
+                    return Err(ClipboardTransferError::TooLarge(msg.size, self.limit));
+                }
+                self.incoming = Some(IncomingClipboard {
+                    transfer_id: msg.transfer_id,
+                    clipboard_type: msg.info.clipboard_type,
+                    size: msg.size,
+                    content: Vec::with_capacity(msg.size as usize),
+                    received: 0,
+                    last_progress: Instant::now(),
+                });
+                write.push(RvdMessage::ClipboardAck(ClipboardAck {
+                    transfer_id: msg.transfer_id,
+                    received: 0,
+                }));
+                Ok(None)
+            }
+            RvdMessage::ClipboardChunk(msg) => {
+                let incoming = match &mut self.incoming {
+                    Some(incoming) if incoming.transfer_id == msg.transfer_id => incoming,
+                    _ => return Ok(None),
+                };
+                if msg.sequence != incoming.received {
+                    return Err(ClipboardTransferError::OutOfOrder(
+                        msg.transfer_id,
+                        msg.sequence,
+                    ));
+                }
+                let data = msg.data.0;
+                if data.len() > CLIPBOARD_CHUNK_SIZE
+                    || incoming.content.len() + data.len() > incoming.size as usize
+                {
+                    return Err(ClipboardTransferError::OutOfBounds(msg.transfer_id));
+                }
+                incoming.content.extend_from_slice(&data);
+                incoming.received += 1;
+                incoming.last_progress = Instant::now();
+                write.push(RvdMessage::ClipboardAck(ClipboardAck {
+                    transfer_id: msg.transfer_id,
+                    received: incoming.received,
+                }));
+
+                if incoming.content.len() < incoming.size as usize {
+                    return Ok(None);
+                }
+                // Checked it's there above
+                let incoming = self.incoming.take().unwrap();
+                Ok(Some((incoming.content, incoming.clipboard_type)))
+            }
+            RvdMessage::ClipboardAck(msg) => {
+                let outgoing = match &mut self.outgoing {
+                    Some(outgoing) if outgoing.transfer_id == msg.transfer_id => outgoing,
+                    _ => return Ok(None),
+                };
+                if msg.received < outgoing.acked || msg.received > outgoing.sent {
+                    return Err(ClipboardTransferError::UnexpectedMessage(debug(&msg)));
+                }
+                outgoing.acked = msg.received;
+                outgoing.last_progress = Instant::now();
+                if outgoing.acked == outgoing.chunks() {
+                    self.outgoing = None;
+                } else {
+                    outgoing.send_chunks(write);
+                }
+                Ok(None)
+            }
+            _ => Err(ClipboardTransferError::UnexpectedMessage(debug(&msg))),
+        }
+    }
+
+    // Drops transfers that have stopped making progress
+    fn expire(&mut self) {
+        let expired = |last_progress: Instant| last_progress.elapsed() > CLIPBOARD_TIMEOUT;
+        if self
+            .outgoing
+            .as_ref()
+            .map_or(false, |outgoing| expired(outgoing.last_progress))
+        {
+            self.outgoing = None;
+        }
+        if self
+            .incoming
+            .as_ref()
+            .map_or(false, |incoming| expired(incoming.last_progress))
+        {
+            self.incoming = None;
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ClipboardTransferError {
+    #[error("clipboard content of {0} bytes is larger than the limit of {1}")]
+    TooLarge(u32, u32),
+    #[error("chunk {1} of clipboard transfer {0} is out of order")]
+    OutOfOrder(TransferId, u32),
+    #[error("clipboard transfer {0} received more data than it announced")]
+    OutOfBounds(TransferId),
+    #[error("unexpected message {0}")]
+    UnexpectedMessage(String),
+}
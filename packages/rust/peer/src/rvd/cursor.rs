@@ -0,0 +1,145 @@
+// The Host sends a cursor shape once, after that it refers to it by its CursorId. Both Peers keep
+// the last CURSOR_CACHE_SIZE shapes that were sent, dropping the oldest first. Shapes are only
+// added by CursorShape messages, which arrive in the order they were sent, and the Host only adds
+// one once it's been sent, so the Host always knows exactly which shapes the Client has.
+
+use crate::debug;
+use common::{
+    constants::HashAlgo,
+    messages::{
+        rvd::{CursorId, CursorShape, CursorShapeCached, RvdMessage},
+        Data,
+    },
+};
+use std::{borrow::Cow, collections::VecDeque};
+
+/// How many cursor shapes each Peer remembers
+pub const CURSOR_CACHE_SIZE: usize = 16;
+
+/// The most cursor data a CursorShape can carry without extended frames, leaving room for the
+/// headers of every layer below it
+pub const MAX_SHORT_FRAME_CURSOR_DATA: usize = 63 * 1024;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CursorImage {
+    pub width: u16,
+    pub height: u16,
+    pub hotspot_x: u16,
+    pub hotspot_y: u16,
+    /// width * height RGBA pixels, not premultiplied
+    pub data: Vec<u8>,
+}
+
+impl CursorImage {
+    pub fn id(&self) -> CursorId {
+        let mut hasher = HashAlgo::new();
+        for field in [self.width, self.height, self.hotspot_x, self.hotspot_y] {
+            hasher.update(&field.to_le_bytes());
+        }
+        hasher.update(&self.data);
+        let mut id = [0u8; 8];
+        id.copy_from_slice(&hasher.finalize().as_bytes()[.. 8]);
+        CursorId::from_le_bytes(id)
+    }
+}
+
+/// The Host's side, tracks which shapes the Client has
+#[derive(Default)]
+pub struct CursorSender {
+    sent: VecDeque<CursorId>,
+    current: Option<CursorId>,
+}
+
+impl CursorSender {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the message that shows this cursor on the Client, None if it's already showing.
+    /// Nothing is recorded until cursor_sent is called.
+    pub fn cursor_changed(&self, cursor: &CursorImage) -> Option<RvdMessage<'static>> {
+        let cursor_id = cursor.id();
+        if self.current == Some(cursor_id) {
+            return None;
+        }
+        if self.sent.contains(&cursor_id) {
+            return Some(RvdMessage::CursorShapeCached(CursorShapeCached {
+                cursor_id,
+            }));
+        }
+        Some(RvdMessage::CursorShape(CursorShape {
+            cursor_id,
+            width: cursor.width,
+            height: cursor.height,
+            hotspot_x: cursor.hotspot_x,
+            hotspot_y: cursor.hotspot_y,
+            data: Data(Cow::Owned(cursor.data.clone())),
+        }))
+    }
+
+    /// Records that the message from cursor_changed was sent, the Client now has the cursor
+    pub fn cursor_sent(&mut self, cursor: &CursorImage) {
+        let cursor_id = cursor.id();
+        self.current = Some(cursor_id);
+        if self.sent.contains(&cursor_id) {
+            return;
+        }
+        if self.sent.len() == CURSOR_CACHE_SIZE {
+            self.sent.pop_front();
+        }
+        self.sent.push_back(cursor_id);
+    }
+}
+
+/// The Client's side, keeps the shapes the Host may refer to later
+#[derive(Default)]
+pub struct CursorReceiver {
+    shapes: VecDeque<(CursorId, CursorImage)>,
+}
+
+impl CursorReceiver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the cursor the Host is now showing
+    pub fn handle(&mut self, msg: RvdMessage<'_>) -> Result<CursorImage, CursorError> {
+        match msg {
+            RvdMessage::CursorShape(msg) => {
+                let pixels = usize::from(msg.width) * usize::from(msg.height);
+                if msg.data.0.len() != pixels * 4 {
+                    return Err(CursorError::BadImage(msg.cursor_id));
+                }
+                let cursor = CursorImage {
+                    width: msg.width,
+                    height: msg.height,
+                    hotspot_x: msg.hotspot_x,
+                    hotspot_y: msg.hotspot_y,
+                    data: msg.data.0.into_owned(),
+                };
+                if self.shapes.len() == CURSOR_CACHE_SIZE {
+                    self.shapes.pop_front();
+                }
+                self.shapes.push_back((msg.cursor_id, cursor.clone()));
+                Ok(cursor)
+            }
+            RvdMessage::CursorShapeCached(msg) => self
+                .shapes
+                .iter()
+                .find(|(cursor_id, _)| *cursor_id == msg.cursor_id)
+                .map(|(_, cursor)| cursor.clone())
+                .ok_or(CursorError::UnknownCursor(msg.cursor_id)),
+            _ => Err(CursorError::UnexpectedMessage(debug(&msg))),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum CursorError {
+    #[error("cursor {0} doesn't match its size")]
+    BadImage(CursorId),
+    #[error("cursor {0} was never sent")]
+    UnknownCursor(CursorId),
+    #[error("unexpected message {0}")]
+    UnexpectedMessage(String),
+}
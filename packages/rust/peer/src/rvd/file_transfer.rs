@@ -0,0 +1,370 @@
+// Both Peers can offer files so the bookkeeping lives here and is shared by the Host and Client
+// handlers. Reading and writing the files themselves is left to the caller, this only checks that
+// both sides agree on where each transfer is at.
+//
+// A transfer goes: FileOffer -> FileAccept (or FileReject) -> FileChunk... -> FileComplete. The
+// receiver picks the offset to start from when it accepts, which lets an interrupted transfer be
+// resumed. Every chunk is answered with a FileAck and the sender stops once FILE_WINDOW_SIZE bytes
+// are unacknowledged, so a fast sender can't bury a slow connection. Once every byte has arrived
+// the receiver checks the hash and replies with FileComplete. Either side can send FileCancel at
+// any point.
+
+use crate::debug;
+use common::messages::{
+    rvd::{
+        FileAccept,
+        FileAck,
+        FileCancel,
+        FileChunk,
+        FileComplete,
+        FileOffer,
+        FileReject,
+        RvdMessage,
+        TransferId,
+    },
+    Data,
+};
+use std::{borrow::Cow, collections::HashMap};
+
+/// The most data sent in a single FileChunk
+pub const FILE_CHUNK_SIZE: usize = 32 * 1024;
+/// The most data sent without being acknowledged
+pub const FILE_WINDOW_SIZE: u64 = 32 * FILE_CHUNK_SIZE as u64;
+
+struct Transfer {
+    size: u64,
+    // How far we've sent or received
+    offset: u64,
+    // How far the receiver has acknowledged, only used for outgoing transfers
+    acked: u64,
+    accepted: bool,
+}
+
+impl Transfer {
+    fn new(size: u64) -> Self {
+        Self {
+            size,
+            offset: 0,
+            acked: 0,
+            accepted: false,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct FileTransfers {
+    next_transfer_id: TransferId,
+    // Transfers we offered, keyed by our ID
+    outgoing: HashMap<TransferId, Transfer>,
+    // Transfers offered to us, keyed by the other Peer's ID
+    incoming: HashMap<TransferId, Transfer>,
+}
+
+impl FileTransfers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn offer(
+        &mut self,
+        name: String,
+        size: u64,
+        hash: [u8; 32],
+    ) -> (TransferId, RvdMessage<'static>) {
+        let transfer_id = self.next_transfer_id;
+        self.next_transfer_id = self.next_transfer_id.wrapping_add(1);
+        self.outgoing.insert(transfer_id, Transfer::new(size));
+        let msg = RvdMessage::FileOffer(FileOffer {
+            transfer_id,
+            size,
+            hash,
+            name,
+        });
+        (transfer_id, msg)
+    }
+
+    /// Accepts an incoming file, starting from offset. If offset is the size of the file no chunks
+    /// will follow and complete can be called straight away.
+    pub fn accept(
+        &mut self,
+        transfer_id: TransferId,
+        offset: u64,
+    ) -> Result<RvdMessage<'static>, FileTransferError> {
+        let transfer = match self.incoming.get_mut(&transfer_id) {
+            Some(transfer) if !transfer.accepted => transfer,
+            _ => return Err(FileTransferError::NotFound(transfer_id)),
+        };
+        if offset > transfer.size {
+            return Err(FileTransferError::OutOfBounds(transfer_id, offset));
+        }
+        transfer.accepted = true;
+        transfer.offset = offset;
+        Ok(RvdMessage::FileAccept(FileAccept {
+            transfer_id,
+            offset,
+        }))
+    }
+
+    pub fn reject(
+        &mut self,
+        transfer_id: TransferId,
+    ) -> Result<RvdMessage<'static>, FileTransferError> {
+        match self.incoming.get(&transfer_id) {
+            Some(transfer) if !transfer.accepted => {}
+            _ => return Err(FileTransferError::NotFound(transfer_id)),
+        }
+        self.incoming.remove(&transfer_id);
+        Ok(RvdMessage::FileReject(FileReject { transfer_id }))
+    }
+
+    /// Cancels a transfer in either direction. outgoing says whether it's one we offered.
+    pub fn cancel(
+        &mut self,
+        transfer_id: TransferId,
+        outgoing: bool,
+    ) -> Result<RvdMessage<'static>, FileTransferError> {
+        let transfers = if outgoing {
+            &mut self.outgoing
+        } else {
+            &mut self.incoming
+        };
+        if transfers.remove(&transfer_id).is_none() {
+            return Err(FileTransferError::NotFound(transfer_id));
+        }
+        Ok(RvdMessage::FileCancel(FileCancel {
+            transfer_id,
+            outgoing,
+        }))
+    }
+
+    /// Whether another chunk of an outgoing transfer can be sent right now. Once the window is full
+    /// the next FileAck opens it again.
+    pub fn window_open(&self, transfer_id: TransferId) -> bool {
+        match self.outgoing.get(&transfer_id) {
+            Some(transfer) =>
+                transfer.accepted
+                    && transfer.offset < transfer.size
+                    && transfer.offset - transfer.acked < FILE_WINDOW_SIZE,
+            None => false,
+        }
+    }
+
+    /// Sends the next part of an accepted outgoing transfer, data picks up where the last chunk
+    /// left off. Returns the new offset along with the message.
+    pub fn chunk<'a>(
+        &mut self,
+        transfer_id: TransferId,
+        data: &'a [u8],
+    ) -> Result<(u64, RvdMessage<'a>), FileTransferError> {
+        if !self.window_open(transfer_id) {
+            return Err(FileTransferError::WindowClosed(transfer_id));
+        }
+        // window_open checked it's there
+        let transfer = self.outgoing.get_mut(&transfer_id).unwrap();
+        if data.len() > FILE_CHUNK_SIZE {
+            return Err(FileTransferError::ChunkTooLarge(data.len()));
+        }
+        let offset = transfer.offset;
+        let end = offset + data.len() as u64;
+        if end > transfer.size {
+            return Err(FileTransferError::OutOfBounds(transfer_id, end));
+        }
+        transfer.offset = end;
+        let msg = RvdMessage::FileChunk(FileChunk {
+            transfer_id,
+            offset,
+            data: Data(Cow::Borrowed(data)),
+        });
+        Ok((end, msg))
+    }
+
+    /// Tells the other Peer whether the file they sent matched its hash. Every byte must have been
+    /// received first.
+    pub fn complete(
+        &mut self,
+        transfer_id: TransferId,
+        hash_ok: bool,
+    ) -> Result<RvdMessage<'static>, FileTransferError> {
+        match self.incoming.get(&transfer_id) {
+            Some(transfer) if transfer.accepted && transfer.offset == transfer.size => {}
+            _ => return Err(FileTransferError::NotFound(transfer_id)),
+        }
+        self.incoming.remove(&transfer_id);
+        Ok(RvdMessage::FileComplete(FileComplete {
+            transfer_id,
+            hash_ok,
+        }))
+    }
+
+    /// Handles a file transfer message. Messages for transfers we don't know about are ignored,
+    /// they can still be in flight after a transfer is cancelled.
+    pub fn handle(
+        &mut self,
+        msg: RvdMessage<'_>,
+        write: &mut Vec<RvdMessage<'_>>,
+    ) -> Result<Option<FileTransferInform>, FileTransferError> {
+        match msg {
+            RvdMessage::FileOffer(msg) => {
+                if self.incoming.contains_key(&msg.transfer_id) {
+                    return Err(FileTransferError::DuplicateOffer(msg.transfer_id));
+                }
+                self.incoming
+                    .insert(msg.transfer_id, Transfer::new(msg.size));
+                Ok(Some(FileTransferInform::Offered(msg)))
+            }
+            RvdMessage::FileAccept(msg) => {
+                let transfer = match self.outgoing.get_mut(&msg.transfer_id) {
+                    Some(transfer) => transfer,
+                    None => return Ok(None),
+                };
+                if transfer.accepted {
+                    return Err(FileTransferError::UnexpectedMessage(debug(&msg)));
+                }
+                if msg.offset > transfer.size {
+                    return Err(FileTransferError::OutOfBounds(msg.transfer_id, msg.offset));
+                }
+                transfer.accepted = true;
+                transfer.offset = msg.offset;
+                transfer.acked = msg.offset;
+                Ok(Some(FileTransferInform::Accepted {
+                    transfer_id: msg.transfer_id,
+                    offset: msg.offset,
+                }))
+            }
+            RvdMessage::FileReject(msg) => {
+                match self.outgoing.get(&msg.transfer_id) {
+                    None => return Ok(None),
+                    Some(transfer) if transfer.accepted =>
+                        return Err(FileTransferError::UnexpectedMessage(debug(&msg))),
+                    _ => {}
+                }
+                self.outgoing.remove(&msg.transfer_id);
+                Ok(Some(FileTransferInform::Rejected(msg.transfer_id)))
+            }
+            RvdMessage::FileChunk(msg) => {
+                let transfer = match self.incoming.get_mut(&msg.transfer_id) {
+                    Some(transfer) => transfer,
+                    None => return Ok(None),
+                };
+                if !transfer.accepted {
+                    return Err(FileTransferError::UnexpectedMessage(debug(&msg)));
+                }
+                // Chunks arrive in order over the reliable channel so there mustn't be any gaps
+                if msg.offset != transfer.offset {
+                    return Err(FileTransferError::OutOfOrder(msg.transfer_id, msg.offset));
+                }
+                let end = msg.offset + msg.data.0.len() as u64;
+                if end > transfer.size {
+                    return Err(FileTransferError::OutOfBounds(msg.transfer_id, end));
+                }
+                transfer.offset = end;
+                write.push(RvdMessage::FileAck(FileAck {
+                    transfer_id: msg.transfer_id,
+                    offset: end,
+                }));
+                Ok(Some(FileTransferInform::Chunk {
+                    transfer_id: msg.transfer_id,
+                    offset: msg.offset,
+                    data: msg.data.0.into_owned(),
+                    done: end == transfer.size,
+                }))
+            }
+            RvdMessage::FileAck(msg) => {
+                let transfer = match self.outgoing.get_mut(&msg.transfer_id) {
+                    Some(transfer) => transfer,
+                    None => return Ok(None),
+                };
+                if msg.offset < transfer.acked || msg.offset > transfer.offset {
+                    return Err(FileTransferError::OutOfBounds(msg.transfer_id, msg.offset));
+                }
+                transfer.acked = msg.offset;
+                Ok(Some(FileTransferInform::Acked {
+                    transfer_id: msg.transfer_id,
+                    offset: msg.offset,
+                }))
+            }
+            RvdMessage::FileCancel(msg) => {
+                // Their outgoing transfers are our incoming ones
+                let transfers = if msg.outgoing {
+                    &mut self.incoming
+                } else {
+                    &mut self.outgoing
+                };
+                if transfers.remove(&msg.transfer_id).is_none() {
+                    return Ok(None);
+                }
+                Ok(Some(FileTransferInform::Cancelled {
+                    transfer_id: msg.transfer_id,
+                    outgoing: !msg.outgoing,
+                }))
+            }
+            RvdMessage::FileComplete(msg) => {
+                match self.outgoing.get(&msg.transfer_id) {
+                    None => return Ok(None),
+                    Some(transfer) if !transfer.accepted || transfer.offset != transfer.size =>
+                        return Err(FileTransferError::UnexpectedMessage(debug(&msg))),
+                    _ => {}
+                }
+                self.outgoing.remove(&msg.transfer_id);
+                Ok(Some(FileTransferInform::Completed {
+                    transfer_id: msg.transfer_id,
+                    hash_ok: msg.hash_ok,
+                }))
+            }
+            _ => Err(FileTransferError::UnexpectedMessage(debug(&msg))),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum FileTransferError {
+    #[error("no transfer with id {0} in a suitable state")]
+    NotFound(TransferId),
+    #[error("transfer id {0} was offered twice")]
+    DuplicateOffer(TransferId),
+    #[error("offset {1} is out of bounds for transfer {0}")]
+    OutOfBounds(TransferId, u64),
+    #[error("chunk at offset {1} of transfer {0} is out of order")]
+    OutOfOrder(TransferId, u64),
+    #[error("chunk of {0} bytes is larger than the maximum")]
+    ChunkTooLarge(usize),
+    #[error("transfer {0} can't send another chunk until more are acknowledged")]
+    WindowClosed(TransferId),
+    #[error("unexpected message {0}")]
+    UnexpectedMessage(String),
+}
+
+#[derive(Debug)]
+pub enum FileTransferInform {
+    /// The other Peer wants to send us a file, answer with accept or reject
+    Offered(FileOffer),
+    /// A file we offered was accepted, chunks should be sent from offset onwards
+    Accepted {
+        transfer_id: TransferId,
+        offset: u64,
+    },
+    Rejected(TransferId),
+    /// Part of an incoming file. Once done is set the hash should be checked and the result sent
+    /// with complete.
+    Chunk {
+        transfer_id: TransferId,
+        offset: u64,
+        data: Vec<u8>,
+        done: bool,
+    },
+    /// The other Peer has received a file we're sending up to offset, more chunks may be sent
+    Acked {
+        transfer_id: TransferId,
+        offset: u64,
+    },
+    /// The other Peer received all of a file we sent and checked its hash
+    Completed {
+        transfer_id: TransferId,
+        hash_ok: bool,
+    },
+    /// The other Peer cancelled a transfer. outgoing says whether it's one we offered.
+    Cancelled {
+        transfer_id: TransferId,
+        outgoing: bool,
+    },
+}
@@ -0,0 +1,90 @@
+// The Host keeps track of the keys and mouse buttons each Client is holding down. If the Client
+// goes away, loses the controls or can no longer reach a display while something is held, the
+// Host would otherwise be left with e.g. a stuck Ctrl or a drag that never ends. Releases are
+// handed out as the same RvdHostInform events input arrives as, so they reach the native API the
+// same way.
+
+use crate::rvd::{MouseInputEvent, RvdHostInform};
+use common::messages::rvd::{ButtonsMask, DisplayId, KeyInput};
+use std::collections::BTreeMap;
+
+struct HeldButtons {
+    buttons: ButtonsMask,
+    // Where the pointer was last put, the buttons are let go there
+    x_location: u16,
+    y_location: u16,
+}
+
+#[derive(Default)]
+pub struct HeldInput {
+    keys: Vec<u32>,
+    buttons: BTreeMap<DisplayId, HeldButtons>,
+}
+
+impl HeldInput {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records keyboard input that was passed on to the native API
+    pub fn key_input(&mut self, input: &KeyInput) {
+        if !input.down {
+            self.keys.retain(|&key| key != input.key);
+        } else if !self.keys.contains(&input.key) {
+            self.keys.push(input.key);
+        }
+    }
+
+    /// Records mouse input that was passed on to the native API
+    pub fn mouse_input(&mut self, event: &MouseInputEvent) {
+        let held = self.buttons.entry(event.display_id).or_insert(HeldButtons {
+            buttons: ButtonsMask::empty(),
+            x_location: 0,
+            y_location: 0,
+        });
+        held.buttons =
+            (held.buttons - event.button_delta) | (event.button_state & event.button_delta);
+        held.x_location = event.x_location;
+        held.y_location = event.y_location;
+        if held.buttons.is_empty() {
+            self.buttons.remove(&event.display_id);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty() && self.buttons.is_empty()
+    }
+
+    /// Lets go of every key, most recently pressed first
+    pub fn release_keys(&mut self) -> Vec<RvdHostInform> {
+        self.keys
+            .drain(..)
+            .rev()
+            .map(|key| RvdHostInform::KeyboardInput(KeyInput { down: false, key }))
+            .collect()
+    }
+
+    /// Lets go of the buttons held on the display
+    pub fn release_buttons(&mut self, display_id: DisplayId) -> Option<RvdHostInform> {
+        self.buttons.remove(&display_id).map(|held| {
+            RvdHostInform::MouseInput(MouseInputEvent {
+                display_id,
+                x_location: held.x_location,
+                y_location: held.y_location,
+                button_delta: held.buttons,
+                button_state: ButtonsMask::empty(),
+            })
+        })
+    }
+
+    /// Lets go of everything, buttons first so a drag ends before its modifiers do
+    pub fn release_all(&mut self) -> Vec<RvdHostInform> {
+        let displays: Vec<_> = self.buttons.keys().copied().collect();
+        let mut releases: Vec<_> = displays
+            .into_iter()
+            .filter_map(|display_id| self.release_buttons(display_id))
+            .collect();
+        releases.extend(self.release_keys());
+        releases
+    }
+}
@@ -3,6 +3,14 @@ use crate::{
     helpers::crypto::random_bytes_const,
     rvd::{
         ClientState,
+        ClipboardTransferError,
+        ClipboardTransfers,
+        CursorImage,
+        CursorSender,
+        FileTransferError,
+        FileTransferInform,
+        FileTransfers,
+        HeldInput,
         PermissionError::{
             ClipboardRead,
             ClipboardWrite,
@@ -22,7 +30,12 @@ use common::{
         rvd::{
             AccessMask,
             ButtonsMask,
+            ClipboardMeta,
+            ClipboardNotification,
             ClipboardType,
+            ControlGrant,
+            ControlRevoke,
+            DisplayGeometry,
             DisplayId,
             DisplayShare,
             DisplayUnshare,
@@ -33,6 +46,7 @@ use common::{
             PermissionsUpdate,
             ProtocolVersion,
             ProtocolVersionResponse,
+            ReceiverReport,
             RvdMessage,
             UnreliableAuthFinal,
             UnreliableAuthInitial,
@@ -45,9 +59,14 @@ use std::{
     borrow::Cow,
     collections::HashMap,
     fmt::Debug,
+    mem,
     time::{Duration, Instant},
 };
 
+// How long after a display stops being controllable input for it is dropped rather than treated as
+// an error, the Client may have sent it before it heard
+const CONTROL_LOSS_GRACE: Duration = Duration::from_secs(5);
+
 enum ShareTime {
     WaitingAck(Instant),
     Acked,
@@ -56,6 +75,8 @@ enum ShareTime {
 struct SharedDisplay {
     share_time: ShareTime,
     access_mask: AccessMask,
+    name: String,
+    geometry: Option<DisplayGeometry>,
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -66,11 +87,21 @@ pub enum HostState {
     Ready,
 }
 
-// While the spec allows for each individual display to be controllable or not we only support all are controllable or none are
 pub struct RvdHostHandler {
     state: HostState,
     permissions: PermissionMask,
     shared_displays: HashMap<DisplayId, SharedDisplay>,
+    // Whether this Client holds the controls. A Host with a single Client leaves it holding them,
+    // a Host with several decides who holds them using grant_control and revoke_control.
+    control: bool,
+    file_transfers: FileTransfers,
+    clipboard_transfers: ClipboardTransfers,
+    cursor: CursorSender,
+    // What the Client is holding down, and the releases for it that haven't been taken yet
+    held: HeldInput,
+    releases: Vec<RvdHostInform>,
+    // When each display last stopped being controllable
+    control_lost: HashMap<DisplayId, Instant>,
 }
 
 impl Default for RvdHostHandler {
@@ -85,6 +116,57 @@ impl RvdHostHandler {
             state: HostState::ProtocolVersion,
             permissions: PermissionMask::empty(),
             shared_displays: HashMap::new(),
+            control: true,
+            file_transfers: FileTransfers::new(),
+            clipboard_transfers: ClipboardTransfers::new(),
+            cursor: CursorSender::new(),
+            held: HeldInput::new(),
+            releases: Vec::new(),
+            control_lost: HashMap::new(),
+        }
+    }
+
+    pub fn has_control(&self) -> bool {
+        self.control
+    }
+
+    /// Hands the controls to this Client. The caller is responsible for revoking them from
+    /// whoever held them before.
+    pub fn grant_control(&mut self) -> RvdMessage<'static> {
+        self.control = true;
+        RvdMessage::ControlGrant(ControlGrant {})
+    }
+
+    pub fn revoke_control(&mut self) -> RvdMessage<'static> {
+        self.control = false;
+        self.release_input();
+        RvdMessage::ControlRevoke(ControlRevoke {})
+    }
+
+    /// Lets go of everything the Client is holding down, for when it goes away. The releases are
+    /// collected with take_input_releases.
+    pub fn release_input(&mut self) {
+        let releases = self.held.release_all();
+        self.releases.extend(releases);
+    }
+
+    /// Takes the input the Host has to let go of because the Client can no longer hold it, these
+    /// should be handled like input from the Client
+    pub fn take_input_releases(&mut self) -> Vec<RvdHostInform> {
+        mem::take(&mut self.releases)
+    }
+
+    // Lets go of what's held on a display that's no longer shared, and of the keys when no
+    // display is controllable anymore
+    fn display_removed(&mut self, display_id: DisplayId) {
+        self.releases.extend(self.held.release_buttons(display_id));
+        if !self
+            .shared_displays
+            .values()
+            .any(|s| s.access_mask.contains(AccessMask::CONTROLLABLE))
+        {
+            let releases = self.held.release_keys();
+            self.releases.extend(releases);
         }
     }
 
@@ -95,6 +177,21 @@ impl RvdHostHandler {
         })
     }
 
+    fn any_controllable(&self) -> bool {
+        self.shared_displays
+            .values()
+            .any(|shared| shared.access_mask.contains(AccessMask::CONTROLLABLE))
+    }
+
+    // Whether the display, or any display if None, stopped being controllable within
+    // CONTROL_LOSS_GRACE
+    fn control_lost_recently(&self, display_id: Option<DisplayId>) -> bool {
+        self.control_lost
+            .iter()
+            .filter(|&(&id, _)| display_id.is_none() || display_id == Some(id))
+            .any(|(_, lost)| lost.elapsed() < CONTROL_LOSS_GRACE)
+    }
+
     fn find_unused_display_id(&self) -> Option<DisplayId> {
         for i in 0 .. u8::MAX {
             if !self.shared_displays.contains_key(&i) {
@@ -112,46 +209,175 @@ impl RvdHostHandler {
         let display_id = self
             .find_unused_display_id()
             .ok_or(RvdHostError::RanOutOfDisplayIds)?;
+        let msg = self.share_display_with_id(display_id, name, access)?;
+        Ok((display_id, msg))
+    }
 
-        let msg = RvdMessage::DisplayShare(DisplayShare {
-            display_id,
-            name,
-            access,
-        });
+    /// Shares a display using an ID chosen by the caller, for when the same displays are shared
+    /// with more than one Client and they should all agree on the IDs.
+    pub fn share_display_with_id(
+        &mut self,
+        display_id: DisplayId,
+        name: String,
+        access: AccessMask,
+    ) -> Result<RvdMessage<'static>, RvdHostError> {
+        if self.shared_displays.contains_key(&display_id) {
+            return Err(RvdHostError::DisplayIdInUse(display_id));
+        }
 
         self.shared_displays.insert(display_id, SharedDisplay {
             share_time: ShareTime::WaitingAck(Instant::now()),
             access_mask: access,
+            name: name.clone(),
+            geometry: None,
         });
 
-        Ok((display_id, msg))
+        Ok(RvdMessage::DisplayShare(DisplayShare {
+            display_id,
+            name,
+            access,
+        }))
     }
 
     pub fn unshare_display(
         &mut self,
         display_id: DisplayId,
     ) -> Result<RvdMessage<'static>, RvdHostError> {
-        if self.shared_displays.remove(&display_id).is_none() {
-            return Err(RvdHostError::DisplayNotFound(display_id));
+        let shared = self
+            .shared_displays
+            .remove(&display_id)
+            .ok_or(RvdHostError::DisplayNotFound(display_id))?;
+        if shared.access_mask.contains(AccessMask::CONTROLLABLE) {
+            self.control_lost.insert(display_id, Instant::now());
         }
+        self.display_removed(display_id);
         Ok(RvdMessage::DisplayUnshare(DisplayUnshare { display_id }))
     }
 
+    /// Changes the AccessMask of a shared display. A display can't be shared twice so it's
+    /// unshared and shared again under the same ID, nothing is sent if the access is unchanged.
+    pub fn set_display_access(
+        &mut self,
+        display_id: DisplayId,
+        access: AccessMask,
+    ) -> Result<Vec<RvdMessage<'static>>, RvdHostError> {
+        let shared = self
+            .shared_displays
+            .get(&display_id)
+            .ok_or(RvdHostError::DisplayNotFound(display_id))?;
+        if shared.access_mask == access {
+            return Ok(Vec::new());
+        }
+
+        let name = shared.name.clone();
+        let geometry = shared.geometry.clone();
+        let unshare = self.unshare_display(display_id)?;
+        let share = self.share_display_with_id(display_id, name, access)?;
+        let mut messages = vec![unshare, share];
+        if let Some(geometry) = geometry {
+            messages.extend(self.set_display_geometry(geometry)?);
+        }
+        Ok(messages)
+    }
+
+    /// Tells the Client the size of a shared display, nothing is sent if it hasn't changed.
+    pub fn set_display_geometry(
+        &mut self,
+        geometry: DisplayGeometry,
+    ) -> Result<Option<RvdMessage<'static>>, RvdHostError> {
+        let shared = self
+            .shared_displays
+            .get_mut(&geometry.display_id)
+            .ok_or(RvdHostError::DisplayNotFound(geometry.display_id))?;
+        if shared.geometry.as_ref() == Some(&geometry) {
+            return Ok(None);
+        }
+        shared.geometry = Some(geometry.clone());
+        Ok(Some(RvdMessage::DisplayGeometry(geometry)))
+    }
+
     /// This should be called every so often, at minimum probably every second.
     pub fn check_expired_shares(&mut self) -> Vec<RvdMessage<'static>> {
-        let mut unshares = Vec::new();
+        let mut expired = Vec::new();
         self.shared_displays
             .retain(|&display_id, share| match share.share_time {
                 ShareTime::WaitingAck(start) =>
                     if start.elapsed() > Duration::from_secs(5) {
-                        unshares.push(RvdMessage::DisplayUnshare(DisplayUnshare { display_id }));
+                        expired.push(display_id);
                         false
                     } else {
                         true
                     },
                 _ => true,
             });
-        unshares
+        expired
+            .into_iter()
+            .map(|display_id| {
+                self.display_removed(display_id);
+                RvdMessage::DisplayUnshare(DisplayUnshare { display_id })
+            })
+            .collect()
+    }
+
+    /// Tells the Client about new content on the local clipboard, large content is sent in chunks
+    /// as the Client acknowledges them. Returns None if the Client isn't allowed to read the
+    /// clipboard or the content is larger than it accepts.
+    pub fn clipboard_changed(
+        &mut self,
+        clipboard_type: ClipboardType,
+        content: Vec<u8>,
+    ) -> Option<RvdMessage<'static>> {
+        if !self.permissions.contains(PermissionMask::CLIPBOARD_READ) {
+            return None;
+        }
+        self.clipboard_transfers.send(
+            ClipboardMeta {
+                clipboard_type,
+                content_request: true,
+            },
+            content,
+        )
+    }
+
+    /// Answers a ClipboardRequest, content is None if the clipboard doesn't hold the requested type.
+    /// Like other clipboard content it's sent in chunks if it's large. Returns None if the Client
+    /// is no longer allowed to read the clipboard or the content is larger than it accepts.
+    pub fn clipboard_reply(
+        &mut self,
+        clipboard_type: ClipboardType,
+        is_content: bool,
+        content: Option<Vec<u8>>,
+    ) -> Option<RvdMessage<'static>> {
+        if !self.permissions.contains(PermissionMask::CLIPBOARD_READ) {
+            return None;
+        }
+        let info = ClipboardMeta {
+            clipboard_type,
+            content_request: is_content,
+        };
+        match content {
+            Some(content) if is_content => self.clipboard_transfers.send(info, content),
+            content => Some(RvdMessage::ClipboardNotification(ClipboardNotification {
+                info,
+                type_exists: content.is_some(),
+                content: None,
+            })),
+        }
+    }
+
+    /// Tells the Client what the cursor looks like. Returns None if it already knows, or the
+    /// handshake isn't complete yet.
+    pub fn cursor_changed(&mut self, cursor: &CursorImage) -> Option<RvdMessage<'static>> {
+        if !matches!(self.state, HostState::Ready) {
+            return None;
+        }
+        self.cursor.cursor_changed(cursor)
+    }
+
+    /// Called once the message from cursor_changed has been sent, later messages may refer to the
+    /// cursor from then on
+    pub fn cursor_sent(&mut self, cursor: &CursorImage) {
+        self.cursor.cursor_sent(cursor);
     }
 
     pub fn frame_update(display_id: DisplayId, data: &[u8]) -> RvdMessage<'_> {
@@ -202,6 +428,7 @@ impl RvdHostHandler {
                         self.state = HostState::Ready;
                         events.push(InformEvent::RvdHostInform(RvdHostInform::HandshakeComplete));
                         write.push(RvdMessage::HandshakeComplete(HandshakeComplete {}));
+                        write.push(self.clipboard_transfers.announce_limit());
                     } else {
                         return Err(RvdHostError::UnreliableAuthFailed);
                     }
@@ -218,6 +445,32 @@ impl RvdHostHandler {
                     shared.share_time = ShareTime::Acked;
                     Ok(())
                 }
+                RvdMessage::PictureLoss(msg) => {
+                    // The display may have been unshared while this was on its way
+                    if self.shared_displays.contains_key(&msg.display_id) {
+                        events.push(InformEvent::RvdHostInform(RvdHostInform::PictureLoss(
+                            msg.display_id,
+                        )));
+                    }
+                    Ok(())
+                }
+                RvdMessage::FrameNack(msg) => {
+                    if self.shared_displays.contains_key(&msg.display_id) {
+                        events.push(InformEvent::RvdHostInform(RvdHostInform::FrameNack(
+                            msg.display_id,
+                            msg.sequences,
+                        )));
+                    }
+                    Ok(())
+                }
+                RvdMessage::ReceiverReport(msg) => {
+                    if self.shared_displays.contains_key(&msg.display_id) {
+                        events.push(InformEvent::RvdHostInform(RvdHostInform::ReceiverReport(
+                            msg,
+                        )));
+                    }
+                    Ok(())
+                }
                 RvdMessage::MouseInput(msg) => {
                     let shared = match self.shared_displays.get(&msg.display_id) {
                         None => return Ok(()),
@@ -225,31 +478,49 @@ impl RvdHostHandler {
                     };
 
                     if !shared.access_mask.contains(AccessMask::CONTROLLABLE) {
+                        // The display may have been made view only after the Client sent this, so
+                        // it's dropped like input from a Client without the controls
+                        if self.control_lost_recently(Some(msg.display_id)) {
+                            events.push(InformEvent::RvdHostInform(RvdHostInform::InputRejected));
+                            return Ok(());
+                        }
                         return Err(RvdHostError::PermissionsError(MouseInput));
                     }
 
-                    events.push(InformEvent::RvdHostInform(RvdHostInform::MouseInput(
-                        MouseInputEvent {
-                            display_id: msg.display_id,
-                            x_location: msg.x_location,
-                            y_location: msg.y_location,
-                            button_delta: msg.buttons_delta,
-                            button_state: msg.buttons_state,
-                        },
-                    )));
+                    if !self.control {
+                        events.push(InformEvent::RvdHostInform(RvdHostInform::InputRejected));
+                        return Ok(());
+                    }
+
+                    let event = MouseInputEvent {
+                        display_id: msg.display_id,
+                        x_location: msg.x_location,
+                        y_location: msg.y_location,
+                        button_delta: msg.buttons_delta,
+                        button_state: msg.buttons_state,
+                    };
+                    self.held.mouse_input(&event);
+                    events.push(InformEvent::RvdHostInform(RvdHostInform::MouseInput(event)));
 
                     Ok(())
                 }
                 RvdMessage::KeyInput(msg) => {
-                    // TODO this is dumb, i'm losing brain cells here
-                    if !self
-                        .shared_displays
-                        .iter()
-                        .any(|(_, s)| s.access_mask.contains(AccessMask::CONTROLLABLE))
-                    {
+                    // Key presses aren't aimed at a display, so they're allowed as long as any of
+                    // them is controllable
+                    if !self.any_controllable() {
+                        if self.control_lost_recently(None) {
+                            events.push(InformEvent::RvdHostInform(RvdHostInform::InputRejected));
+                            return Ok(());
+                        }
                         return Err(RvdHostError::PermissionsError(KeyInputPermission));
                     }
 
+                    if !self.control {
+                        events.push(InformEvent::RvdHostInform(RvdHostInform::InputRejected));
+                        return Ok(());
+                    }
+
+                    self.held.key_input(&msg);
                     events.push(InformEvent::RvdHostInform(RvdHostInform::KeyboardInput(
                         KeyInput {
                             down: msg.down,
@@ -258,6 +529,49 @@ impl RvdHostHandler {
                     )));
                     Ok(())
                 }
+                RvdMessage::TextInput(msg) => {
+                    // Typing is keyboard input, so it's allowed under the same conditions
+                    if !self.any_controllable() {
+                        if self.control_lost_recently(None) {
+                            events.push(InformEvent::RvdHostInform(RvdHostInform::InputRejected));
+                            return Ok(());
+                        }
+                        return Err(RvdHostError::PermissionsError(KeyInputPermission));
+                    }
+
+                    if !self.control {
+                        events.push(InformEvent::RvdHostInform(RvdHostInform::InputRejected));
+                        return Ok(());
+                    }
+
+                    events.push(InformEvent::RvdHostInform(RvdHostInform::TextInput(
+                        msg.text,
+                    )));
+                    Ok(())
+                }
+                RvdMessage::ControlRequest(_) => {
+                    if self.control {
+                        // They already hold the controls, they may have missed the grant
+                        write.push(RvdMessage::ControlGrant(ControlGrant {}));
+                    } else {
+                        events.push(InformEvent::RvdHostInform(RvdHostInform::ControlRequest));
+                    }
+                    Ok(())
+                }
+                msg @ (RvdMessage::FileOffer(_)
+                | RvdMessage::FileAccept(_)
+                | RvdMessage::FileReject(_)
+                | RvdMessage::FileChunk(_)
+                | RvdMessage::FileAck(_)
+                | RvdMessage::FileCancel(_)
+                | RvdMessage::FileComplete(_)) => {
+                    if let Some(inform) = self.file_transfers.handle(msg, write)? {
+                        events.push(InformEvent::RvdHostInform(RvdHostInform::FileTransfer(
+                            inform,
+                        )));
+                    }
+                    Ok(())
+                }
                 RvdMessage::ClipboardRequest(msg) => {
                     if !self.permissions.contains(PermissionMask::CLIPBOARD_READ) {
                         return Err(RvdHostError::PermissionsError(ClipboardRead));
@@ -280,6 +594,26 @@ impl RvdHostHandler {
                     }
                     Ok(())
                 }
+                msg @ (RvdMessage::ClipboardLimit(_)
+                | RvdMessage::ClipboardStart(_)
+                | RvdMessage::ClipboardChunk(_)
+                | RvdMessage::ClipboardAck(_)) => {
+                    if matches!(
+                        msg,
+                        RvdMessage::ClipboardStart(_) | RvdMessage::ClipboardChunk(_)
+                    ) && !self.permissions.contains(PermissionMask::CLIPBOARD_WRITE)
+                    {
+                        return Err(RvdHostError::PermissionsError(ClipboardWrite));
+                    }
+                    if let Some((content, clipboard_type)) =
+                        self.clipboard_transfers.handle(msg, write)?
+                    {
+                        events.push(InformEvent::RvdHostInform(
+                            RvdHostInform::ClipboardNotification(content, clipboard_type),
+                        ));
+                    }
+                    Ok(())
+                }
                 _ => Err(RvdHostError::WrongMessageForState(debug(&msg), self.state)),
             },
         }
@@ -295,6 +629,10 @@ impl RvdHandlerTrait for RvdHostHandler {
     ) -> Result<(), RvdError> {
         Ok(self._handle(msg, write, events)?)
     }
+
+    fn file_transfers(&mut self) -> &mut FileTransfers {
+        &mut self.file_transfers
+    }
 }
 
 #[derive(Debug)]
@@ -313,10 +651,16 @@ pub enum RvdHostError {
     PermissionsError(PermissionError),
     #[error("display not found: id number {0}")]
     DisplayNotFound(DisplayId),
+    #[error("display id {0} is already in use")]
+    DisplayIdInUse(DisplayId),
     #[error("ran out of DisplayIDs. Are you sharing 256 displays?")]
     RanOutOfDisplayIds,
     #[error("unreliable auth failed")]
     UnreliableAuthFailed,
+    #[error("file transfer error: {0}")]
+    FileTransfer(#[from] FileTransferError),
+    #[error("clipboard transfer error: {0}")]
+    ClipboardTransfer(#[from] ClipboardTransferError),
 }
 
 #[derive(Debug)]
@@ -336,7 +680,21 @@ pub enum RvdHostInform {
 
     MouseInput(MouseInputEvent),
     KeyboardInput(KeyInput),
+    TextInput(String),
+    /// Input arrived from a Client that doesn't hold the controls, or for a display that stopped
+    /// being controllable while it was on its way, and was dropped
+    InputRejected,
+    /// A Client that doesn't hold the controls asked for them
+    ControlRequest,
+    /// The Client lost FrameData for this display, the next frame sent should be a keyframe
+    PictureLoss(DisplayId),
+    /// The Client is missing these FrameData packets for a display, by RTP sequence number
+    FrameNack(DisplayId, Vec<u16>),
+    /// How the FrameData for a display is reaching the Client
+    ReceiverReport(ReceiverReport),
 
     ClipboardRequest(bool, ClipboardType),
     ClipboardNotification(Vec<u8>, ClipboardType),
+
+    FileTransfer(FileTransferInform),
 }
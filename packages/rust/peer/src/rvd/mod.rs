@@ -1,11 +1,19 @@
 mod client;
+mod clipboard_transfer;
+mod cursor;
+mod file_transfer;
+mod held_input;
 mod host;
 
 pub use client::*;
+pub use clipboard_transfer::*;
+pub use cursor::*;
+pub use file_transfer::*;
+pub use held_input::*;
 pub use host::*;
 
 use crate::InformEvent;
-use common::messages::rvd::{ClipboardMeta, ClipboardNotification, ClipboardType, RvdMessage};
+use common::messages::rvd::RvdMessage;
 
 // most of RVD messages result in purely external changes. As such, RVD emits events for almost all messages. It is the job of the caller to respond to these events
 pub trait RvdHandlerTrait {
@@ -16,21 +24,7 @@ pub trait RvdHandlerTrait {
         events: &mut Vec<InformEvent>,
     ) -> Result<(), RvdError>;
 
-    fn clipboard_data(
-        data: Option<Vec<u8>>, // on input Option refers to whether the content exists
-        is_content: bool,
-        clipboard_type: ClipboardType,
-    ) -> RvdMessage<'static> {
-        RvdMessage::ClipboardNotification(ClipboardNotification {
-            info: ClipboardMeta {
-                clipboard_type,
-                content_request: is_content,
-            },
-            type_exists: data.is_some(),
-            // but here it it's only Some if (a) the type exists AND (b) it is a content request
-            content: if is_content { data } else { None },
-        })
-    }
+    fn file_transfers(&mut self) -> &mut FileTransfers;
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -39,4 +33,6 @@ pub enum RvdError {
     Host(#[from] RvdHostError),
     #[error("client error: {0}")]
     Client(#[from] RvdClientError),
+    #[error("file transfer error: {0}")]
+    FileTransfer(#[from] FileTransferError),
 }
@@ -59,7 +59,6 @@ impl SelHandler {
     /// Warning: This resets unreliable
     pub fn derive_unreliable(&mut self, session_id: &[u8], peer_id: &[u8], peer_key: &[u8]) {
         let (send_key, receive_key) = kdf2(hash!(session_id, peer_id, peer_key));
-        // TODO Security: Look into zeroing out the data here
         self.unreliable = Some(CipherUnreliablePeer::new(
             send_key.to_vec(),
             receive_key.to_vec(),
@@ -178,7 +178,7 @@ impl SvscHandler {
                     Ok(None)
                 }
 
-                SvscMessage::SessionEnd(_msg) => {
+                SvscMessage::SessionEnd(_) | SvscMessage::SessionEndNotification(_) => {
                     // TODO should we error if session doesn't exist
                     event.push(InformEvent::SvscInform(SvscInform::SessionEnd));
                     self.session = None;
@@ -1,9 +1,31 @@
+use crate::hash;
+use public_key_client::PublicKeyAuthClient;
+use public_key_host::PublicKeyAuthHost;
 use ring::agreement::PublicKey;
 use srp_client::SrpAuthClient;
 use srp_host::SrpAuthHost;
 
+pub mod public_key_client;
+pub mod public_key_host;
 pub mod srp_client;
 pub mod srp_host;
+mod trusted_keys;
+
+pub use trusted_keys::*;
+
+pub(crate) const HOST_LABEL: &[u8] = b"screenview wpskka public key host";
+pub(crate) const CLIENT_LABEL: &[u8] = b"screenview wpskka public key client";
+
+/// The data each side signs with its identity key. Including both ephemeral keys binds the
+/// signature to this session, the label prevents one side's signature being reflected back as the
+/// other's.
+pub(crate) fn public_key_transcript(
+    label: &[u8],
+    host_public_key: &[u8],
+    client_public_key: &[u8],
+) -> [u8; 32] {
+    *hash!(label, host_public_key, client_public_key)
+}
 
 #[derive(Debug)]
 pub enum AuthScheme<const N: usize> {
@@ -13,4 +35,6 @@ pub enum AuthScheme<const N: usize> {
     },
     SrpAuthClient(SrpAuthClient<N>),
     SrpAuthHost(SrpAuthHost<N>),
+    PublicKeyAuthClient(PublicKeyAuthClient<N>),
+    PublicKeyAuthHost(PublicKeyAuthHost<N>),
 }
@@ -0,0 +1,120 @@
+use crate::{
+    debug,
+    helpers::crypto::{verify_signature, IdentityKeyPair},
+    wpskka::auth::{public_key_transcript, TrustedKeys, CLIENT_LABEL, HOST_LABEL},
+};
+use common::messages::auth::public_key::{ClientIdentity, PublicKeyMessage};
+use ring::agreement::PublicKey;
+
+#[derive(Debug, Copy, Clone)]
+pub enum State {
+    PreHostIdentity,
+    Failed,
+    Done,
+}
+
+// The Client verifies the Host's identity before revealing its own. Once the Host is verified the
+// Client waits for a successful AuthResult which indicates the Host has verified the Client.
+#[derive(Debug)]
+pub struct PublicKeyAuthClient<const N: usize> {
+    state: State,
+    host_public_key: [u8; N],
+    our_public_key: PublicKey,
+    host_identity: Option<[u8; 32]>,
+}
+
+impl<const N: usize> PublicKeyAuthClient<N> {
+    pub fn new(our_public_key: PublicKey, host_public_key: [u8; N]) -> Self {
+        Self {
+            state: State::PreHostIdentity,
+            host_public_key,
+            our_public_key,
+            host_identity: None,
+        }
+    }
+
+    pub fn finish(self) -> (PublicKey, [u8; N]) {
+        (self.our_public_key, self.host_public_key)
+    }
+
+    /// Whether the Host has proven its identity. This does not mean the Host has accepted us.
+    pub fn is_authenticated(&self) -> bool {
+        matches!(self.state, State::Done)
+    }
+
+    /// The identity key the Host authenticated with
+    pub fn host_identity(&self) -> Option<&[u8; 32]> {
+        self.host_identity.as_ref()
+    }
+
+    pub fn handle(
+        &mut self,
+        msg: PublicKeyMessage,
+        identity: Option<&IdentityKeyPair>,
+        trusted_hosts: &TrustedKeys,
+    ) -> Result<PublicKeyMessage, PublicKeyClientError> {
+        match self.state {
+            State::PreHostIdentity => match msg {
+                PublicKeyMessage::HostIdentity(msg) => {
+                    let transcript = public_key_transcript(
+                        HOST_LABEL,
+                        &self.host_public_key,
+                        self.our_public_key.as_ref(),
+                    );
+
+                    if !verify_signature(&msg.public_key, &transcript, &msg.signature) {
+                        self.state = State::Failed;
+                        return Err(PublicKeyClientError::AuthFailed);
+                    }
+
+                    if !trusted_hosts.contains(&msg.public_key) {
+                        self.state = State::Failed;
+                        return Err(PublicKeyClientError::UntrustedHost(msg.public_key));
+                    }
+
+                    let identity = match identity {
+                        Some(identity) => identity,
+                        None => {
+                            self.state = State::Failed;
+                            return Err(PublicKeyClientError::NoIdentity);
+                        }
+                    };
+
+                    let transcript = public_key_transcript(
+                        CLIENT_LABEL,
+                        &self.host_public_key,
+                        self.our_public_key.as_ref(),
+                    );
+
+                    self.state = State::Done;
+                    self.host_identity = Some(msg.public_key);
+
+                    Ok(PublicKeyMessage::ClientIdentity(ClientIdentity {
+                        public_key: identity.public_key(),
+                        signature: identity.sign(&transcript),
+                    }))
+                }
+                _ => Err(PublicKeyClientError::WrongMessageForState(
+                    debug(&msg),
+                    self.state,
+                )),
+            },
+            _ => Err(PublicKeyClientError::WrongMessageForState(
+                debug(&msg),
+                self.state,
+            )),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum PublicKeyClientError {
+    #[error("auth failed")]
+    AuthFailed,
+    #[error("host identity {0:?} is not trusted")]
+    UntrustedHost([u8; 32]),
+    #[error("no identity key has been set")]
+    NoIdentity,
+    #[error("invalid message {0} for state {1:?}")]
+    WrongMessageForState(String, State),
+}
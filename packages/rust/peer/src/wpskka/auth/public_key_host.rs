@@ -0,0 +1,115 @@
+use crate::{
+    debug,
+    helpers::crypto::{verify_signature, IdentityKeyPair},
+    wpskka::auth::{public_key_transcript, TrustedKeys, CLIENT_LABEL, HOST_LABEL},
+};
+use common::messages::auth::public_key::{HostIdentity, PublicKeyMessage};
+use ring::agreement::PublicKey;
+
+#[derive(Debug, Copy, Clone)]
+pub enum State {
+    PreHostIdentity,
+    WaitingForClientIdentity,
+    Done,
+}
+
+// Authenticates the Client's ephemeral key with the Client's identity key. Unlike SRP the Host
+// doesn't need any input from the Client to prove its own identity, so it does so up front.
+#[derive(Debug)]
+pub struct PublicKeyAuthHost<const N: usize> {
+    state: State,
+    authenticated: bool,
+    client_public_key: [u8; N],
+    our_public_key: PublicKey,
+    client_identity: Option<[u8; 32]>,
+}
+
+impl<const N: usize> PublicKeyAuthHost<N> {
+    pub fn new(our_public_key: PublicKey, client_public_key: [u8; N]) -> Self {
+        Self {
+            state: State::PreHostIdentity,
+            authenticated: false,
+            client_public_key,
+            our_public_key,
+            client_identity: None,
+        }
+    }
+
+    pub fn finish(self) -> (PublicKey, [u8; N]) {
+        (self.our_public_key, self.client_public_key)
+    }
+
+    pub fn is_authenticated(&self) -> bool {
+        self.authenticated
+    }
+
+    /// The identity key the Client authenticated with
+    pub fn client_identity(&self) -> Option<&[u8; 32]> {
+        self.client_identity.as_ref()
+    }
+
+    pub fn init(&mut self, identity: &IdentityKeyPair) -> PublicKeyMessage {
+        let transcript = public_key_transcript(
+            HOST_LABEL,
+            self.our_public_key.as_ref(),
+            &self.client_public_key,
+        );
+
+        self.state = State::WaitingForClientIdentity;
+
+        PublicKeyMessage::HostIdentity(HostIdentity {
+            public_key: identity.public_key(),
+            signature: identity.sign(&transcript),
+        })
+    }
+
+    pub fn handle(
+        &mut self,
+        msg: PublicKeyMessage,
+        authorized_clients: &TrustedKeys,
+    ) -> Result<(), PublicKeyHostError> {
+        match self.state {
+            State::WaitingForClientIdentity => match msg {
+                PublicKeyMessage::ClientIdentity(msg) => {
+                    let transcript = public_key_transcript(
+                        CLIENT_LABEL,
+                        self.our_public_key.as_ref(),
+                        &self.client_public_key,
+                    );
+
+                    if !verify_signature(&msg.public_key, &transcript, &msg.signature) {
+                        return Err(PublicKeyHostError::AuthFailed);
+                    }
+
+                    if !authorized_clients.contains(&msg.public_key) {
+                        return Err(PublicKeyHostError::UnauthorizedClient(msg.public_key));
+                    }
+
+                    self.state = State::Done;
+                    self.authenticated = true;
+                    self.client_identity = Some(msg.public_key);
+
+                    Ok(())
+                }
+                _ => Err(PublicKeyHostError::WrongMessageForState(
+                    debug(&msg),
+                    self.state,
+                )),
+            },
+            _ => Err(PublicKeyHostError::WrongMessageForState(
+                debug(&msg),
+                self.state,
+            )),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum PublicKeyHostError {
+    #[error("auth failed")]
+    AuthFailed,
+    #[error("client identity {0:?} is not authorized")]
+    UnauthorizedClient([u8; 32]),
+    #[error("invalid message {0} for state {1:?}")]
+    WrongMessageForState(String, State),
+}
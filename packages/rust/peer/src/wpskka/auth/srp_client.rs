@@ -3,6 +3,7 @@ use crate::{
     helpers::{
         crypto::{hmac, hmac_verify, kdf1, random_srp_private_value},
         left_pad::left_pad,
+        secret::SecretKey,
     },
     wpskka::WpskkaClientInform,
 };
@@ -30,7 +31,7 @@ pub struct SrpAuthClient<const N: usize> {
     host_public_key: [u8; N],
     our_public_key: PublicKey,
     host_hello: Option<Box<HostHello>>,
-    hmac_key: Option<Box<[u8; 32]>>,
+    hmac_key: Option<SecretKey<32>>,
 }
 
 impl<const N: usize> SrpAuthClient<N> {
@@ -66,10 +67,10 @@ impl<const N: usize> SrpAuthClient<N> {
 
         let srp_key_kdf = kdf1(srp_key);
 
-        let mac = hmac(&srp_key_kdf, self.our_public_key.as_ref());
+        let mac = hmac(&srp_key_kdf[..], self.our_public_key.as_ref());
 
         // save some stuff we'll need soon
-        self.hmac_key = Some(Box::new(srp_key_kdf));
+        self.hmac_key = Some(srp_key_kdf);
         self.state = State::PreVerify;
 
 
@@ -100,7 +101,7 @@ impl<const N: usize> SrpAuthClient<N> {
                 SrpMessage::HostVerify(msg) => {
                     let hmac_key = self.hmac_key.take().unwrap();
 
-                    if !hmac_verify(&*hmac_key, self.host_public_key.as_ref(), &msg.mac) {
+                    if !hmac_verify(&hmac_key[..], self.host_public_key.as_ref(), &msg.mac) {
                         self.state = State::Failed;
                         return Err(SrpClientError::AuthFailed);
                     }
@@ -3,6 +3,7 @@ use crate::{
     helpers::{
         crypto::{hmac, hmac_verify, kdf1, random_bytes, random_srp_private_value},
         left_pad::left_pad,
+        secret::SecretVec,
     },
     wpskka::auth::srp_host::State::Done,
 };
@@ -26,10 +27,10 @@ pub enum State {
 pub struct SrpAuthHost<const N: usize> {
     state: State,
     authenticated: bool,
-    verifier: Option<Vec<u8>>,
+    verifier: Option<SecretVec>,
     client_public_key: [u8; N],
     our_public_key: PublicKey,
-    b: Option<Vec<u8>>,
+    b: Option<SecretVec>,
 }
 
 impl<const N: usize> SrpAuthHost<N> {
@@ -57,7 +58,7 @@ impl<const N: usize> SrpAuthHost<N> {
         let client = SrpClient::<'static, HashAlgo>::new(SRP_PARAM);
         let username = random_bytes(16);
         let salt = random_bytes(16);
-        let verifier = client.compute_verifier(&username, password, &salt);
+        let verifier = SecretVec::new(client.compute_verifier(&username, password, &salt));
 
         let b = random_srp_private_value();
         let srp_server = SrpServer::<'static, HashAlgo>::new(SRP_PARAM);
@@ -91,11 +92,11 @@ impl<const N: usize> SrpAuthHost<N> {
                         kdf1(srp_verifier.key())
                     };
 
-                    if !hmac_verify(&srp_key_kdf, &self.client_public_key, &msg.mac) {
+                    if !hmac_verify(&srp_key_kdf[..], &self.client_public_key, &msg.mac) {
                         return Err(SrpHostError::AuthFailed);
                     }
 
-                    let mac = hmac(&srp_key_kdf, self.our_public_key.as_ref());
+                    let mac = hmac(&srp_key_kdf[..], self.our_public_key.as_ref());
 
                     self.state = Done;
                     self.authenticated = true;
@@ -0,0 +1,46 @@
+use std::collections::HashSet;
+
+/// A set of identity public keys. Clients use this to decide which Hosts they trust, Hosts use it
+/// to decide which Clients may connect with [`AuthSchemeType::PublicKey`].
+///
+/// [`AuthSchemeType::PublicKey`]: common::messages::wpskka::AuthSchemeType::PublicKey
+#[derive(Debug, Default, Clone)]
+pub struct TrustedKeys {
+    keys: HashSet<[u8; 32]>,
+}
+
+impl TrustedKeys {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns false if the key was already trusted
+    pub fn insert(&mut self, public_key: [u8; 32]) -> bool {
+        self.keys.insert(public_key)
+    }
+
+    /// Returns false if the key was not trusted
+    pub fn remove(&mut self, public_key: &[u8; 32]) -> bool {
+        self.keys.remove(public_key)
+    }
+
+    pub fn contains(&self, public_key: &[u8; 32]) -> bool {
+        self.keys.contains(public_key)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &[u8; 32]> {
+        self.keys.iter()
+    }
+}
+
+impl FromIterator<[u8; 32]> for TrustedKeys {
+    fn from_iter<T: IntoIterator<Item = [u8; 32]>>(iter: T) -> Self {
+        Self {
+            keys: iter.into_iter().collect(),
+        }
+    }
+}
@@ -3,27 +3,34 @@ use crate::{
     helpers::{
         cipher_reliable_peer::{CipherError, CipherReliablePeer},
         cipher_unreliable_peer::CipherUnreliablePeer,
-        crypto::{diffie_hellman, keypair, parse_foreign_public, KeyPair},
+        crypto::{diffie_hellman, keypair, parse_foreign_public, IdentityKeyPair, KeyPair},
     },
     wpskka::{
         auth::{
+            public_key_client::{PublicKeyAuthClient, PublicKeyClientError},
             srp_client::{SrpAuthClient, SrpClientError},
             AuthScheme,
+            TrustedKeys,
         },
+        known_hosts::{HostKey, KnownHost},
         KeyState,
+        RekeyPolicy,
+        RekeyState,
         WpskkaError,
         WpskkaHandlerTrait,
     },
     InformEvent,
 };
 use common::messages::{
-    auth::srp::SrpMessage,
+    auth::{public_key::PublicKeyMessage, srp::SrpMessage},
     wpskka::{
         AuthMessage,
         AuthResult,
         AuthScheme as AuthSchemeMessage,
         AuthSchemeType,
         KeyExchange,
+        Rekey,
+        RekeyAck,
         TransportDataMessageReliable,
         TransportDataMessageUnreliable,
         TryAuth,
@@ -34,6 +41,7 @@ use common::messages::{
 };
 use ring::agreement::EphemeralPrivateKey;
 use std::{
+    borrow::Cow,
     fmt::{Debug, Formatter},
     io::Cursor,
     mem,
@@ -54,6 +62,7 @@ pub enum State {
     Authenticated {
         reliable: CipherReliablePeer,
         unreliable: CipherUnreliablePeer,
+        rekey: RekeyState,
     },
 }
 
@@ -71,6 +80,19 @@ impl Debug for State {
 
 pub struct WpskkaClientHandler {
     state: State,
+
+    identity: Option<IdentityKeyPair>,
+    trusted_hosts: TrustedKeys,
+    known_host: Option<KnownHostState>,
+
+    rekey_policy: RekeyPolicy,
+}
+
+struct KnownHostState {
+    host: HostKey,
+    pinned: Option<[u8; 32]>,
+    // The key the Host presented on first use, it's only pinned once the Host accepts us too
+    unconfirmed: Option<[u8; 32]>,
 }
 
 impl Default for WpskkaClientHandler {
@@ -83,6 +105,10 @@ impl WpskkaClientHandler {
     pub fn new() -> Self {
         Self {
             state: State::KeyExchange,
+            identity: None,
+            trusted_hosts: TrustedKeys::new(),
+            known_host: None,
+            rekey_policy: RekeyPolicy::default(),
         }
     }
 
@@ -99,6 +125,8 @@ impl WpskkaClientHandler {
             WpskkaMessage::AuthResult(msg) => self.handle_auth_result(msg, write, events),
             WpskkaMessage::TransportDataMessageReliable(msg) =>
                 self.handle_message_reliable(msg, write, events),
+            WpskkaMessage::Rekey(msg) => self.handle_rekey(write, msg),
+            WpskkaMessage::RekeyAck(msg) => self.handle_rekey_ack(msg),
             WpskkaMessage::TransportDataMessageUnreliable(msg) =>
                 self.handle_message_unreliable(msg, write, events),
             _ => Err(WpskkaClientError::WrongMessageForState(
@@ -156,7 +184,6 @@ impl WpskkaClientHandler {
         let client_public_key = parse_foreign_public(&foreign_public_key);
         let (receive_reliable, send_reliable, receive_unreliable, send_unreliable) =
             diffie_hellman(key_pair.ephemeral_private_key, client_public_key).map_err(|_| ())?;
-        // TODO zero hella
         Ok((
             CipherReliablePeer::new(send_reliable.to_vec(), receive_reliable.to_vec()),
             CipherUnreliablePeer::new(send_unreliable.to_vec(), receive_unreliable.to_vec()),
@@ -173,6 +200,7 @@ impl WpskkaClientHandler {
                 self.state = State::Authenticated {
                     reliable,
                     unreliable,
+                    rekey: RekeyState::new(),
                 };
                 events.push(InformEvent::WpskkaClientInform(
                     WpskkaClientInform::AuthSuccessful,
@@ -250,10 +278,112 @@ impl WpskkaClientHandler {
         }
     }
 
+    fn handle_auth_message_public_key_client(
+        &mut self,
+        msg: AuthMessage,
+        write: &mut Vec<WpskkaMessage<'_>>,
+        events: &mut Vec<InformEvent>,
+        mut public_key_client: PublicKeyAuthClient<32>,
+        private_key: EphemeralPrivateKey,
+    ) -> Result<Option<Vec<u8>>, WpskkaClientError> {
+        let msg = match PublicKeyMessage::read(&mut Cursor::new(&msg.data)) {
+            Ok(msg) => msg,
+            Err(_) => {
+                self.state = State::IsAuthenticating {
+                    auth_scheme: AuthScheme::PublicKeyAuthClient(public_key_client),
+                    private_key,
+                };
+                return Err(WpskkaClientError::BadAuthSchemeMessage);
+            }
+        };
+
+        // With a known host we trust whatever key was pinned, or on first use whatever key the
+        // Host presents. The signature is still checked before we pin anything.
+        let first_use = match (&msg, &self.known_host) {
+            (PublicKeyMessage::HostIdentity(msg), Some(known_host))
+                if known_host.pinned.is_none() && !self.trusted_hosts.contains(&msg.public_key) =>
+                Some(msg.public_key),
+            _ => None,
+        };
+        let trusted_hosts = match self
+            .known_host
+            .as_ref()
+            .and_then(|known_host| known_host.pinned)
+            .or(first_use)
+        {
+            Some(public_key) => {
+                let mut trusted_hosts = self.trusted_hosts.clone();
+                trusted_hosts.insert(public_key);
+                Cow::Owned(trusted_hosts)
+            }
+            None => Cow::Borrowed(&self.trusted_hosts),
+        };
+
+        match public_key_client.handle(msg, self.identity.as_ref(), &trusted_hosts) {
+            Ok(outgoing) => {
+                if let (Some(public_key), Some(known_host)) = (first_use, &mut self.known_host) {
+                    known_host.unconfirmed = Some(public_key);
+                }
+
+                let data = outgoing
+                    .to_bytes()
+                    .expect("unable to convert public key message to bytes");
+                write.push(WpskkaMessage::AuthMessage(AuthMessage { data }));
+                // The Host still has to verify us, so we wait for the AuthResult
+                self.state = State::IsAuthenticating {
+                    auth_scheme: AuthScheme::PublicKeyAuthClient(public_key_client),
+                    private_key,
+                };
+                Ok(None)
+            }
+            Err(PublicKeyClientError::WrongMessageForState(..)) => {
+                self.state = State::IsAuthenticating {
+                    auth_scheme: AuthScheme::PublicKeyAuthClient(public_key_client),
+                    private_key,
+                };
+                Err(WpskkaClientError::BadAuthSchemeMessage)
+            }
+            Err(err) => {
+                if let PublicKeyClientError::UntrustedHost(public_key) = err {
+                    let event = match &self.known_host {
+                        Some(KnownHostState {
+                            host,
+                            pinned: Some(previous),
+                            ..
+                        }) => WpskkaClientInform::HostKeyChanged {
+                            host: host.clone(),
+                            previous: *previous,
+                            public_key,
+                        },
+                        _ => WpskkaClientInform::UntrustedHost(public_key),
+                    };
+                    events.push(InformEvent::WpskkaClientInform(event));
+                }
+                events.push(InformEvent::WpskkaClientInform(
+                    WpskkaClientInform::AuthFailed,
+                ));
+
+                let (public_key, foreign_public_key) = public_key_client.finish();
+
+                let key_pair = KeyPair {
+                    public_key,
+                    ephemeral_private_key: private_key,
+                };
+
+                self.state = State::ChooseAnAuthScheme {
+                    key_pair,
+                    foreign_public_key,
+                };
+
+                Ok(None)
+            }
+        }
+    }
+
     fn handle_auth_message(
         &mut self,
         msg: AuthMessage,
-        _write: &mut Vec<WpskkaMessage<'_>>,
+        write: &mut Vec<WpskkaMessage<'_>>,
         events: &mut Vec<InformEvent>,
     ) -> Result<Option<Vec<u8>>, WpskkaClientError> {
         match mem::replace(&mut self.state, State::Modifying) {
@@ -263,6 +393,14 @@ impl WpskkaClientHandler {
             } => match auth_scheme {
                 AuthScheme::SrpAuthClient(srp_client) =>
                     self.handle_auth_message_srp_auth_client(msg, events, srp_client, private_key),
+                AuthScheme::PublicKeyAuthClient(public_key_client) => self
+                    .handle_auth_message_public_key_client(
+                        msg,
+                        write,
+                        events,
+                        public_key_client,
+                        private_key,
+                    ),
                 _ => {
                     panic!(
                         "Somehow an unsupported auth scheme ended up in the auth scheme. Someone \
@@ -284,6 +422,12 @@ impl WpskkaClientHandler {
         _write: &mut Vec<WpskkaMessage<'_>>,
         events: &mut Vec<InformEvent>,
     ) -> Result<Option<Vec<u8>>, WpskkaClientError> {
+        // Whatever the result the key presented on first use is no longer waiting on it
+        let unconfirmed = self
+            .known_host
+            .as_mut()
+            .and_then(|known_host| known_host.unconfirmed.take());
+
         if !msg.ok {
             let result = match mem::replace(&mut self.state, State::Modifying) {
                 // We are already in the proper state. TODO is this really an error?
@@ -301,6 +445,8 @@ impl WpskkaClientHandler {
                             let (public_key, foreign_public_key) = srp_client.finish();
                             (public_key, foreign_public_key)
                         }
+                        AuthScheme::PublicKeyAuthClient(public_key_client) =>
+                            public_key_client.finish(),
                         _ => {
                             panic!(
                                 "Somehow an unsupported auth scheme ended up in the auth scheme. \
@@ -354,18 +500,37 @@ impl WpskkaClientHandler {
                     };
                     None
                 }
+                // The Host must prove its identity before it accepts ours, otherwise we'd be
+                // deriving keys with an unauthenticated Host
+                AuthScheme::PublicKeyAuthClient(public_key_client) => {
+                    let host_verified = public_key_client.is_authenticated();
+                    let (public_key, foreign_public_key) = public_key_client.finish();
+                    let key_pair = KeyPair {
+                        public_key,
+                        ephemeral_private_key: private_key,
+                    };
+                    if host_verified {
+                        Some((key_pair, foreign_public_key))
+                    } else {
+                        self.state = State::ChooseAnAuthScheme {
+                            key_pair,
+                            foreign_public_key,
+                        };
+                        events.push(InformEvent::WpskkaClientInform(
+                            WpskkaClientInform::AuthFailed,
+                        ));
+                        None
+                    }
+                }
                 _ => {
-                    todo!("handle auth result for non-srp auth scheme");
+                    panic!(
+                        "Somehow an unsupported auth scheme ended up in the auth scheme. Someone \
+                         should get fired."
+                    );
                 }
             },
-            State::Authenticated {
-                reliable,
-                unreliable,
-            } => {
-                self.state = State::Authenticated {
-                    reliable,
-                    unreliable,
-                };
+            state @ State::Authenticated { .. } => {
+                self.state = state;
                 None
             }
             _ =>
@@ -383,6 +548,16 @@ impl WpskkaClientHandler {
                 },
                 events,
             )?;
+
+            if let (Some(public_key), Some(known_host)) = (unconfirmed, &mut self.known_host) {
+                known_host.pinned = Some(public_key);
+                events.push(InformEvent::WpskkaClientInform(
+                    WpskkaClientInform::HostKeyPinned(KnownHost {
+                        host: known_host.host.clone(),
+                        public_key,
+                    }),
+                ));
+            }
         }
 
         Ok(None)
@@ -426,6 +601,44 @@ impl WpskkaClientHandler {
         }
     }
 
+    fn handle_rekey(
+        &mut self,
+        write: &mut Vec<WpskkaMessage<'_>>,
+        msg: Rekey,
+    ) -> Result<Option<Vec<u8>>, WpskkaClientError> {
+        match &mut self.state {
+            State::Authenticated {
+                reliable,
+                unreliable,
+                ..
+            } => {
+                write.push(RekeyState::handle_rekey(reliable, unreliable, msg)?);
+                Ok(None)
+            }
+            _ => Err(WpskkaClientError::WrongMessageForState(
+                debug(&msg),
+                debug(&self.state),
+            )),
+        }
+    }
+
+    fn handle_rekey_ack(&mut self, msg: RekeyAck) -> Result<Option<Vec<u8>>, WpskkaClientError> {
+        match &mut self.state {
+            State::Authenticated {
+                reliable,
+                unreliable,
+                rekey,
+            } => {
+                rekey.handle_rekey_ack(reliable, unreliable, msg)?;
+                Ok(None)
+            }
+            _ => Err(WpskkaClientError::WrongMessageForState(
+                debug(&msg),
+                debug(&self.state),
+            )),
+        }
+    }
+
     pub fn process_password(
         &mut self,
         password: &[u8],
@@ -469,6 +682,8 @@ impl WpskkaClientHandler {
                         foreign_public_key,
                     } => (public_key, foreign_public_key),
                     AuthScheme::SrpAuthClient(srp_client) => srp_client.finish(),
+                    AuthScheme::PublicKeyAuthClient(public_key_client) =>
+                        public_key_client.finish(),
                     _ => panic!("unexpected auth scheme"),
                 };
                 let key_pair = KeyPair {
@@ -492,7 +707,9 @@ impl WpskkaClientHandler {
                 AuthScheme::SrpAuthClient(srp_client)
             }
             AuthSchemeType::PublicKey => {
-                todo!("PublicKey")
+                let public_key_client =
+                    PublicKeyAuthClient::new(key_pair.public_key, foreign_public_key);
+                AuthScheme::PublicKeyAuthClient(public_key_client)
             }
         };
 
@@ -504,6 +721,37 @@ impl WpskkaClientHandler {
             auth_scheme: scheme,
         })
     }
+
+    /// Sets the identity used for [`AuthSchemeType::PublicKey`] authentication
+    pub fn set_identity(&mut self, identity: Option<IdentityKeyPair>) {
+        self.identity = identity;
+    }
+
+    pub fn identity(&self) -> Option<&IdentityKeyPair> {
+        self.identity.as_ref()
+    }
+
+    /// The Host identities we accept for [`AuthSchemeType::PublicKey`] authentication
+    pub fn trusted_hosts(&self) -> &TrustedKeys {
+        &self.trusted_hosts
+    }
+
+    pub fn trusted_hosts_mut(&mut self) -> &mut TrustedKeys {
+        &mut self.trusted_hosts
+    }
+
+    /// Enables trust on first use for [`AuthSchemeType::PublicKey`] authentication. `pinned` is
+    /// the key previously pinned for `host`, if there is none the first key `host` presents is
+    /// pinned and a [`WpskkaClientInform::HostKeyPinned`] is emitted once `host` accepts us. If
+    /// `host` presents a key other than `pinned` a [`WpskkaClientInform::HostKeyChanged`] is
+    /// emitted and authentication fails.
+    pub fn set_known_host(&mut self, host: Option<(HostKey, Option<[u8; 32]>)>) {
+        self.known_host = host.map(|(host, pinned)| KnownHostState {
+            host,
+            pinned,
+            unconfirmed: None,
+        });
+    }
 }
 
 impl WpskkaHandlerTrait for WpskkaClientHandler {
@@ -528,6 +776,30 @@ impl WpskkaHandlerTrait for WpskkaClientHandler {
         }
     }
 
+    fn rekey(&mut self) -> Result<Option<WpskkaMessage<'static>>, CipherError> {
+        match &mut self.state {
+            State::Authenticated {
+                reliable, rekey, ..
+            } => rekey.start(reliable),
+            _ => Ok(None),
+        }
+    }
+
+    fn rekey_if_due(&mut self) -> Result<Option<WpskkaMessage<'static>>, CipherError> {
+        match &mut self.state {
+            State::Authenticated {
+                reliable,
+                unreliable,
+                rekey,
+            } if rekey.due(&self.rekey_policy, reliable, unreliable) => rekey.start(reliable),
+            _ => Ok(None),
+        }
+    }
+
+    fn set_rekey_policy(&mut self, policy: RekeyPolicy) {
+        self.rekey_policy = policy;
+    }
+
     fn reliable_cipher_mut(&mut self) -> &mut CipherReliablePeer {
         match &mut self.state {
             State::Authenticated { reliable, .. } => reliable,
@@ -562,4 +834,11 @@ pub enum WpskkaClientInform {
     PasswordPrompt,                  // UI needs to prompt for a password
     AuthFailed,                      // Authentication Failed
     AuthSuccessful,
+    UntrustedHost([u8; 32]), // The Host proved an identity that isn't in our trusted hosts
+    HostKeyPinned(KnownHost), // First use of a known host, the caller should persist the key
+    HostKeyChanged {
+        host: HostKey,
+        previous: [u8; 32],
+        public_key: [u8; 32],
+    }, // A known host proved a different identity than the one pinned
 }
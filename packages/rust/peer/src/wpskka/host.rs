@@ -3,27 +3,34 @@ use crate::{
     helpers::{
         cipher_reliable_peer::{CipherError, CipherReliablePeer},
         cipher_unreliable_peer::CipherUnreliablePeer,
-        crypto::{diffie_hellman, keypair, parse_foreign_public, KeyPair},
+        crypto::{diffie_hellman, keypair, parse_foreign_public, IdentityKeyPair, KeyPair},
+        secret::SecretVec,
     },
     wpskka::{
         auth::{
+            public_key_host::{PublicKeyAuthHost, PublicKeyHostError},
             srp_host::{SrpAuthHost, SrpHostError},
             AuthScheme,
+            TrustedKeys,
         },
         KeyState,
+        RekeyPolicy,
+        RekeyState,
         WpskkaError,
         WpskkaHandlerTrait,
     },
     InformEvent,
 };
 use common::messages::{
-    auth::srp::SrpMessage,
+    auth::{public_key::PublicKeyMessage, srp::SrpMessage},
     wpskka::{
         AuthMessage,
         AuthResult,
         AuthScheme as AuthSchemeMessage,
         AuthSchemeType,
         KeyExchange,
+        Rekey,
+        RekeyAck,
         TransportDataMessageReliable,
         TransportDataMessageUnreliable,
         TryAuth,
@@ -56,6 +63,7 @@ pub enum State {
     Authenticated {
         reliable: CipherReliablePeer,
         unreliable: CipherUnreliablePeer,
+        rekey: RekeyState,
     },
 }
 
@@ -75,9 +83,14 @@ impl Debug for State {
 pub struct WpskkaHostHandler {
     state: State,
 
-    dynamic_password: Option<Vec<u8>>,
-    static_password: Option<Vec<u8>>,
+    dynamic_password: Option<SecretVec>,
+    static_password: Option<SecretVec>,
     none_scheme: bool,
+
+    identity: Option<IdentityKeyPair>,
+    authorized_clients: TrustedKeys,
+
+    rekey_policy: RekeyPolicy,
 }
 
 impl Default for WpskkaHostHandler {
@@ -93,6 +106,9 @@ impl WpskkaHostHandler {
             dynamic_password: None,
             static_password: None,
             none_scheme: false,
+            identity: None,
+            authorized_clients: TrustedKeys::new(),
+            rekey_policy: RekeyPolicy::default(),
         }
     }
 
@@ -108,6 +124,8 @@ impl WpskkaHostHandler {
             WpskkaMessage::AuthMessage(msg) => self.handle_auth_message(write, events, msg),
             WpskkaMessage::TransportDataMessageReliable(msg) =>
                 self.handle_message_reliable(write, events, msg),
+            WpskkaMessage::Rekey(msg) => self.handle_rekey(write, msg),
+            WpskkaMessage::RekeyAck(msg) => self.handle_rekey_ack(msg),
             WpskkaMessage::TransportDataMessageUnreliable(msg) =>
                 self.handle_message_unreliable(write, events, msg),
             msg => Err(WpskkaHostError::WrongMessageForState(
@@ -153,7 +171,6 @@ impl WpskkaHostHandler {
         let client_public_key = parse_foreign_public(&foreign_public_key);
         let (send_reliable, receive_reliable, send_unreliable, receive_unreliable) =
             diffie_hellman(key_pair.ephemeral_private_key, client_public_key).map_err(|_| ())?;
-        // TODO zero hella
         Ok((
             CipherReliablePeer::new(send_reliable.to_vec(), receive_reliable.to_vec()),
             CipherUnreliablePeer::new(send_unreliable.to_vec(), receive_unreliable.to_vec()),
@@ -165,16 +182,19 @@ impl WpskkaHostHandler {
             Ok((reliable, unreliable)) => Ok(State::Authenticated {
                 reliable,
                 unreliable,
+                rekey: RekeyState::new(),
             }),
             Err(()) => Err((State::PreInit, WpskkaHostError::RingError)),
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn handle_try_auth_stateless(
         write: &mut Vec<WpskkaMessage<'_>>,
         events: &mut Vec<InformEvent>,
         dynamic_password: Option<&[u8]>,
         static_password: Option<&[u8]>,
+        identity: Option<&IdentityKeyPair>,
         key_state: KeyState,
         auth_scheme: AuthSchemeType,
         none_scheme: bool,
@@ -231,8 +251,31 @@ impl WpskkaHostHandler {
                 })
             }
             AuthSchemeType::PublicKey => {
-                write.push(WpskkaMessage::AuthResult(AuthResult { ok: false }));
-                Ok(State::PreAuthSelect { key_state })
+                let identity = match identity {
+                    None => {
+                        write.push(WpskkaMessage::AuthResult(AuthResult { ok: false }));
+                        return Ok(State::PreAuthSelect { key_state });
+                    }
+                    Some(identity) => identity,
+                };
+
+                let mut public_key_host = PublicKeyAuthHost::new(
+                    key_state.key_pair.public_key,
+                    key_state.foreign_public_key,
+                );
+
+                let message = public_key_host.init(identity);
+
+                write.push(WpskkaMessage::AuthMessage(AuthMessage {
+                    data: message
+                        .to_bytes()
+                        .expect("unable to convert public key message to bytes"),
+                }));
+
+                Ok(State::IsAuthenticating {
+                    auth_scheme: AuthScheme::PublicKeyAuthHost(public_key_host),
+                    private_key: key_state.key_pair.ephemeral_private_key,
+                })
             }
         }
     }
@@ -260,6 +303,16 @@ impl WpskkaHostHandler {
                         foreign_public_key: their_public,
                     }
                 }
+                AuthScheme::PublicKeyAuthHost(public_key_host) => {
+                    let (my_public, their_public) = public_key_host.finish();
+                    KeyState {
+                        key_pair: KeyPair {
+                            public_key: my_public,
+                            ephemeral_private_key: my_private_key,
+                        },
+                        foreign_public_key: their_public,
+                    }
+                }
                 _ => {
                     panic!(
                         "Somehow an unsupported auth scheme ended up in the auth scheme. Someone \
@@ -279,6 +332,7 @@ impl WpskkaHostHandler {
             events,
             self.dynamic_password.as_deref(),
             self.static_password.as_deref(),
+            self.identity.as_ref(),
             key_state,
             msg.auth_scheme,
             self.none_scheme,
@@ -294,6 +348,81 @@ impl WpskkaHostHandler {
         }
     }
 
+    fn handle_auth_message_public_key_host(
+        &mut self,
+        write: &mut Vec<WpskkaMessage<'_>>,
+        events: &mut Vec<InformEvent>,
+        msg: AuthMessage,
+        mut public_key_host: PublicKeyAuthHost<32>,
+        my_private_key: EphemeralPrivateKey,
+    ) -> Result<Option<Vec<u8>>, WpskkaHostError> {
+        let msg = match PublicKeyMessage::read(&mut Cursor::new(&msg.data)) {
+            Ok(msg) => msg,
+            Err(_) => {
+                self.state = State::IsAuthenticating {
+                    auth_scheme: AuthScheme::PublicKeyAuthHost(public_key_host),
+                    private_key: my_private_key,
+                };
+                return Err(WpskkaHostError::BadAuthSchemeMessage);
+            }
+        };
+
+        match public_key_host.handle(msg, &self.authorized_clients) {
+            Ok(()) => {
+                let (my_pub, their_pub) = public_key_host.finish();
+                let key_pair = KeyPair {
+                    public_key: my_pub,
+                    ephemeral_private_key: my_private_key,
+                };
+                match Self::derive_key_wrapper(KeyState {
+                    key_pair,
+                    foreign_public_key: their_pub,
+                }) {
+                    Ok(state) => {
+                        write.push(WpskkaMessage::AuthResult(AuthResult { ok: true }));
+                        events.push(InformEvent::WpskkaHostInform(
+                            WpskkaHostInform::AuthSuccessful,
+                        ));
+                        self.state = state;
+                        Ok(None)
+                    }
+                    Err((state, err)) => {
+                        write.push(WpskkaMessage::AuthResult(AuthResult { ok: false }));
+                        self.state = state;
+                        Err(err)
+                    }
+                }
+            }
+            Err(PublicKeyHostError::WrongMessageForState(..)) => {
+                self.state = State::IsAuthenticating {
+                    auth_scheme: AuthScheme::PublicKeyAuthHost(public_key_host),
+                    private_key: my_private_key,
+                };
+                Err(WpskkaHostError::BadAuthSchemeMessage)
+            }
+            Err(_) => {
+                write.push(WpskkaMessage::AuthResult(AuthResult { ok: false }));
+                events.push(InformEvent::WpskkaHostInform(WpskkaHostInform::AuthFailed));
+
+                let (public_key, foreign_public_key) = public_key_host.finish();
+
+                let key_pair = KeyPair {
+                    public_key,
+                    ephemeral_private_key: my_private_key,
+                };
+
+                self.state = State::PreAuthSelect {
+                    key_state: KeyState {
+                        key_pair,
+                        foreign_public_key,
+                    },
+                };
+
+                Ok(None)
+            }
+        }
+    }
+
     fn handle_auth_message(
         &mut self,
         write: &mut Vec<WpskkaMessage<'_>>,
@@ -379,6 +508,14 @@ impl WpskkaHostHandler {
                         },
                     }
                 }
+                AuthScheme::PublicKeyAuthHost(public_key_host) => self
+                    .handle_auth_message_public_key_host(
+                        write,
+                        events,
+                        msg,
+                        public_key_host,
+                        my_private_key,
+                    ),
                 _ => {
                     panic!(
                         "Somehow an unsupported auth scheme ended up in the auth scheme. Someone \
@@ -434,6 +571,44 @@ impl WpskkaHostHandler {
         }
     }
 
+    fn handle_rekey(
+        &mut self,
+        write: &mut Vec<WpskkaMessage<'_>>,
+        msg: Rekey,
+    ) -> Result<Option<Vec<u8>>, WpskkaHostError> {
+        match &mut self.state {
+            State::Authenticated {
+                reliable,
+                unreliable,
+                ..
+            } => {
+                write.push(RekeyState::handle_rekey(reliable, unreliable, msg)?);
+                Ok(None)
+            }
+            _ => Err(WpskkaHostError::WrongMessageForState(
+                debug(&msg),
+                debug(&self.state),
+            )),
+        }
+    }
+
+    fn handle_rekey_ack(&mut self, msg: RekeyAck) -> Result<Option<Vec<u8>>, WpskkaHostError> {
+        match &mut self.state {
+            State::Authenticated {
+                reliable,
+                unreliable,
+                rekey,
+            } => {
+                rekey.handle_rekey_ack(reliable, unreliable, msg)?;
+                Ok(None)
+            }
+            _ => Err(WpskkaHostError::WrongMessageForState(
+                debug(&msg),
+                debug(&self.state),
+            )),
+        }
+    }
+
     pub fn auth_schemes(&self) -> WpskkaMessage<'static> {
         let mut auth_schemes = Vec::new();
         if self.none_scheme {
@@ -445,6 +620,9 @@ impl WpskkaHostHandler {
         if self.dynamic_password.is_some() {
             auth_schemes.push(AuthSchemeType::SrpDynamic);
         }
+        if self.identity.is_some() && !self.authorized_clients.is_empty() {
+            auth_schemes.push(AuthSchemeType::PublicKey);
+        }
         WpskkaMessage::AuthScheme(AuthSchemeMessage { auth_schemes })
     }
 
@@ -468,14 +646,32 @@ impl WpskkaHostHandler {
         Ok(msg)
     }
 
-    pub fn set_dynamic_password(&mut self, dynamic_password: Option<Vec<u8>>) {
+    pub fn set_dynamic_password(&mut self, dynamic_password: Option<SecretVec>) {
         self.dynamic_password = dynamic_password;
     }
 
-    pub fn set_static_password(&mut self, static_password: Option<Vec<u8>>) {
+    pub fn set_static_password(&mut self, static_password: Option<SecretVec>) {
         self.static_password = static_password;
     }
 
+    /// Sets the identity used for [`AuthSchemeType::PublicKey`] authentication
+    pub fn set_identity(&mut self, identity: Option<IdentityKeyPair>) {
+        self.identity = identity;
+    }
+
+    pub fn identity(&self) -> Option<&IdentityKeyPair> {
+        self.identity.as_ref()
+    }
+
+    /// The Client identities allowed to connect with [`AuthSchemeType::PublicKey`] authentication
+    pub fn authorized_clients(&self) -> &TrustedKeys {
+        &self.authorized_clients
+    }
+
+    pub fn authorized_clients_mut(&mut self) -> &mut TrustedKeys {
+        &mut self.authorized_clients
+    }
+
     /// Warning: Setting this to true allows ANY client to connect to the Host and should only be used in debug mode
     pub fn set_none_scheme(&mut self, allow_none: bool) {
         self.none_scheme = allow_none;
@@ -507,6 +703,30 @@ impl WpskkaHandlerTrait for WpskkaHostHandler {
         }
     }
 
+    fn rekey(&mut self) -> Result<Option<WpskkaMessage<'static>>, CipherError> {
+        match &mut self.state {
+            State::Authenticated {
+                reliable, rekey, ..
+            } => rekey.start(reliable),
+            _ => Ok(None),
+        }
+    }
+
+    fn rekey_if_due(&mut self) -> Result<Option<WpskkaMessage<'static>>, CipherError> {
+        match &mut self.state {
+            State::Authenticated {
+                reliable,
+                unreliable,
+                rekey,
+            } if rekey.due(&self.rekey_policy, reliable, unreliable) => rekey.start(reliable),
+            _ => Ok(None),
+        }
+    }
+
+    fn set_rekey_policy(&mut self, policy: RekeyPolicy) {
+        self.rekey_policy = policy;
+    }
+
     fn reliable_cipher_mut(&mut self) -> &mut CipherReliablePeer {
         match &mut self.state {
             State::Authenticated { reliable, .. } => reliable,
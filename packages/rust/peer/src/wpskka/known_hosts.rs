@@ -0,0 +1,262 @@
+use crate::hash;
+use common::messages::svsc::LeaseId;
+use std::{
+    collections::BTreeMap,
+    fmt::{Display, Formatter, Write as _},
+    fs,
+    io,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+// The known hosts file has one Host per line:
+//
+//     <host> <public key> <fingerprint>
+//
+// where <host> is `lease:<lease id>` or `addr:<address>`, <public key> is the hex encoded identity
+// key and <fingerprint> is the key's fingerprint. The fingerprint is redundant but makes the file
+// easy to compare against what a user sees on the Host. Empty lines and lines starting with `#`
+// are ignored.
+
+/// How a Host is identified in the known hosts file. Signal Hosts are identified by their lease ID,
+/// Direct Hosts by the address we connected to.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum HostKey {
+    Lease(LeaseId),
+    Address(String),
+}
+
+impl Display for HostKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HostKey::Lease(id) => write!(f, "lease:{}", u32::from_be_bytes(*id)),
+            HostKey::Address(addr) => write!(f, "addr:{}", addr),
+        }
+    }
+}
+
+impl FromStr for HostKey {
+    type Err = KnownHostsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, value) = s
+            .split_once(':')
+            .ok_or(KnownHostsError::BadHost("missing host type"))?;
+
+        if value.is_empty() || value.contains(char::is_whitespace) {
+            return Err(KnownHostsError::BadHost(
+                "host must not be empty or contain whitespace",
+            ));
+        }
+
+        match kind {
+            "lease" => value
+                .parse::<u32>()
+                .map(|id| HostKey::Lease(id.to_be_bytes()))
+                .map_err(|_| KnownHostsError::BadHost("invalid lease ID")),
+            "addr" => Ok(HostKey::Address(value.to_string())),
+            _ => Err(KnownHostsError::BadHost("unknown host type")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KnownHost {
+    pub host: HostKey,
+    pub public_key: [u8; 32],
+}
+
+impl KnownHost {
+    pub fn fingerprint(&self) -> String {
+        fingerprint(&self.public_key)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum HostKeyStatus {
+    /// The Host's key matches the pinned key
+    Trusted,
+    /// We've never seen this Host before
+    Unknown,
+    /// The Host presented a different key than the one we pinned
+    Changed { previous: [u8; 32] },
+}
+
+/// A human readable fingerprint of an identity key, e.g. `3f:a0:...`. Only the first 16 bytes of
+/// the hash are used to keep it short enough to compare by eye.
+pub fn fingerprint(public_key: &[u8; 32]) -> String {
+    let hash = *hash!(b"screenview identity fingerprint", public_key);
+    let mut fingerprint = String::with_capacity(16 * 3);
+    for (i, byte) in hash[.. 16].iter().enumerate() {
+        if i != 0 {
+            fingerprint.push(':');
+        }
+        write!(fingerprint, "{:02x}", byte).unwrap();
+    }
+    fingerprint
+}
+
+pub fn encode_public_key(public_key: &[u8; 32]) -> String {
+    public_key
+        .iter()
+        .fold(String::with_capacity(64), |mut s, byte| {
+            write!(s, "{:02x}", byte).unwrap();
+            s
+        })
+}
+
+pub fn decode_public_key(hex: &str) -> Option<[u8; 32]> {
+    if hex.len() != 64 || !hex.is_ascii() {
+        return None;
+    }
+    let mut public_key = [0u8; 32];
+    for (i, byte) in public_key.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2 .. i * 2 + 2], 16).ok()?;
+    }
+    Some(public_key)
+}
+
+/// A trust-on-first-use store of Host identity keys. If the store was opened from a file, changes
+/// are only written back when [`save`](Self::save) is called.
+#[derive(Debug, Default)]
+pub struct KnownHosts {
+    path: Option<PathBuf>,
+    hosts: BTreeMap<HostKey, [u8; 32]>,
+}
+
+impl KnownHosts {
+    /// Creates an empty store that isn't backed by a file
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Opens the known hosts file at `path`. A missing file is treated as empty and will be
+    /// created on the first save.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, KnownHostsError> {
+        let path = path.as_ref().to_path_buf();
+        let mut known_hosts = match fs::read_to_string(&path) {
+            Ok(contents) => Self::parse(&contents)?,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Self::new(),
+            Err(error) => return Err(error.into()),
+        };
+        known_hosts.path = Some(path);
+        Ok(known_hosts)
+    }
+
+    pub fn parse(contents: &str) -> Result<Self, KnownHostsError> {
+        let mut hosts = BTreeMap::new();
+
+        for (i, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let bad_line = |reason| KnownHostsError::BadLine {
+                line: i + 1,
+                reason,
+            };
+
+            let mut parts = line.split_whitespace();
+            let host = parts
+                .next()
+                .ok_or_else(|| bad_line("missing host"))?
+                .parse::<HostKey>()
+                .map_err(|_| bad_line("invalid host"))?;
+            let public_key = parts
+                .next()
+                .and_then(decode_public_key)
+                .ok_or_else(|| bad_line("invalid public key"))?;
+            // The fingerprint is optional, but if it's there it must match
+            if let Some(fp) = parts.next() {
+                if fp != fingerprint(&public_key) {
+                    return Err(bad_line("fingerprint does not match public key"));
+                }
+            }
+            if parts.next().is_some() {
+                return Err(bad_line("unexpected trailing data"));
+            }
+
+            hosts.insert(host, public_key);
+        }
+
+        Ok(Self { path: None, hosts })
+    }
+
+    /// Writes the store back to the file it was opened from. This does nothing for stores that
+    /// aren't backed by a file.
+    pub fn save(&self) -> Result<(), KnownHostsError> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+
+        // Write to a temporary file and rename so a crash can't leave us with a truncated file
+        let mut tmp = path.clone().into_os_string();
+        tmp.push(".tmp");
+        fs::write(&tmp, self.to_string())?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    pub fn get(&self, host: &HostKey) -> Option<&[u8; 32]> {
+        self.hosts.get(host)
+    }
+
+    pub fn check(&self, host: &HostKey, public_key: &[u8; 32]) -> HostKeyStatus {
+        match self.hosts.get(host) {
+            None => HostKeyStatus::Unknown,
+            Some(pinned) if pinned == public_key => HostKeyStatus::Trusted,
+            Some(pinned) => HostKeyStatus::Changed { previous: *pinned },
+        }
+    }
+
+    /// Pins `public_key` for `host`, replacing any previously pinned key which is returned
+    pub fn pin(&mut self, host: HostKey, public_key: [u8; 32]) -> Option<[u8; 32]> {
+        self.hosts.insert(host, public_key)
+    }
+
+    /// Removes the pinned key for `host`, returning it if there was one
+    pub fn revoke(&mut self, host: &HostKey) -> Option<[u8; 32]> {
+        self.hosts.remove(host)
+    }
+
+    pub fn list(&self) -> Vec<KnownHost> {
+        self.hosts
+            .iter()
+            .map(|(host, public_key)| KnownHost {
+                host: host.clone(),
+                public_key: *public_key,
+            })
+            .collect()
+    }
+}
+
+impl Display for KnownHosts {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (host, public_key) in &self.hosts {
+            writeln!(
+                f,
+                "{} {} {}",
+                host,
+                encode_public_key(public_key),
+                fingerprint(public_key)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum KnownHostsError {
+    #[error("io error: {0}")]
+    Io(#[from] io::Error),
+    #[error("invalid host: {0}")]
+    BadHost(&'static str),
+    #[error("invalid known hosts entry on line {line}: {reason}")]
+    BadLine { line: usize, reason: &'static str },
+}
@@ -1,9 +1,12 @@
 pub mod auth;
 mod client;
 mod host;
+pub mod known_hosts;
+mod rekey;
 
 pub use client::*;
 pub use host::*;
+pub use rekey::*;
 
 use super::helpers::cipher_reliable_peer::CipherError;
 use crate::{
@@ -44,8 +47,20 @@ use std::borrow::Cow;
 //
 // "What happens if the Client authenticates the Host but then the Client receives a failed authentication result from the Host?"
 // This is guaranteed not to happen as per the spec. The Host MUST send out a successful authentication result after it sends the final SRP message to the Client.
+//
+// For PublicKey authentication both sides need an identity set with set_identity. The Host only offers the scheme if it also has at least one authorized client.
+//
+// 1. After WpsskkaClient::try_auth() is called (with PublicKey as the scheme), the Host signs both ephemeral keys with its identity and sends a HostIdentity message.
+// 2. Client verifies the signature and checks the Host's identity is in its trusted hosts. If the Host is untrusted an InformEvent::UntrustedHost event is emitted followed by InformEvent::AuthFailed, and the Client does not reveal its own identity.
+// 3. Otherwise the Client signs both ephemeral keys with its identity and sends a ClientIdentity message.
+// 4. Host verifies the signature and checks the Client's identity is authorized, then sends an AuthResult.
+// 5. Unlike SRP, the Client waits for the successful AuthResult before updating the state to IsAuthenticated, since the Host has authenticated itself but not yet accepted us.
+//
+// Once authenticated either peer can rekey the keys it sends with, see rekey.rs. The higher handler calls rekey_if_due after each message it sends.
 
 
+// The ephemeral private key is owned by ring, which wipes it when it's consumed by the key agreement
+// in derive_keys. Everything derived from it is held in a SecretKey or SecretVec.
 pub struct KeyState {
     foreign_public_key: [u8; 32],
     key_pair: KeyPair,
@@ -74,6 +89,15 @@ pub trait WpskkaHandlerTrait {
 
     fn reliable_cipher_mut(&mut self) -> &mut CipherReliablePeer;
 
+    /// Starts rekeying the keys we send with, returning a Rekey message to send. Returns None if
+    /// a rekey is already in progress.
+    fn rekey(&mut self) -> Result<Option<WpskkaMessage<'static>>, CipherError>;
+
+    /// Like [`rekey`](Self::rekey) but only if the [`RekeyPolicy`] says it's time
+    fn rekey_if_due(&mut self) -> Result<Option<WpskkaMessage<'static>>, CipherError>;
+
+    fn set_rekey_policy(&mut self, policy: RekeyPolicy);
+
     fn wrap_reliable(
         &mut self,
         msg: Vec<u8>,
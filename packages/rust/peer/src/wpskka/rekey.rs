@@ -0,0 +1,132 @@
+use crate::helpers::{
+    cipher_reliable_peer::{CipherError, CipherReliablePeer},
+    cipher_unreliable_peer::CipherUnreliablePeer,
+};
+use common::messages::wpskka::{Rekey, RekeyAck, WpskkaMessage};
+use std::time::{Duration, Instant};
+
+// Each peer only rekeys the keys it sends with, so both peers can rekey at the same time without
+// coordinating.
+//
+// 1. The sender ratchets its TCP send key and sends a Rekey message. Everything it sends over TCP
+//    after the Rekey uses the new key.
+// 2. The receiver ratchets its TCP and UDP receive keys, and replies with a RekeyAck. UDP packets
+//    encrypted with the old key are still accepted for a grace window since they may have been
+//    in flight.
+// 3. The sender ratchets its UDP send key once it receives the RekeyAck, at which point the
+//    receiver already has the new key.
+
+/// When to rekey the keys we send with
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct RekeyPolicy {
+    /// Rekey once the keys are this old
+    pub interval: Option<Duration>,
+    /// Rekey once this many TCP messages or UDP packets have been sent with the keys
+    pub max_messages: Option<u64>,
+}
+
+impl Default for RekeyPolicy {
+    fn default() -> Self {
+        Self {
+            interval: Some(Duration::from_secs(60 * 60)),
+            max_messages: Some(1 << 32),
+        }
+    }
+}
+
+impl RekeyPolicy {
+    /// Never rekey automatically, [`WpskkaHandlerTrait::rekey`](super::WpskkaHandlerTrait::rekey)
+    /// can still be called
+    pub fn never() -> Self {
+        Self {
+            interval: None,
+            max_messages: None,
+        }
+    }
+}
+
+pub struct RekeyState {
+    // The epoch we've sent a Rekey for but haven't had acknowledged yet
+    pending: Option<u64>,
+    last_rekey: Instant,
+}
+
+impl Default for RekeyState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RekeyState {
+    pub fn new() -> Self {
+        Self {
+            pending: None,
+            last_rekey: Instant::now(),
+        }
+    }
+
+    pub fn pending(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    pub fn due(
+        &self,
+        policy: &RekeyPolicy,
+        reliable: &CipherReliablePeer,
+        unreliable: &CipherUnreliablePeer,
+    ) -> bool {
+        if self.pending() {
+            return false;
+        }
+        let expired = policy
+            .interval
+            .map_or(false, |interval| self.last_rekey.elapsed() >= interval);
+        let exhausted = policy
+            .max_messages
+            .map_or(false, |max| reliable.sent().max(unreliable.sent()) >= max);
+        expired || exhausted
+    }
+
+    /// Starts a rekey, returning the Rekey message to send. Returns None if a rekey is already in
+    /// progress.
+    pub fn start(
+        &mut self,
+        reliable: &mut CipherReliablePeer,
+    ) -> Result<Option<WpskkaMessage<'static>>, CipherError> {
+        if self.pending() {
+            return Ok(None);
+        }
+        let (epoch, mac) = reliable.rekey_send()?;
+        self.pending = Some(epoch);
+        Ok(Some(WpskkaMessage::Rekey(Rekey { epoch, mac })))
+    }
+
+    /// Handles the peer rekeying, returning the RekeyAck to send
+    pub fn handle_rekey(
+        reliable: &mut CipherReliablePeer,
+        unreliable: &mut CipherUnreliablePeer,
+        msg: Rekey,
+    ) -> Result<WpskkaMessage<'static>, CipherError> {
+        let mac = reliable.rekey_receive(msg.epoch, &msg.mac)?;
+        unreliable.rekey_receive();
+        Ok(WpskkaMessage::RekeyAck(RekeyAck {
+            epoch: msg.epoch,
+            mac,
+        }))
+    }
+
+    pub fn handle_rekey_ack(
+        &mut self,
+        reliable: &CipherReliablePeer,
+        unreliable: &mut CipherUnreliablePeer,
+        msg: RekeyAck,
+    ) -> Result<(), CipherError> {
+        if self.pending != Some(msg.epoch) || !reliable.verify_rekey_ack(msg.epoch, &msg.mac) {
+            return Err(CipherError::BadRekey(msg.epoch));
+        }
+        unreliable.rekey_send();
+        self.pending = None;
+        self.last_rekey = Instant::now();
+        Ok(())
+    }
+}
@@ -0,0 +1,124 @@
+use peer::wpskka::known_hosts::{
+    decode_public_key,
+    encode_public_key,
+    fingerprint,
+    HostKey,
+    HostKeyStatus,
+    KnownHosts,
+    KnownHostsError,
+};
+use std::{env, fs, process};
+
+#[test]
+fn test_host_key() {
+    let lease = HostKey::Lease(1234u32.to_be_bytes());
+    assert_eq!(lease.to_string(), "lease:1234");
+    assert_eq!("lease:1234".parse::<HostKey>().unwrap(), lease);
+
+    let addr = HostKey::Address("10.0.0.1:9000".to_string());
+    assert_eq!(addr.to_string(), "addr:10.0.0.1:9000");
+    assert_eq!("addr:10.0.0.1:9000".parse::<HostKey>().unwrap(), addr);
+
+    assert!("lease:".parse::<HostKey>().is_err());
+    assert!("lease:abc".parse::<HostKey>().is_err());
+    assert!("addr:a b".parse::<HostKey>().is_err());
+    assert!("host:10.0.0.1".parse::<HostKey>().is_err());
+    assert!("10.0.0.1".parse::<HostKey>().is_err());
+}
+
+#[test]
+fn test_public_key_encoding() {
+    let mut public_key = [0u8; 32];
+    for (i, byte) in public_key.iter_mut().enumerate() {
+        *byte = i as u8;
+    }
+    let hex = encode_public_key(&public_key);
+    assert_eq!(hex.len(), 64);
+    assert_eq!(decode_public_key(&hex), Some(public_key));
+
+    assert_eq!(decode_public_key(&hex[.. 62]), None);
+    assert_eq!(decode_public_key(&"zz".repeat(32)), None);
+
+    let fp = fingerprint(&public_key);
+    assert_eq!(fp.len(), 16 * 3 - 1);
+    assert_ne!(fp, fingerprint(&[0; 32]));
+}
+
+#[test]
+fn test_pin_check_revoke() {
+    let mut known_hosts = KnownHosts::new();
+    let host = HostKey::Lease([0, 0, 0, 1]);
+
+    assert_eq!(known_hosts.check(&host, &[1; 32]), HostKeyStatus::Unknown);
+    assert_eq!(known_hosts.pin(host.clone(), [1; 32]), None);
+    assert_eq!(known_hosts.check(&host, &[1; 32]), HostKeyStatus::Trusted);
+    assert_eq!(known_hosts.check(&host, &[2; 32]), HostKeyStatus::Changed {
+        previous: [1; 32]
+    });
+
+    assert_eq!(known_hosts.pin(host.clone(), [2; 32]), Some([1; 32]));
+    assert_eq!(known_hosts.get(&host), Some(&[2; 32]));
+
+    assert_eq!(known_hosts.revoke(&host), Some([2; 32]));
+    assert_eq!(known_hosts.revoke(&host), None);
+    assert!(known_hosts.list().is_empty());
+}
+
+#[test]
+fn test_parse_round_trip() {
+    let mut known_hosts = KnownHosts::new();
+    known_hosts.pin(HostKey::Lease([0, 0, 0, 1]), [1; 32]);
+    known_hosts.pin(HostKey::Address("10.0.0.1:9000".to_string()), [2; 32]);
+
+    let contents = format!("# pinned hosts\n\n{}", known_hosts);
+    let parsed = KnownHosts::parse(&contents).unwrap();
+    assert_eq!(parsed.list(), known_hosts.list());
+
+    // the fingerprint column is optional
+    let contents = format!("lease:1 {}\n", encode_public_key(&[1; 32]));
+    let parsed = KnownHosts::parse(&contents).unwrap();
+    assert_eq!(parsed.get(&HostKey::Lease([0, 0, 0, 1])), Some(&[1; 32]));
+}
+
+#[test]
+fn test_parse_bad_line() {
+    let contents = format!(
+        "lease:1 {}\nlease:2 {} {}\n",
+        encode_public_key(&[1; 32]),
+        encode_public_key(&[2; 32]),
+        fingerprint(&[1; 32])
+    );
+    assert!(matches!(
+        KnownHosts::parse(&contents),
+        Err(KnownHostsError::BadLine { line: 2, .. })
+    ));
+
+    assert!(matches!(
+        KnownHosts::parse("lease:1 1234\n"),
+        Err(KnownHostsError::BadLine { line: 1, .. })
+    ));
+    assert!(matches!(
+        KnownHosts::parse("lease:1\n"),
+        Err(KnownHostsError::BadLine { line: 1, .. })
+    ));
+}
+
+#[test]
+fn test_open_save() {
+    let path = env::temp_dir().join(format!("screenview_known_hosts_{}", process::id()));
+    let _ = fs::remove_file(&path);
+
+    let mut known_hosts = KnownHosts::open(&path).unwrap();
+    assert!(known_hosts.list().is_empty());
+    known_hosts.pin(HostKey::Lease([0, 0, 0, 1]), [1; 32]);
+    known_hosts.save().unwrap();
+
+    let known_hosts = KnownHosts::open(&path).unwrap();
+    assert_eq!(known_hosts.path(), Some(path.as_path()));
+    assert_eq!(
+        known_hosts.get(&HostKey::Lease([0, 0, 0, 1])),
+        Some(&[1; 32])
+    );
+
+    fs::remove_file(&path).unwrap();
+}
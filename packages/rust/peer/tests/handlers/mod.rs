@@ -1,5 +1,8 @@
+mod known_hosts_test;
 mod rvd_test;
+mod secret_test;
 mod sel_test;
 mod svsc_test;
+mod wpskka_auth_public_key_test;
 mod wpskka_auth_srp_test;
 mod wpskka_test;
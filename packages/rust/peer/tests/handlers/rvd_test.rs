@@ -1,24 +1,64 @@
 use crate::helper::rvd_helper::handshake;
-use common::messages::rvd::{
-    AccessMask,
-    ButtonsMask,
-    ClipboardMeta,
-    ClipboardNotification,
-    ClipboardRequest,
-    ClipboardType,
-    DisplayShare,
-    DisplayShareAck,
-    KeyInput,
-    MouseInput,
-    MouseLocation,
-    PermissionMask,
-    ProtocolVersion,
-    RvdMessage,
+use common::messages::{
+    rvd::{
+        AccessMask,
+        ButtonsMask,
+        ClipboardAck,
+        ClipboardChunk,
+        ClipboardLimit,
+        ClipboardMeta,
+        ClipboardNotification,
+        ClipboardRequest,
+        ClipboardStart,
+        ClipboardType,
+        ControlRequest,
+        CursorShape,
+        CursorShapeCached,
+        DisplayGeometry,
+        DisplayRotation,
+        DisplayShare,
+        DisplayShareAck,
+        FileAck,
+        FileChunk,
+        FrameNack,
+        KeyInput,
+        MouseInput,
+        MouseLocation,
+        PermissionMask,
+        PictureLoss,
+        ProtocolVersion,
+        ProtocolVersionResponse,
+        ReceiverReport,
+        RvdMessage,
+        TextInput,
+    },
+    Data,
 };
 use peer::{
-    rvd::{RvdClientHandler, RvdClientInform, RvdHandlerTrait, RvdHostHandler, RvdHostInform},
+    rvd::{
+        ClipboardTransferError,
+        CursorError,
+        CursorImage,
+        FileTransferError,
+        FileTransferInform,
+        RvdClientError,
+        RvdClientHandler,
+        RvdClientInform,
+        RvdError,
+        RvdHandlerTrait,
+        RvdHostError,
+        RvdHostHandler,
+        RvdHostInform,
+        CLIPBOARD_CHUNK_SIZE,
+        CLIPBOARD_WINDOW,
+        CURSOR_CACHE_SIZE,
+        DEFAULT_CLIPBOARD_LIMIT,
+        FILE_CHUNK_SIZE,
+        FILE_WINDOW_SIZE,
+    },
     InformEvent,
 };
+use std::borrow::Cow;
 
 #[test]
 fn test_rvd_version_mismatch() {
@@ -57,6 +97,28 @@ fn test_rvd_version_mismatch() {
     ));
 }
 
+#[test]
+fn test_rvd_version_short_frames() {
+    let mut write = Vec::new();
+    let mut events = Vec::new();
+
+    let mut host = RvdHostHandler::new();
+
+    // Peers from before extended frames don't know the messages added since
+    let msg = RvdMessage::ProtocolVersion(ProtocolVersion {
+        version: "RVD 001.000".to_string(),
+    });
+    host.handle(msg, &mut write, &mut events)
+        .expect("handler failed");
+    assert!(matches!(
+        &write[..],
+        [RvdMessage::ProtocolVersionResponse(msg)] if !msg.ok
+    ));
+    assert!(matches!(&events[..], [InformEvent::RvdHostInform(
+        RvdHostInform::VersionBad
+    )]));
+}
+
 #[test]
 fn test_rvd_handshake() {
     let mut write = Vec::new();
@@ -110,7 +172,7 @@ fn test_rvd_handshake() {
 
     host.handle(msg, &mut write, &mut events)
         .expect("handler failed");
-    assert_eq!(write.len(), 1);
+    assert_eq!(write.len(), 2);
     assert_eq!(events.len(), 1);
     let event = events.remove(0);
     assert!(matches!(
@@ -119,8 +181,10 @@ fn test_rvd_handshake() {
     ));
 
     let msg = write.remove(0);
+    let limit = write.remove(0);
 
     assert!(matches!(&msg, &RvdMessage::HandshakeComplete(_)));
+    assert!(matches!(&limit, &RvdMessage::ClipboardLimit(_)));
 
     client
         .handle(msg, &mut write, &mut events)
@@ -132,6 +196,19 @@ fn test_rvd_handshake() {
         event,
         InformEvent::RvdClientInform(RvdClientInform::HandshakeComplete)
     ));
+
+    // The Client answers with its own clipboard limit
+    client
+        .handle(limit, &mut write, &mut events)
+        .expect("handler failed");
+    assert_eq!(write.len(), 1);
+    let msg = write.remove(0);
+    assert!(matches!(&msg, &RvdMessage::ClipboardLimit(_)));
+
+    host.handle(msg, &mut write, &mut events)
+        .expect("handler failed");
+    assert_eq!(write.len(), 0);
+    assert_eq!(events.len(), 0);
 }
 
 #[test]
@@ -286,6 +363,109 @@ fn test_rvd_host() {
         matches!(event, InformEvent::RvdHostInform(RvdHostInform::KeyboardInput(k))  if k == key_input)
     );
 
+    // TextInput
+
+    host.handle(
+        RvdMessage::TextInput(TextInput {
+            text: "ü€😀".to_string(),
+        }),
+        &mut write,
+        &mut events,
+    )
+    .expect("handler failed");
+    assert_eq!(write.len(), 0);
+    assert_eq!(events.len(), 1);
+
+    let event = events.remove(0);
+
+    assert!(
+        matches!(event, InformEvent::RvdHostInform(RvdHostInform::TextInput(text)) if text == "ü€😀")
+    );
+
+    // PictureLoss
+
+    host.handle(
+        RvdMessage::PictureLoss(PictureLoss { display_id }),
+        &mut write,
+        &mut events,
+    )
+    .expect("handler failed");
+    assert_eq!(write.len(), 0);
+    assert_eq!(events.len(), 1);
+
+    let event = events.remove(0);
+
+    assert!(
+        matches!(event, InformEvent::RvdHostInform(RvdHostInform::PictureLoss(id)) if id == display_id)
+    );
+
+    // Displays that aren't shared have nothing to send a keyframe for
+    host.handle(
+        RvdMessage::PictureLoss(PictureLoss {
+            display_id: display_id + 1,
+        }),
+        &mut write,
+        &mut events,
+    )
+    .expect("handler failed");
+    assert_eq!(write.len(), 0);
+    assert_eq!(events.len(), 0);
+
+    // FrameNack
+
+    host.handle(
+        RvdMessage::FrameNack(FrameNack {
+            display_id,
+            sequences: vec![4, 6],
+        }),
+        &mut write,
+        &mut events,
+    )
+    .expect("handler failed");
+    assert_eq!(write.len(), 0);
+    assert_eq!(events.len(), 1);
+
+    let event = events.remove(0);
+
+    assert!(
+        matches!(event, InformEvent::RvdHostInform(RvdHostInform::FrameNack(id, sequences)) if id == display_id && sequences == [4, 6])
+    );
+
+    // ReceiverReport
+
+    let report = ReceiverReport {
+        display_id,
+        fraction_lost: 64,
+        lost: 5,
+        jitter: 900,
+        received_bytes: 123456,
+    };
+    host.handle(
+        RvdMessage::ReceiverReport(report.clone()),
+        &mut write,
+        &mut events,
+    )
+    .expect("handler failed");
+    assert_eq!(write.len(), 0);
+    assert_eq!(events.len(), 1);
+
+    let event = events.remove(0);
+
+    assert!(
+        matches!(event, InformEvent::RvdHostInform(RvdHostInform::ReceiverReport(r)) if r == report)
+    );
+
+    host.handle(
+        RvdMessage::ReceiverReport(ReceiverReport {
+            display_id: display_id + 1,
+            ..report
+        }),
+        &mut write,
+        &mut events,
+    )
+    .expect("handler failed");
+    assert_eq!(events.len(), 0);
+
 
     // ClipboardRequest
 
@@ -341,3 +521,893 @@ fn test_rvd_host() {
 
 
 // TODO test permission errors
+
+#[test]
+fn test_rvd_host_share_display_with_id() {
+    let mut host = RvdHostHandler::new();
+    handshake(Some(&mut host), None);
+
+    let msg = host
+        .share_display_with_id(0, "fake_display".to_string(), AccessMask::empty())
+        .expect("share_display_with_id failed");
+    assert!(matches!(
+        msg,
+        RvdMessage::DisplayShare(DisplayShare { display_id: 0, access, .. }) if access.is_empty()
+    ));
+
+    assert!(matches!(
+        host.share_display_with_id(0, "fake_display".to_string(), AccessMask::empty()),
+        Err(RvdHostError::DisplayIdInUse(0))
+    ));
+
+    // IDs picked by share_display skip the ones already in use
+    let (display_id, _) = host
+        .share_display("fake_display".to_string(), AccessMask::empty())
+        .expect("share_display failed");
+    assert_eq!(display_id, 1);
+}
+
+#[test]
+fn test_rvd_client_clipboard_changed() {
+    let mut write = Vec::new();
+    let mut events = Vec::new();
+
+    let mut host = RvdHostHandler::new();
+    let mut client = RvdClientHandler::new();
+    handshake(Some(&mut host), Some(&mut client));
+
+    // Nothing is sent until the Host lets us write to its clipboard
+    assert!(client
+        .clipboard_changed(ClipboardType::Text, vec![1, 2, 3])
+        .is_none());
+
+    let msg = host.set_permissions(PermissionMask::CLIPBOARD_WRITE);
+    client
+        .handle(msg, &mut write, &mut events)
+        .expect("handler failed");
+    assert!(write.is_empty());
+    assert!(events.is_empty());
+
+    let msg = client
+        .clipboard_changed(ClipboardType::Text, vec![1, 2, 3])
+        .expect("nothing was sent");
+    host.handle(msg, &mut write, &mut events)
+        .expect("handler failed");
+    assert_eq!(events.len(), 1);
+    assert!(matches!(
+        events.remove(0),
+        InformEvent::RvdHostInform(RvdHostInform::ClipboardNotification(
+            content,
+            ClipboardType::Text
+        )) if content == vec![1, 2, 3]
+    ));
+}
+
+#[test]
+fn test_rvd_host_clipboard_reply() {
+    let mut host = RvdHostHandler::new();
+    handshake(Some(&mut host), None);
+
+    // Clients that can't read the clipboard aren't answered
+    assert!(host
+        .clipboard_reply(ClipboardType::Text, false, Some(vec![1, 2, 3]))
+        .is_none());
+
+    host.set_permissions(PermissionMask::CLIPBOARD_READ);
+    let msg = host.clipboard_reply(ClipboardType::Text, false, Some(vec![1, 2, 3]));
+    assert!(matches!(
+        msg,
+        Some(RvdMessage::ClipboardNotification(ClipboardNotification {
+            info: ClipboardMeta {
+                clipboard_type: ClipboardType::Text,
+                content_request: false,
+            },
+            type_exists: true,
+            content: None,
+        }))
+    ));
+    let msg = host.clipboard_reply(ClipboardType::Image, true, None);
+    assert!(matches!(
+        msg,
+        Some(RvdMessage::ClipboardNotification(ClipboardNotification {
+            type_exists: false,
+            content: None,
+            ..
+        }))
+    ));
+}
+
+#[test]
+fn test_rvd_host_clipboard_changed() {
+    let mut host = RvdHostHandler::new();
+    handshake(Some(&mut host), None);
+
+    // Nothing is pushed without permission to read the clipboard
+    assert!(host
+        .clipboard_changed(ClipboardType::Text, vec![1, 2, 3])
+        .is_none());
+
+    host.set_permissions(PermissionMask::CLIPBOARD_READ);
+    let msg = host.clipboard_changed(ClipboardType::Text, vec![1, 2, 3]);
+    assert!(matches!(
+        msg,
+        Some(RvdMessage::ClipboardNotification(ClipboardNotification {
+            info: ClipboardMeta {
+                clipboard_type: ClipboardType::Text,
+                content_request: true,
+            },
+            type_exists: true,
+            content: Some(content),
+        })) if content == vec![1, 2, 3]
+    ));
+}
+
+#[test]
+fn test_rvd_host_display_access() {
+    let mut write = Vec::new();
+    let mut events = Vec::new();
+
+    let mut host = RvdHostHandler::new();
+    handshake(Some(&mut host), None);
+
+    // Keys are only accepted once some display is controllable
+    assert!(matches!(
+        host._handle(
+            RvdMessage::KeyInput(KeyInput {
+                down: true,
+                key: 20
+            }),
+            &mut write,
+            &mut events
+        ),
+        Err(RvdHostError::PermissionsError(_))
+    ));
+
+    let (controllable, _) = host
+        .share_display("controllable".to_string(), AccessMask::CONTROLLABLE)
+        .expect("share_display failed");
+    let (view_only, _) = host
+        .share_display("view_only".to_string(), AccessMask::empty())
+        .expect("share_display failed");
+
+    let mouse_input = |display_id| MouseInput {
+        display_id,
+        x_location: 1,
+        y_location: 2,
+        buttons_delta: ButtonsMask::empty(),
+        buttons_state: ButtonsMask::empty(),
+    };
+
+    // Mouse input is checked against the display it targets
+    host.handle(
+        RvdMessage::MouseInput(mouse_input(controllable)),
+        &mut write,
+        &mut events,
+    )
+    .expect("handler failed");
+    assert!(matches!(
+        events.remove(0),
+        InformEvent::RvdHostInform(RvdHostInform::MouseInput(_))
+    ));
+    assert!(matches!(
+        host._handle(
+            RvdMessage::MouseInput(mouse_input(view_only)),
+            &mut write,
+            &mut events
+        ),
+        Err(RvdHostError::PermissionsError(_))
+    ));
+
+    // Changing access reshares the display under the same ID
+    let messages = host
+        .set_display_access(view_only, AccessMask::CONTROLLABLE)
+        .expect("set_display_access failed");
+    assert_eq!(messages.len(), 2);
+    assert!(matches!(
+        &messages[0],
+        RvdMessage::DisplayUnshare(msg) if msg.display_id == view_only
+    ));
+    assert!(matches!(
+        &messages[1],
+        RvdMessage::DisplayShare(DisplayShare { display_id, access, name })
+        if *display_id == view_only && *access == AccessMask::CONTROLLABLE && name == "view_only"
+    ));
+
+    host.handle(
+        RvdMessage::MouseInput(mouse_input(view_only)),
+        &mut write,
+        &mut events,
+    )
+    .expect("handler failed");
+    assert!(matches!(
+        events.remove(0),
+        InformEvent::RvdHostInform(RvdHostInform::MouseInput(_))
+    ));
+
+    // Nothing is sent when the access doesn't change
+    let messages = host
+        .set_display_access(controllable, AccessMask::CONTROLLABLE)
+        .expect("set_display_access failed");
+    assert!(messages.is_empty());
+
+    // Input still in flight when a display is made view only doesn't end the connection
+    host.set_display_access(controllable, AccessMask::empty())
+        .expect("set_display_access failed");
+    host.handle(
+        RvdMessage::MouseInput(mouse_input(controllable)),
+        &mut write,
+        &mut events,
+    )
+    .expect("handler failed");
+    assert!(matches!(
+        events.remove(0),
+        InformEvent::RvdHostInform(RvdHostInform::InputRejected)
+    ));
+
+    assert!(matches!(
+        host.set_display_access(42, AccessMask::empty()),
+        Err(RvdHostError::DisplayNotFound(42))
+    ));
+}
+
+#[test]
+fn test_rvd_control() {
+    let mut write = Vec::new();
+    let mut events = Vec::new();
+
+    let mut host = RvdHostHandler::new();
+    let mut client = RvdClientHandler::new();
+    handshake(Some(&mut host), Some(&mut client));
+    assert!(host.has_control());
+    assert!(client.has_control());
+
+    let (display_id, _) = host
+        .share_display("fake_display".to_string(), AccessMask::CONTROLLABLE)
+        .expect("share_display failed");
+
+    // Revoke
+    let msg = host.revoke_control();
+    assert!(matches!(msg, RvdMessage::ControlRevoke(_)));
+    assert!(!host.has_control());
+
+    client
+        .handle(msg, &mut write, &mut events)
+        .expect("handler failed");
+    assert!(!client.has_control());
+    assert!(matches!(
+        events.remove(0),
+        InformEvent::RvdClientInform(RvdClientInform::ControlChanged(false))
+    ));
+
+    // Input is rejected without the controls
+    let mouse_input = MouseInput {
+        display_id,
+        x_location: 1,
+        y_location: 2,
+        buttons_delta: ButtonsMask::empty(),
+        buttons_state: ButtonsMask::empty(),
+    };
+    host.handle(RvdMessage::MouseInput(mouse_input), &mut write, &mut events)
+        .expect("handler failed");
+    host.handle(
+        RvdMessage::KeyInput(KeyInput {
+            down: true,
+            key: 20,
+        }),
+        &mut write,
+        &mut events,
+    )
+    .expect("handler failed");
+    host.handle(
+        RvdMessage::TextInput(TextInput {
+            text: "a".to_string(),
+        }),
+        &mut write,
+        &mut events,
+    )
+    .expect("handler failed");
+    assert_eq!(write.len(), 0);
+    assert_eq!(events.len(), 3);
+    assert!(events.drain(..).all(|event| matches!(
+        event,
+        InformEvent::RvdHostInform(RvdHostInform::InputRejected)
+    )));
+
+    // Request
+    host.handle(
+        RvdMessage::ControlRequest(ControlRequest {}),
+        &mut write,
+        &mut events,
+    )
+    .expect("handler failed");
+    assert_eq!(write.len(), 0);
+    assert!(matches!(
+        events.remove(0),
+        InformEvent::RvdHostInform(RvdHostInform::ControlRequest)
+    ));
+
+    // Grant
+    let msg = host.grant_control();
+    assert!(matches!(msg, RvdMessage::ControlGrant(_)));
+    assert!(host.has_control());
+
+    client
+        .handle(msg, &mut write, &mut events)
+        .expect("handler failed");
+    assert!(client.has_control());
+    assert!(matches!(
+        events.remove(0),
+        InformEvent::RvdClientInform(RvdClientInform::ControlChanged(true))
+    ));
+
+    // Requests from the Client holding the controls are answered with another grant
+    host.handle(
+        RvdMessage::ControlRequest(ControlRequest {}),
+        &mut write,
+        &mut events,
+    )
+    .expect("handler failed");
+    assert_eq!(events.len(), 0);
+    assert!(matches!(write.remove(0), RvdMessage::ControlGrant(_)));
+}
+
+#[test]
+fn test_rvd_file_transfer() {
+    let mut write = Vec::new();
+    let mut events = Vec::new();
+
+    let mut host = RvdHostHandler::new();
+    let mut client = RvdClientHandler::new();
+    handshake(Some(&mut host), Some(&mut client));
+
+    // Client -> Host
+    let (transfer_id, msg) = client
+        .file_transfers()
+        .offer("logs.zip".to_string(), 5, [1; 32]);
+    host.handle(msg, &mut write, &mut events)
+        .expect("handler failed");
+    assert!(matches!(
+        events.remove(0),
+        InformEvent::RvdHostInform(RvdHostInform::FileTransfer(FileTransferInform::Offered(offer)))
+            if offer.transfer_id == transfer_id && offer.name == "logs.zip" && offer.size == 5
+    ));
+
+    // The Host already has the first 2 bytes
+    let msg = host
+        .file_transfers()
+        .accept(transfer_id, 2)
+        .expect("accept failed");
+    client
+        .handle(msg, &mut write, &mut events)
+        .expect("handler failed");
+    assert!(matches!(
+        events.remove(0),
+        InformEvent::RvdClientInform(RvdClientInform::FileTransfer(
+            FileTransferInform::Accepted { transfer_id: id, offset: 2 }
+        )) if id == transfer_id
+    ));
+
+    // Sending past the end of the file fails
+    assert!(client.file_transfers().chunk(transfer_id, b"cdef").is_err());
+
+    let (offset, msg) = client
+        .file_transfers()
+        .chunk(transfer_id, b"cde")
+        .expect("chunk failed");
+    assert_eq!(offset, 5);
+    host.handle(msg, &mut write, &mut events)
+        .expect("handler failed");
+    assert!(matches!(
+        events.remove(0),
+        InformEvent::RvdHostInform(RvdHostInform::FileTransfer(FileTransferInform::Chunk {
+            offset: 2,
+            data,
+            done: true,
+            ..
+        })) if data == b"cde"
+    ));
+
+    // Chunks are acknowledged
+    let ack = write.remove(0);
+    assert!(matches!(
+        ack,
+        RvdMessage::FileAck(FileAck { offset: 5, .. })
+    ));
+    client
+        .handle(ack, &mut write, &mut events)
+        .expect("handler failed");
+    assert!(matches!(
+        events.remove(0),
+        InformEvent::RvdClientInform(RvdClientInform::FileTransfer(FileTransferInform::Acked {
+            offset: 5,
+            ..
+        }))
+    ));
+
+    let msg = host
+        .file_transfers()
+        .complete(transfer_id, true)
+        .expect("complete failed");
+    client
+        .handle(msg, &mut write, &mut events)
+        .expect("handler failed");
+    assert!(matches!(
+        events.remove(0),
+        InformEvent::RvdClientInform(RvdClientInform::FileTransfer(
+            FileTransferInform::Completed { hash_ok: true, .. }
+        ))
+    ));
+
+    // Host -> Client, cancelled by the Client while a chunk is in flight
+    let (transfer_id, msg) = host
+        .file_transfers()
+        .offer("collector.sh".to_string(), 10, [2; 32]);
+    client
+        .handle(msg, &mut write, &mut events)
+        .expect("handler failed");
+    events.clear();
+    let msg = client
+        .file_transfers()
+        .accept(transfer_id, 0)
+        .expect("accept failed");
+    host.handle(msg, &mut write, &mut events)
+        .expect("handler failed");
+    events.clear();
+
+    let (_, chunk) = host
+        .file_transfers()
+        .chunk(transfer_id, b"#!/bin")
+        .expect("chunk failed");
+    let cancel = client
+        .file_transfers()
+        .cancel(transfer_id, false)
+        .expect("cancel failed");
+    client
+        .handle(chunk, &mut write, &mut events)
+        .expect("handler failed");
+    assert!(events.is_empty());
+    host.handle(cancel, &mut write, &mut events)
+        .expect("handler failed");
+    assert!(matches!(
+        events.remove(0),
+        InformEvent::RvdHostInform(RvdHostInform::FileTransfer(FileTransferInform::Cancelled {
+            transfer_id: id,
+            outgoing: true,
+        })) if id == transfer_id
+    ));
+    assert_eq!(write.len(), 0);
+}
+
+#[test]
+fn test_rvd_file_transfer_out_of_order() {
+    let mut write = Vec::new();
+    let mut events = Vec::new();
+
+    let mut host = RvdHostHandler::new();
+    let mut client = RvdClientHandler::new();
+    handshake(Some(&mut host), Some(&mut client));
+
+    let (transfer_id, msg) = host.file_transfers().offer("file".to_string(), 10, [0; 32]);
+    client
+        .handle(msg, &mut write, &mut events)
+        .expect("handler failed");
+    let msg = client
+        .file_transfers()
+        .accept(transfer_id, 0)
+        .expect("accept failed");
+    host.handle(msg, &mut write, &mut events)
+        .expect("handler failed");
+
+    let chunk = RvdMessage::FileChunk(FileChunk {
+        transfer_id,
+        offset: 4,
+        data: Data(Cow::Borrowed(b"data")),
+    });
+    assert!(matches!(
+        client.handle(chunk, &mut write, &mut events),
+        Err(RvdError::Client(RvdClientError::FileTransfer(
+            FileTransferError::OutOfOrder(_, 4)
+        )))
+    ));
+}
+
+#[test]
+fn test_rvd_file_transfer_window() {
+    let mut write = Vec::new();
+    let mut events = Vec::new();
+
+    let mut host = RvdHostHandler::new();
+    let mut client = RvdClientHandler::new();
+    handshake(Some(&mut host), Some(&mut client));
+
+    let size = FILE_WINDOW_SIZE * 2;
+    let (transfer_id, msg) = host
+        .file_transfers()
+        .offer("file".to_string(), size, [0; 32]);
+    client
+        .handle(msg, &mut write, &mut events)
+        .expect("handler failed");
+    let msg = client
+        .file_transfers()
+        .accept(transfer_id, 0)
+        .expect("accept failed");
+    host.handle(msg, &mut write, &mut events)
+        .expect("handler failed");
+    events.clear();
+
+    // The sender stops once a window's worth is unacknowledged
+    let data = vec![0; FILE_CHUNK_SIZE];
+    let mut chunks = Vec::new();
+    while host.file_transfers().window_open(transfer_id) {
+        let (_, chunk) = host
+            .file_transfers()
+            .chunk(transfer_id, &data)
+            .expect("chunk failed");
+        chunks.push(chunk);
+    }
+    assert_eq!(
+        chunks.len() as u64 * FILE_CHUNK_SIZE as u64,
+        FILE_WINDOW_SIZE
+    );
+    assert!(host.file_transfers().chunk(transfer_id, &data).is_err());
+
+    for chunk in chunks {
+        client
+            .handle(chunk, &mut write, &mut events)
+            .expect("handler failed");
+    }
+    for ack in write.drain(..).collect::<Vec<_>>() {
+        host.handle(ack, &mut write, &mut events)
+            .expect("handler failed");
+    }
+    assert!(host.file_transfers().window_open(transfer_id));
+}
+
+#[test]
+fn test_rvd_clipboard_chunked() {
+    let mut write = Vec::new();
+    let mut events = Vec::new();
+
+    let mut host = RvdHostHandler::new();
+    let mut client = RvdClientHandler::new();
+    handshake(Some(&mut host), Some(&mut client));
+    host.set_permissions(PermissionMask::CLIPBOARD_READ);
+
+    let content: Vec<u8> = (0 .. CLIPBOARD_CHUNK_SIZE * 10 + 7)
+        .map(|i| i as u8)
+        .collect();
+    // Nothing larger than a chunk is sent until the Client says how much it accepts
+    assert!(host
+        .clipboard_changed(ClipboardType::Image, content.clone())
+        .is_none());
+    client
+        .handle(
+            RvdMessage::ClipboardLimit(ClipboardLimit {
+                max_size: DEFAULT_CLIPBOARD_LIMIT,
+            }),
+            &mut write,
+            &mut events,
+        )
+        .expect("handler failed");
+    let msg = write.remove(0);
+    host.handle(msg, &mut write, &mut events)
+        .expect("handler failed");
+
+    let start = host
+        .clipboard_changed(ClipboardType::Image, content.clone())
+        .expect("nothing was sent");
+    assert!(matches!(
+        &start,
+        RvdMessage::ClipboardStart(ClipboardStart { size, .. }) if *size as usize == content.len()
+    ));
+
+    // Chunks only go out as the Client acknowledges them
+    let mut to_client = vec![start];
+    while !to_client.is_empty() {
+        let mut to_host = Vec::new();
+        for msg in to_client.drain(..) {
+            client
+                .handle(msg, &mut to_host, &mut events)
+                .expect("handler failed");
+        }
+        for msg in to_host {
+            host.handle(msg, &mut to_client, &mut events)
+                .expect("handler failed");
+        }
+        assert!(to_client.len() <= CLIPBOARD_WINDOW as usize);
+    }
+
+    assert_eq!(events.len(), 1);
+    assert!(matches!(
+        events.remove(0),
+        InformEvent::RvdClientInform(RvdClientInform::ClipboardNotification(
+            received,
+            ClipboardType::Image
+        )) if received == content
+    ));
+}
+
+#[test]
+fn test_rvd_clipboard_limit() {
+    let mut write = Vec::new();
+    let mut events = Vec::new();
+
+    let mut host = RvdHostHandler::new();
+    handshake(Some(&mut host), None);
+    host.set_permissions(PermissionMask::CLIPBOARD_READ);
+
+    host.handle(
+        RvdMessage::ClipboardLimit(ClipboardLimit {
+            max_size: CLIPBOARD_CHUNK_SIZE as u32 * 2,
+        }),
+        &mut write,
+        &mut events,
+    )
+    .expect("handler failed");
+    assert!(host
+        .clipboard_changed(ClipboardType::Text, vec![0; CLIPBOARD_CHUNK_SIZE * 2 + 1])
+        .is_none());
+    assert!(host
+        .clipboard_changed(ClipboardType::Text, vec![0; CLIPBOARD_CHUNK_SIZE * 2])
+        .is_some());
+
+    let start = || {
+        RvdMessage::ClipboardStart(ClipboardStart {
+            transfer_id: 0,
+            info: ClipboardMeta {
+                clipboard_type: ClipboardType::Text,
+                content_request: true,
+            },
+            size: DEFAULT_CLIPBOARD_LIMIT + 1,
+        })
+    };
+    // Clients need permission to write to the clipboard
+    assert!(matches!(
+        host.handle(start(), &mut write, &mut events),
+        Err(RvdError::Host(RvdHostError::PermissionsError(_)))
+    ));
+    // and mustn't send more than the Host accepts
+    host.set_permissions(PermissionMask::CLIPBOARD_WRITE);
+    assert!(matches!(
+        host.handle(start(), &mut write, &mut events),
+        Err(RvdError::Host(RvdHostError::ClipboardTransfer(
+            ClipboardTransferError::TooLarge(..)
+        )))
+    ));
+}
+
+#[test]
+fn test_rvd_clipboard_out_of_order() {
+    let mut write = Vec::new();
+    let mut events = Vec::new();
+
+    let mut client = RvdClientHandler::new();
+    handshake(None, Some(&mut client));
+
+    client
+        .handle(
+            RvdMessage::ClipboardStart(ClipboardStart {
+                transfer_id: 3,
+                info: ClipboardMeta {
+                    clipboard_type: ClipboardType::Text,
+                    content_request: true,
+                },
+                size: CLIPBOARD_CHUNK_SIZE as u32 * 2,
+            }),
+            &mut write,
+            &mut events,
+        )
+        .expect("handler failed");
+    assert!(matches!(
+        write.remove(0),
+        RvdMessage::ClipboardAck(ClipboardAck {
+            transfer_id: 3,
+            received: 0
+        })
+    ));
+
+    // Chunks of transfers that were replaced are ignored
+    let data = vec![0; CLIPBOARD_CHUNK_SIZE];
+    client
+        .handle(
+            RvdMessage::ClipboardChunk(ClipboardChunk {
+                transfer_id: 2,
+                sequence: 0,
+                data: Data(Cow::Borrowed(&data)),
+            }),
+            &mut write,
+            &mut events,
+        )
+        .expect("handler failed");
+    assert!(write.is_empty());
+    assert!(events.is_empty());
+
+    assert!(matches!(
+        client.handle(
+            RvdMessage::ClipboardChunk(ClipboardChunk {
+                transfer_id: 3,
+                sequence: 1,
+                data: Data(Cow::Borrowed(&data)),
+            }),
+            &mut write,
+            &mut events,
+        ),
+        Err(RvdError::Client(RvdClientError::ClipboardTransfer(
+            ClipboardTransferError::OutOfOrder(3, 1)
+        )))
+    ));
+}
+
+fn cursor(width: u16, fill: u8) -> CursorImage {
+    CursorImage {
+        width,
+        height: 2,
+        hotspot_x: 0,
+        hotspot_y: 1,
+        data: vec![fill; width as usize * 2 * 4],
+    }
+}
+
+// Sends the cursor from the Host to the Client, returns whether it was sent in full
+fn show_cursor(
+    host: &mut RvdHostHandler,
+    client: &mut RvdClientHandler,
+    cursor: &CursorImage,
+) -> bool {
+    let mut write = Vec::new();
+    let mut events = Vec::new();
+
+    let msg = host.cursor_changed(cursor).expect("cursor not sent");
+    host.cursor_sent(cursor);
+    let full = matches!(msg, RvdMessage::CursorShape(_));
+    client
+        .handle(msg, &mut write, &mut events)
+        .expect("handler failed");
+    match events.remove(0) {
+        InformEvent::RvdClientInform(RvdClientInform::CursorShape(shown)) =>
+            assert_eq!(&shown, cursor),
+        _ => panic!("wrong event"),
+    }
+    full
+}
+
+#[test]
+fn test_rvd_cursor_cache() {
+    let mut write = Vec::new();
+    let mut events = Vec::new();
+
+    let mut host = RvdHostHandler::new();
+    let mut client = RvdClientHandler::new();
+
+    let arrow = cursor(2, 0);
+    let beam = cursor(1, 255);
+
+    // Nothing is sent before the handshake
+    assert!(host.cursor_changed(&arrow).is_none());
+
+    handshake(Some(&mut host), Some(&mut client));
+
+    assert!(show_cursor(&mut host, &mut client, &arrow));
+    // The cursor hasn't changed so there's nothing to send
+    assert!(host.cursor_changed(&arrow).is_none());
+    assert!(show_cursor(&mut host, &mut client, &beam));
+    assert!(!show_cursor(&mut host, &mut client, &arrow));
+
+    // A shape that never made it to the Client is sent in full again
+    let pointer = cursor(3, 128);
+    assert!(matches!(
+        host.cursor_changed(&pointer),
+        Some(RvdMessage::CursorShape(_))
+    ));
+    assert!(show_cursor(&mut host, &mut client, &pointer));
+
+    assert!(matches!(
+        client.handle(
+            RvdMessage::CursorShapeCached(CursorShapeCached { cursor_id: 7 }),
+            &mut write,
+            &mut events,
+        ),
+        Err(RvdError::Client(RvdClientError::Cursor(
+            CursorError::UnknownCursor(7)
+        )))
+    ));
+}
+
+#[test]
+fn test_rvd_cursor_eviction() {
+    let mut host = RvdHostHandler::new();
+    let mut client = RvdClientHandler::new();
+    handshake(Some(&mut host), Some(&mut client));
+
+    let cursors: Vec<CursorImage> = (0 ..= CURSOR_CACHE_SIZE as u8)
+        .map(|fill| cursor(1, fill))
+        .collect();
+    for cursor in &cursors {
+        assert!(show_cursor(&mut host, &mut client, cursor));
+    }
+
+    // The second is still remembered, the first was forgotten so it's sent in full again
+    assert!(!show_cursor(&mut host, &mut client, &cursors[1]));
+    assert!(show_cursor(&mut host, &mut client, &cursors[0]));
+    assert!(!show_cursor(&mut host, &mut client, &cursors[2]));
+}
+
+#[test]
+fn test_rvd_cursor_bad_image() {
+    let mut write = Vec::new();
+    let mut events = Vec::new();
+
+    let mut client = RvdClientHandler::new();
+    handshake(None, Some(&mut client));
+
+    assert!(matches!(
+        client.handle(
+            RvdMessage::CursorShape(CursorShape {
+                cursor_id: 1,
+                width: 4,
+                height: 4,
+                hotspot_x: 0,
+                hotspot_y: 0,
+                data: Data(Cow::Owned(vec![0; 4])),
+            }),
+            &mut write,
+            &mut events,
+        ),
+        Err(RvdError::Client(RvdClientError::Cursor(
+            CursorError::BadImage(1)
+        )))
+    ));
+}
+
+#[test]
+fn test_rvd_display_geometry() {
+    let mut write = Vec::new();
+    let mut events = Vec::new();
+
+    let mut host = RvdHostHandler::new();
+    let mut client = RvdClientHandler::new();
+    handshake(Some(&mut host), Some(&mut client));
+
+    let geometry = DisplayGeometry {
+        display_id: 0,
+        width: 1080,
+        height: 1920,
+        scale: 150,
+        rotation: DisplayRotation::Clockwise90,
+    };
+    assert!(matches!(
+        host.set_display_geometry(geometry.clone()),
+        Err(RvdHostError::DisplayNotFound(0))
+    ));
+
+    let (display_id, _) = host
+        .share_display("Display".to_string(), AccessMask::empty())
+        .expect("share_display failed");
+    let msg = host
+        .set_display_geometry(geometry.clone())
+        .expect("set_display_geometry failed")
+        .expect("geometry not sent");
+    assert!(host
+        .set_display_geometry(geometry.clone())
+        .expect("set_display_geometry failed")
+        .is_none());
+
+    client
+        .handle(msg, &mut write, &mut events)
+        .expect("handler failed");
+    match events.remove(0) {
+        InformEvent::RvdClientInform(RvdClientInform::DisplayGeometry(received)) =>
+            assert_eq!(received, geometry),
+        _ => panic!("wrong event"),
+    }
+
+    // Sharing the display again doesn't lose its geometry
+    let messages = host
+        .set_display_access(display_id, AccessMask::CONTROLLABLE)
+        .expect("set_display_access failed");
+    assert!(matches!(messages.as_slice(), [
+        RvdMessage::DisplayUnshare(_),
+        RvdMessage::DisplayShare(_),
+        RvdMessage::DisplayGeometry(_)
+    ]));
+}
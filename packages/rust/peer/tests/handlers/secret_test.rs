@@ -0,0 +1,49 @@
+use peer::helpers::{
+    crypto::{kdf2, ratchet},
+    secret::{zeroize, SecretKey, SecretVec},
+};
+
+#[test]
+fn test_zeroize() {
+    let mut bytes = [7u8; 64];
+    zeroize(&mut bytes);
+    assert_eq!(bytes, [0u8; 64]);
+}
+
+#[test]
+fn test_secret_vec_zeroize() {
+    let mut password = Vec::with_capacity(32);
+    password.extend_from_slice(b"hunter2");
+    let mut password = SecretVec::from(password);
+    assert_eq!(&*password, b"hunter2");
+
+    password.zeroize();
+    assert_eq!(&*password, &[0u8; 7]);
+}
+
+#[test]
+fn test_secret_key_zeroize() {
+    // Drop calls zeroize, so checking it is checking what's left behind when a key is dropped
+    let mut key = SecretKey::new([7u8; 32]);
+    key.zeroize();
+    assert_eq!(*key, [0u8; 32]);
+}
+
+#[test]
+fn test_secret_debug_redacted() {
+    let password = SecretVec::from(&b"hunter2"[..]);
+    assert_eq!(format!("{:?}", password), "SecretVec([REDACTED; 7])");
+
+    let key = SecretKey::new([7u8; 32]);
+    assert_eq!(format!("{:?}", key), "SecretKey([REDACTED; 32])");
+}
+
+#[test]
+fn test_kdf_keys() {
+    let (send_key, receive_key) = kdf2(&[1u8; 32]);
+    assert_ne!(*send_key, *receive_key);
+    assert_eq!(*kdf2(&[1u8; 32]).0, *send_key);
+
+    let next = ratchet(&send_key[..]);
+    assert_ne!(*next, *send_key);
+}
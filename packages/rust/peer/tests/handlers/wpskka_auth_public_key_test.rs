@@ -0,0 +1,149 @@
+use common::messages::auth::public_key::PublicKeyMessage;
+use peer::{
+    helpers::crypto::{keypair, IdentityKeyPair, KeyPair},
+    wpskka::auth::{
+        public_key_client::{PublicKeyAuthClient, PublicKeyClientError},
+        public_key_host::{PublicKeyAuthHost, PublicKeyHostError},
+        TrustedKeys,
+    },
+};
+
+fn auth_pair(
+    host_keys: KeyPair,
+    client_keys: KeyPair,
+) -> (PublicKeyAuthHost<32>, PublicKeyAuthClient<32>) {
+    let host = PublicKeyAuthHost::<32>::new(
+        host_keys.public_key.clone(),
+        client_keys.public_key.as_ref().try_into().unwrap(),
+    );
+    let client = PublicKeyAuthClient::<32>::new(
+        client_keys.public_key,
+        host_keys.public_key.as_ref().try_into().unwrap(),
+    );
+    (host, client)
+}
+
+#[test]
+fn auth_succeed() {
+    let host_identity = IdentityKeyPair::generate().unwrap();
+    let client_identity = IdentityKeyPair::generate().unwrap();
+    let trusted_hosts = TrustedKeys::from_iter([host_identity.public_key()]);
+    let authorized_clients = TrustedKeys::from_iter([client_identity.public_key()]);
+
+    let (mut host, mut client) = auth_pair(keypair().unwrap(), keypair().unwrap());
+
+    assert!(!host.is_authenticated());
+    assert!(!client.is_authenticated());
+
+    // HostIdentity
+
+    let message = host.init(&host_identity);
+    assert!(matches!(message, PublicKeyMessage::HostIdentity(_)));
+
+    let message = client
+        .handle(message, Some(&client_identity), &trusted_hosts)
+        .expect("handle failed");
+    assert!(client.is_authenticated());
+    assert_eq!(client.host_identity(), Some(&host_identity.public_key()));
+    assert!(matches!(message, PublicKeyMessage::ClientIdentity(_)));
+
+    // ClientIdentity
+
+    host.handle(message, &authorized_clients)
+        .expect("handle failed");
+    assert!(host.is_authenticated());
+    assert_eq!(host.client_identity(), Some(&client_identity.public_key()));
+}
+
+#[test]
+fn auth_untrusted_host() {
+    let host_identity = IdentityKeyPair::generate().unwrap();
+    let client_identity = IdentityKeyPair::generate().unwrap();
+
+    let (mut host, mut client) = auth_pair(keypair().unwrap(), keypair().unwrap());
+
+    let message = host.init(&host_identity);
+    let error = client
+        .handle(message, Some(&client_identity), &TrustedKeys::new())
+        .err()
+        .unwrap();
+
+    assert!(!client.is_authenticated());
+    assert!(
+        matches!(error, PublicKeyClientError::UntrustedHost(key) if key == host_identity.public_key())
+    );
+}
+
+#[test]
+fn auth_unauthorized_client() {
+    let host_identity = IdentityKeyPair::generate().unwrap();
+    let client_identity = IdentityKeyPair::generate().unwrap();
+    let trusted_hosts = TrustedKeys::from_iter([host_identity.public_key()]);
+
+    let (mut host, mut client) = auth_pair(keypair().unwrap(), keypair().unwrap());
+
+    let message = host.init(&host_identity);
+    let message = client
+        .handle(message, Some(&client_identity), &trusted_hosts)
+        .expect("handle failed");
+
+    let error = host.handle(message, &TrustedKeys::new()).err().unwrap();
+
+    assert!(!host.is_authenticated());
+    assert!(matches!(error, PublicKeyHostError::UnauthorizedClient(_)));
+}
+
+#[test]
+fn auth_no_client_identity() {
+    let host_identity = IdentityKeyPair::generate().unwrap();
+    let trusted_hosts = TrustedKeys::from_iter([host_identity.public_key()]);
+
+    let (mut host, mut client) = auth_pair(keypair().unwrap(), keypair().unwrap());
+
+    let message = host.init(&host_identity);
+    let error = client.handle(message, None, &trusted_hosts).err().unwrap();
+
+    assert!(!client.is_authenticated());
+    assert!(matches!(error, PublicKeyClientError::NoIdentity));
+}
+
+#[test]
+fn auth_signature_bound_to_session() {
+    let host_identity = IdentityKeyPair::generate().unwrap();
+    let client_identity = IdentityKeyPair::generate().unwrap();
+    let trusted_hosts = TrustedKeys::from_iter([host_identity.public_key()]);
+    let authorized_clients = TrustedKeys::from_iter([client_identity.public_key()]);
+
+    let host_keys = keypair().unwrap();
+    let host_public_key = host_keys.public_key.clone();
+    let (mut host, mut client) = auth_pair(host_keys, keypair().unwrap());
+
+    // A HostIdentity from another session can't be replayed
+    let (mut other_host, _) = auth_pair(keypair().unwrap(), keypair().unwrap());
+    let replayed = other_host.init(&host_identity);
+    let error = client
+        .handle(replayed, Some(&client_identity), &trusted_hosts)
+        .err()
+        .unwrap();
+    assert!(matches!(error, PublicKeyClientError::AuthFailed));
+
+    // Neither can a ClientIdentity
+    let other_client_keys = keypair().unwrap();
+    let mut other_client = PublicKeyAuthClient::<32>::new(
+        other_client_keys.public_key,
+        host_public_key.as_ref().try_into().unwrap(),
+    );
+    let message = host.init(&host_identity);
+    let replayed = other_client
+        .handle(message, Some(&client_identity), &trusted_hosts)
+        .expect("handle failed");
+    let error = host.handle(replayed, &authorized_clients).err().unwrap();
+    assert!(matches!(error, PublicKeyHostError::AuthFailed));
+}
+
+#[test]
+fn identity_pkcs8_round_trip() {
+    let identity = IdentityKeyPair::generate().unwrap();
+    let restored = IdentityKeyPair::from_pkcs8(identity.pkcs8()).unwrap();
+    assert_eq!(identity.public_key(), restored.public_key());
+}
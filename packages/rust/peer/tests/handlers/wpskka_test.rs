@@ -8,7 +8,15 @@ use common::messages::{
     Data,
 };
 use peer::{
+    helpers::{
+        cipher_reliable_peer::CipherReliablePeer,
+        cipher_unreliable_peer::CipherUnreliablePeer,
+        crypto::IdentityKeyPair,
+        secret::SecretVec,
+    },
     wpskka::{
+        known_hosts::HostKey,
+        RekeyPolicy,
         WpskkaClientHandler,
         WpskkaClientInform,
         WpskkaHandlerTrait,
@@ -360,7 +368,7 @@ fn wpskka_test_auth_srp_static() {
 
     let password = b"static";
 
-    host.set_static_password(Some(password.to_vec()));
+    host.set_static_password(Some(SecretVec::from(&password[..])));
 
     test_srp_full(
         &mut host,
@@ -382,7 +390,7 @@ fn wpskka_test_auth_srp_dynamic() {
 
     let password = b"dynamic";
 
-    host.set_dynamic_password(Some(password.to_vec()));
+    host.set_dynamic_password(Some(SecretVec::from(&password[..])));
 
     test_srp_full(
         &mut host,
@@ -469,7 +477,7 @@ fn wpskka_test_auth_srp_interrupt() {
     let password = b"dynamic";
 
     host.set_none_scheme(true);
-    host.set_dynamic_password(Some(password.to_vec()));
+    host.set_dynamic_password(Some(SecretVec::from(&password[..])));
 
     let auth_schemes = key_exchange(&mut host, &mut client, &mut write, &mut events);
 
@@ -509,7 +517,7 @@ fn wpskka_test_auth_none_interrupt() {
     let password = b"dynamic";
 
     host.set_none_scheme(true);
-    host.set_dynamic_password(Some(password.to_vec()));
+    host.set_dynamic_password(Some(SecretVec::from(&password[..])));
 
     key_exchange(&mut host, &mut client, &mut write, &mut events);
 
@@ -528,3 +536,459 @@ fn wpskka_test_auth_none_interrupt() {
 
     test_ciphers(&mut host, &mut client);
 }
+
+fn public_key_peers() -> (WpskkaHostHandler, WpskkaClientHandler) {
+    let host_identity = IdentityKeyPair::generate().unwrap();
+    let client_identity = IdentityKeyPair::generate().unwrap();
+
+    let mut client = WpskkaClientHandler::new();
+    client
+        .trusted_hosts_mut()
+        .insert(host_identity.public_key());
+    client.set_identity(Some(client_identity));
+
+    let mut host = WpskkaHostHandler::new();
+    host.authorized_clients_mut()
+        .insert(client.identity().unwrap().public_key());
+    host.set_identity(Some(host_identity));
+
+    (host, client)
+}
+
+#[test]
+fn wpskka_test_auth_public_key() {
+    let (mut host, mut client) = public_key_peers();
+    let mut write = Vec::new();
+    let mut events = Vec::new();
+
+    let auth_schemes = key_exchange(&mut host, &mut client, &mut write, &mut events);
+
+    auth_schemes_check(
+        &mut client,
+        &mut write,
+        &mut events,
+        auth_schemes,
+        Some(&[AuthSchemeType::PublicKey]),
+    );
+
+    let try_auth = client.try_auth(AuthSchemeType::PublicKey);
+
+    let result = host
+        .handle(try_auth, &mut write, &mut events)
+        .expect("client->host try auth failed");
+
+    assert_eq!(result, None);
+    assert_eq!(write.len(), 1);
+    assert_eq!(events.len(), 0);
+
+    // HostIdentity
+    let auth_msg = write.remove(0);
+
+    let result = client
+        .handle(auth_msg, &mut write, &mut events)
+        .expect("host->client auth msg failed");
+
+    assert_eq!(result, None);
+    assert_eq!(write.len(), 1);
+    assert_eq!(events.len(), 0);
+
+    // ClientIdentity
+    let auth_msg = write.remove(0);
+
+    let result = host
+        .handle(auth_msg, &mut write, &mut events)
+        .expect("client->host auth msg failed");
+
+    assert_eq!(result, None);
+    assert_eq!(write.len(), 1);
+    assert_eq!(events.len(), 1);
+
+    let evt = match events.remove(0) {
+        InformEvent::WpskkaHostInform(evt) => evt,
+        _ => panic!("expected WpskkaHostInform"),
+    };
+
+    assert!(matches!(evt, WpskkaHostInform::AuthSuccessful));
+
+    let auth_success = write.remove(0);
+
+    let result = client
+        .handle(auth_success, &mut write, &mut events)
+        .expect("host->client auth success failed");
+
+    assert_eq!(result, None);
+    assert_eq!(write.len(), 0);
+    assert_eq!(events.len(), 1);
+
+    let evt = match events.remove(0) {
+        InformEvent::WpskkaClientInform(evt) => evt,
+        _ => panic!("expected WpskkaClientInform"),
+    };
+
+    assert!(matches!(evt, WpskkaClientInform::AuthSuccessful));
+
+    test_ciphers(&mut host, &mut client);
+}
+
+#[test]
+fn wpskka_test_auth_public_key_untrusted_host() {
+    let (mut host, mut client) = public_key_peers();
+    let mut write = Vec::new();
+    let mut events = Vec::new();
+
+    let host_public_key = host.identity().unwrap().public_key();
+    client.trusted_hosts_mut().remove(&host_public_key);
+
+    host.set_none_scheme(true);
+
+    key_exchange(&mut host, &mut client, &mut write, &mut events);
+
+    let try_auth = client.try_auth(AuthSchemeType::PublicKey);
+    host.handle(try_auth, &mut write, &mut events)
+        .expect("client->host try auth failed");
+
+    let auth_msg = write.remove(0);
+
+    let result = client
+        .handle(auth_msg, &mut write, &mut events)
+        .expect("host->client auth msg failed");
+
+    // We must not reveal our identity to a Host we don't trust
+    assert_eq!(result, None);
+    assert_eq!(write.len(), 0);
+    assert_eq!(events.len(), 2);
+
+    match events.remove(0) {
+        InformEvent::WpskkaClientInform(WpskkaClientInform::UntrustedHost(public_key)) =>
+            assert_eq!(public_key, host_public_key),
+        _ => panic!("expected UntrustedHost"),
+    }
+
+    assert!(matches!(
+        events.remove(0),
+        InformEvent::WpskkaClientInform(WpskkaClientInform::AuthFailed)
+    ));
+
+    // the Host is still waiting on us so we can fall back to another scheme
+    none_auth(&mut host, &mut client, &mut write, &mut events);
+
+    test_ciphers(&mut host, &mut client);
+}
+
+#[test]
+fn wpskka_test_auth_public_key_unauthorized_client() {
+    let (mut host, mut client) = public_key_peers();
+    let mut write = Vec::new();
+    let mut events = Vec::new();
+
+    // Keep the scheme offered, but not for this client
+    host.authorized_clients_mut().insert([0; 32]);
+    let client_public_key = client.identity().unwrap().public_key();
+    host.authorized_clients_mut().remove(&client_public_key);
+
+    key_exchange(&mut host, &mut client, &mut write, &mut events);
+
+    let try_auth = client.try_auth(AuthSchemeType::PublicKey);
+    host.handle(try_auth, &mut write, &mut events)
+        .expect("client->host try auth failed");
+
+    let auth_msg = write.remove(0);
+    client
+        .handle(auth_msg, &mut write, &mut events)
+        .expect("host->client auth msg failed");
+
+    let auth_msg = write.remove(0);
+    host.handle(auth_msg, &mut write, &mut events)
+        .expect("client->host auth msg failed");
+
+    assert_eq!(write.len(), 1);
+    assert_eq!(events.len(), 1);
+    assert!(matches!(
+        events.remove(0),
+        InformEvent::WpskkaHostInform(WpskkaHostInform::AuthFailed)
+    ));
+
+    let auth_failed = write.remove(0);
+    client
+        .handle(auth_failed, &mut write, &mut events)
+        .expect("host->client auth result failed");
+
+    assert_eq!(events.len(), 1);
+    assert!(matches!(
+        events.remove(0),
+        InformEvent::WpskkaClientInform(WpskkaClientInform::AuthFailed)
+    ));
+}
+
+#[test]
+fn wpskka_test_auth_public_key_first_use() {
+    let (mut host, mut client) = public_key_peers();
+    let mut write = Vec::new();
+    let mut events = Vec::new();
+
+    let host_public_key = host.identity().unwrap().public_key();
+    client.trusted_hosts_mut().remove(&host_public_key);
+    client.set_known_host(Some((HostKey::Lease([0, 0, 0, 1]), None)));
+
+    key_exchange(&mut host, &mut client, &mut write, &mut events);
+
+    let try_auth = client.try_auth(AuthSchemeType::PublicKey);
+    host.handle(try_auth, &mut write, &mut events)
+        .expect("client->host try auth failed");
+
+    let auth_msg = write.remove(0);
+    client
+        .handle(auth_msg, &mut write, &mut events)
+        .expect("host->client auth msg failed");
+
+    // Nothing is pinned until the Host has accepted us
+    assert_eq!(write.len(), 1);
+    assert_eq!(events.len(), 0);
+
+    let auth_msg = write.remove(0);
+    host.handle(auth_msg, &mut write, &mut events)
+        .expect("client->host auth msg failed");
+    events.clear();
+
+    let auth_success = write.remove(0);
+    client
+        .handle(auth_success, &mut write, &mut events)
+        .expect("host->client auth success failed");
+
+    assert_eq!(events.len(), 2);
+    assert!(matches!(
+        events.remove(0),
+        InformEvent::WpskkaClientInform(WpskkaClientInform::AuthSuccessful)
+    ));
+
+    match events.remove(0) {
+        InformEvent::WpskkaClientInform(WpskkaClientInform::HostKeyPinned(known_host)) => {
+            assert_eq!(known_host.host, HostKey::Lease([0, 0, 0, 1]));
+            assert_eq!(known_host.public_key, host_public_key);
+        }
+        _ => panic!("expected HostKeyPinned"),
+    }
+
+    test_ciphers(&mut host, &mut client);
+}
+
+#[test]
+fn wpskka_test_auth_public_key_first_use_rejected() {
+    let (mut host, mut client) = public_key_peers();
+    let mut write = Vec::new();
+    let mut events = Vec::new();
+
+    let host_public_key = host.identity().unwrap().public_key();
+    client.trusted_hosts_mut().remove(&host_public_key);
+    client.set_known_host(Some((HostKey::Lease([0, 0, 0, 1]), None)));
+    host.authorized_clients_mut().insert([0; 32]);
+    let client_public_key = client.identity().unwrap().public_key();
+    host.authorized_clients_mut().remove(&client_public_key);
+
+    key_exchange(&mut host, &mut client, &mut write, &mut events);
+
+    let try_auth = client.try_auth(AuthSchemeType::PublicKey);
+    host.handle(try_auth, &mut write, &mut events)
+        .expect("client->host try auth failed");
+
+    let auth_msg = write.remove(0);
+    client
+        .handle(auth_msg, &mut write, &mut events)
+        .expect("host->client auth msg failed");
+
+    let auth_msg = write.remove(0);
+    host.handle(auth_msg, &mut write, &mut events)
+        .expect("client->host auth msg failed");
+    events.clear();
+
+    let auth_failed = write.remove(0);
+    client
+        .handle(auth_failed, &mut write, &mut events)
+        .expect("host->client auth result failed");
+
+    // A Host that turns us away doesn't get its key pinned
+    assert_eq!(events.len(), 1);
+    assert!(matches!(
+        events.remove(0),
+        InformEvent::WpskkaClientInform(WpskkaClientInform::AuthFailed)
+    ));
+}
+
+#[test]
+fn wpskka_test_auth_public_key_host_key_changed() {
+    let (mut host, mut client) = public_key_peers();
+    let mut write = Vec::new();
+    let mut events = Vec::new();
+
+    let host_public_key = host.identity().unwrap().public_key();
+    client.trusted_hosts_mut().remove(&host_public_key);
+    client.set_known_host(Some((
+        HostKey::Address("127.0.0.1:9999".to_string()),
+        Some([1; 32]),
+    )));
+
+    key_exchange(&mut host, &mut client, &mut write, &mut events);
+
+    let try_auth = client.try_auth(AuthSchemeType::PublicKey);
+    host.handle(try_auth, &mut write, &mut events)
+        .expect("client->host try auth failed");
+
+    let auth_msg = write.remove(0);
+    client
+        .handle(auth_msg, &mut write, &mut events)
+        .expect("host->client auth msg failed");
+
+    assert_eq!(write.len(), 0);
+    assert_eq!(events.len(), 2);
+
+    match events.remove(0) {
+        InformEvent::WpskkaClientInform(WpskkaClientInform::HostKeyChanged {
+            host,
+            previous,
+            public_key,
+        }) => {
+            assert_eq!(host, HostKey::Address("127.0.0.1:9999".to_string()));
+            assert_eq!(previous, [1; 32]);
+            assert_eq!(public_key, host_public_key);
+        }
+        _ => panic!("expected HostKeyChanged"),
+    }
+
+    assert!(matches!(
+        events.remove(0),
+        InformEvent::WpskkaClientInform(WpskkaClientInform::AuthFailed)
+    ));
+}
+
+fn reliable_message(cipher: &mut CipherReliablePeer, data: &[u8]) -> WpskkaMessage<'static> {
+    WpskkaMessage::TransportDataMessageReliable(TransportDataMessageReliable {
+        data: Data(Cow::Owned(cipher.encrypt(data).unwrap())),
+    })
+}
+
+fn unreliable_message(cipher: &mut CipherUnreliablePeer, data: &[u8]) -> WpskkaMessage<'static> {
+    let (ciphertext, counter) = cipher.encrypt(data).unwrap();
+    WpskkaMessage::TransportDataMessageUnreliable(TransportDataMessageUnreliable {
+        data: Data(Cow::Owned(ciphertext)),
+        counter,
+    })
+}
+
+#[test]
+fn wpskka_test_rekey() {
+    let mut host = WpskkaHostHandler::new();
+    let mut client = WpskkaClientHandler::new();
+    let mut write = Vec::new();
+    let mut events = Vec::new();
+
+    host.set_none_scheme(true);
+
+    none_auth_full(&mut host, &mut client, &mut write, &mut events);
+
+    let data = vec![9, 0, 5, 1];
+
+    // sent before the rekey but arrives after the client has switched keys
+    let in_flight = unreliable_message(host.unreliable_cipher(), &data);
+
+    let rekey = host.rekey().unwrap().expect("expected rekey");
+    assert!(matches!(rekey, WpskkaMessage::Rekey(_)));
+
+    // only one rekey at a time
+    assert!(host.rekey().unwrap().is_none());
+
+    // the host's reliable key has already changed
+    let message = reliable_message(host.reliable_cipher_mut(), &data);
+
+    let result = client
+        .handle(rekey, &mut write, &mut events)
+        .expect("host->client rekey failed");
+    assert_eq!(result, None);
+    assert_eq!(write.len(), 1);
+    assert_eq!(events.len(), 0);
+
+    let result = client
+        .handle(message, &mut write, &mut events)
+        .expect("reliable after rekey failed")
+        .expect("expected data");
+    assert_eq!(&result, &data);
+
+    let result = client
+        .handle(in_flight, &mut write, &mut events)
+        .expect("in flight unreliable failed")
+        .expect("expected data");
+    assert_eq!(&result, &data);
+
+    let rekey_ack = write.remove(0);
+    assert!(matches!(rekey_ack, WpskkaMessage::RekeyAck(_)));
+    let result = host
+        .handle(rekey_ack, &mut write, &mut events)
+        .expect("client->host rekey ack failed");
+    assert_eq!(result, None);
+    assert_eq!(write.len(), 0);
+    assert_eq!(events.len(), 0);
+
+    test_ciphers(&mut host, &mut client);
+
+    // the client rekeys independently
+    let rekey = client.rekey().unwrap().expect("expected rekey");
+    host.handle(rekey, &mut write, &mut events)
+        .expect("client->host rekey failed");
+    let rekey_ack = write.remove(0);
+    client
+        .handle(rekey_ack, &mut write, &mut events)
+        .expect("host->client rekey ack failed");
+
+    test_ciphers(&mut host, &mut client);
+}
+
+#[test]
+fn wpskka_test_rekey_bad_mac() {
+    let mut host = WpskkaHostHandler::new();
+    let mut client = WpskkaClientHandler::new();
+    let mut write = Vec::new();
+    let mut events = Vec::new();
+
+    host.set_none_scheme(true);
+
+    none_auth_full(&mut host, &mut client, &mut write, &mut events);
+
+    let rekey = match host.rekey().unwrap() {
+        Some(WpskkaMessage::Rekey(mut rekey)) => {
+            rekey.mac[0] ^= 1;
+            rekey
+        }
+        _ => panic!("expected rekey"),
+    };
+
+    assert!(client
+        .handle(WpskkaMessage::Rekey(rekey), &mut write, &mut events)
+        .is_err());
+    assert_eq!(write.len(), 0);
+}
+
+#[test]
+fn wpskka_test_rekey_policy() {
+    let mut host = WpskkaHostHandler::new();
+    let mut client = WpskkaClientHandler::new();
+    let mut write = Vec::new();
+    let mut events = Vec::new();
+
+    host.set_none_scheme(true);
+    host.set_rekey_policy(RekeyPolicy {
+        interval: None,
+        max_messages: Some(2),
+    });
+
+    assert!(host.rekey_if_due().unwrap().is_none());
+
+    none_auth_full(&mut host, &mut client, &mut write, &mut events);
+
+    reliable_message(host.reliable_cipher_mut(), &[0]);
+    assert!(host.rekey_if_due().unwrap().is_none());
+    reliable_message(host.reliable_cipher_mut(), &[0]);
+    assert!(matches!(
+        host.rekey_if_due().unwrap(),
+        Some(WpskkaMessage::Rekey(_))
+    ));
+    assert!(host.rekey_if_due().unwrap().is_none());
+}
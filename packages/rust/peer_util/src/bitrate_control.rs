@@ -0,0 +1,135 @@
+use common::messages::rvd::{DisplayId, ReceiverReport};
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+use video_process::{rtp::CLOCK_RATE, vp9::DEFAULT_BITRATE};
+
+/// The least a display is encoded at, in kbit/s
+pub const MIN_BITRATE: u32 = 100;
+/// The most a display is encoded at, in kbit/s
+pub const MAX_BITRATE: u32 = 10000;
+/// The fraction of FrameData lost above which the link is taken to be congested
+pub const HIGH_LOSS: f32 = 0.1;
+/// The fraction of FrameData lost below which the bitrate may go up
+pub const LOW_LOSS: f32 = 0.02;
+/// Jitter above which packets are taken to be queueing up somewhere along the link
+pub const JITTER_LIMIT: Duration = Duration::from_millis(30);
+
+// How much the bitrate goes up by when the link keeps up, and down by when packets queue up
+const INCREASE: f32 = 1.08;
+const JITTER_DECREASE: f32 = 0.85;
+
+struct Link {
+    // In kbit/s
+    bitrate: u32,
+    // When the last report arrived and the bytes it said had been received
+    last: Option<(Instant, u64)>,
+}
+
+/// Picks the bitrate each display is encoded at from the ReceiverReports Clients send, along the
+/// lines of loss based congestion control. Each Client's link gets a bitrate of its own: it's cut
+/// when packets are lost or queue up and goes up slowly while they get through. All Clients get the
+/// same frames so a display is encoded at the lowest bitrate of the Clients it's shared with.
+#[derive(Default)]
+pub struct BitrateControl {
+    links: HashMap<(DisplayId, u32), Link>,
+    bitrates: HashMap<DisplayId, u32>,
+}
+
+impl BitrateControl {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Takes in a report from a Client, returns the bitrate to encode the display at if it changed
+    pub fn report(&mut self, client: u32, report: &ReceiverReport, now: Instant) -> Option<u32> {
+        let display_id = report.display_id;
+        let link = self.links.entry((display_id, client)).or_insert(Link {
+            bitrate: DEFAULT_BITRATE,
+            last: None,
+        });
+
+        // How fast FrameData arrived since the last report, in kbit/s
+        let receive_rate = link.last.and_then(|(last, received_bytes)| {
+            let elapsed = now.saturating_duration_since(last).as_secs_f32();
+            (elapsed > 0.0).then(|| {
+                report.received_bytes.saturating_sub(received_bytes) as f32 * 8.0 / 1000.0 / elapsed
+            })
+        });
+        link.last = Some((now, report.received_bytes));
+
+        let loss = report.fraction_lost as f32 / 256.0;
+        let jitter = Duration::from_secs_f64(report.jitter as f64 / CLOCK_RATE as f64);
+        let bitrate = link.bitrate as f32;
+        let bitrate = if loss > HIGH_LOSS {
+            bitrate * (1.0 - loss / 2.0)
+        } else if jitter > JITTER_LIMIT {
+            bitrate * JITTER_DECREASE
+        } else if loss < LOW_LOSS
+            // Only go up if the encoder is close to using what it has, otherwise the bitrate would
+            // climb on a still screen without the link having been tried
+            && matches!(receive_rate, Some(rate) if rate >= bitrate / 2.0)
+        {
+            bitrate * INCREASE
+        } else {
+            bitrate
+        };
+        link.bitrate = (bitrate as u32).clamp(MIN_BITRATE, MAX_BITRATE);
+
+        self.update(display_id)
+    }
+
+    /// The bitrate a display is encoded at, in kbit/s
+    pub fn bitrate(&self, display_id: DisplayId) -> u32 {
+        self.bitrates
+            .get(&display_id)
+            .copied()
+            .unwrap_or(DEFAULT_BITRATE)
+    }
+
+    /// Forgets a display that's no longer shared
+    pub fn remove(&mut self, display_id: DisplayId) {
+        self.links.retain(|&(display, _), _| display != display_id);
+        self.bitrates.remove(&display_id);
+    }
+
+    /// Forgets a Client that left, returns the displays that can be encoded at a new bitrate now
+    /// that it's no longer holding them back
+    pub fn remove_client(&mut self, client: u32) -> Vec<(DisplayId, u32)> {
+        let mut displays: Vec<DisplayId> = self
+            .links
+            .keys()
+            .filter(|&&(_, c)| c == client)
+            .map(|&(display, _)| display)
+            .collect();
+        displays.sort_unstable();
+        self.links.retain(|&(_, c), _| c != client);
+        displays
+            .into_iter()
+            .filter_map(|display_id| self.update(display_id).map(|bitrate| (display_id, bitrate)))
+            .collect()
+    }
+
+    // Works out the bitrate of a display from its links, returns it if it changed
+    fn update(&mut self, display_id: DisplayId) -> Option<u32> {
+        let bitrate = self
+            .links
+            .iter()
+            .filter(|(&(display, _), _)| display == display_id)
+            .map(|(_, link)| link.bitrate)
+            .min();
+        let bitrate = match bitrate {
+            Some(bitrate) => bitrate,
+            None => {
+                self.bitrates.remove(&display_id);
+                return None;
+            }
+        };
+        if self.bitrates.insert(display_id, bitrate) == Some(bitrate) {
+            None
+        } else {
+            Some(bitrate)
+        }
+    }
+}
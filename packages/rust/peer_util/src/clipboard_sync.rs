@@ -0,0 +1,41 @@
+use common::messages::rvd::ClipboardType;
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+};
+
+/// Remembers the last content exchanged with the peer for each clipboard type. Writing content the
+/// peer sent to the local clipboard is itself a clipboard change, this is how we avoid echoing it
+/// straight back.
+#[derive(Default)]
+pub struct ClipboardSync {
+    last: HashMap<ClipboardType, u64>,
+}
+
+impl ClipboardSync {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records content received from the peer before it's written to the local clipboard
+    pub fn remote_change(&mut self, clipboard_type: &ClipboardType, content: &[u8]) {
+        self.last
+            .insert(clipboard_type.clone(), Self::hash(content));
+    }
+
+    /// Records a change of the local clipboard, returning whether it should be sent to the peer
+    pub fn local_change(&mut self, clipboard_type: &ClipboardType, content: &[u8]) -> bool {
+        let hash = Self::hash(content);
+        if self.last.get(clipboard_type) == Some(&hash) {
+            return false;
+        }
+        self.last.insert(clipboard_type.clone(), hash);
+        true
+    }
+
+    fn hash(content: &[u8]) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        content.hash(&mut hasher);
+        hasher.finish()
+    }
+}
@@ -1,10 +1,14 @@
 use common::messages::rvd::ClipboardType as ClipboardNetwork;
 use native::api::ClipboardType as ClipboardNative;
 
-pub fn get_native_clipboard(network: &ClipboardNetwork) -> ClipboardNative {
+/// Returns None for types that have no native counterpart
+pub fn get_native_clipboard(network: &ClipboardNetwork) -> Option<ClipboardNative> {
     match network {
-        ClipboardNetwork::Text => ClipboardNative::Text,
-        ClipboardNetwork::Custom(str) => ClipboardNative::Custom(str.clone()),
-        _ => panic!("map map from network clipboard to native"),
+        ClipboardNetwork::Text => Some(ClipboardNative::Text),
+        ClipboardNetwork::Rtf => Some(ClipboardNative::Rtf),
+        ClipboardNetwork::Html => Some(ClipboardNative::Html),
+        ClipboardNetwork::Image => Some(ClipboardNative::Image),
+        ClipboardNetwork::Custom(str) => Some(ClipboardNative::Custom(str.clone())),
+        ClipboardNetwork::FilePointer => None,
     }
 }
@@ -0,0 +1,199 @@
+// The disk side of RVD file transfers, peer::rvd::FileTransfers keeps track of the protocol side.
+// Incoming files are written to a .part file next to where they'll end up. It's only renamed once
+// the hash checks out, and a later offer of the same file resumes from whatever it already holds.
+// The .part file is named after the hash and size of the file, so a different file that happens
+// to have the same name never resumes from it.
+
+use peer::rvd::FILE_CHUNK_SIZE;
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+pub struct OutgoingFile {
+    file: File,
+    name: String,
+    size: u64,
+    hash: [u8; 32],
+    offset: u64,
+    buffer: Vec<u8>,
+}
+
+impl OutgoingFile {
+    /// Opens a file to offer, this reads the whole file to hash it
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, FileError> {
+        let path = path.as_ref();
+        let name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| FileError::BadName(path.display().to_string()))?
+            .to_owned();
+
+        let mut file = File::open(path)?;
+        let mut hasher = blake3::Hasher::new();
+        let size = io::copy(&mut file, &mut hasher)?;
+        file.seek(SeekFrom::Start(0))?;
+
+        Ok(Self {
+            file,
+            name,
+            size,
+            hash: *hasher.finalize().as_bytes(),
+            offset: 0,
+            buffer: vec![0; FILE_CHUNK_SIZE],
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    pub fn hash(&self) -> [u8; 32] {
+        self.hash
+    }
+
+    /// Moves to the offset the other Peer accepted the file from
+    pub fn seek(&mut self, offset: u64) -> Result<(), FileError> {
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.offset = offset;
+        Ok(())
+    }
+
+    /// Reads the next chunk to send. It's empty once the whole file has been read.
+    pub fn next_chunk(&mut self) -> Result<&[u8], FileError> {
+        let len = (self.size - self.offset).min(FILE_CHUNK_SIZE as u64) as usize;
+        // The file shrinking after it was offered makes this fail
+        self.file.read_exact(&mut self.buffer[.. len])?;
+        self.offset += len as u64;
+        Ok(&self.buffer[.. len])
+    }
+}
+
+pub struct IncomingFile {
+    file: File,
+    path: PathBuf,
+    part_path: PathBuf,
+    size: u64,
+    hash: [u8; 32],
+    offset: u64,
+}
+
+impl IncomingFile {
+    /// Starts receiving a file into dir. name comes from the other Peer so anything that isn't a
+    /// plain file name is refused.
+    pub fn create(
+        dir: impl AsRef<Path>,
+        name: &str,
+        size: u64,
+        hash: [u8; 32],
+    ) -> Result<Self, FileError> {
+        if Path::new(name).file_name() != Some(name.as_ref()) {
+            return Err(FileError::BadName(name.to_owned()));
+        }
+        let path = dir.as_ref().join(name);
+        let part_path = dir.as_ref().join(part_file_name(name, size, &hash));
+
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .open(&part_path)?;
+        let mut offset = file.metadata()?.len();
+        // Whatever's there can't be the start of this file
+        if offset > size {
+            file.set_len(0)?;
+            offset = 0;
+        }
+        file.seek(SeekFrom::Start(offset))?;
+
+        Ok(Self {
+            file,
+            path,
+            part_path,
+            size,
+            hash,
+            offset,
+        })
+    }
+
+    /// Where the transfer should be accepted from
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    pub fn write_chunk(&mut self, offset: u64, data: &[u8]) -> Result<(), FileError> {
+        if offset != self.offset {
+            return Err(FileError::OutOfOrder(offset, self.offset));
+        }
+        self.file.write_all(data)?;
+        self.offset += data.len() as u64;
+        Ok(())
+    }
+
+    /// Checks the hash of the received file and moves it into place, returning where it ended up.
+    /// A file that doesn't match is deleted.
+    pub fn finish(mut self) -> Result<PathBuf, FileError> {
+        self.file.seek(SeekFrom::Start(0))?;
+        let mut hasher = blake3::Hasher::new();
+        io::copy(&mut self.file, &mut hasher)?;
+        drop(self.file);
+
+        if *hasher.finalize().as_bytes() != self.hash {
+            fs::remove_file(&self.part_path)?;
+            return Err(FileError::HashMismatch);
+        }
+
+        let path = free_path(self.path);
+        fs::rename(&self.part_path, &path)?;
+        Ok(path)
+    }
+}
+
+/// The name of the file an incoming file is written to until its hash has been checked
+pub fn part_file_name(name: &str, size: u64, hash: &[u8; 32]) -> String {
+    let short_hash: String = hash[.. 8]
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    format!("{}.{}-{}.part", name, short_hash, size)
+}
+
+// Adds a number to the file name if something's already using it, name.txt becomes name (1).txt
+fn free_path(path: PathBuf) -> PathBuf {
+    if !path.exists() {
+        return path;
+    }
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let extension = path
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()))
+        .unwrap_or_default();
+    (1 ..)
+        .map(|i| path.with_file_name(format!("{} ({}){}", stem, i, extension)))
+        .find(|path| !path.exists())
+        .unwrap()
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum FileError {
+    #[error("io error: {0}")]
+    Io(#[from] io::Error),
+    #[error("invalid file name {0:?}")]
+    BadName(String),
+    #[error("chunk at offset {0} doesn't follow on from offset {1}")]
+    OutOfOrder(u64, u64),
+    #[error("file doesn't match its hash")]
+    HashMismatch,
+}
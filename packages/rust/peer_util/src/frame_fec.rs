@@ -0,0 +1,93 @@
+use common::messages::rvd::DisplayId;
+use rtp::packet::Packet;
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+use video_process::fec::FecEncoder;
+
+/// The fraction of FrameData lost above which repair packets are sent
+pub const FEC_LOSS_THRESHOLD: f32 = 0.01;
+/// The fewest repair packets sent for each media packet while FEC is on
+pub const FEC_MIN_RATIO: f32 = 0.1;
+/// The most repair packets sent for each media packet
+pub const FEC_MAX_RATIO: f32 = 0.5;
+/// How long FEC stays on after the loss was last above the threshold. Repair packets hide the loss
+/// they make up for, so without this FEC would turn itself off as soon as it worked.
+pub const FEC_HOLD: Duration = Duration::from_secs(10);
+/// How many packets the loss is measured over
+pub const FEC_LOSS_WINDOW: usize = 256;
+
+#[derive(Default)]
+struct DisplayFec {
+    encoder: FecEncoder,
+    // Packets sent and reported lost since the loss was last measured
+    sent: usize,
+    lost: usize,
+    // Smoothed fraction of packets lost
+    loss: f32,
+    // When the loss was last above the threshold
+    lossy: Option<Instant>,
+}
+
+impl DisplayFec {
+    fn measure(&mut self) {
+        let loss = (self.lost as f32 / self.sent as f32).min(1.0);
+        self.loss = (self.loss + loss) / 2.0;
+        self.sent = 0;
+        self.lost = 0;
+
+        if self.loss > FEC_LOSS_THRESHOLD {
+            self.lossy = Some(Instant::now());
+            self.encoder
+                .set_ratio((self.loss * 2.0).clamp(FEC_MIN_RATIO, FEC_MAX_RATIO));
+        } else if !matches!(self.lossy, Some(lossy) if lossy.elapsed() < FEC_HOLD) {
+            self.lossy = None;
+            self.encoder.set_ratio(0.0);
+        }
+    }
+}
+
+/// Sends repair packets along with the FrameData for each display when Clients report enough of it
+/// lost, so they can recover from the loss without waiting for packets to be sent again. The more
+/// is lost the more repair packets are sent.
+#[derive(Default)]
+pub struct FrameFec {
+    displays: HashMap<DisplayId, DisplayFec>,
+}
+
+impl FrameFec {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Makes the repair packets to send after a frame's packets, if any
+    pub fn protect(&mut self, display_id: DisplayId, packets: &[Packet]) -> Vec<Packet> {
+        let fec = self.displays.entry(display_id).or_default();
+        fec.sent += packets.len();
+        if fec.sent >= FEC_LOSS_WINDOW {
+            fec.measure();
+        }
+        fec.encoder.protect(packets)
+    }
+
+    /// Counts packets a Client reported lost
+    pub fn lost(&mut self, display_id: DisplayId, count: usize) {
+        if let Some(fec) = self.displays.get_mut(&display_id) {
+            fec.lost += count;
+        }
+    }
+
+    /// How many repair packets are sent for each packet of a display
+    pub fn ratio(&self, display_id: DisplayId) -> f32 {
+        self.displays
+            .get(&display_id)
+            .map(|fec| fec.encoder.ratio())
+            .unwrap_or(0.0)
+    }
+
+    /// Forgets a display that's no longer shared
+    pub fn remove(&mut self, display_id: DisplayId) {
+        self.displays.remove(&display_id);
+    }
+}
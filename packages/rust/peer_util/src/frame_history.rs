@@ -0,0 +1,76 @@
+use common::messages::rvd::DisplayId;
+use rtp::packet::Packet;
+use std::{
+    collections::{HashMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+/// How many packets are kept for each display
+pub const FRAME_HISTORY_SIZE: usize = 512;
+/// How long a packet is worth sending again, after this the Client is better off with a keyframe
+pub const FRAME_HISTORY_AGE: Duration = Duration::from_secs(1);
+
+#[derive(Default)]
+struct DisplayHistory {
+    packets: HashMap<u16, (Instant, Packet)>,
+    // Sequence numbers in the order they were sent, so the oldest can be dropped
+    order: VecDeque<u16>,
+}
+
+/// Keeps the FrameData packets recently sent for each display so the ones a Client missed can be
+/// sent again
+#[derive(Default)]
+pub struct FrameHistory {
+    displays: HashMap<DisplayId, DisplayHistory>,
+}
+
+impl FrameHistory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, display_id: DisplayId, packets: &[Packet]) {
+        let history = self.displays.entry(display_id).or_default();
+        let now = Instant::now();
+        for packet in packets {
+            let sequence = packet.header.sequence_number;
+            if history
+                .packets
+                .insert(sequence, (now, packet.clone()))
+                .is_some()
+            {
+                // The sequence number wrapped around, the packet it's for now is the newest
+                history.order.retain(|&recorded| recorded != sequence);
+            }
+            history.order.push_back(sequence);
+        }
+        while history.order.len() > FRAME_HISTORY_SIZE {
+            if let Some(sequence) = history.order.pop_front() {
+                history.packets.remove(&sequence);
+            }
+        }
+    }
+
+    /// Finds the packets to send again. The bool is false if some of them are too old or were
+    /// never sent, in which case the display needs a keyframe instead.
+    pub fn retransmit(&self, display_id: DisplayId, sequences: &[u16]) -> (Vec<Packet>, bool) {
+        let history = match self.displays.get(&display_id) {
+            Some(history) => history,
+            None => return (Vec::new(), sequences.is_empty()),
+        };
+        let packets: Vec<Packet> = sequences
+            .iter()
+            .filter_map(|sequence| history.packets.get(sequence))
+            .filter(|(sent, _)| sent.elapsed() <= FRAME_HISTORY_AGE)
+            .map(|(_, packet)| packet.clone())
+            .collect();
+        let complete = packets.len() == sequences.len();
+        (packets, complete)
+    }
+
+    /// Forgets a display that's no longer shared, its sequence numbers start over if it's shared
+    /// again
+    pub fn remove(&mut self, display_id: DisplayId) {
+        self.displays.remove(&display_id);
+    }
+}
@@ -12,6 +12,8 @@ use video_process::{
 pub struct FrameProcessor {
     vp9_encoder: Option<VP9Encoder>,
     rtp_encoder: RtpEncoder,
+    // The bitrate asked for, which carries over to a remade encoder
+    bitrate: Option<u32>,
 }
 
 impl FrameProcessor {
@@ -26,7 +28,11 @@ impl FrameProcessor {
         // TODO: we remake the encoder if the incoming frame size changes, is this the right
         // thing to do?
         if stale {
-            self.vp9_encoder = Some(VP9Encoder::new(incoming.width, incoming.height)?);
+            let mut vp9_encoder = VP9Encoder::new(incoming.width, incoming.height)?;
+            if let Some(kbps) = self.bitrate {
+                vp9_encoder.set_bitrate(kbps)?;
+            }
+            self.vp9_encoder = Some(vp9_encoder);
         }
 
         Ok(())
@@ -43,6 +49,7 @@ impl ProcessFrame for FrameProcessor {
         Self {
             vp9_encoder: None,
             rtp_encoder: RtpEncoder::new(args, 0),
+            bitrate: None,
         }
     }
 
@@ -85,6 +92,21 @@ impl ProcessFrame for FrameProcessor {
 
         FrameProcessResult::Success
     }
+
+    fn request_keyframe(&mut self) {
+        // A new encoder starts with a keyframe anyway
+        if let Some(vp9_encoder) = &mut self.vp9_encoder {
+            vp9_encoder.request_keyframe();
+        }
+    }
+
+    fn set_bitrate(&mut self, kbps: u32) {
+        self.bitrate = Some(kbps);
+        if let Some(vp9_encoder) = &mut self.vp9_encoder {
+            // TODO: log the error, the encoder keeps the bitrate it had
+            let _ = vp9_encoder.set_bitrate(kbps);
+        }
+    }
 }
 
 impl<'a> ViewResources<'a> for FrameProcessor {
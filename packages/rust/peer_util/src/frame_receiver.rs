@@ -0,0 +1,231 @@
+use common::messages::rvd::{DisplayId, ReceiverReport};
+use rtp::packet::Packet;
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+use video_process::{
+    jitter_buffer::{Frame, JitterBuffer, ReceiveStats},
+    rtp::{DecoderError, RtpDecoder, CLOCK_RATE},
+};
+use webrtc_util::Unmarshal;
+
+/// How long to wait for a keyframe before asking the Host again
+pub const PICTURE_LOSS_INTERVAL: Duration = Duration::from_millis(500);
+/// How long to wait for a packet that was asked for again before giving up on it
+pub const RETRANSMIT_TIMEOUT: Duration = Duration::from_millis(300);
+/// How long a missing packet is left to be recovered from repair packets before it's asked for
+pub const FEC_WAIT: Duration = Duration::from_millis(50);
+/// How long after the last repair packet the Host is taken to still be sending them
+const FEC_ACTIVE: Duration = Duration::from_secs(1);
+/// How often the Host is sent a ReceiverReport for each display
+pub const REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+struct DisplayFrames {
+    decoder: RtpDecoder,
+    // Puts the packets that arrived and were recovered back together into frames. Its stats go into
+    // the ReceiverReports, they don't count recovered packets so the Host sees the loss before FEC.
+    buffer: JitterBuffer,
+    // The missing packets we've asked the Host for and when
+    requested: Vec<(u16, Instant)>,
+    // When each missing packet was noticed
+    noticed: Vec<(u16, Instant)>,
+    // When the last repair packet arrived
+    repaired: Option<Instant>,
+    // When we last told the Host we'd lost a picture on this display
+    reported: Option<Instant>,
+    // When the last ReceiverReport was sent and the packets expected and received by then
+    last_report: Option<Instant>,
+    report_counts: (u64, u64),
+}
+
+/// What the Host should be told after FrameData arrived
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct FrameFeedback {
+    /// Sequence numbers of the packets to send a FrameNack for
+    pub nack: Vec<u16>,
+    /// Whether to send a PictureLoss
+    pub picture_loss: bool,
+    /// A ReceiverReport to send, every REPORT_INTERVAL
+    pub report: Option<ReceiverReport>,
+}
+
+/// Follows the FrameData the Host sends for each display, noticing when packets go missing.
+/// FrameData goes over UDP, so without this the Client could be stuck with a broken picture until
+/// the Host happens to send a keyframe. Missing packets are asked for once, if they don't show up
+/// within RETRANSMIT_TIMEOUT a keyframe is asked for instead. While the Host sends repair packets
+/// missing packets are given FEC_WAIT to be recovered from them first. How the FrameData is
+/// arriving is reported to the Host every REPORT_INTERVAL so it can adapt the bitrate. The packets
+/// are put back together into whole VP9 frames, which are handed out in order once they're due.
+#[derive(Default)]
+pub struct FrameReceiver {
+    displays: HashMap<DisplayId, DisplayFrames>,
+}
+
+impl FrameReceiver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Takes in FrameData for a display. While no keyframe arrives a PictureLoss is only asked for
+    /// every PICTURE_LOSS_INTERVAL.
+    pub fn receive(
+        &mut self,
+        display_id: DisplayId,
+        data: &[u8],
+    ) -> Result<FrameFeedback, DecoderError> {
+        let frames = self
+            .displays
+            .entry(display_id)
+            .or_insert_with(|| DisplayFrames {
+                decoder: RtpDecoder::new(),
+                buffer: JitterBuffer::new(),
+                requested: Vec::new(),
+                noticed: Vec::new(),
+                reported: None,
+                repaired: None,
+                last_report: None,
+                report_counts: (0, 0),
+            });
+        let packet = Packet::unmarshal(&mut &data[..])?;
+        // Only repair packets have nothing to depacketize
+        let repair = frames.decoder.decode_packet(&packet)?.is_none();
+
+        let now = Instant::now();
+        if repair {
+            frames.repaired = Some(now);
+        } else {
+            frames.buffer.push(&packet, now)?;
+        }
+        for packet in frames.decoder.take_recovered() {
+            frames.buffer.push_recovered(&packet, now)?;
+        }
+        let report = match frames.last_report {
+            Some(last_report) if now - last_report < REPORT_INTERVAL => None,
+            Some(_) => {
+                frames.last_report = Some(now);
+                Some(receiver_report(
+                    display_id,
+                    frames.buffer.receive_stats(),
+                    &mut frames.report_counts,
+                ))
+            }
+            // The first report covers a whole interval
+            None => {
+                frames.last_report = Some(now);
+                None
+            }
+        };
+        let decoder = &mut frames.decoder;
+        frames
+            .requested
+            .retain(|(sequence, _)| decoder.missing().contains(sequence));
+        if frames
+            .requested
+            .iter()
+            .any(|&(_, requested)| now - requested > RETRANSMIT_TIMEOUT)
+        {
+            decoder.give_up();
+            frames.requested.clear();
+        }
+        frames
+            .noticed
+            .retain(|(sequence, _)| decoder.missing().contains(sequence));
+        for &sequence in decoder.missing() {
+            if !frames
+                .noticed
+                .iter()
+                .any(|&(noticed, _)| noticed == sequence)
+            {
+                frames.noticed.push((sequence, now));
+            }
+        }
+
+        let fec_active = matches!(frames.repaired, Some(repaired) if now - repaired < FEC_ACTIVE);
+        let nack: Vec<u16> = frames
+            .noticed
+            .iter()
+            .filter(|&&(_, noticed)| !fec_active || now - noticed >= FEC_WAIT)
+            .map(|&(sequence, _)| sequence)
+            .filter(|sequence| {
+                !frames
+                    .requested
+                    .iter()
+                    .any(|(requested, _)| requested == sequence)
+            })
+            .collect();
+        frames
+            .requested
+            .extend(nack.iter().map(|&sequence| (sequence, now)));
+
+        // Frames the jitter buffer dropped, e.g. ones that completed too late, leave the ones after
+        // them nothing to be decoded against too
+        let dropped = frames.buffer.needs_keyframe() && frames.buffer.stats().dropped_frames > 0;
+        let picture_loss = if !decoder.needs_keyframe() && !dropped {
+            frames.reported = None;
+            false
+        } else if matches!(frames.reported, Some(reported) if now - reported < PICTURE_LOSS_INTERVAL)
+        {
+            false
+        } else {
+            frames.reported = Some(now);
+            true
+        };
+
+        Ok(FrameFeedback {
+            nack,
+            picture_loss,
+            report,
+        })
+    }
+
+    /// Hands out the frames that are due, in order for each display
+    pub fn frames(&mut self, now: Instant) -> Vec<(DisplayId, Frame)> {
+        let mut due = Vec::new();
+        for (&display_id, frames) in &mut self.displays {
+            while let Some(frame) = frames.buffer.pop(now) {
+                due.push((display_id, frame));
+            }
+        }
+        due
+    }
+
+    /// When the next frame is due to be handed out, if there's one waiting
+    pub fn next_due(&self) -> Option<Instant> {
+        self.displays
+            .values()
+            .filter_map(|frames| frames.buffer.next_due())
+            .min()
+    }
+
+    /// Forgets a display that's no longer shared
+    pub fn remove(&mut self, display_id: DisplayId) {
+        self.displays.remove(&display_id);
+    }
+}
+
+/// Makes a ReceiverReport for a display from what arrived so far. since holds the packets expected
+/// and received as of the last report, the fraction lost is worked out from what changed since then.
+pub fn receiver_report(
+    display_id: DisplayId,
+    stats: &ReceiveStats,
+    since: &mut (u64, u64),
+) -> ReceiverReport {
+    let (expected, received) = (stats.expected(), stats.received());
+    let expected_interval = expected.saturating_sub(since.0);
+    let lost_interval = expected_interval.saturating_sub(received.saturating_sub(since.1));
+    *since = (expected, received);
+
+    let fraction_lost = if expected_interval == 0 {
+        0
+    } else {
+        (lost_interval * 256 / expected_interval).min(u8::MAX as u64) as u8
+    };
+    ReceiverReport {
+        display_id,
+        fraction_lost,
+        lost: stats.lost().min(u32::MAX as u64) as u32,
+        jitter: (stats.jitter().as_secs_f64() * CLOCK_RATE as f64) as u32,
+        received_bytes: stats.received_bytes(),
+    }
+}
@@ -1,5 +1,14 @@
 use common::messages::{
-    rvd::{AccessMask, DisplayId, RvdMessage},
+    rvd::{
+        AccessMask,
+        ClipboardType,
+        DisplayGeometry,
+        DisplayId,
+        PermissionMask,
+        ReceiverReport,
+        RvdMessage,
+        TransferId,
+    },
     svsc::{Cookie, LeaseId},
     wpskka::AuthSchemeType,
     ChanneledMessage,
@@ -16,9 +25,18 @@ use io::{
     UnreliableState,
 };
 use peer::{
+    helpers::{crypto::IdentityKeyPair, secret::SecretVec},
     higher_handler::{HigherError, HigherHandlerClient, HigherHandlerHost, HigherHandlerTrait},
     lower::{LowerError, LowerHandlerSignal, LowerHandlerTrait},
-    wpskka::{WpskkaClientError, WpskkaError, WpskkaHostError},
+    rvd::{CursorImage, FileTransferError, RvdError, RvdHostInform},
+    wpskka::{
+        auth::TrustedKeys,
+        known_hosts::HostKey,
+        RekeyPolicy,
+        WpskkaClientError,
+        WpskkaError,
+        WpskkaHostError,
+    },
     InformEvent,
 };
 use rtp::packet::Packet;
@@ -29,6 +47,7 @@ macro_rules! send {
         let higher_output = $self.higher.send($message)?;
         let lower_output = $self.lower.send(higher_output)?;
         $self.io_handle.send(lower_output)?;
+        $self.rekey_if_due()?;
     };
 }
 
@@ -47,7 +66,8 @@ where
     R: Reliable,
     U: Unreliable,
 {
-    pub fn new(higher: H, lower: L, io_handle: IoHandle<R, U>) -> Self {
+    pub fn new(mut higher: H, lower: L, io_handle: IoHandle<R, U>) -> Self {
+        higher.set_extended_frames(L::EXTENDED_FRAMES);
         Self {
             higher,
             lower,
@@ -56,12 +76,26 @@ where
         }
     }
 
+    pub fn set_rekey_policy(&mut self, policy: RekeyPolicy) {
+        self.higher.set_rekey_policy(policy)
+    }
+
+    fn rekey_if_due(&mut self) -> Result<(), HandlerError> {
+        if let Some(higher_output) = self.higher.rekey_if_due()? {
+            let lower_output = self.lower.send(higher_output)?;
+            self.io_handle.send(lower_output)?;
+        }
+        Ok(())
+    }
+
     pub fn handle_next_message(&mut self) -> Option<Result<Vec<InformEvent>, HandlerError>> {
         let result = self.io_handle.recv()?;
-        Some(self.handle_next_message_internal(result))
+        Some(self.handle_transport_result(result))
     }
 
-    fn handle_next_message_internal(
+    /// Handles a result received outside of our own [`IoHandle`], e.g. a datagram from a socket
+    /// shared with other stacks
+    pub fn handle_transport_result(
         &mut self,
         result: TransportResult,
     ) -> Result<Vec<InformEvent>, HandlerError> {
@@ -139,6 +173,94 @@ where
 
         Ok((to_wire, inform_events))
     }
+
+    /// Offers a file to the other Peer, returning the ID of the transfer
+    pub fn offer_file(
+        &mut self,
+        name: String,
+        size: u64,
+        hash: [u8; 32],
+    ) -> Result<TransferId, HandlerError> {
+        let (transfer_id, message) = self.higher.file_transfers().offer(name, size, hash);
+        send!(self, message);
+        Ok(transfer_id)
+    }
+
+    pub fn accept_file(
+        &mut self,
+        transfer_id: TransferId,
+        offset: u64,
+    ) -> Result<(), HandlerError> {
+        let message = self
+            .higher
+            .file_transfers()
+            .accept(transfer_id, offset)
+            .map_err(file_transfer_error)?;
+        send!(self, message);
+        Ok(())
+    }
+
+    pub fn reject_file(&mut self, transfer_id: TransferId) -> Result<(), HandlerError> {
+        let message = self
+            .higher
+            .file_transfers()
+            .reject(transfer_id)
+            .map_err(file_transfer_error)?;
+        send!(self, message);
+        Ok(())
+    }
+
+    pub fn cancel_file(
+        &mut self,
+        transfer_id: TransferId,
+        outgoing: bool,
+    ) -> Result<(), HandlerError> {
+        let message = self
+            .higher
+            .file_transfers()
+            .cancel(transfer_id, outgoing)
+            .map_err(file_transfer_error)?;
+        send!(self, message);
+        Ok(())
+    }
+
+    /// Whether another chunk of the transfer can be sent before the other Peer acknowledges more
+    pub fn file_window_open(&mut self, transfer_id: TransferId) -> bool {
+        self.higher.file_transfers().window_open(transfer_id)
+    }
+
+    /// Sends the next chunk of an accepted transfer, returning how much of the file has been sent
+    pub fn send_file_chunk(
+        &mut self,
+        transfer_id: TransferId,
+        data: &[u8],
+    ) -> Result<u64, HandlerError> {
+        let (offset, message) = self
+            .higher
+            .file_transfers()
+            .chunk(transfer_id, data)
+            .map_err(file_transfer_error)?;
+        send!(self, message);
+        Ok(offset)
+    }
+
+    pub fn complete_file(
+        &mut self,
+        transfer_id: TransferId,
+        hash_ok: bool,
+    ) -> Result<(), HandlerError> {
+        let message = self
+            .higher
+            .file_transfers()
+            .complete(transfer_id, hash_ok)
+            .map_err(file_transfer_error)?;
+        send!(self, message);
+        Ok(())
+    }
+}
+
+fn file_transfer_error(error: FileTransferError) -> HandlerError {
+    HigherError::from(RvdError::from(error)).into()
 }
 
 impl<H, R, U> HandlerStack<H, LowerHandlerSignal, R, U>
@@ -175,6 +297,55 @@ where
         Ok(())
     }
 
+    pub fn request_control(&mut self) -> Result<(), HandlerError> {
+        let msg = self.higher.control_request();
+        send!(self, msg);
+        Ok(())
+    }
+
+    pub fn text_input(&mut self, text: String) -> Result<(), HandlerError> {
+        let msg = self.higher.text_input(text);
+        send!(self, msg);
+        Ok(())
+    }
+
+    /// Sends new local clipboard content if the peer lets us write to its clipboard
+    pub fn clipboard_changed(
+        &mut self,
+        clipboard_type: ClipboardType,
+        content: &[u8],
+    ) -> Result<(), HandlerError> {
+        if let Some(message) = self
+            .higher
+            .clipboard_changed(clipboard_type, content.to_vec())
+        {
+            send!(self, message);
+        }
+        Ok(())
+    }
+
+    pub fn picture_loss(&mut self, display_id: DisplayId) -> Result<(), HandlerError> {
+        let msg = self.higher.picture_loss(display_id);
+        send!(self, msg);
+        Ok(())
+    }
+
+    pub fn frame_nack(
+        &mut self,
+        display_id: DisplayId,
+        sequences: Vec<u16>,
+    ) -> Result<(), HandlerError> {
+        let msg = self.higher.frame_nack(display_id, sequences);
+        send!(self, msg);
+        Ok(())
+    }
+
+    pub fn receiver_report(&mut self, report: ReceiverReport) -> Result<(), HandlerError> {
+        let msg = self.higher.receiver_report(report);
+        send!(self, msg);
+        Ok(())
+    }
+
     pub fn process_password(&mut self, password: &[u8]) -> Result<(), HandlerError> {
         let message = self.higher.process_password(password)?;
         let higher_output = self.higher.send(message)?;
@@ -188,6 +359,18 @@ where
         send!(self, message);
         Ok(())
     }
+
+    pub fn set_identity(&mut self, identity: Option<IdentityKeyPair>) {
+        self.higher.set_identity(identity)
+    }
+
+    pub fn trusted_hosts_mut(&mut self) -> &mut TrustedKeys {
+        self.higher.trusted_hosts_mut()
+    }
+
+    pub fn set_known_host(&mut self, host: Option<(HostKey, Option<[u8; 32]>)>) {
+        self.higher.set_known_host(host)
+    }
 }
 
 impl<L, R, U> HandlerStack<HigherHandlerHost, L, R, U>
@@ -204,10 +387,18 @@ where
         Ok(())
     }
 
-    pub fn set_static_password(&mut self, static_password: Option<Vec<u8>>) {
+    pub fn set_static_password(&mut self, static_password: Option<SecretVec>) {
         self.higher.set_static_password(static_password)
     }
 
+    pub fn set_identity(&mut self, identity: Option<IdentityKeyPair>) {
+        self.higher.set_identity(identity)
+    }
+
+    pub fn authorized_clients_mut(&mut self) -> &mut TrustedKeys {
+        self.higher.authorized_clients_mut()
+    }
+
     pub fn share_display(
         &mut self,
         name: String,
@@ -218,12 +409,112 @@ where
         Ok(display_id)
     }
 
+    pub fn share_display_with_id(
+        &mut self,
+        display_id: DisplayId,
+        name: String,
+        access: AccessMask,
+    ) -> Result<(), HandlerError> {
+        let message = self
+            .higher
+            .share_display_with_id(display_id, name, access)?;
+        send!(self, message);
+        Ok(())
+    }
+
     pub fn unshare_display(&mut self, display_id: DisplayId) -> Result<(), HandlerError> {
         let message = self.higher.unshare_display(display_id)?;
         send!(self, message);
         Ok(())
     }
 
+    pub fn set_display_access(
+        &mut self,
+        display_id: DisplayId,
+        access: AccessMask,
+    ) -> Result<(), HandlerError> {
+        for message in self.higher.set_display_access(display_id, access)? {
+            send!(self, message);
+        }
+        Ok(())
+    }
+
+    pub fn set_display_geometry(&mut self, geometry: DisplayGeometry) -> Result<(), HandlerError> {
+        if let Some(message) = self.higher.set_display_geometry(geometry)? {
+            send!(self, message);
+        }
+        Ok(())
+    }
+
+    pub fn set_permissions(&mut self, permissions: PermissionMask) -> Result<(), HandlerError> {
+        let message = self.higher.set_permissions(permissions);
+        send!(self, message);
+        Ok(())
+    }
+
+    pub fn grant_control(&mut self) -> Result<(), HandlerError> {
+        let message = self.higher.grant_control();
+        send!(self, message);
+        Ok(())
+    }
+
+    pub fn revoke_control(&mut self) -> Result<(), HandlerError> {
+        let message = self.higher.revoke_control();
+        send!(self, message);
+        Ok(())
+    }
+
+    /// Lets go of every key and button the peer is holding down, for when it's gone away
+    pub fn release_input(&mut self) {
+        self.higher.release_input();
+    }
+
+    /// Takes the releases for input the peer was holding down when it went away, lost the controls
+    /// or lost access to a display. They should be handled like input from the peer.
+    pub fn take_input_releases(&mut self) -> Vec<RvdHostInform> {
+        self.higher.take_input_releases()
+    }
+
+    /// Sends new local clipboard content if the peer is allowed to read the clipboard
+    pub fn clipboard_changed(
+        &mut self,
+        clipboard_type: ClipboardType,
+        content: &[u8],
+    ) -> Result<(), HandlerError> {
+        if let Some(message) = self
+            .higher
+            .clipboard_changed(clipboard_type, content.to_vec())
+        {
+            send!(self, message);
+        }
+        Ok(())
+    }
+
+    pub fn cursor_changed(&mut self, cursor: &CursorImage) -> Result<(), HandlerError> {
+        if let Some(message) = self.higher.cursor_changed(cursor) {
+            send!(self, message);
+            // Until it's on its way the Client can't be told to show it again by its ID
+            self.higher.cursor_sent(cursor);
+        }
+        Ok(())
+    }
+
+    /// Answers a ClipboardRequest, content is None if the clipboard doesn't hold the requested type
+    pub fn clipboard_reply(
+        &mut self,
+        clipboard_type: ClipboardType,
+        is_content: bool,
+        content: Option<Vec<u8>>,
+    ) -> Result<(), HandlerError> {
+        if let Some(message) = self
+            .higher
+            .clipboard_reply(clipboard_type, is_content, content)
+        {
+            send!(self, message);
+        }
+        Ok(())
+    }
+
     pub fn send_frame_update(
         &mut self,
         display_id: DisplayId,
@@ -1,7 +1,15 @@
+pub mod bitrate_control;
+pub mod clipboard_sync;
 pub mod clipboard_type_map;
+pub mod file_transfer;
 pub mod frame_data_mtu;
+pub mod frame_fec;
+pub mod frame_history;
 pub mod frame_processor;
+pub mod frame_receiver;
 pub mod handler_stack;
 pub mod native_thumbnails;
 pub mod network_mouse_button_to_native;
+pub mod playout_timer;
 pub mod rvd_native_helper;
+pub mod watcher_thread;
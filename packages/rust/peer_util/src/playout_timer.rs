@@ -0,0 +1,53 @@
+use event_loop::{event_loop::ThreadWaker, JoinOnDrop};
+use std::{
+    sync::mpsc::{self, RecvTimeoutError, Sender},
+    thread,
+    time::Instant,
+};
+
+/// Wakes the given waker when the next frame is due to be handed out, the event loop has no timers
+/// of its own. The thread is only started once it's first needed.
+pub struct PlayoutTimer {
+    waker: ThreadWaker,
+    // The sender is dropped first, which is what stops the thread
+    thread: Option<(Sender<Instant>, JoinOnDrop<()>)>,
+}
+
+impl PlayoutTimer {
+    pub fn new(waker: ThreadWaker) -> Self {
+        Self {
+            waker,
+            thread: None,
+        }
+    }
+
+    /// Wakes the waker at the given time, instead of when it was last asked to
+    pub fn wake_at(&mut self, at: Instant) {
+        let waker = &self.waker;
+        let (sender, _) = self.thread.get_or_insert_with(|| {
+            let (sender, receiver) = mpsc::channel();
+            let waker = waker.clone();
+            let thread = thread::spawn(move || {
+                let mut deadline = None;
+                loop {
+                    let next = match deadline {
+                        Some(deadline) => receiver
+                            .recv_timeout(deadline.saturating_duration_since(Instant::now())),
+                        None => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+                    };
+                    deadline = match next {
+                        Ok(at) => Some(at),
+                        Err(RecvTimeoutError::Timeout) => {
+                            waker.wake();
+                            None
+                        }
+                        Err(RecvTimeoutError::Disconnected) => break,
+                    };
+                }
+            });
+            (sender, JoinOnDrop::new(thread))
+        });
+        // The thread only stops once we're dropped
+        let _ = sender.send(at);
+    }
+}
@@ -2,20 +2,15 @@ use crate::{
     clipboard_type_map::get_native_clipboard,
     network_mouse_button_to_native::network_mouse_button_to_native,
 };
-use common::messages::rvd::{ButtonsMask, DisplayId, RvdMessage};
-use native::api::{NativeApiTemplate, NativeId};
-use peer::{
-    rvd::{
-        RvdClientError,
-        RvdClientHandler,
-        RvdClientInform,
-        RvdHandlerTrait,
-        RvdHostError,
-        RvdHostHandler,
-        RvdHostInform,
-    },
-    InformEvent,
+use common::messages::rvd::{
+    ButtonsMask,
+    ClipboardType,
+    DisplayGeometry,
+    DisplayId,
+    DisplayRotation,
 };
+use native::api::{Monitor, NativeApiTemplate, NativeId, Rotation};
+use peer::rvd::{RvdClientError, RvdClientInform, RvdHostError, RvdHostInform};
 use std::{
     collections::HashMap,
     fmt::{Debug, Formatter},
@@ -27,9 +22,11 @@ pub fn rvd_client_native_helper<T: NativeApiTemplate>(
 ) -> Result<Option<RvdClientInform>, ClientError<T>> {
     match event {
         RvdClientInform::ClipboardNotification(data, clip_type) => {
-            native
-                .set_clipboard_content(&get_native_clipboard(&clip_type), &data)
-                .map_err(ClientError::NativeError)?;
+            if let Some(clip_type) = get_native_clipboard(&clip_type) {
+                native
+                    .set_clipboard_content(&clip_type, &data)
+                    .map_err(ClientError::NativeError)?;
+            }
             Ok(None)
         }
         _ => Ok(Some(event)),
@@ -56,31 +53,86 @@ impl<T: NativeApiTemplate> From<RvdClientError> for ClientError<T> {
     }
 }
 
+/// A display the Host has shared
+#[derive(Clone)]
+pub struct HostDisplay {
+    pub native_id: NativeId,
+    /// What the Client has been told about the display's size, only monitors have one
+    pub geometry: Option<DisplayGeometry>,
+}
+
+impl HostDisplay {
+    // MouseInput positions are in the display's pixels, the pointer is positioned in whatever units
+    // the native API uses
+    fn native_position(&self, x: u16, y: u16) -> (u32, u32) {
+        let geometry = match &self.geometry {
+            Some(geometry) => geometry,
+            None => return (x as u32, y as u32),
+        };
+        // The display may have shrunk since the Client sent this
+        let x = x.min(geometry.width.saturating_sub(1)) as u32;
+        let y = y.min(geometry.height.saturating_sub(1)) as u32;
+        let scale = geometry.scale.max(1) as u32;
+        (x * 100 / scale, y * 100 / scale)
+    }
+}
+
+/// Describes a monitor to the Client. Sizes that don't fit in a message are capped.
+pub fn monitor_geometry(display_id: DisplayId, monitor: &Monitor) -> DisplayGeometry {
+    let pixels = |length: u32| {
+        (length as f64 * monitor.scale_factor)
+            .round()
+            .min(u16::MAX as f64)
+    };
+    DisplayGeometry {
+        display_id,
+        width: pixels(monitor.width) as u16,
+        height: pixels(monitor.height) as u16,
+        scale: (monitor.scale_factor * 100.0)
+            .round()
+            .clamp(1.0, u16::MAX as f64) as u16,
+        rotation: match monitor.rotation {
+            Rotation::None => DisplayRotation::None,
+            Rotation::Clockwise90 => DisplayRotation::Clockwise90,
+            Rotation::Clockwise180 => DisplayRotation::Clockwise180,
+            Rotation::Clockwise270 => DisplayRotation::Clockwise270,
+        },
+    }
+}
+
 pub fn rvd_host_native_helper<T: NativeApiTemplate>(
     event: RvdHostInform,
     native: &mut T,
-    rvd_native_id_map: &HashMap<DisplayId, NativeId>,
-) -> Result<(Option<RvdHostInform>, Option<RvdMessage<'static>>), HostError<T>> {
+    displays: &HashMap<DisplayId, HostDisplay>,
+) -> Result<Option<RvdHostInform>, HostError<T>> {
     match event {
         RvdHostInform::MouseInput(event) => {
-            let native_id = rvd_native_id_map.get(&event.display_id).unwrap(); // TODO
+            let display = match displays.get(&event.display_id) {
+                Some(display) => display,
+                // The display went away while buttons were held on it, they can still be let go
+                None => {
+                    for mask in ButtonsMask::iter() {
+                        if event.button_delta.contains(*mask) && !event.button_state.contains(*mask)
+                        {
+                            native
+                                .toggle_mouse(network_mouse_button_to_native(mask), false, None)
+                                .map_err(HostError::NativeError)?;
+                        }
+                    }
+                    return Ok(None);
+                }
+            };
+            let native_id = &display.native_id;
+            let (x, y) = display.native_position(event.x_location, event.y_location);
             match native_id {
                 NativeId::Monitor(id) => {
                     native
-                        .set_pointer_position_absolute(
-                            event.x_location as u32,
-                            event.y_location as u32,
-                            *id,
-                        )
+                        .set_pointer_position_absolute(x, y, *id)
                         .map_err(HostError::NativeError)?;
                 }
                 NativeId::Window(id) => {
                     native
-                        .set_pointer_position_relative(
-                            event.x_location as u32,
-                            event.y_location as u32,
-                            *id,
-                        )
+                        .set_pointer_position_relative(x, y, *id)
                         .map_err(HostError::NativeError)?;
                 }
             }
@@ -99,31 +151,39 @@ pub fn rvd_host_native_helper<T: NativeApiTemplate>(
                         .map_err(HostError::NativeError)?;
                 }
             }
-            Ok((None, None))
+            Ok(None)
         }
         RvdHostInform::KeyboardInput(input) => {
             native
                 .key_toggle(input.key, input.down)
                 .map_err(HostError::NativeError)?;
-            Ok((None, None))
+            Ok(None)
         }
-        RvdHostInform::ClipboardRequest(is_content, clip_type) => {
-            let msg = <RvdHostHandler as RvdHandlerTrait>::clipboard_data(
-                native
-                    .clipboard_content(&get_native_clipboard(&clip_type))
-                    .map_err(HostError::NativeError)?,
-                is_content,
-                clip_type.clone(),
-            );
-            Ok((None, Some(msg)))
+        RvdHostInform::TextInput(text) => {
+            native.type_text(&text).map_err(HostError::NativeError)?;
+            Ok(None)
         }
         RvdHostInform::ClipboardNotification(data, clip_type) => {
-            native
-                .set_clipboard_content(&get_native_clipboard(&clip_type), &data)
-                .map_err(HostError::NativeError)?;
-            Ok((None, None))
+            if let Some(clip_type) = get_native_clipboard(&clip_type) {
+                native
+                    .set_clipboard_content(&clip_type, &data)
+                    .map_err(HostError::NativeError)?;
+            }
+            Ok(None)
         }
-        _ => Ok((Some(event), None)),
+        _ => Ok(Some(event)),
+    }
+}
+
+/// What the local clipboard holds of a type, for answering a ClipboardRequest. None if it doesn't
+/// hold that type.
+pub fn clipboard_content<T: NativeApiTemplate>(
+    native: &mut T,
+    clipboard_type: &ClipboardType,
+) -> Result<Option<Vec<u8>>, T::Error> {
+    match get_native_clipboard(clipboard_type) {
+        Some(native_type) => native.clipboard_content(&native_type),
+        None => Ok(None),
     }
 }
 
@@ -0,0 +1,49 @@
+use event_loop::{event_loop::ThreadWaker, JoinOnDrop};
+use native::api::WatcherTemplate;
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+/// Runs a watcher on its own thread, waking the given waker every time what it watches may have
+/// changed. If the watcher fails the thread stops and the waker isn't woken again.
+pub struct WatcherThread {
+    stop: Arc<AtomicBool>,
+    _thread: JoinOnDrop<()>,
+}
+
+impl WatcherThread {
+    // How long the thread can take to notice it should stop
+    const STOP_LATENCY: Duration = Duration::from_millis(500);
+
+    pub fn new<W>(mut watcher: W, waker: ThreadWaker) -> Self
+    where W: WatcherTemplate + Send + 'static {
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = Arc::clone(&stop);
+
+        let thread = thread::spawn(move || {
+            while !thread_stop.load(Ordering::Relaxed) {
+                match watcher.wait_for_change(Self::STOP_LATENCY) {
+                    Ok(true) => waker.wake(),
+                    Ok(false) => {}
+                    Err(_) => break,
+                }
+            }
+        });
+
+        Self {
+            stop,
+            _thread: JoinOnDrop::new(thread),
+        }
+    }
+}
+
+impl Drop for WatcherThread {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,197 @@
+use peer_util::{
+    bitrate_control::{BitrateControl, HIGH_LOSS, MAX_BITRATE},
+    frame_receiver::{receiver_report, REPORT_INTERVAL},
+};
+use rtp::{header::Header, packet::Packet};
+use std::time::{Duration, Instant};
+use video_process::{jitter_buffer::ReceiveStats, rtp::CLOCK_RATE, vp9::DEFAULT_BITRATE};
+
+const FRAME_INTERVAL: Duration = Duration::from_millis(100);
+const PAYLOAD_SIZE: usize = 1000;
+// The most a packet waits behind others on the link before it's dropped
+const MAX_QUEUE: Duration = Duration::from_millis(200);
+
+// A link that sends packets one after another at a fixed capacity, drops packets when too many are
+// queued up and loses some at random
+struct Link {
+    // In kbit/s
+    capacity: u32,
+    // Out of 1000
+    random_loss: u32,
+    busy_until: Duration,
+    seed: u32,
+}
+
+impl Link {
+    fn new(capacity: u32, random_loss: u32) -> Self {
+        Self {
+            capacity,
+            random_loss,
+            busy_until: Duration::ZERO,
+            seed: 1,
+        }
+    }
+
+    // When a packet sent at the given time arrives, if it does
+    fn send(&mut self, sent: Duration, size: usize) -> Option<Duration> {
+        self.seed = self.seed.wrapping_mul(1103515245).wrapping_add(12345);
+        if (self.seed >> 16) % 1000 < self.random_loss {
+            return None;
+        }
+        let start = self.busy_until.max(sent);
+        if start - sent > MAX_QUEUE {
+            return None;
+        }
+        self.busy_until =
+            start + Duration::from_secs_f64(size as f64 * 8.0 / (self.capacity as f64 * 1000.0));
+        Some(self.busy_until)
+    }
+}
+
+struct Simulation {
+    link: Link,
+    control: BitrateControl,
+    stats: ReceiveStats,
+    since: (u64, u64),
+    start: Instant,
+    now: Duration,
+    sequence: u16,
+    bitrate: u32,
+    // The fraction of the bitrate the encoder makes use of
+    usage: f64,
+    losses: Vec<f32>,
+}
+
+impl Simulation {
+    fn new(link: Link, usage: f64) -> Self {
+        Self {
+            link,
+            control: BitrateControl::new(),
+            stats: ReceiveStats::new(),
+            since: (0, 0),
+            start: Instant::now(),
+            now: Duration::ZERO,
+            sequence: 0,
+            bitrate: DEFAULT_BITRATE,
+            usage,
+            losses: Vec::new(),
+        }
+    }
+
+    // Sends a frame the size the encoder aims for
+    fn send_frame(&mut self) {
+        let timestamp = (self.now.as_secs_f64() * CLOCK_RATE as f64) as u32;
+        let mut size = (self.bitrate as f64 * 1000.0 / 8.0
+            * FRAME_INTERVAL.as_secs_f64()
+            * self.usage) as usize;
+        while size > 0 {
+            let len = size.min(PAYLOAD_SIZE);
+            size -= len;
+            let packet = Packet {
+                header: Header {
+                    version: 2,
+                    marker: size == 0,
+                    sequence_number: self.sequence,
+                    timestamp,
+                    ..Default::default()
+                },
+                payload: vec![0; len].into(),
+            };
+            self.sequence = self.sequence.wrapping_add(1);
+            if let Some(arrival) = self.link.send(self.now, len) {
+                self.stats.record(&packet, self.start + arrival);
+            }
+        }
+    }
+
+    fn run(&mut self, duration: Duration) {
+        let mut next_report = self.now + REPORT_INTERVAL;
+        let end = self.now + duration;
+        while self.now < end {
+            self.send_frame();
+            self.now += FRAME_INTERVAL;
+            if self.now >= next_report {
+                next_report += REPORT_INTERVAL;
+                let report = receiver_report(0, &self.stats, &mut self.since);
+                self.losses.push(report.fraction_lost as f32 / 256.0);
+                if let Some(bitrate) = self.control.report(0, &report, self.start + self.now) {
+                    self.bitrate = bitrate;
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_bitrate_congestion() {
+    // A link slower than the bitrate we start at
+    let capacity = 1000;
+    let mut sim = Simulation::new(Link::new(capacity, 0), 1.0);
+    sim.run(Duration::from_secs(60));
+    assert!(sim.bitrate >= capacity / 2);
+    assert!(sim.bitrate <= capacity * 6 / 5);
+    // Once settled the link isn't kept congested
+    for &loss in &sim.losses[30 ..] {
+        assert!(loss <= HIGH_LOSS);
+    }
+}
+
+#[test]
+fn test_bitrate_increase() {
+    // A link with room to spare
+    let mut sim = Simulation::new(Link::new(MAX_BITRATE * 10, 0), 1.0);
+    sim.run(Duration::from_secs(60));
+    assert_eq!(sim.bitrate, MAX_BITRATE);
+    assert_eq!(sim.control.bitrate(0), MAX_BITRATE);
+
+    // Unless the encoder isn't making use of what it has, e.g. the screen isn't changing
+    let mut sim = Simulation::new(Link::new(MAX_BITRATE * 10, 0), 0.1);
+    sim.run(Duration::from_secs(60));
+    assert_eq!(sim.bitrate, DEFAULT_BITRATE);
+}
+
+#[test]
+fn test_bitrate_random_loss() {
+    // Loss that isn't from congestion neither cuts the bitrate down to nothing nor lets it climb
+    let mut sim = Simulation::new(Link::new(MAX_BITRATE * 10, 50), 1.0);
+    sim.run(Duration::from_secs(60));
+    assert!(sim.bitrate >= DEFAULT_BITRATE * 4 / 5);
+    assert!(sim.bitrate <= DEFAULT_BITRATE * 3 / 2);
+}
+
+#[test]
+fn test_bitrate_clients() {
+    let mut control = BitrateControl::new();
+    let now = Instant::now();
+    let mut stats = ReceiveStats::new();
+    let mut since = (0, 0);
+    for sequence in 0 .. 10 {
+        let packet = Packet {
+            header: Header {
+                sequence_number: sequence,
+                ..Default::default()
+            },
+            payload: vec![0; PAYLOAD_SIZE].into(),
+        };
+        // Every other one is lost
+        if sequence % 2 == 0 {
+            stats.record(&packet, now);
+        }
+    }
+    let lossy = receiver_report(0, &stats, &mut since);
+    assert_eq!(lossy.fraction_lost, 113);
+    let fine = receiver_report(0, &stats, &mut since);
+    assert_eq!(fine.fraction_lost, 0);
+
+    // A display is encoded at the bitrate of its slowest Client
+    assert_eq!(control.report(1, &fine, now), Some(DEFAULT_BITRATE));
+    let cut = control.report(2, &lossy, now).expect("bitrate wasn't cut");
+    assert!(cut < DEFAULT_BITRATE);
+    assert_eq!(control.report(1, &fine, now), None);
+    assert_eq!(control.bitrate(0), cut);
+
+    // Until it leaves
+    assert_eq!(control.remove_client(2), vec![(0, DEFAULT_BITRATE)]);
+    control.remove(0);
+    assert_eq!(control.bitrate(0), DEFAULT_BITRATE);
+}
@@ -0,0 +1,32 @@
+use common::messages::rvd::ClipboardType;
+use peer_util::clipboard_sync::ClipboardSync;
+
+#[test]
+fn test_remote_change_not_echoed() {
+    let mut sync = ClipboardSync::new();
+
+    // Writing what the peer sent to the local clipboard triggers a local change with the same content
+    sync.remote_change(&ClipboardType::Text, b"from peer");
+    assert!(!sync.local_change(&ClipboardType::Text, b"from peer"));
+
+    // Anything new is sent
+    assert!(sync.local_change(&ClipboardType::Text, b"copied locally"));
+}
+
+#[test]
+fn test_local_change_sent_once() {
+    let mut sync = ClipboardSync::new();
+
+    assert!(sync.local_change(&ClipboardType::Text, b"copied"));
+    assert!(!sync.local_change(&ClipboardType::Text, b"copied"));
+    assert!(sync.local_change(&ClipboardType::Text, b"copied again"));
+}
+
+#[test]
+fn test_clipboard_types_independent() {
+    let mut sync = ClipboardSync::new();
+
+    sync.remote_change(&ClipboardType::Text, b"content");
+    assert!(sync.local_change(&ClipboardType::Html, b"content"));
+    assert!(!sync.local_change(&ClipboardType::Text, b"content"));
+}
@@ -0,0 +1,137 @@
+use peer::rvd::FILE_CHUNK_SIZE;
+use peer_util::file_transfer::{part_file_name, FileError, IncomingFile, OutgoingFile};
+use std::{env, fs, path::PathBuf, process};
+
+fn test_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("screenview_{}_{}", name, process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn send(outgoing: &mut OutgoingFile, incoming: &mut IncomingFile, offset: u64) {
+    outgoing.seek(offset).unwrap();
+    let mut offset = offset;
+    loop {
+        let chunk = outgoing.next_chunk().unwrap();
+        if chunk.is_empty() {
+            break;
+        }
+        incoming.write_chunk(offset, chunk).unwrap();
+        offset += chunk.len() as u64;
+    }
+}
+
+#[test]
+fn test_file_transfer() {
+    let dir = test_dir("file_transfer");
+    let content: Vec<u8> = (0 .. FILE_CHUNK_SIZE * 2 + 100).map(|i| i as u8).collect();
+    fs::write(dir.join("logs.tar"), &content).unwrap();
+
+    let mut outgoing = OutgoingFile::open(dir.join("logs.tar")).unwrap();
+    assert_eq!(outgoing.name(), "logs.tar");
+    assert_eq!(outgoing.size(), content.len() as u64);
+
+    let received = dir.join("received");
+    fs::create_dir(&received).unwrap();
+    let mut incoming =
+        IncomingFile::create(&received, outgoing.name(), outgoing.size(), outgoing.hash()).unwrap();
+    assert_eq!(incoming.offset(), 0);
+
+    send(&mut outgoing, &mut incoming, 0);
+    let path = incoming.finish().unwrap();
+    assert_eq!(path, received.join("logs.tar"));
+    assert_eq!(fs::read(&path).unwrap(), content);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_file_transfer_resume() {
+    let dir = test_dir("file_transfer_resume");
+    let content: Vec<u8> = (0 .. FILE_CHUNK_SIZE * 3).map(|i| (i * 7) as u8).collect();
+    fs::write(dir.join("bundle.zip"), &content).unwrap();
+    let received = dir.join("received");
+    fs::create_dir(&received).unwrap();
+    let mut outgoing = OutgoingFile::open(dir.join("bundle.zip")).unwrap();
+    // An earlier transfer got part of the way
+    let part_name = part_file_name("bundle.zip", outgoing.size(), &outgoing.hash());
+    fs::write(received.join(part_name), &content[.. 1000]).unwrap();
+    // and there's already a file with the same name
+    fs::write(received.join("bundle.zip"), b"older").unwrap();
+
+    let mut incoming =
+        IncomingFile::create(&received, "bundle.zip", outgoing.size(), outgoing.hash()).unwrap();
+    assert_eq!(incoming.offset(), 1000);
+
+    send(&mut outgoing, &mut incoming, 1000);
+    let path = incoming.finish().unwrap();
+    assert_eq!(path, received.join("bundle (1).zip"));
+    assert_eq!(fs::read(&path).unwrap(), content);
+    assert_eq!(fs::read(received.join("bundle.zip")).unwrap(), b"older");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_file_transfer_resume_other_file() {
+    let dir = test_dir("file_transfer_resume_other_file");
+    let content = vec![1u8; FILE_CHUNK_SIZE];
+    fs::write(dir.join("notes.txt"), &content).unwrap();
+    let received = dir.join("received");
+    fs::create_dir(&received).unwrap();
+    // Part of an older notes.txt of the same size
+    let old_hash = *blake3::hash(&[2u8; FILE_CHUNK_SIZE]).as_bytes();
+    let part_name = part_file_name("notes.txt", content.len() as u64, &old_hash);
+    fs::write(received.join(&part_name), [2u8; 1000]).unwrap();
+
+    // isn't resumed from
+    let mut outgoing = OutgoingFile::open(dir.join("notes.txt")).unwrap();
+    let mut incoming =
+        IncomingFile::create(&received, "notes.txt", outgoing.size(), outgoing.hash()).unwrap();
+    assert_eq!(incoming.offset(), 0);
+
+    send(&mut outgoing, &mut incoming, 0);
+    let path = incoming.finish().unwrap();
+    assert_eq!(fs::read(&path).unwrap(), content);
+    assert!(received.join(part_name).exists());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_file_transfer_hash_mismatch() {
+    let dir = test_dir("file_transfer_hash_mismatch");
+    let mut incoming = IncomingFile::create(&dir, "file", 4, [0; 32]).unwrap();
+    incoming.write_chunk(0, b"data").unwrap();
+    assert!(matches!(incoming.finish(), Err(FileError::HashMismatch)));
+    assert!(!dir.join("file").exists());
+    assert!(!dir.join(part_file_name("file", 4, &[0; 32])).exists());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_file_transfer_bad_name() {
+    let dir = test_dir("file_transfer_bad_name");
+    for name in ["../escape", "/etc/passwd", "nested/file", "..", ""] {
+        assert!(matches!(
+            IncomingFile::create(&dir, name, 0, [0; 32]),
+            Err(FileError::BadName(_))
+        ));
+    }
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_file_transfer_out_of_order() {
+    let dir = test_dir("file_transfer_out_of_order");
+    let mut incoming = IncomingFile::create(&dir, "file", 10, [0; 32]).unwrap();
+    assert!(matches!(
+        incoming.write_chunk(5, b"data"),
+        Err(FileError::OutOfOrder(5, 0))
+    ));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,253 @@
+use peer_util::{
+    frame_fec::{FrameFec, FEC_LOSS_THRESHOLD, FEC_LOSS_WINDOW, FEC_MAX_RATIO},
+    frame_history::{FrameHistory, FRAME_HISTORY_SIZE},
+    frame_receiver::{FrameFeedback, FrameReceiver, FEC_WAIT, REPORT_INTERVAL, RETRANSMIT_TIMEOUT},
+};
+use rtp::packet::Packet;
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+use video_process::{jitter_buffer::MAX_PLAYOUT_DELAY, rtp::RtpEncoder};
+use webrtc_util::Marshal;
+
+const FRAME: &[u8] = include_bytes!("../../video_process/tests/img.vp9");
+
+fn keyframe(rtp: &mut RtpEncoder) -> Vec<Packet> {
+    let frame = FRAME;
+    let packets = rtp
+        .process_vp9(frame.to_vec())
+        .expect("could not encode frame");
+    assert!(packets.len() > 3);
+    packets
+}
+
+fn receive(receiver: &mut FrameReceiver, packet: &Packet) -> FrameFeedback {
+    let data = packet.marshal().expect("could not marshal packet");
+    receiver.receive(0, &data).expect("could not decode frame")
+}
+
+#[test]
+fn test_frame_nack() {
+    let mut rtp = RtpEncoder::new(500, 1);
+    let mut history = FrameHistory::new();
+    let mut receiver = FrameReceiver::new();
+
+    let packets = keyframe(&mut rtp);
+    history.record(0, &packets);
+    for packet in &packets {
+        assert_eq!(receive(&mut receiver, packet), FrameFeedback::default());
+    }
+
+    // A lost packet is asked for once
+    let packets = keyframe(&mut rtp);
+    history.record(0, &packets);
+    let lost = packets[1].header.sequence_number;
+    assert_eq!(
+        receive(&mut receiver, &packets[0]),
+        FrameFeedback::default()
+    );
+    assert_eq!(receive(&mut receiver, &packets[2]), FrameFeedback {
+        nack: vec![lost],
+        picture_loss: false,
+        ..FrameFeedback::default()
+    });
+    for packet in &packets[3 ..] {
+        assert_eq!(receive(&mut receiver, packet), FrameFeedback::default());
+    }
+
+    let (resent, complete) = history.retransmit(0, &[lost]);
+    assert!(complete);
+    assert_eq!(resent.len(), 1);
+    assert_eq!(resent[0].header.sequence_number, lost);
+    assert_eq!(receive(&mut receiver, &resent[0]), FrameFeedback::default());
+
+    // Packets that never arrive are given up on for a keyframe
+    let packets = keyframe(&mut rtp);
+    assert_eq!(
+        receive(&mut receiver, &packets[0]),
+        FrameFeedback::default()
+    );
+    assert_eq!(receive(&mut receiver, &packets[2]), FrameFeedback {
+        nack: vec![packets[1].header.sequence_number],
+        picture_loss: false,
+        ..FrameFeedback::default()
+    });
+    thread::sleep(RETRANSMIT_TIMEOUT + Duration::from_millis(50));
+    assert_eq!(receive(&mut receiver, &packets[3]), FrameFeedback {
+        nack: Vec::new(),
+        picture_loss: true,
+        ..FrameFeedback::default()
+    });
+
+    // Which ends the loss
+    for packet in &keyframe(&mut rtp) {
+        assert_eq!(receive(&mut receiver, packet), FrameFeedback::default());
+    }
+}
+
+#[test]
+fn test_frame_history() {
+    let mut rtp = RtpEncoder::new(500, 1);
+    let mut history = FrameHistory::new();
+
+    let first = keyframe(&mut rtp);
+    history.record(0, &first);
+    let sequence = first[0].header.sequence_number;
+
+    // Packets that were never sent need a keyframe
+    let (resent, complete) = history.retransmit(0, &[sequence, sequence.wrapping_sub(1)]);
+    assert!(!complete);
+    assert_eq!(resent.len(), 1);
+    let (resent, complete) = history.retransmit(1, &[sequence]);
+    assert!(!complete);
+    assert!(resent.is_empty());
+
+    // So do ones that are too old to be kept
+    let mut recorded = first.len();
+    while recorded <= FRAME_HISTORY_SIZE {
+        let packets = keyframe(&mut rtp);
+        history.record(0, &packets);
+        recorded += packets.len();
+    }
+    let (resent, complete) = history.retransmit(0, &[sequence]);
+    assert!(!complete);
+    assert!(resent.is_empty());
+
+    // And ones for displays that have been unshared
+    let last = first.last().unwrap().header.sequence_number.wrapping_add(1);
+    assert!(history.retransmit(0, &[last]).1);
+    history.remove(0);
+    assert!(!history.retransmit(0, &[last]).1);
+
+    // A sequence number that comes around again is kept as long as the packets sent with it
+    let numbered = |sequence: u16| {
+        let mut packet = first[0].clone();
+        packet.header.sequence_number = sequence;
+        packet
+    };
+    let packets: Vec<Packet> = (0 .. FRAME_HISTORY_SIZE as u16).map(&numbered).collect();
+    history.record(0, &packets);
+    history.record(0, &[numbered(0)]);
+    history.record(0, &[numbered(FRAME_HISTORY_SIZE as u16)]);
+    assert!(history.retransmit(0, &[0]).1);
+    assert!(!history.retransmit(0, &[1]).1);
+}
+
+// Sends frames until the loss is measured again
+fn send_window(rtp: &mut RtpEncoder, fec: &mut FrameFec, receiver: &mut FrameReceiver) {
+    let mut sent = 0;
+    while sent < FEC_LOSS_WINDOW {
+        let packets = keyframe(rtp);
+        let repairs = fec.protect(0, &packets);
+        for packet in packets.iter().chain(&repairs) {
+            receive(receiver, packet);
+        }
+        sent += packets.len();
+    }
+}
+
+#[test]
+fn test_frame_fec() {
+    let mut rtp = RtpEncoder::new(500, 1);
+    let mut fec = FrameFec::new();
+    let mut receiver = FrameReceiver::new();
+
+    // Nothing extra is sent until packets are reported lost
+    send_window(&mut rtp, &mut fec, &mut receiver);
+    assert_eq!(fec.ratio(0), 0.0);
+
+    // The more that's lost the more repair packets are sent
+    fec.lost(
+        0,
+        (FEC_LOSS_WINDOW as f32 * FEC_LOSS_THRESHOLD * 4.0) as usize,
+    );
+    send_window(&mut rtp, &mut fec, &mut receiver);
+    let ratio = fec.ratio(0);
+    assert!(ratio > 0.0);
+    fec.lost(0, FEC_LOSS_WINDOW);
+    send_window(&mut rtp, &mut fec, &mut receiver);
+    assert!(fec.ratio(0) > ratio);
+    assert!(fec.ratio(0) <= FEC_MAX_RATIO);
+
+    // A lost packet is recovered without asking for it
+    receiver.frames(Instant::now() + MAX_PLAYOUT_DELAY * 2);
+    let packets = keyframe(&mut rtp);
+    let repairs = fec.protect(0, &packets);
+    for (_, packet) in packets.iter().enumerate().filter(|&(i, _)| i != 1) {
+        assert!(receive(&mut receiver, packet).nack.is_empty());
+    }
+    for repair in &repairs {
+        assert!(receive(&mut receiver, repair).nack.is_empty());
+    }
+    let frames = receiver.frames(Instant::now() + MAX_PLAYOUT_DELAY * 2);
+    assert_eq!(frames.len(), 1);
+    assert_eq!(frames[0].1.timestamp, packets[0].header.timestamp);
+    assert_eq!(frames[0].1.data, FRAME);
+
+    // One that can't be recovered is asked for once it's had the chance
+    let packets = keyframe(&mut rtp);
+    fec.protect(0, &packets);
+    receive(&mut receiver, &packets[0]);
+    assert!(receive(&mut receiver, &packets[2]).nack.is_empty());
+    thread::sleep(FEC_WAIT + Duration::from_millis(10));
+    assert_eq!(receive(&mut receiver, &packets[3]).nack, vec![
+        packets[1].header.sequence_number
+    ]);
+
+    fec.remove(0);
+    assert_eq!(fec.ratio(0), 0.0);
+}
+
+#[test]
+fn test_receiver_report() {
+    let mut rtp = RtpEncoder::new(500, 1);
+    let mut receiver = FrameReceiver::new();
+
+    let packets = keyframe(&mut rtp);
+    for packet in &packets {
+        assert!(receive(&mut receiver, packet).report.is_none());
+    }
+
+    // Reports go out every REPORT_INTERVAL, covering the loss since the last one
+    thread::sleep(REPORT_INTERVAL);
+    let packets = keyframe(&mut rtp);
+    let report = receive(&mut receiver, &packets[1])
+        .report
+        .expect("no report was made");
+    assert_eq!(report.display_id, 0);
+    assert_eq!(report.fraction_lost, (256 / (packets.len() + 2)) as u8);
+    assert_eq!(report.lost, 1);
+    assert!(report.received_bytes > 0);
+    assert!(receive(&mut receiver, &packets[2]).report.is_none());
+}
+
+#[test]
+fn test_frame_playout() {
+    let mut rtp = RtpEncoder::new(500, 1);
+    let mut receiver = FrameReceiver::new();
+
+    // Packets that arrive out of order are put back together
+    let packets = keyframe(&mut rtp);
+    for packet in packets.iter().rev() {
+        receive(&mut receiver, packet);
+    }
+    let now = Instant::now();
+    let due = receiver.next_due().expect("no frame is waiting");
+    assert!(due <= now + MAX_PLAYOUT_DELAY);
+    // once they're due
+    if now < due {
+        assert!(receiver.frames(now).is_empty());
+    }
+    let frames = receiver.frames(due);
+    assert_eq!(frames.len(), 1);
+    assert_eq!(frames[0].0, 0);
+    assert!(frames[0].1.keyframe);
+    assert_eq!(frames[0].1.data, FRAME);
+    assert!(receiver.next_due().is_none());
+
+    receive(&mut receiver, &keyframe(&mut rtp)[0]);
+    assert!(receiver.next_due().is_some());
+    receiver.remove(0);
+    assert!(receiver.next_due().is_none());
+}
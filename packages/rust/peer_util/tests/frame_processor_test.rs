@@ -3,12 +3,14 @@ use native::{
     api::{
         BGRAFrame,
         ClipboardType as NativeClipboardType,
+        CursorImage,
         Key,
         Monitor,
         MonitorId,
         MouseButton,
         MousePosition,
         NativeApiTemplate,
+        Rotation,
         Window,
         WindowId,
     },
@@ -30,6 +32,8 @@ impl TesterNative {
                 name: "Mock Display 1".to_string(),
                 width: 1000,
                 height: 1000,
+                scale_factor: 1.0,
+                rotation: Rotation::None,
             },
         }
     }
@@ -42,6 +46,10 @@ impl NativeApiTemplate for TesterNative {
         unimplemented!()
     }
 
+    fn type_text(&mut self, text: &str) -> Result<(), Self::Error> {
+        unimplemented!()
+    }
+
     fn pointer_position(&mut self, _windows: &[WindowId]) -> Result<MousePosition, Self::Error> {
         unimplemented!()
     }
@@ -88,6 +96,10 @@ impl NativeApiTemplate for TesterNative {
         unimplemented!()
     }
 
+    fn cursor_image(&mut self) -> Result<Option<CursorImage>, Self::Error> {
+        Ok(None)
+    }
+
     fn monitors(&mut self) -> Result<Vec<Monitor>, Self::Error> {
         Ok(vec![self.monitor.clone()])
     }
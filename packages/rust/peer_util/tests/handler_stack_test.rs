@@ -0,0 +1,145 @@
+use common::messages::wpskka::AuthSchemeType;
+use event_loop::event_loop::{ThreadWaker, ThreadWakerCore};
+use io::{IoHandle, TcpHandle};
+use peer::{
+    helpers::secret::SecretVec,
+    higher_handler::{HigherHandlerClient, HigherHandlerHost, HigherHandlerTrait},
+    lower::LowerHandlerDirect,
+    rvd::{CursorImage, RvdClientInform, RvdHostInform},
+    wpskka::WpskkaClientInform,
+    InformEvent,
+};
+use peer_util::handler_stack::HandlerStack;
+use std::{
+    net::{TcpListener, TcpStream},
+    thread,
+    time::{Duration, Instant},
+};
+
+const PASSWORD: &[u8] = b"password";
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+type DirectStack<H> = HandlerStack<H, LowerHandlerDirect, TcpHandle, TcpHandle>;
+
+fn connect() -> (TcpStream, TcpStream) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (other, _) = listener.accept().unwrap();
+    (other, stream)
+}
+
+// Unreliable messages go over a second TCP connection, which is enough to test the reliable one
+fn stack<H: HigherHandlerTrait>(
+    higher: H,
+    reliable: TcpStream,
+    unreliable: TcpStream,
+    waker: &ThreadWaker,
+) -> DirectStack<H> {
+    let mut io_handle = IoHandle::new();
+    io_handle.connect_reliable_with(|sender| TcpHandle::new_from(reliable, sender, waker.clone()));
+    io_handle.bind_unreliable_with(|sender| TcpHandle::new_from(unreliable, sender, waker.clone()));
+    HandlerStack::new(higher, LowerHandlerDirect::new(), io_handle)
+}
+
+fn drain<H: HigherHandlerTrait>(stack: &mut DirectStack<H>) -> Vec<InformEvent> {
+    let mut events = Vec::new();
+    while let Some(result) = stack.handle_next_message() {
+        events.extend(result.expect("handler failed"));
+    }
+    events
+}
+
+// Handles messages on both stacks until either has events
+fn pump(
+    host: &mut DirectStack<HigherHandlerHost>,
+    client: &mut DirectStack<HigherHandlerClient>,
+) -> (Vec<InformEvent>, Vec<InformEvent>) {
+    let deadline = Instant::now() + TIMEOUT;
+    loop {
+        let host_events = drain(host);
+        let client_events = drain(client);
+        if !host_events.is_empty() || !client_events.is_empty() {
+            return (host_events, client_events);
+        }
+        assert!(Instant::now() < deadline, "timed out");
+        thread::sleep(Duration::from_millis(1));
+    }
+}
+
+fn handshake(
+    host: &mut DirectStack<HigherHandlerHost>,
+    client: &mut DirectStack<HigherHandlerClient>,
+) {
+    host.set_static_password(Some(SecretVec::from(PASSWORD)));
+    host.key_exchange().unwrap();
+
+    let mut host_ready = false;
+    let mut client_ready = false;
+    while !host_ready || !client_ready {
+        let (host_events, client_events) = pump(host, client);
+        for event in host_events {
+            if let InformEvent::RvdHostInform(RvdHostInform::HandshakeComplete) = event {
+                host_ready = true;
+            }
+        }
+        for event in client_events {
+            match event {
+                InformEvent::WpskkaClientInform(WpskkaClientInform::AuthScheme(_)) =>
+                    client.try_auth(AuthSchemeType::SrpStatic).unwrap(),
+                InformEvent::WpskkaClientInform(WpskkaClientInform::PasswordPrompt) =>
+                    client.process_password(PASSWORD).unwrap(),
+                InformEvent::WpskkaClientInform(WpskkaClientInform::AuthSuccessful) =>
+                    client.protocol_version().unwrap(),
+                InformEvent::RvdClientInform(RvdClientInform::HandshakeComplete) =>
+                    client_ready = true,
+                _ => {}
+            }
+        }
+    }
+}
+
+#[test]
+fn test_handler_stack_extended_frames() {
+    let waker_core = ThreadWakerCore::new_current_thread();
+    let waker = waker_core.make_waker(0);
+    let (host_reliable, client_reliable) = connect();
+    let (host_unreliable, client_unreliable) = connect();
+    let mut host = stack(
+        HigherHandlerHost::new(),
+        host_reliable,
+        host_unreliable,
+        &waker,
+    );
+    let mut client = stack(
+        HigherHandlerClient::new(),
+        client_reliable,
+        client_unreliable,
+        &waker,
+    );
+
+    handshake(&mut host, &mut client);
+
+    // Too large for a single frame before extended frames
+    let cursor = CursorImage {
+        width: 256,
+        height: 256,
+        hotspot_x: 0,
+        hotspot_y: 0,
+        data: (0 .. 256 * 256 * 4).map(|i| (i % 251) as u8).collect(),
+    };
+    host.cursor_changed(&cursor).unwrap();
+
+    let deadline = Instant::now() + TIMEOUT;
+    loop {
+        let (_, client_events) = pump(&mut host, &mut client);
+        let received = client_events.into_iter().find_map(|event| match event {
+            InformEvent::RvdClientInform(RvdClientInform::CursorShape(received)) => Some(received),
+            _ => None,
+        });
+        if let Some(received) = received {
+            assert_eq!(received, cursor);
+            break;
+        }
+        assert!(Instant::now() < deadline, "timed out");
+    }
+}
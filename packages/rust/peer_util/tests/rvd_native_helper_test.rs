@@ -5,6 +5,8 @@ use common::messages::rvd::{
     ClipboardNotification,
     ClipboardRequest,
     ClipboardType,
+    DisplayId,
+    DisplayRotation,
     DisplayShareAck,
     HandshakeComplete,
     KeyInput,
@@ -12,6 +14,7 @@ use common::messages::rvd::{
     PermissionMask,
     ProtocolVersionResponse,
     RvdMessage,
+    TextInput,
     UnreliableAuthFinal,
     UnreliableAuthInitial,
     UnreliableAuthInter,
@@ -19,6 +22,7 @@ use common::messages::rvd::{
 use native::api::{
     BGRAFrame,
     ClipboardType as NativeClipboardType,
+    CursorImage,
     Key,
     Monitor,
     MonitorId,
@@ -26,14 +30,21 @@ use native::api::{
     MousePosition,
     NativeApiTemplate,
     NativeId,
+    Rotation,
     Window,
     WindowId,
 };
 use peer::{
-    rvd::{RvdClientHandler, RvdHandlerTrait, RvdHostHandler},
+    rvd::{RvdClientHandler, RvdHandlerTrait, RvdHostHandler, RvdHostInform},
     InformEvent,
 };
-use peer_util::rvd_native_helper::{rvd_client_native_helper, rvd_host_native_helper};
+use peer_util::rvd_native_helper::{
+    clipboard_content,
+    monitor_geometry,
+    rvd_client_native_helper,
+    rvd_host_native_helper,
+    HostDisplay,
+};
 use std::{collections::HashMap, convert::Infallible};
 
 // TODO consider not involving the RvdHandlers and just testing rvd_{client, host}_native_helper
@@ -179,11 +190,10 @@ fn test_host_notification() {
     };
 
 
-    let (event, msg) =
+    let event =
         rvd_host_native_helper(event, &mut native, &HashMap::new()).expect("handler failed");
 
     assert!(event.is_none());
-    assert!(msg.is_none());
 
     assert_eq!(native.clipboard_content, clipboard);
 }
@@ -220,11 +230,10 @@ fn test_host_key_input() {
     };
 
 
-    let (event, msg) =
+    let event =
         rvd_host_native_helper(event, &mut native, &HashMap::new()).expect("handler failed");
 
     assert!(event.is_none());
-    assert!(msg.is_none());
 
     assert!(native.down_keys.contains(&40));
 
@@ -246,15 +255,50 @@ fn test_host_key_input() {
     };
 
 
-    let (event, msg) =
+    let event =
         rvd_host_native_helper(event, &mut native, &HashMap::new()).expect("handler failed");
 
     assert!(event.is_none());
-    assert!(msg.is_none());
 
     assert!(!native.down_keys.contains(&40));
 }
 
+#[test]
+fn test_host_text_input() {
+    let mut write = Vec::new();
+    let mut events = Vec::new();
+    let mut native = TesterNative::new();
+    let mut host = RvdHostHandler::new();
+
+    handshake(Some(&mut host), None);
+
+    host.share_display("test".to_string(), AccessMask::CONTROLLABLE)
+        .expect("share_display failed");
+
+    let msg = RvdMessage::TextInput(TextInput {
+        text: "こんにちは".to_string(),
+    });
+
+    host.handle(msg, &mut write, &mut events)
+        .expect("handler failed");
+
+    assert_eq!(write.len(), 0);
+    assert_eq!(events.len(), 1);
+
+    let event = match events.remove(0) {
+        InformEvent::RvdHostInform(e) => e,
+        _ => panic!("unexpected event"),
+    };
+
+    let event =
+        rvd_host_native_helper(event, &mut native, &HashMap::new()).expect("handler failed");
+
+    assert!(event.is_none());
+
+    assert_eq!(native.typed, "こんにちは");
+    assert!(native.down_keys.is_empty());
+}
+
 
 #[test]
 fn test_host_mouse_input() {
@@ -271,7 +315,10 @@ fn test_host_mouse_input() {
         .share_display(monitor.name.clone(), AccessMask::CONTROLLABLE)
         .expect("share_display failed");
 
-    map.insert(display_id, NativeId::Monitor(monitor.id));
+    map.insert(display_id, HostDisplay {
+        native_id: NativeId::Monitor(monitor.id),
+        geometry: None,
+    });
 
     host.handle(
         RvdMessage::DisplayShareAck(DisplayShareAck { display_id }),
@@ -307,10 +354,9 @@ fn test_host_mouse_input() {
     };
 
 
-    let (event, msg) = rvd_host_native_helper(event, &mut native, &map).expect("handler failed");
+    let event = rvd_host_native_helper(event, &mut native, &map).expect("handler failed");
 
     assert!(event.is_none());
-    assert!(msg.is_none());
 
     assert_eq!(native.pointer_x, 150);
     assert_eq!(native.pointer_y, 300);
@@ -318,6 +364,219 @@ fn test_host_mouse_input() {
     // TODO test button mask
 }
 
+#[test]
+fn test_host_mouse_input_scaled() {
+    let mut write = Vec::new();
+    let mut events = Vec::new();
+    let mut native = TesterNative::new();
+    let mut host = RvdHostHandler::new();
+    handshake(Some(&mut host), None);
+
+    // A HiDPI monitor turned on its side, positions arrive in pixels but are set in points
+    let mut monitor = native.monitors[0].clone();
+    monitor.scale_factor = 2.0;
+    monitor.rotation = Rotation::Clockwise90;
+
+    let (display_id, _) = host
+        .share_display(monitor.name.clone(), AccessMask::CONTROLLABLE)
+        .expect("share_display failed");
+    let geometry = monitor_geometry(display_id, &monitor);
+    assert_eq!(geometry.width, monitor.width as u16 * 2);
+    assert_eq!(geometry.height, monitor.height as u16 * 2);
+    assert_eq!(geometry.scale, 200);
+    assert_eq!(geometry.rotation, DisplayRotation::Clockwise90);
+
+    assert!(matches!(
+        host.set_display_geometry(geometry.clone()),
+        Ok(Some(RvdMessage::DisplayGeometry(_)))
+    ));
+    // Nothing changed so there's nothing to send
+    assert!(matches!(
+        host.set_display_geometry(geometry.clone()),
+        Ok(None)
+    ));
+
+    let mut map = HashMap::new();
+    map.insert(display_id, HostDisplay {
+        native_id: NativeId::Monitor(monitor.id),
+        geometry: Some(geometry),
+    });
+
+    host.handle(
+        RvdMessage::DisplayShareAck(DisplayShareAck { display_id }),
+        &mut write,
+        &mut events,
+    )
+    .expect("handler failed");
+
+    for (x_location, y_location, pointer_x, pointer_y) in [
+        (150, 300, 75, 150),
+        // Positions outside the display end up on its edge
+        (u16::MAX, u16::MAX, monitor.width - 1, monitor.height - 1),
+    ] {
+        host.handle(
+            RvdMessage::MouseInput(MouseInput {
+                display_id,
+                x_location,
+                y_location,
+                buttons_delta: ButtonsMask::empty(),
+                buttons_state: ButtonsMask::empty(),
+            }),
+            &mut write,
+            &mut events,
+        )
+        .expect("handler failed");
+        let event = match events.remove(0) {
+            InformEvent::RvdHostInform(e) => e,
+            _ => panic!("unexpected event"),
+        };
+        rvd_host_native_helper(event, &mut native, &map).expect("handler failed");
+
+        assert_eq!(native.pointer_x, pointer_x);
+        assert_eq!(native.pointer_y, pointer_y);
+    }
+}
+
+
+// Passes the input the Host let through or let go of to the native API
+fn apply_input(
+    informs: impl IntoIterator<Item = RvdHostInform>,
+    native: &mut TesterNative,
+    displays: &HashMap<DisplayId, HostDisplay>,
+) {
+    for inform in informs {
+        let inform = rvd_host_native_helper(inform, native, displays).expect("handler failed");
+        assert!(inform.is_none());
+    }
+}
+
+fn host_informs(events: &mut Vec<InformEvent>) -> Vec<RvdHostInform> {
+    events
+        .drain(..)
+        .map(|event| match event {
+            InformEvent::RvdHostInform(inform) => inform,
+            _ => panic!("unexpected event"),
+        })
+        .collect()
+}
+
+// Shares the monitors of the TesterNative and holds a key down and the left button on the first
+fn hold_input(
+    host: &mut RvdHostHandler,
+    native: &mut TesterNative,
+) -> (Vec<DisplayId>, HashMap<DisplayId, HostDisplay>) {
+    let mut write = Vec::new();
+    let mut events = Vec::new();
+    handshake(Some(&mut *host), None);
+
+    let mut displays = HashMap::new();
+    let mut display_ids = Vec::new();
+    for monitor in &native.monitors {
+        let (display_id, _) = host
+            .share_display(monitor.name.clone(), AccessMask::CONTROLLABLE)
+            .expect("share_display failed");
+        host.handle(
+            RvdMessage::DisplayShareAck(DisplayShareAck { display_id }),
+            &mut write,
+            &mut events,
+        )
+        .expect("handler failed");
+        displays.insert(display_id, HostDisplay {
+            native_id: NativeId::Monitor(monitor.id),
+            geometry: None,
+        });
+        display_ids.push(display_id);
+    }
+
+    host.handle(
+        RvdMessage::KeyInput(KeyInput {
+            down: true,
+            key: 40,
+        }),
+        &mut write,
+        &mut events,
+    )
+    .expect("handler failed");
+    host.handle(
+        RvdMessage::MouseInput(MouseInput {
+            display_id: display_ids[0],
+            x_location: 10,
+            y_location: 20,
+            buttons_delta: ButtonsMask::LEFT,
+            buttons_state: ButtonsMask::LEFT,
+        }),
+        &mut write,
+        &mut events,
+    )
+    .expect("handler failed");
+    apply_input(host_informs(&mut events), native, &displays);
+
+    assert_eq!(native.down_keys, vec![40]);
+    assert!(matches!(native.mouse_button, Some(MouseButton::Left)));
+    // Nothing is let go while the Client can still reach it
+    assert!(host.take_input_releases().is_empty());
+
+    (display_ids, displays)
+}
+
+#[test]
+fn test_host_release_input() {
+    let mut native = TesterNative::new();
+    let mut host = RvdHostHandler::new();
+    let (_, displays) = hold_input(&mut host, &mut native);
+
+    // The Client goes away
+    host.release_input();
+    apply_input(host.take_input_releases(), &mut native, &displays);
+
+    assert!(native.down_keys.is_empty());
+    assert!(native.mouse_button.is_none());
+    // Buttons are let go where they were pressed
+    assert_eq!((native.pointer_x, native.pointer_y), (10, 20));
+
+    // There's nothing left to let go of
+    host.release_input();
+    assert!(host.take_input_releases().is_empty());
+}
+
+#[test]
+fn test_host_release_input_revoke_control() {
+    let mut native = TesterNative::new();
+    let mut host = RvdHostHandler::new();
+    let (_, displays) = hold_input(&mut host, &mut native);
+
+    host.revoke_control();
+    apply_input(host.take_input_releases(), &mut native, &displays);
+
+    assert!(native.down_keys.is_empty());
+    assert!(native.mouse_button.is_none());
+}
+
+#[test]
+fn test_host_release_input_display_access() {
+    let mut native = TesterNative::new();
+    let mut host = RvdHostHandler::new();
+    let (display_ids, mut displays) = hold_input(&mut host, &mut native);
+
+    // The buttons held on a display are let go when it's unshared, keys are kept while another
+    // display is controllable
+    host.unshare_display(display_ids[0])
+        .expect("unshare_display failed");
+    displays.remove(&display_ids[0]);
+    let releases = host.take_input_releases();
+    assert_eq!(releases.len(), 1);
+    apply_input(releases, &mut native, &displays);
+
+    assert!(native.mouse_button.is_none());
+    assert_eq!(native.down_keys, vec![40]);
+
+    // Keys are let go once no display is controllable
+    host.set_display_access(display_ids[1], AccessMask::empty())
+        .expect("set_display_access failed");
+    apply_input(host.take_input_releases(), &mut native, &displays);
+
+    assert!(native.down_keys.is_empty());
+}
 
 #[test]
 fn test_host_clipboard_request() {
@@ -353,15 +612,18 @@ fn test_host_clipboard_request() {
     };
 
 
-    let (event, msg) =
+    // The request is for whoever owns the connection to answer
+    let event =
         rvd_host_native_helper(event, &mut native, &HashMap::new()).expect("handler failed");
-
-    assert!(event.is_none());
-
-    let msg = match msg {
-        Some(m) => m,
-        _ => panic!("expected a message but found none"),
+    let (is_content, clipboard_type) = match event {
+        Some(RvdHostInform::ClipboardRequest(is_content, clipboard_type)) =>
+            (is_content, clipboard_type),
+        _ => panic!("expected a clipboard request"),
     };
+    let content = clipboard_content(&mut native, &clipboard_type).expect("native failed");
+    let msg = host
+        .clipboard_reply(clipboard_type, is_content, content)
+        .expect("expected a message but found none");
 
     assert!(matches!(msg,
         RvdMessage::ClipboardNotification(notificaiton)
@@ -380,6 +642,7 @@ struct TesterNative {
     pub monitors: Vec<Monitor>,
     pub windows: Vec<Window>,
     pub down_keys: Vec<Key>,
+    pub typed: String,
     pub mouse_button: Option<MouseButton>,
 }
 
@@ -395,12 +658,16 @@ impl TesterNative {
                     name: "Mock Display 1".to_string(),
                     width: 1000,
                     height: 1000,
+                    scale_factor: 1.0,
+                    rotation: Rotation::None,
                 },
                 Monitor {
                     id: 2,
                     name: "Mock Display 2".to_string(),
                     width: 1980,
                     height: 1080,
+                    scale_factor: 1.0,
+                    rotation: Rotation::None,
                 },
             ],
             windows: vec![
@@ -418,6 +685,7 @@ impl TesterNative {
                 },
             ],
             down_keys: vec![],
+            typed: String::new(),
             mouse_button: None,
         }
     }
@@ -435,6 +703,11 @@ impl NativeApiTemplate for TesterNative {
         Ok(())
     }
 
+    fn type_text(&mut self, text: &str) -> Result<(), Self::Error> {
+        self.typed.push_str(text);
+        Ok(())
+    }
+
     fn pointer_position(&mut self, _windows: &[WindowId]) -> Result<MousePosition, Self::Error> {
         Ok(MousePosition {
             x: 0,
@@ -490,6 +763,10 @@ impl NativeApiTemplate for TesterNative {
         Ok(())
     }
 
+    fn cursor_image(&mut self) -> Result<Option<CursorImage>, Self::Error> {
+        Ok(None)
+    }
+
     fn monitors(&mut self) -> Result<Vec<Monitor>, Self::Error> {
         Ok(self.monitors.clone())
     }
@@ -0,0 +1,367 @@
+use common::{
+    chrono::{Duration, Utc},
+    constants::{Hmac, Mac},
+    messages::svsc::{Cookie, ExpirationTime, LeaseId, LeaseResponseData},
+};
+use peer::helpers::crypto::random_bytes_const;
+use std::{collections::HashMap, net::IpAddr};
+
+/// The smallest keyspace the spec allows for ID generation, see section 4.3.5 of the SVSC spec
+pub const MIN_ID_BITS: u32 = 26;
+
+/// The spec allows up to 33 bits, but a [`LeaseId`] is only 4 bytes
+pub const MAX_ID_BITS: u32 = 32;
+
+/// Default lease duration in seconds.
+pub const DEFAULT_LEASE_DURATION: i64 = 60 * 60;
+
+/// Default time in seconds a cookie can still reclaim its ID after the lease expired
+pub const DEFAULT_RENEWAL_WINDOW: i64 = 24 * 60 * 60;
+
+/// Number of MAC bytes in a cookie. The remaining bytes of the cookie are the lease ID.
+const COOKIE_MAC_LEN: usize = 20;
+
+/// Number of random bytes identifying each time an ID is issued
+const NONCE_LEN: usize = 16;
+
+/// Number of attempts at generating an unused ID before giving up
+const MAX_GENERATION_ATTEMPTS: usize = 64;
+
+/// A source of the current time. This exists so leases can be tested without waiting on the
+/// system clock.
+pub trait Clock {
+    fn now(&self) -> ExpirationTime;
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> ExpirationTime {
+        Utc::now()
+    }
+}
+
+/// Generates candidate lease IDs. The [`LeaseManager`] is responsible for making sure IDs are
+/// unique, generators only need to produce uniformly random IDs in their keyspace.
+pub trait IdGenerator {
+    /// `active` is the number of currently active leases
+    fn generate(&mut self, active: usize) -> LeaseId;
+}
+
+/// Generates IDs from a fixed size keyspace
+pub struct FixedIdGenerator {
+    bits: u32,
+}
+
+impl FixedIdGenerator {
+    pub fn new(bits: u32) -> Self {
+        assert!(
+            (MIN_ID_BITS ..= MAX_ID_BITS).contains(&bits),
+            "keyspace must be between {} and {} bits",
+            MIN_ID_BITS,
+            MAX_ID_BITS
+        );
+        Self { bits }
+    }
+}
+
+impl IdGenerator for FixedIdGenerator {
+    fn generate(&mut self, _active: usize) -> LeaseId {
+        random_id(self.bits)
+    }
+}
+
+/// Generates IDs from the smallest keyspace which keeps the active IDs below `1 / load_factor` of
+/// the keyspace. This keeps IDs short while there are few users.
+pub struct ScalingIdGenerator {
+    load_factor: u64,
+}
+
+impl Default for ScalingIdGenerator {
+    fn default() -> Self {
+        Self::new(64)
+    }
+}
+
+impl ScalingIdGenerator {
+    pub fn new(load_factor: u64) -> Self {
+        Self { load_factor }
+    }
+
+    pub fn bits(&self, active: usize) -> u32 {
+        let needed = (active as u64)
+            .saturating_add(1)
+            .saturating_mul(self.load_factor);
+        let bits = u64::BITS - needed.leading_zeros();
+        bits.clamp(MIN_ID_BITS, MAX_ID_BITS)
+    }
+}
+
+impl IdGenerator for ScalingIdGenerator {
+    fn generate(&mut self, active: usize) -> LeaseId {
+        random_id(self.bits(active))
+    }
+}
+
+fn random_id(bits: u32) -> LeaseId {
+    let mask = u32::MAX >> (u32::BITS - bits);
+    (u32::from_be_bytes(random_bytes_const::<4>()) & mask).to_be_bytes()
+}
+
+#[derive(Clone, Debug)]
+pub struct LeaseConfig {
+    pub lease_duration: Duration,
+    /// Maximum number of new IDs a single IP address can be issued in `rate_limit_window`
+    pub rate_limit: u32,
+    pub rate_limit_window: Duration,
+    /// How long after a lease expires its cookie can still be used to request the same ID
+    pub renewal_window: Duration,
+}
+
+impl Default for LeaseConfig {
+    fn default() -> Self {
+        Self {
+            lease_duration: Duration::seconds(DEFAULT_LEASE_DURATION),
+            rate_limit: 10,
+            rate_limit_window: Duration::minutes(10),
+            renewal_window: Duration::seconds(DEFAULT_RENEWAL_WINDOW),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Lease {
+    expiration: ExpirationTime,
+    // Changes every time the ID is issued to someone new, so cookies from earlier issuances stop
+    // working
+    nonce: [u8; NONCE_LEN],
+}
+
+struct RateLimitWindow {
+    start: ExpirationTime,
+    count: u32,
+}
+
+/// Issues, extends and expires leases according to sections 4.3.5 to 4.3.7 of the SVSC spec.
+///
+/// Cookies are `MAC(secret, id || nonce) || id` where the nonce is picked each time the ID is
+/// issued to a new peer. Swept leases are remembered for the renewal window, so a peer can prove it
+/// was issued an ID for a while after its lease expired, but once the ID goes to someone else the
+/// old cookie is worthless.
+pub struct LeaseManager {
+    secret: [u8; 32],
+    config: LeaseConfig,
+    clock: Box<dyn Clock>,
+    generator: Box<dyn IdGenerator>,
+    leases: HashMap<LeaseId, Lease>,
+    // Swept leases whose cookies can still reclaim the ID
+    expired: HashMap<LeaseId, Lease>,
+    rate_limits: HashMap<IpAddr, RateLimitWindow>,
+}
+
+impl Default for LeaseManager {
+    fn default() -> Self {
+        Self::new(LeaseConfig::default())
+    }
+}
+
+impl LeaseManager {
+    /// Creates a lease manager with a random secret, the system clock and a
+    /// [`ScalingIdGenerator`]
+    pub fn new(config: LeaseConfig) -> Self {
+        Self::with_parts(
+            random_bytes_const::<32>(),
+            config,
+            Box::new(SystemClock),
+            Box::new(ScalingIdGenerator::default()),
+        )
+    }
+
+    pub fn with_parts(
+        secret: [u8; 32],
+        config: LeaseConfig,
+        clock: Box<dyn Clock>,
+        generator: Box<dyn IdGenerator>,
+    ) -> Self {
+        Self {
+            secret,
+            config,
+            clock,
+            generator,
+            leases: HashMap::new(),
+            expired: HashMap::new(),
+            rate_limits: HashMap::new(),
+        }
+    }
+
+    pub fn now(&self) -> ExpirationTime {
+        self.clock.now()
+    }
+
+    pub fn active_leases(&self) -> usize {
+        self.leases.len()
+    }
+
+    pub fn is_active(&self, id: &LeaseId) -> bool {
+        matches!(self.leases.get(id), Some(lease) if lease.expiration > self.now())
+    }
+
+    /// Returns the cookie for the current issuance of the ID, if it's leased or can be renewed
+    pub fn cookie(&self, id: &LeaseId) -> Option<Cookie> {
+        let lease = self.leases.get(id).or_else(|| self.expired.get(id))?;
+        let mut cookie = [0u8; 24];
+        cookie[.. COOKIE_MAC_LEN].copy_from_slice(
+            &self.mac(id, &lease.nonce).finalize().into_bytes()[.. COOKIE_MAC_LEN],
+        );
+        cookie[COOKIE_MAC_LEN ..].copy_from_slice(id);
+        Some(cookie)
+    }
+
+    /// Returns the ID the cookie was issued for if the cookie is authentic and from the latest
+    /// issuance of the ID
+    pub fn verify_cookie(&self, cookie: &Cookie) -> Option<LeaseId> {
+        let id: LeaseId = cookie[COOKIE_MAC_LEN ..].try_into().unwrap();
+        let lease = self.leases.get(&id).or_else(|| self.expired.get(&id))?;
+        self.mac(&id, &lease.nonce)
+            .verify_truncated_left(&cookie[.. COOKIE_MAC_LEN])
+            .ok()
+            .map(|_| id)
+    }
+
+    /// Issues a lease. If `cookie` is provided and authentic the ID it was issued for is renewed,
+    /// otherwise a new ID is generated. The caller is responsible for making sure the peer isn't
+    /// already using the ID on another connection.
+    ///
+    /// Only generating new IDs counts toward the rate limit.
+    pub fn request(
+        &mut self,
+        ip: IpAddr,
+        cookie: Option<&Cookie>,
+    ) -> Result<LeaseResponseData, LeaseError> {
+        let expiration = self.now() + self.config.lease_duration;
+        let id = match cookie.and_then(|cookie| self.verify_cookie(cookie)) {
+            Some(id) => {
+                let mut lease = self
+                    .leases
+                    .remove(&id)
+                    .or_else(|| self.expired.remove(&id))
+                    .expect("verified cookies have a lease");
+                lease.expiration = expiration;
+                self.leases.insert(id, lease);
+                id
+            }
+            None => {
+                self.check_rate_limit(ip)?;
+                let id = self.generate_id()?;
+                // Whoever held the ID before can't use their cookie for it anymore
+                self.expired.remove(&id);
+                self.leases.insert(id, Lease {
+                    expiration,
+                    nonce: random_bytes_const::<NONCE_LEN>(),
+                });
+                id
+            }
+        };
+
+        Ok(LeaseResponseData {
+            id,
+            cookie: self.cookie(&id).expect("lease was just issued"),
+            expiration,
+        })
+    }
+
+    pub fn extend(&mut self, id: &LeaseId, cookie: &Cookie) -> Result<ExpirationTime, LeaseError> {
+        if self.verify_cookie(cookie) != Some(*id) {
+            return Err(LeaseError::BadCookie);
+        }
+
+        let now = self.now();
+        let lease = self.leases.get_mut(id).ok_or(LeaseError::NotFound)?;
+        if lease.expiration <= now {
+            return Err(LeaseError::Expired);
+        }
+
+        lease.expiration = now + self.config.lease_duration;
+        Ok(lease.expiration)
+    }
+
+    /// Removes expired leases and stale rate limit windows. Returns the IDs of the removed
+    /// leases.
+    pub fn sweep(&mut self) -> Vec<LeaseId> {
+        let now = self.now();
+        let mut expired = Vec::new();
+
+        for (id, lease) in self
+            .leases
+            .iter()
+            .filter(|(_, lease)| lease.expiration <= now)
+        {
+            expired.push(*id);
+            self.expired.insert(*id, *lease);
+        }
+        self.leases.retain(|_, lease| lease.expiration > now);
+
+        let renewal_window = self.config.renewal_window;
+        self.expired
+            .retain(|_, lease| lease.expiration + renewal_window > now);
+
+        let window = self.config.rate_limit_window;
+        self.rate_limits
+            .retain(|_, limit| limit.start + window > now);
+
+        expired
+    }
+
+    fn mac(&self, id: &LeaseId, nonce: &[u8; NONCE_LEN]) -> Hmac {
+        let mut mac = <Hmac as Mac>::new_from_slice(&self.secret).unwrap();
+        mac.update(id);
+        mac.update(nonce);
+        mac
+    }
+
+    fn check_rate_limit(&mut self, ip: IpAddr) -> Result<(), LeaseError> {
+        let now = self.now();
+        let limit = self.rate_limits.entry(ip).or_insert(RateLimitWindow {
+            start: now,
+            count: 0,
+        });
+
+        if limit.start + self.config.rate_limit_window <= now {
+            limit.start = now;
+            limit.count = 0;
+        }
+
+        if limit.count >= self.config.rate_limit {
+            return Err(LeaseError::RateLimited);
+        }
+
+        limit.count += 1;
+        Ok(())
+    }
+
+    fn generate_id(&mut self) -> Result<LeaseId, LeaseError> {
+        let now = self.now();
+        for _ in 0 .. MAX_GENERATION_ATTEMPTS {
+            let id = self.generator.generate(self.leases.len());
+            match self.leases.get(&id) {
+                Some(lease) if lease.expiration > now => continue,
+                _ => return Ok(id),
+            }
+        }
+        Err(LeaseError::Exhausted)
+    }
+}
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum LeaseError {
+    #[error("too many lease requests")]
+    RateLimited,
+    #[error("unable to generate an unused ID")]
+    Exhausted,
+    #[error("cookie does not match lease")]
+    BadCookie,
+    #[error("lease not found")]
+    NotFound,
+    #[error("lease expired")]
+    Expired,
+}
@@ -1,8 +1,16 @@
-#[cfg(test)]
-mod tests {
-    #[test]
-    fn it_works() {
-        let result = 2 + 2;
-        assert_eq!(result, 4);
-    }
+#![deny(rust_2018_idioms)]
+
+pub mod lease_manager;
+pub mod sel_server;
+pub mod signal_server;
+pub mod svsc_server;
+
+#[cold]
+pub(crate) fn debug<T: std::fmt::Debug>(val: &T) -> String {
+    use std::fmt::Write;
+
+    let mut buf = String::new();
+    buf.write_fmt(format_args!("{:?}", val))
+        .expect("Debug implementation returned an error unexpectedly");
+    buf
 }
@@ -0,0 +1,19 @@
+use server::signal_server::SignalServer;
+use std::env;
+
+const DEFAULT_RELIABLE_ADDR: &str = "0.0.0.0:9999";
+const DEFAULT_UNRELIABLE_ADDR: &str = "0.0.0.0:9999";
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let reliable_addr = args
+        .next()
+        .unwrap_or_else(|| DEFAULT_RELIABLE_ADDR.to_string());
+    let unreliable_addr = args
+        .next()
+        .unwrap_or_else(|| DEFAULT_UNRELIABLE_ADDR.to_string());
+
+    if let Err(error) = SignalServer::run(&reliable_addr, &unreliable_addr) {
+        eprintln!("signal server exited with error: {}", error);
+    }
+}
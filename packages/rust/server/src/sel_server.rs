@@ -0,0 +1,133 @@
+use crate::debug;
+use common::messages::{
+    sel::{SelMessage, TransportDataServerMessageUnreliable},
+    svsc::{PeerId, SessionData},
+    Data,
+};
+use peer::{
+    hash,
+    helpers::{
+        cipher_reliable_peer::CipherError,
+        cipher_unreliable_peer::CipherUnreliablePeer,
+        crypto::kdf2,
+    },
+};
+use std::{borrow::Cow, collections::HashMap, net::SocketAddr};
+
+struct SelPeer {
+    cipher: CipherUnreliablePeer,
+    addr: Option<SocketAddr>,
+}
+
+/// The server half of SEL. Unreliable messages from a peer are decrypted with that peer's keys and
+/// re-encrypted with the keys of the peer they are destined for.
+pub struct SelServer {
+    peers: HashMap<PeerId, SelPeer>,
+    // reliable is TLS and is handled elsewhere
+}
+
+impl Default for SelServer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SelServer {
+    pub fn new() -> Self {
+        Self {
+            peers: HashMap::new(),
+        }
+    }
+
+    pub fn has_peer(&self, peer_id: &PeerId) -> bool {
+        self.peers.contains_key(peer_id)
+    }
+
+    /// Derives the keys for a peer. These are the keys derived by
+    /// [`SelHandler::derive_unreliable`](peer::sel_handler::SelHandler::derive_unreliable),
+    /// swapped for the receiving side.
+    ///
+    /// Warning: This resets the peer's unreliable state
+    pub fn add_peer(&mut self, session_data: &SessionData) {
+        let (receive_key, send_key) = kdf2(hash!(
+            &session_data.session_id,
+            &session_data.peer_id,
+            &session_data.peer_key
+        ));
+        self.peers.insert(session_data.peer_id, SelPeer {
+            cipher: CipherUnreliablePeer::new(send_key.to_vec(), receive_key.to_vec()),
+            addr: None,
+        });
+    }
+
+    pub fn remove_peer(&mut self, peer_id: &PeerId) {
+        self.peers.remove(peer_id);
+    }
+
+    /// Removes all peers for which `f` returns false
+    pub fn retain<F>(&mut self, mut f: F)
+    where F: FnMut(&PeerId) -> bool {
+        self.peers.retain(|peer_id, _| f(peer_id));
+    }
+
+    /// Decrypts a message from a peer, returning the peer it came from along with the plaintext.
+    /// The address the message came from is only recorded once the message has been authenticated.
+    pub fn handle(
+        &mut self,
+        msg: &SelMessage<'_>,
+        addr: SocketAddr,
+    ) -> Result<(PeerId, Vec<u8>), SelServerError> {
+        let msg = match msg {
+            SelMessage::TransportDataPeerMessageUnreliable(msg) => msg,
+            _ => return Err(SelServerError::WrongMessage(debug(msg))),
+        };
+
+        let peer = self
+            .peers
+            .get_mut(&msg.peer_id)
+            .ok_or(SelServerError::UnknownPeer)?;
+        let plaintext = peer.cipher.decrypt(&*msg.data.0, msg.counter)?;
+        peer.addr = Some(addr);
+
+        Ok((msg.peer_id, plaintext))
+    }
+
+    /// Encrypts a message for a peer. Returns `None` if we haven't yet received anything from the
+    /// peer over the unreliable transport, in which case the message should be dropped.
+    pub fn wrap_unreliable(
+        &mut self,
+        peer_id: &PeerId,
+        msg: Vec<u8>,
+    ) -> Result<Option<(SocketAddr, SelMessage<'static>)>, SelServerError> {
+        let peer = self
+            .peers
+            .get_mut(peer_id)
+            .ok_or(SelServerError::UnknownPeer)?;
+
+        let addr = match peer.addr {
+            Some(addr) => addr,
+            None => return Ok(None),
+        };
+
+        let (data, counter) = peer.cipher.encrypt(&msg)?;
+        Ok(Some((
+            addr,
+            SelMessage::TransportDataServerMessageUnreliable(
+                TransportDataServerMessageUnreliable {
+                    counter,
+                    data: Data(Cow::Owned(data)),
+                },
+            ),
+        )))
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SelServerError {
+    #[error("{0}")]
+    CipherError(#[from] CipherError),
+    #[error("unknown peer")]
+    UnknownPeer,
+    #[error("invalid message {0}")]
+    WrongMessage(String),
+}
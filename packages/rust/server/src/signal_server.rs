@@ -0,0 +1,298 @@
+use crate::{
+    debug,
+    sel_server::{SelServer, SelServerError},
+    svsc_server::{ConnectionId, SvscServer, SvscServerError},
+};
+use common::messages::{
+    sel::SelMessage,
+    svsc::SvscMessage,
+    Error as MessageComponentError,
+    Message,
+    MessageComponent,
+};
+use crossbeam_channel::{unbounded, Receiver};
+use event_loop::event_loop::{event_loop, EventLoopState, ThreadWakerCore};
+use io::{
+    DirectServer,
+    Reliable,
+    TcpHandle,
+    TransportError,
+    TransportResponse,
+    TransportResult,
+    UdpServer,
+};
+use peer::sel_handler::SelHandler;
+use std::{
+    collections::HashMap,
+    io::Cursor,
+    net::{SocketAddr, TcpStream, ToSocketAddrs},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// How often leases and keepalives are checked
+const TICK_INTERVAL: Duration = Duration::from_secs(1);
+
+#[repr(u32)]
+enum Events {
+    RemoteMessage,
+    UnreliableMessage,
+    Connection,
+    Tick,
+}
+
+struct ServerConnection {
+    handle: TcpHandle,
+    results: Receiver<TransportResult>,
+}
+
+/// Runs an [`SvscServer`] over TCP and UDP. Every TCP connection gets its own reader and writer
+/// threads from [`TcpHandle`], all peers share a single [`UdpServer`] socket. The server state
+/// itself lives on a single event loop thread.
+pub struct SignalServer {
+    svsc: SvscServer,
+    sel: SelServer,
+    connections: HashMap<ConnectionId, ServerConnection>,
+    next_connection_id: ConnectionId,
+}
+
+impl Default for SignalServer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SignalServer {
+    pub fn new() -> Self {
+        Self {
+            svsc: SvscServer::new(),
+            sel: SelServer::new(),
+            connections: HashMap::new(),
+            next_connection_id: 0,
+        }
+    }
+
+    /// Binds to the given addresses and runs the server on the current thread. This only returns
+    /// if binding fails.
+    pub fn run<A1: ToSocketAddrs, A2: ToSocketAddrs>(
+        reliable_addr: A1,
+        unreliable_addr: A2,
+    ) -> Result<(), std::io::Error> {
+        let waker_core = ThreadWakerCore::new_current_thread();
+        let direct_server = DirectServer::new(
+            reliable_addr,
+            waker_core.make_waker(Events::Connection as u32),
+        )?;
+
+        let (unreliable_sender, unreliable_results) = unbounded();
+        let udp_server = UdpServer::new(
+            unreliable_addr,
+            unreliable_sender,
+            waker_core.make_waker(Events::UnreliableMessage as u32),
+        )?;
+
+        let tick_waker = waker_core.make_waker(Events::Tick as u32);
+        thread::spawn(move || loop {
+            thread::sleep(TICK_INTERVAL);
+            tick_waker.wake();
+        });
+
+        let mut server = Self::new();
+
+        event_loop(waker_core, move |waker_core| {
+            if waker_core.check_and_unset(Events::Connection as u32) {
+                // We don't need to put this in a loop due to the guarantee provided by
+                // `next_incoming`
+                if let Some(Ok(stream)) = direct_server.next_incoming() {
+                    // See instance_main in node-interop, on macOS the stream may inherit
+                    // non-blocking from the listener
+                    if stream.set_nonblocking(false).is_ok() {
+                        server.accept(stream, waker_core);
+                    }
+                }
+            }
+
+            if waker_core.check_and_unset(Events::RemoteMessage as u32) {
+                server.poll_connections();
+            }
+
+            if waker_core.check_and_unset(Events::UnreliableMessage as u32) {
+                while let Ok(result) = unreliable_results.try_recv() {
+                    if let Ok(TransportResponse::UnreliableMessage(wire, addr)) = result {
+                        // Bad datagrams are dropped, they don't affect anyone's connection
+                        let _ = server.handle_unreliable(&udp_server, &wire, addr);
+                    }
+                }
+            }
+
+            if waker_core.check_and_unset(Events::Tick as u32) {
+                server.tick();
+            }
+
+            EventLoopState::Working
+        });
+
+        Ok(())
+    }
+
+    fn accept(&mut self, stream: TcpStream, waker_core: &ThreadWakerCore) {
+        // The IP is needed for rate limiting, so we can't accept a connection without it
+        let ip = match stream.peer_addr() {
+            Ok(addr) => addr.ip(),
+            Err(_) => return,
+        };
+
+        let id = self.next_connection_id;
+        self.next_connection_id += 1;
+
+        let (result_sender, results) = unbounded();
+        let handle = TcpHandle::new_from(
+            stream,
+            result_sender,
+            waker_core.make_waker(Events::RemoteMessage as u32),
+        );
+        self.connections
+            .insert(id, ServerConnection { handle, results });
+
+        let mut write = Vec::new();
+        self.svsc.connect(id, ip, &mut write);
+        self.send(write);
+    }
+
+    fn poll_connections(&mut self) {
+        let mut write = Vec::new();
+        let mut closed = Vec::new();
+
+        for (&id, connection) in &self.connections {
+            while let Ok(result) = connection.results.try_recv() {
+                match result {
+                    Ok(TransportResponse::ReliableMessage(wire)) => {
+                        if Self::handle_reliable(&mut self.svsc, id, &wire, &mut write).is_err() {
+                            closed.push(id);
+                            break;
+                        }
+                    }
+                    // unreliable messages come through the UdpServer
+                    Ok(TransportResponse::UnreliableMessage(..)) => {}
+                    Ok(TransportResponse::Shutdown(_)) | Err(TransportError::Fatal { .. }) => {
+                        closed.push(id);
+                        break;
+                    }
+                    Err(_) => {}
+                }
+            }
+        }
+
+        for id in closed {
+            self.close(id, &mut write);
+        }
+
+        self.send(write);
+    }
+
+    fn tick(&mut self) {
+        let mut write = Vec::new();
+
+        self.svsc.remove_expired_leases();
+        let svsc = &self.svsc;
+        self.sel.retain(|peer_id| svsc.has_peer(peer_id));
+        for id in self.svsc.check_keepalive(Instant::now(), &mut write) {
+            self.close(id, &mut write);
+        }
+
+        self.send(write);
+    }
+
+    fn handle_reliable(
+        svsc: &mut SvscServer,
+        id: ConnectionId,
+        wire: &[u8],
+        write: &mut Vec<(ConnectionId, SvscMessage<'static>)>,
+    ) -> Result<(), SignalServerError> {
+        let sel = SelMessage::read(&mut Cursor::new(wire))?;
+        let data = match &sel {
+            SelMessage::TransportDataMessageReliable(msg) => &*msg.data.0,
+            _ => return Err(SignalServerError::WrongMessage(debug(&sel))),
+        };
+        let message = SvscMessage::read(&mut Cursor::new(data))?;
+        svsc.handle(id, message, write).map_err(Into::into)
+    }
+
+    fn handle_unreliable(
+        &mut self,
+        udp_server: &UdpServer,
+        wire: &[u8],
+        addr: SocketAddr,
+    ) -> Result<(), SignalServerError> {
+        let sel = SelMessage::read(&mut Cursor::new(wire))?;
+
+        // Keys are derived lazily the first time we hear from either side of a session
+        if let SelMessage::TransportDataPeerMessageUnreliable(msg) = &sel {
+            if !self.sel.has_peer(&msg.peer_id) {
+                let peers = self
+                    .svsc
+                    .session_peers(&msg.peer_id)
+                    .ok_or(SelServerError::UnknownPeer)?;
+                for session_data in peers {
+                    if !self.sel.has_peer(&session_data.peer_id) {
+                        self.sel.add_peer(session_data);
+                    }
+                }
+            }
+        }
+
+        let (peer_id, data) = self.sel.handle(&sel, addr)?;
+        let message = SvscMessage::read(&mut Cursor::new(&data))?;
+
+        let (other, message) = match self.svsc.handle_unreliable(&peer_id, message)? {
+            Some(forward) => forward,
+            None => return Ok(()),
+        };
+
+        if let Some((addr, sel)) = self.sel.wrap_unreliable(&other, message.to_bytes()?)? {
+            udp_server.send_to(&sel.to_bytes()?, addr)?;
+        }
+
+        Ok(())
+    }
+
+    fn close(&mut self, id: ConnectionId, write: &mut Vec<(ConnectionId, SvscMessage<'static>)>) {
+        // Dropping the handle shuts down the stream and joins its threads
+        self.connections.remove(&id);
+        self.svsc.disconnect(id, write);
+    }
+
+    fn send(&mut self, write: Vec<(ConnectionId, SvscMessage<'static>)>) {
+        for (id, message) in write {
+            let connection = match self.connections.get_mut(&id) {
+                Some(connection) => connection,
+                None => continue,
+            };
+
+            let bytes = match message
+                .to_bytes()
+                .and_then(|bytes| SelHandler::wrap_reliable(bytes).to_bytes())
+            {
+                Ok(bytes) => bytes,
+                Err(_) => continue,
+            };
+
+            // If the write thread is gone we'll find out through the result channel
+            let _ = connection.handle.send(bytes);
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SignalServerError {
+    #[error("failed to decode message: {0}")]
+    Decode(#[from] MessageComponentError),
+    #[error("SVSC error: {0}")]
+    Svsc(#[from] SvscServerError),
+    #[error("SEL error: {0}")]
+    Sel(#[from] SelServerError),
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("invalid message {0}")]
+    WrongMessage(String),
+}
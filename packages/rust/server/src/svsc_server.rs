@@ -0,0 +1,494 @@
+use crate::{debug, lease_manager::LeaseManager};
+use common::{
+    constants::SVSC_VERSION,
+    messages::{
+        svsc::{
+            Cookie,
+            EstablishSessionNotification,
+            EstablishSessionResponse,
+            EstablishSessionStatus,
+            ExpirationTime,
+            KeepAlive,
+            LeaseExtensionResponse,
+            LeaseId,
+            LeaseResponse,
+            LeaseResponseData,
+            PeerId,
+            ProtocolVersion,
+            SessionData,
+            SessionDataReceive,
+            SessionEndNotification,
+            SessionId,
+            SvscMessage,
+        },
+        Data,
+    },
+};
+use peer::helpers::crypto::random_bytes_const;
+use std::{borrow::Cow, collections::HashMap, net::IpAddr, time::Instant};
+
+pub type ConnectionId = u64;
+
+/// Default KeepaliveTimeout in seconds. See section 4.5 of the SVSC spec.
+pub const DEFAULT_KEEPALIVE_TIMEOUT: u64 = 30;
+
+#[derive(Copy, Clone, Debug)]
+pub enum State {
+    Handshake,
+    PostHandshake,
+}
+
+struct Connection {
+    ip: IpAddr,
+    state: State,
+    lease: Option<LeaseId>,
+    session: Option<SessionId>,
+    last_received: Instant,
+    keepalive_sent: Option<Instant>,
+}
+
+struct Session {
+    peers: [(ConnectionId, SessionData); 2],
+}
+
+impl Session {
+    fn other(&self, connection: ConnectionId) -> ConnectionId {
+        if self.peers[0].0 == connection {
+            self.peers[1].0
+        } else {
+            self.peers[0].0
+        }
+    }
+}
+
+/// The server half of SVSC. Like the peer handlers this does no IO itself, messages to be sent are
+/// pushed to `write` along with the connection they are destined for.
+pub struct SvscServer {
+    connections: HashMap<ConnectionId, Connection>,
+    leases: LeaseManager,
+    lease_owners: HashMap<LeaseId, ConnectionId>,
+    sessions: HashMap<SessionId, Session>,
+    peers: HashMap<PeerId, SessionId>,
+    keepalive_timeout: std::time::Duration,
+}
+
+impl Default for SvscServer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SvscServer {
+    pub fn new() -> Self {
+        Self::with_config(
+            LeaseManager::default(),
+            std::time::Duration::from_secs(DEFAULT_KEEPALIVE_TIMEOUT),
+        )
+    }
+
+    pub fn with_config(leases: LeaseManager, keepalive_timeout: std::time::Duration) -> Self {
+        Self {
+            connections: HashMap::new(),
+            leases,
+            lease_owners: HashMap::new(),
+            sessions: HashMap::new(),
+            peers: HashMap::new(),
+            keepalive_timeout,
+        }
+    }
+
+    pub fn session_data(&self, connection: ConnectionId) -> Option<&SessionData> {
+        let session = self
+            .sessions
+            .get(&self.connections.get(&connection)?.session?)?;
+        session
+            .peers
+            .iter()
+            .find(|(id, _)| *id == connection)
+            .map(|(_, data)| data)
+    }
+
+    /// Returns the session data of the given peer followed by that of the peer on the other side
+    /// of the session
+    pub fn session_peers(&self, peer_id: &PeerId) -> Option<[&SessionData; 2]> {
+        let session = self.sessions.get(self.peers.get(peer_id)?)?;
+        let [(_, first), (_, second)] = &session.peers;
+        if &first.peer_id == peer_id {
+            Some([first, second])
+        } else {
+            Some([second, first])
+        }
+    }
+
+    pub fn has_peer(&self, peer_id: &PeerId) -> bool {
+        self.peers.contains_key(peer_id)
+    }
+
+    /// Registers a new connection and begins the handshake
+    pub fn connect(
+        &mut self,
+        connection: ConnectionId,
+        ip: IpAddr,
+        write: &mut Vec<(ConnectionId, SvscMessage<'static>)>,
+    ) {
+        self.connections.insert(connection, Connection {
+            ip,
+            state: State::Handshake,
+            lease: None,
+            session: None,
+            last_received: Instant::now(),
+            keepalive_sent: None,
+        });
+        write.push((
+            connection,
+            SvscMessage::ProtocolVersion(ProtocolVersion {
+                version: SVSC_VERSION.to_string(),
+            }),
+        ));
+    }
+
+    /// Removes a connection. Disconnection does not end a lease, but it does end any session the
+    /// connection was a part of.
+    pub fn disconnect(
+        &mut self,
+        connection: ConnectionId,
+        write: &mut Vec<(ConnectionId, SvscMessage<'static>)>,
+    ) {
+        let conn = match self.connections.remove(&connection) {
+            Some(conn) => conn,
+            None => return,
+        };
+
+        if let Some(id) = conn.lease {
+            self.lease_owners.remove(&id);
+        }
+
+        if let Some(session_id) = conn.session {
+            self.end_session(connection, session_id, write);
+        }
+    }
+
+    pub fn handle(
+        &mut self,
+        connection: ConnectionId,
+        msg: SvscMessage<'_>,
+        write: &mut Vec<(ConnectionId, SvscMessage<'static>)>,
+    ) -> Result<(), SvscServerError> {
+        let conn = self
+            .connections
+            .get_mut(&connection)
+            .ok_or(SvscServerError::UnknownConnection(connection))?;
+
+        conn.last_received = Instant::now();
+
+        if let SvscMessage::KeepAlive(_) = msg {
+            // this is a response to our keepalive, we must not respond
+            conn.keepalive_sent = None;
+            return Ok(());
+        }
+
+        let state = conn.state;
+        match state {
+            State::Handshake => match msg {
+                SvscMessage::ProtocolVersionResponse(msg) => {
+                    if !msg.ok {
+                        return Err(SvscServerError::VersionRejected);
+                    }
+                    conn.state = State::PostHandshake;
+                    Ok(())
+                }
+                _ => Err(SvscServerError::WrongMessageForState(debug(&msg), state)),
+            },
+            State::PostHandshake => match msg {
+                SvscMessage::LeaseRequest(msg) => {
+                    let response_data = self.lease(connection, msg.cookie);
+                    write.push((
+                        connection,
+                        SvscMessage::LeaseResponse(LeaseResponse { response_data }),
+                    ));
+                    Ok(())
+                }
+                SvscMessage::LeaseExtensionRequest(msg) => {
+                    let new_expiration = self.extend_lease(connection, &msg.cookie);
+                    write.push((
+                        connection,
+                        SvscMessage::LeaseExtensionResponse(LeaseExtensionResponse {
+                            new_expiration,
+                        }),
+                    ));
+                    Ok(())
+                }
+                SvscMessage::EstablishSessionRequest(msg) => {
+                    self.establish_session(connection, msg.lease_id, write);
+                    Ok(())
+                }
+                SvscMessage::SessionEnd(_) => {
+                    if let Some(session_id) = self.connections[&connection].session {
+                        self.end_session(connection, session_id, write);
+                    }
+                    Ok(())
+                }
+                SvscMessage::SessionDataSend(msg) => {
+                    // If there is no session the message is silently dropped
+                    if let Some(other) = self.other_peer(connection) {
+                        write.push((
+                            other,
+                            SvscMessage::SessionDataReceive(SessionDataReceive {
+                                data: Data(Cow::Owned(msg.data.0.into_owned())),
+                            }),
+                        ));
+                    }
+                    Ok(())
+                }
+                _ => Err(SvscServerError::WrongMessageForState(debug(&msg), state)),
+            },
+        }
+    }
+
+    /// Handles an SVSC message received over the unreliable transport. Returns the message to be
+    /// forwarded and the peer it should be forwarded to, if any.
+    pub fn handle_unreliable(
+        &mut self,
+        peer_id: &PeerId,
+        msg: SvscMessage<'_>,
+    ) -> Result<Option<(PeerId, SvscMessage<'static>)>, SvscServerError> {
+        match msg {
+            // Peers send a keepalive to establish the UDP connection, we must not respond
+            SvscMessage::KeepAlive(_) => Ok(None),
+            SvscMessage::SessionDataSend(msg) => {
+                // If the session has ended the message is silently dropped
+                let [_, other] = match self.session_peers(peer_id) {
+                    Some(peers) => peers,
+                    None => return Ok(None),
+                };
+                Ok(Some((
+                    other.peer_id,
+                    SvscMessage::SessionDataReceive(SessionDataReceive {
+                        data: Data(Cow::Owned(msg.data.0.into_owned())),
+                    }),
+                )))
+            }
+            _ => Err(SvscServerError::WrongUnreliableMessage(debug(&msg))),
+        }
+    }
+
+    /// Sends keepalives to connections which have been idle for the KeepaliveTimeout. Returns the
+    /// connections which have not responded in double the KeepaliveTimeout and should be considered
+    /// dropped.
+    pub fn check_keepalive(
+        &mut self,
+        now: Instant,
+        write: &mut Vec<(ConnectionId, SvscMessage<'static>)>,
+    ) -> Vec<ConnectionId> {
+        let mut dropped = Vec::new();
+
+        for (&id, conn) in &mut self.connections {
+            match conn.keepalive_sent {
+                Some(sent) =>
+                    if now.saturating_duration_since(sent) >= self.keepalive_timeout * 2 {
+                        dropped.push(id);
+                    },
+                None =>
+                    if now.saturating_duration_since(conn.last_received) >= self.keepalive_timeout {
+                        conn.keepalive_sent = Some(now);
+                        write.push((id, SvscMessage::KeepAlive(KeepAlive {})));
+                    },
+            }
+        }
+
+        dropped
+    }
+
+    /// Frees any leases which have expired
+    pub fn remove_expired_leases(&mut self) {
+        for id in self.leases.sweep() {
+            if let Some(conn) = self
+                .lease_owners
+                .remove(&id)
+                .and_then(|conn| self.connections.get_mut(&conn))
+            {
+                conn.lease = None;
+            }
+        }
+    }
+
+    fn lease(
+        &mut self,
+        connection: ConnectionId,
+        cookie: Option<Cookie>,
+    ) -> Option<LeaseResponseData> {
+        let conn = self.connections.get(&connection)?;
+
+        // A maximum of 1 ID can be leased per TCP connection
+        if conn.lease.is_some() {
+            return None;
+        }
+
+        // If the cookie is authentic and the ID is not in use by another connection we hand the
+        // same ID back out, otherwise we fall back to a new ID
+        let cookie = cookie.filter(|cookie| {
+            self.leases
+                .verify_cookie(cookie)
+                .map_or(false, |id| !self.lease_owners.contains_key(&id))
+        });
+
+        let data = self.leases.request(conn.ip, cookie.as_ref()).ok()?;
+        self.lease_owners.insert(data.id, connection);
+        self.connections.get_mut(&connection)?.lease = Some(data.id);
+
+        Some(data)
+    }
+
+    fn extend_lease(
+        &mut self,
+        connection: ConnectionId,
+        cookie: &Cookie,
+    ) -> Option<ExpirationTime> {
+        let id = self.connections.get(&connection)?.lease?;
+        self.leases.extend(&id, cookie).ok()
+    }
+
+    fn establish_session(
+        &mut self,
+        connection: ConnectionId,
+        lease_id: LeaseId,
+        write: &mut Vec<(ConnectionId, SvscMessage<'static>)>,
+    ) {
+        let status = self.session_status(connection, &lease_id);
+
+        if status != EstablishSessionStatus::Success {
+            write.push((
+                connection,
+                SvscMessage::EstablishSessionResponse(EstablishSessionResponse {
+                    lease_id,
+                    status,
+                    response_data: None,
+                }),
+            ));
+            return;
+        }
+
+        // session_status ensures this exists
+        let other = self.lease_owners[&lease_id];
+
+        let session_id = random_bytes_const::<16>();
+        let session_data = |session_id| SessionData {
+            session_id,
+            peer_id: random_bytes_const::<16>(),
+            peer_key: random_bytes_const::<16>(),
+        };
+        let requester_data = session_data(session_id);
+        let other_data = session_data(session_id);
+
+        self.peers.insert(requester_data.peer_id, session_id);
+        self.peers.insert(other_data.peer_id, session_id);
+        self.sessions.insert(session_id, Session {
+            peers: [
+                (connection, requester_data.clone()),
+                (other, other_data.clone()),
+            ],
+        });
+        for id in [connection, other] {
+            if let Some(conn) = self.connections.get_mut(&id) {
+                conn.session = Some(session_id);
+            }
+        }
+
+        write.push((
+            connection,
+            SvscMessage::EstablishSessionResponse(EstablishSessionResponse {
+                lease_id,
+                status,
+                response_data: Some(Box::new(requester_data)),
+            }),
+        ));
+        write.push((
+            other,
+            SvscMessage::EstablishSessionNotification(EstablishSessionNotification {
+                session_data: other_data,
+            }),
+        ));
+    }
+
+    fn session_status(
+        &self,
+        connection: ConnectionId,
+        lease_id: &LeaseId,
+    ) -> EstablishSessionStatus {
+        let conn = match self.connections.get(&connection) {
+            Some(conn) => conn,
+            None => return EstablishSessionStatus::OtherError,
+        };
+
+        if conn.session.is_some() {
+            return EstablishSessionStatus::SelfBusy;
+        }
+
+        if !self.leases.is_active(lease_id) {
+            return EstablishSessionStatus::IDNotFound;
+        }
+
+        let other = match self
+            .lease_owners
+            .get(lease_id)
+            .and_then(|&id| Some((id, self.connections.get(&id)?)))
+        {
+            Some(other) => other,
+            None => return EstablishSessionStatus::PeerOffline,
+        };
+
+        if other.0 == connection {
+            return EstablishSessionStatus::OtherError;
+        }
+
+        if other.1.session.is_some() {
+            return EstablishSessionStatus::PeerBusy;
+        }
+
+        EstablishSessionStatus::Success
+    }
+
+    fn other_peer(&self, connection: ConnectionId) -> Option<ConnectionId> {
+        let session_id = self.connections.get(&connection)?.session?;
+        Some(self.sessions.get(&session_id)?.other(connection))
+    }
+
+    fn end_session(
+        &mut self,
+        connection: ConnectionId,
+        session_id: SessionId,
+        write: &mut Vec<(ConnectionId, SvscMessage<'static>)>,
+    ) {
+        let session = match self.sessions.remove(&session_id) {
+            Some(session) => session,
+            None => return,
+        };
+
+        for (id, data) in &session.peers {
+            self.peers.remove(&data.peer_id);
+            if let Some(conn) = self.connections.get_mut(id) {
+                conn.session = None;
+            }
+        }
+
+        let other = session.other(connection);
+        if self.connections.contains_key(&other) {
+            write.push((
+                other,
+                SvscMessage::SessionEndNotification(SessionEndNotification {}),
+            ));
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SvscServerError {
+    #[error("invalid message {0} for state {1:?}")]
+    WrongMessageForState(String, State),
+    #[error("peer rejected protocol version")]
+    VersionRejected,
+    #[error("unknown connection {0}")]
+    UnknownConnection(ConnectionId),
+    #[error("invalid unreliable message {0}")]
+    WrongUnreliableMessage(String),
+}
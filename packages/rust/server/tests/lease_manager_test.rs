@@ -0,0 +1,273 @@
+use common::{
+    chrono::{Duration, TimeZone, Utc},
+    messages::svsc::{ExpirationTime, LeaseId},
+};
+use server::lease_manager::{
+    Clock,
+    FixedIdGenerator,
+    IdGenerator,
+    LeaseConfig,
+    LeaseError,
+    LeaseManager,
+    ScalingIdGenerator,
+    MAX_ID_BITS,
+    MIN_ID_BITS,
+};
+use std::{
+    cell::Cell,
+    net::{IpAddr, Ipv4Addr},
+    rc::Rc,
+};
+
+const IP: IpAddr = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+const OTHER_IP: IpAddr = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2));
+
+#[derive(Clone)]
+struct TestClock(Rc<Cell<ExpirationTime>>);
+
+impl TestClock {
+    fn new() -> Self {
+        Self(Rc::new(Cell::new(Utc.timestamp(1_000_000, 0))))
+    }
+
+    fn advance(&self, duration: Duration) {
+        self.0.set(self.0.get() + duration);
+    }
+}
+
+impl Clock for TestClock {
+    fn now(&self) -> ExpirationTime {
+        self.0.get()
+    }
+}
+
+struct ScriptedIdGenerator(Vec<LeaseId>);
+
+impl IdGenerator for ScriptedIdGenerator {
+    fn generate(&mut self, _active: usize) -> LeaseId {
+        if self.0.len() > 1 {
+            self.0.remove(0)
+        } else {
+            self.0[0]
+        }
+    }
+}
+
+fn config() -> LeaseConfig {
+    LeaseConfig {
+        lease_duration: Duration::minutes(60),
+        rate_limit: 2,
+        rate_limit_window: Duration::minutes(10),
+        renewal_window: Duration::hours(24),
+    }
+}
+
+fn manager(clock: &TestClock) -> LeaseManager {
+    LeaseManager::with_parts(
+        [7u8; 32],
+        config(),
+        Box::new(clock.clone()),
+        Box::new(FixedIdGenerator::new(MIN_ID_BITS)),
+    )
+}
+
+#[test]
+fn test_request() {
+    let clock = TestClock::new();
+    let mut leases = manager(&clock);
+
+    let data = leases.request(IP, None).unwrap();
+    assert!(u32::from_be_bytes(data.id) < 1 << MIN_ID_BITS);
+    assert_eq!(data.expiration, clock.now() + Duration::minutes(60));
+    assert!(leases.is_active(&data.id));
+    assert_eq!(leases.verify_cookie(&data.cookie), Some(data.id));
+
+    let mut tampered = data.cookie;
+    tampered[0] ^= 1;
+    assert_eq!(leases.verify_cookie(&tampered), None);
+
+    // a cookie for one ID can't be used to claim another
+    let mut tampered = data.cookie;
+    tampered[23] ^= 1;
+    assert_eq!(leases.verify_cookie(&tampered), None);
+}
+
+#[test]
+fn test_cookie_secret() {
+    let clock = TestClock::new();
+    let mut leases = manager(&clock);
+    let other = LeaseManager::with_parts(
+        [8u8; 32],
+        config(),
+        Box::new(clock.clone()),
+        Box::new(FixedIdGenerator::new(MIN_ID_BITS)),
+    );
+
+    let data = leases.request(IP, None).unwrap();
+    assert_eq!(other.verify_cookie(&data.cookie), None);
+}
+
+#[test]
+fn test_extend() {
+    let clock = TestClock::new();
+    let mut leases = manager(&clock);
+    let data = leases.request(IP, None).unwrap();
+    let other = leases.request(IP, None).unwrap();
+
+    clock.advance(Duration::minutes(30));
+    assert_eq!(
+        leases.extend(&data.id, &data.cookie),
+        Ok(clock.now() + Duration::minutes(60))
+    );
+    assert_eq!(
+        leases.extend(&data.id, &other.cookie),
+        Err(LeaseError::BadCookie)
+    );
+
+    clock.advance(Duration::minutes(60));
+    assert_eq!(
+        leases.extend(&data.id, &data.cookie),
+        Err(LeaseError::Expired)
+    );
+
+    leases.sweep();
+    assert_eq!(
+        leases.extend(&data.id, &data.cookie),
+        Err(LeaseError::NotFound)
+    );
+}
+
+#[test]
+fn test_sweep() {
+    let clock = TestClock::new();
+    let mut leases = manager(&clock);
+    let first = leases.request(IP, None).unwrap();
+
+    clock.advance(Duration::minutes(30));
+    let second = leases.request(IP, None).unwrap();
+
+    assert!(leases.sweep().is_empty());
+    assert_eq!(leases.active_leases(), 2);
+
+    clock.advance(Duration::minutes(30));
+    assert!(!leases.is_active(&first.id));
+    assert!(leases.is_active(&second.id));
+    assert_eq!(leases.sweep(), vec![first.id]);
+    assert_eq!(leases.active_leases(), 1);
+
+    clock.advance(Duration::minutes(30));
+    assert_eq!(leases.sweep(), vec![second.id]);
+    assert_eq!(leases.active_leases(), 0);
+}
+
+#[test]
+fn test_renew_after_expiry() {
+    let clock = TestClock::new();
+    let mut leases = manager(&clock);
+    let data = leases.request(IP, None).unwrap();
+
+    clock.advance(Duration::minutes(90));
+    leases.sweep();
+
+    assert_eq!(leases.verify_cookie(&data.cookie), Some(data.id));
+    let renewed = leases.request(IP, Some(&data.cookie)).unwrap();
+    assert_eq!(renewed.id, data.id);
+    assert_eq!(renewed.cookie, data.cookie);
+    assert_eq!(renewed.expiration, clock.now() + Duration::minutes(60));
+
+    // but only within the renewal window
+    clock.advance(Duration::minutes(60) + Duration::hours(24));
+    leases.sweep();
+    assert_eq!(leases.verify_cookie(&data.cookie), None);
+    assert_ne!(leases.request(IP, Some(&data.cookie)).unwrap().id, data.id);
+}
+
+#[test]
+fn test_cookie_reissued_id() {
+    let clock = TestClock::new();
+    let ids = vec![[0, 0, 0, 1], [0, 0, 0, 1], [0, 0, 0, 2]];
+    let mut leases = LeaseManager::with_parts(
+        [7u8; 32],
+        config(),
+        Box::new(clock.clone()),
+        Box::new(ScriptedIdGenerator(ids)),
+    );
+    let first = leases.request(IP, None).unwrap();
+
+    clock.advance(Duration::minutes(90));
+    leases.sweep();
+    let second = leases.request(OTHER_IP, None).unwrap();
+    assert_eq!(second.id, first.id);
+    assert_ne!(second.cookie, first.cookie);
+
+    // the cookie from the first issuance can't take the ID back or extend it
+    assert_eq!(leases.verify_cookie(&first.cookie), None);
+    assert_eq!(
+        leases.extend(&first.id, &first.cookie),
+        Err(LeaseError::BadCookie)
+    );
+    let reclaimed = leases.request(IP, Some(&first.cookie)).unwrap();
+    assert_ne!(reclaimed.id, first.id);
+    assert_eq!(leases.verify_cookie(&second.cookie), Some(second.id));
+}
+
+#[test]
+fn test_rate_limit() {
+    let clock = TestClock::new();
+    let mut leases = manager(&clock);
+
+    let data = leases.request(IP, None).unwrap();
+    leases.request(IP, None).unwrap();
+    assert_eq!(leases.request(IP, None), Err(LeaseError::RateLimited));
+
+    // renewals don't generate IDs so they aren't rate limited
+    assert!(leases.request(IP, Some(&data.cookie)).is_ok());
+
+    // limits are per IP
+    assert!(leases.request(OTHER_IP, None).is_ok());
+
+    clock.advance(Duration::minutes(10));
+    leases.sweep();
+    assert!(leases.request(IP, None).is_ok());
+}
+
+#[test]
+fn test_id_collision() {
+    let clock = TestClock::new();
+    let ids = vec![[0, 0, 0, 1], [0, 0, 0, 1], [0, 0, 0, 2]];
+    let mut leases = LeaseManager::with_parts(
+        [7u8; 32],
+        config(),
+        Box::new(clock.clone()),
+        Box::new(ScriptedIdGenerator(ids)),
+    );
+
+    assert_eq!(leases.request(IP, None).unwrap().id, [0, 0, 0, 1]);
+    assert_eq!(leases.request(IP, None).unwrap().id, [0, 0, 0, 2]);
+
+    // the generator is stuck on an active ID
+    assert_eq!(leases.request(OTHER_IP, None), Err(LeaseError::Exhausted));
+
+    // expired IDs can be handed out again
+    clock.advance(Duration::minutes(60));
+    assert_eq!(leases.request(OTHER_IP, None).unwrap().id, [0, 0, 0, 2]);
+}
+
+#[test]
+fn test_scaling_id_generator() {
+    let mut generator = ScalingIdGenerator::new(64);
+
+    assert_eq!(generator.bits(0), MIN_ID_BITS);
+    assert_eq!(generator.bits(1 << 20), MIN_ID_BITS + 1);
+    assert_eq!(generator.bits(usize::MAX), MAX_ID_BITS);
+
+    for _ in 0 .. 100 {
+        assert!(u32::from_be_bytes(generator.generate(0)) < 1 << MIN_ID_BITS);
+    }
+}
+
+#[test]
+#[should_panic]
+fn test_fixed_id_generator_bounds() {
+    FixedIdGenerator::new(MIN_ID_BITS - 1);
+}
@@ -0,0 +1,121 @@
+use common::messages::svsc::SessionData;
+use peer::{helpers::cipher_reliable_peer::CipherError, sel_handler::SelHandler};
+use server::sel_server::{SelServer, SelServerError};
+use std::net::SocketAddr;
+
+fn session_data(peer: u8) -> SessionData {
+    SessionData {
+        session_id: [1u8; 16],
+        peer_id: [peer; 16],
+        peer_key: [peer + 1; 16],
+    }
+}
+
+fn peer_handler(data: &SessionData) -> SelHandler {
+    let mut handler = SelHandler::new();
+    handler.derive_unreliable(&data.session_id, &data.peer_id, &data.peer_key);
+    handler
+}
+
+#[test]
+fn sel_server_relay() {
+    let host_data = session_data(2);
+    let client_data = session_data(4);
+    let mut host = peer_handler(&host_data);
+    let mut client = peer_handler(&client_data);
+
+    let mut server = SelServer::new();
+    server.add_peer(&host_data);
+    server.add_peer(&client_data);
+
+    let host_addr: SocketAddr = "127.0.0.1:1000".parse().unwrap();
+    let client_addr: SocketAddr = "127.0.0.1:2000".parse().unwrap();
+
+    // the client has not sent anything yet so we don't know where to send to
+    assert!(server
+        .wrap_unreliable(&client_data.peer_id, vec![1])
+        .unwrap()
+        .is_none());
+
+    let send_data = vec![9, 0, 5, 1];
+
+    let message = SelHandler::wrap_unreliable(
+        send_data.clone(),
+        host_data.peer_id,
+        host.unreliable_cipher(),
+    )
+    .unwrap();
+    let (peer_id, data) = server.handle(&message, host_addr).unwrap();
+    assert_eq!(peer_id, host_data.peer_id);
+    assert_eq!(data, send_data);
+
+    let message =
+        SelHandler::wrap_unreliable(vec![0], client_data.peer_id, client.unreliable_cipher())
+            .unwrap();
+    server.handle(&message, client_addr).unwrap();
+
+    // relay the host's data to the client
+    let (addr, message) = server
+        .wrap_unreliable(&client_data.peer_id, data)
+        .unwrap()
+        .expect("expected client address");
+    assert_eq!(addr, client_addr);
+    assert_eq!(client.handle(&message).unwrap(), send_data);
+}
+
+#[test]
+fn sel_server_replay() {
+    let host_data = session_data(2);
+    let mut host = peer_handler(&host_data);
+
+    let mut server = SelServer::new();
+    server.add_peer(&host_data);
+
+    let addr: SocketAddr = "127.0.0.1:1000".parse().unwrap();
+    let message =
+        SelHandler::wrap_unreliable(vec![1, 2, 3], host_data.peer_id, host.unreliable_cipher())
+            .unwrap();
+
+    server.handle(&message, addr).unwrap();
+    assert!(matches!(
+        server.handle(&message, addr),
+        Err(SelServerError::CipherError(CipherError::MessageTooOld(0)))
+    ));
+}
+
+#[test]
+fn sel_server_unknown_peer() {
+    let host_data = session_data(2);
+    let mut host = peer_handler(&host_data);
+    let mut server = SelServer::new();
+
+    let message =
+        SelHandler::wrap_unreliable(vec![1, 2, 3], host_data.peer_id, host.unreliable_cipher())
+            .unwrap();
+
+    assert!(matches!(
+        server.handle(&message, "127.0.0.1:1000".parse().unwrap()),
+        Err(SelServerError::UnknownPeer)
+    ));
+}
+
+#[test]
+fn sel_server_wrong_key() {
+    let host_data = session_data(2);
+    let mut host = peer_handler(&host_data);
+
+    let mut server = SelServer::new();
+    server.add_peer(&SessionData {
+        peer_key: [0; 16],
+        ..host_data.clone()
+    });
+
+    let message =
+        SelHandler::wrap_unreliable(vec![1, 2, 3], host_data.peer_id, host.unreliable_cipher())
+            .unwrap();
+
+    assert!(matches!(
+        server.handle(&message, "127.0.0.1:1000".parse().unwrap()),
+        Err(SelServerError::CipherError(CipherError::CipherError))
+    ));
+}
@@ -0,0 +1,412 @@
+use common::{
+    constants::SVSC_VERSION,
+    messages::{
+        svsc::{
+            EstablishSessionRequest,
+            EstablishSessionStatus,
+            KeepAlive,
+            LeaseExtensionRequest,
+            LeaseId,
+            LeaseRequest,
+            LeaseResponseData,
+            ProtocolVersionResponse,
+            SessionDataSend,
+            SessionEnd,
+            SvscMessage,
+        },
+        Data,
+    },
+};
+use server::{
+    lease_manager::{LeaseConfig, LeaseManager, DEFAULT_LEASE_DURATION, MAX_ID_BITS},
+    svsc_server::{ConnectionId, SvscServer, SvscServerError},
+};
+use std::{
+    borrow::Cow,
+    net::{IpAddr, Ipv4Addr},
+    time::{Duration, Instant},
+};
+
+const IP: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+fn connect(server: &mut SvscServer, connection: ConnectionId) {
+    let mut write = Vec::new();
+    server.connect(connection, IP, &mut write);
+
+    assert_eq!(write.len(), 1);
+    assert_eq!(write[0].0, connection);
+    match &write[0].1 {
+        SvscMessage::ProtocolVersion(msg) => assert_eq!(msg.version, SVSC_VERSION),
+        _ => panic!("wrong message returned: {:?}", write[0]),
+    }
+
+    write.clear();
+    server
+        .handle(
+            connection,
+            SvscMessage::ProtocolVersionResponse(ProtocolVersionResponse { ok: true }),
+            &mut write,
+        )
+        .expect("handler failed");
+    assert!(write.is_empty());
+}
+
+fn lease(server: &mut SvscServer, connection: ConnectionId) -> LeaseResponseData {
+    let mut write = Vec::new();
+    server
+        .handle(
+            connection,
+            SvscMessage::LeaseRequest(LeaseRequest { cookie: None }),
+            &mut write,
+        )
+        .expect("handler failed");
+
+    assert_eq!(write.len(), 1);
+    match write.remove(0) {
+        (id, SvscMessage::LeaseResponse(msg)) if id == connection =>
+            msg.response_data.expect("lease rejected"),
+        msg => panic!("wrong message returned: {:?}", msg),
+    }
+}
+
+fn establish_session(
+    server: &mut SvscServer,
+    connection: ConnectionId,
+    lease_id: LeaseId,
+) -> Vec<(ConnectionId, SvscMessage<'static>)> {
+    let mut write = Vec::new();
+    server
+        .handle(
+            connection,
+            SvscMessage::EstablishSessionRequest(EstablishSessionRequest { lease_id }),
+            &mut write,
+        )
+        .expect("handler failed");
+    write
+}
+
+fn session_status(write: &[(ConnectionId, SvscMessage<'static>)]) -> EstablishSessionStatus {
+    match &write[0].1 {
+        SvscMessage::EstablishSessionResponse(msg) => msg.status,
+        _ => panic!("wrong message returned: {:?}", write[0]),
+    }
+}
+
+#[test]
+fn test_protocol_rejected() {
+    let mut server = SvscServer::new();
+    let mut write = Vec::new();
+    server.connect(0, IP, &mut write);
+
+    let result = server.handle(
+        0,
+        SvscMessage::ProtocolVersionResponse(ProtocolVersionResponse { ok: false }),
+        &mut write,
+    );
+    assert!(matches!(result, Err(SvscServerError::VersionRejected)));
+}
+
+#[test]
+fn test_message_before_handshake() {
+    let mut server = SvscServer::new();
+    let mut write = Vec::new();
+    server.connect(0, IP, &mut write);
+
+    let result = server.handle(
+        0,
+        SvscMessage::LeaseRequest(LeaseRequest { cookie: None }),
+        &mut write,
+    );
+    assert!(matches!(
+        result,
+        Err(SvscServerError::WrongMessageForState(..))
+    ));
+}
+
+#[test]
+fn test_lease() {
+    let mut server = SvscServer::new();
+    let mut write = Vec::new();
+    connect(&mut server, 0);
+
+    let data = lease(&mut server, 0);
+    assert!(u64::from(u32::from_be_bytes(data.id)) < 1 << MAX_ID_BITS);
+    assert!(data.expiration > common::chrono::Utc::now());
+    assert!(
+        data.expiration
+            <= common::chrono::Utc::now()
+                + common::chrono::Duration::seconds(DEFAULT_LEASE_DURATION)
+    );
+
+    // only one lease per connection
+    server
+        .handle(
+            0,
+            SvscMessage::LeaseRequest(LeaseRequest { cookie: None }),
+            &mut write,
+        )
+        .expect("handler failed");
+    assert!(matches!(
+        &write[0].1,
+        SvscMessage::LeaseResponse(msg) if msg.response_data.is_none()
+    ));
+    write.clear();
+
+    // extension with a bad cookie
+    server
+        .handle(
+            0,
+            SvscMessage::LeaseExtensionRequest(LeaseExtensionRequest { cookie: [0; 24] }),
+            &mut write,
+        )
+        .expect("handler failed");
+    assert!(matches!(
+        &write[0].1,
+        SvscMessage::LeaseExtensionResponse(msg) if msg.new_expiration.is_none()
+    ));
+    write.clear();
+
+    // extension with the right cookie
+    server
+        .handle(
+            0,
+            SvscMessage::LeaseExtensionRequest(LeaseExtensionRequest {
+                cookie: data.cookie,
+            }),
+            &mut write,
+        )
+        .expect("handler failed");
+    match &write[0].1 {
+        SvscMessage::LeaseExtensionResponse(msg) =>
+            assert!(msg.new_expiration.expect("extension rejected") >= data.expiration),
+        _ => panic!("wrong message returned: {:?}", write[0]),
+    }
+}
+
+#[test]
+fn test_lease_reclaim_with_cookie() {
+    let mut server = SvscServer::new();
+    let mut write = Vec::new();
+    connect(&mut server, 0);
+    let data = lease(&mut server, 0);
+
+    server.disconnect(0, &mut write);
+    connect(&mut server, 1);
+
+    server
+        .handle(
+            1,
+            SvscMessage::LeaseRequest(LeaseRequest {
+                cookie: Some(data.cookie),
+            }),
+            &mut write,
+        )
+        .expect("handler failed");
+
+    match &write[0].1 {
+        SvscMessage::LeaseResponse(msg) => assert_eq!(
+            msg.response_data.as_ref().expect("lease rejected").id,
+            data.id
+        ),
+        _ => panic!("wrong message returned: {:?}", write[0]),
+    }
+}
+
+#[test]
+fn test_establish_session() {
+    let mut server = SvscServer::new();
+    connect(&mut server, 0);
+    connect(&mut server, 1);
+    let host_lease = lease(&mut server, 0);
+
+    let write = establish_session(&mut server, 1, host_lease.id);
+    assert_eq!(write.len(), 2);
+
+    let client_data = match &write[0] {
+        (1, SvscMessage::EstablishSessionResponse(msg)) => {
+            assert_eq!(msg.lease_id, host_lease.id);
+            assert_eq!(msg.status, EstablishSessionStatus::Success);
+            msg.response_data.clone().expect("expected session data")
+        }
+        _ => panic!("wrong message returned: {:?}", write[0]),
+    };
+    let host_data = match &write[1] {
+        (0, SvscMessage::EstablishSessionNotification(msg)) => msg.session_data.clone(),
+        _ => panic!("wrong message returned: {:?}", write[1]),
+    };
+
+    assert_eq!(client_data.session_id, host_data.session_id);
+    assert_ne!(client_data.peer_id, host_data.peer_id);
+    assert_ne!(client_data.peer_key, host_data.peer_key);
+    assert_eq!(server.session_data(0), Some(&host_data));
+    assert_eq!(server.session_data(1), Some(&*client_data));
+}
+
+#[test]
+fn test_establish_session_errors() {
+    let mut server = SvscServer::new();
+    let mut write = Vec::new();
+    connect(&mut server, 0);
+    connect(&mut server, 1);
+    connect(&mut server, 2);
+    let host_lease = lease(&mut server, 0);
+
+    let write_1 = establish_session(&mut server, 1, [0xFF; 4]);
+    assert_eq!(session_status(&write_1), EstablishSessionStatus::IDNotFound);
+
+    let write_1 = establish_session(&mut server, 0, host_lease.id);
+    assert_eq!(session_status(&write_1), EstablishSessionStatus::OtherError);
+
+    establish_session(&mut server, 1, host_lease.id);
+
+    let write_1 = establish_session(&mut server, 2, host_lease.id);
+    assert_eq!(session_status(&write_1), EstablishSessionStatus::PeerBusy);
+
+    let write_1 = establish_session(&mut server, 1, host_lease.id);
+    assert_eq!(session_status(&write_1), EstablishSessionStatus::SelfBusy);
+
+    server.disconnect(0, &mut write);
+    assert!(matches!(
+        write[0],
+        (1, SvscMessage::SessionEndNotification(_))
+    ));
+
+    let write_1 = establish_session(&mut server, 2, host_lease.id);
+    assert_eq!(
+        session_status(&write_1),
+        EstablishSessionStatus::PeerOffline
+    );
+}
+
+#[test]
+fn test_session_data_relay() {
+    let mut server = SvscServer::new();
+    let mut write = Vec::new();
+    connect(&mut server, 0);
+    connect(&mut server, 1);
+    let host_lease = lease(&mut server, 0);
+    establish_session(&mut server, 1, host_lease.id);
+
+    let data = vec![1, 2, 3, 4];
+    server
+        .handle(
+            1,
+            SvscMessage::SessionDataSend(SessionDataSend {
+                data: Data(Cow::Borrowed(&data)),
+            }),
+            &mut write,
+        )
+        .expect("handler failed");
+
+    assert_eq!(write.len(), 1);
+    match &write[0] {
+        (0, SvscMessage::SessionDataReceive(msg)) => assert_eq!(&*msg.data.0, &data),
+        _ => panic!("wrong message returned: {:?}", write[0]),
+    }
+    write.clear();
+
+    // ending the session notifies the other peer and stops the relay
+    server
+        .handle(1, SvscMessage::SessionEnd(SessionEnd {}), &mut write)
+        .expect("handler failed");
+    assert!(matches!(
+        write[0],
+        (0, SvscMessage::SessionEndNotification(_))
+    ));
+    write.clear();
+
+    server
+        .handle(
+            0,
+            SvscMessage::SessionDataSend(SessionDataSend {
+                data: Data(Cow::Borrowed(&data)),
+            }),
+            &mut write,
+        )
+        .expect("handler failed");
+    assert!(write.is_empty());
+}
+
+#[test]
+fn test_keepalive() {
+    let mut server = SvscServer::with_config(
+        LeaseManager::new(LeaseConfig::default()),
+        Duration::from_secs(10),
+    );
+    let mut write = Vec::new();
+    connect(&mut server, 0);
+
+    let now = Instant::now();
+    assert!(server.check_keepalive(now, &mut write).is_empty());
+    assert!(write.is_empty());
+
+    let now = now + Duration::from_secs(10);
+    assert!(server.check_keepalive(now, &mut write).is_empty());
+    assert!(matches!(write[0], (0, SvscMessage::KeepAlive(_))));
+    write.clear();
+
+    // responding to a keepalive must not produce another one
+    server
+        .handle(0, SvscMessage::KeepAlive(KeepAlive {}), &mut write)
+        .expect("handler failed");
+    assert!(write.is_empty());
+
+    // no response to the next keepalive drops the connection
+    let now = Instant::now() + Duration::from_secs(10);
+    assert!(server.check_keepalive(now, &mut write).is_empty());
+    let now = now + Duration::from_secs(20);
+    assert_eq!(server.check_keepalive(now, &mut write), vec![0]);
+}
+
+#[test]
+fn test_unreliable_relay() {
+    let mut server = SvscServer::new();
+    connect(&mut server, 0);
+    connect(&mut server, 1);
+    let host_lease = lease(&mut server, 0);
+    establish_session(&mut server, 1, host_lease.id);
+
+    let client_data = server.session_data(1).unwrap().clone();
+    let host_data = server.session_data(0).unwrap().clone();
+
+    let [own, other] = server.session_peers(&client_data.peer_id).unwrap();
+    assert_eq!(own, &client_data);
+    assert_eq!(other, &host_data);
+
+    // keepalives only establish the connection
+    assert!(server
+        .handle_unreliable(&client_data.peer_id, SvscMessage::KeepAlive(KeepAlive {}))
+        .expect("handler failed")
+        .is_none());
+
+    let data = vec![1, 2, 3, 4];
+    let (to, message) = server
+        .handle_unreliable(
+            &client_data.peer_id,
+            SvscMessage::SessionDataSend(SessionDataSend {
+                data: Data(Cow::Borrowed(&data)),
+            }),
+        )
+        .expect("handler failed")
+        .expect("expected message to forward");
+    assert_eq!(to, host_data.peer_id);
+    match message {
+        SvscMessage::SessionDataReceive(msg) => assert_eq!(&*msg.data.0, &data),
+        _ => panic!("wrong message returned: {:?}", message),
+    }
+
+    let mut write = Vec::new();
+    server
+        .handle(1, SvscMessage::SessionEnd(SessionEnd {}), &mut write)
+        .expect("handler failed");
+    assert!(!server.has_peer(&client_data.peer_id));
+    assert!(server
+        .handle_unreliable(
+            &client_data.peer_id,
+            SvscMessage::SessionDataSend(SessionDataSend {
+                data: Data(Cow::Borrowed(&data)),
+            }),
+        )
+        .expect("handler failed")
+        .is_none());
+}
@@ -0,0 +1,270 @@
+// XOR parity forward error correction, along the lines of ULPFEC and FlexFEC. A block of media
+// packets with consecutive sequence numbers is split into interleaved groups, group g holding
+// packets g, g + groups, g + 2 * groups... Each group gets a repair packet that is the XOR of its
+// packets, so any one packet of a group can be rebuilt from the rest without asking for it again.
+// Interleaving means a burst of up to groups lost packets can be recovered.
+//
+// Repair packets are RTP packets with FEC_PAYLOAD_TYPE and a sequence of their own. Their payload:
+//
+// base sequence (2) | count (2) | groups (1) | group (1) | recovery
+//
+// where recovery is the XOR of marker (1) | timestamp (4) | payload length (2) | payload of every
+// packet in the group, the shorter payloads padded with zeros.
+
+use crate::rtp::VP9_PAYLOAD_TYPE;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use rtp::{header::Header, packet::Packet};
+use std::collections::{HashMap, VecDeque};
+
+pub const FEC_PAYLOAD_TYPE: u8 = 99;
+/// The most media packets kept to recover others with
+const MEDIA_HISTORY: usize = 1024;
+/// The most repair packets kept waiting for the rest of their group
+const MAX_PENDING_REPAIRS: usize = 256;
+
+const REPAIR_HEADER_LEN: usize = 6;
+const RECOVERY_HEADER_LEN: usize = 7;
+
+pub struct FecEncoder {
+    ratio: f32,
+    sequence: u16,
+}
+
+impl Default for FecEncoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FecEncoder {
+    /// Starts off sending no repair packets
+    pub fn new() -> Self {
+        Self {
+            ratio: 0.0,
+            sequence: 0,
+        }
+    }
+
+    /// How many repair packets are sent for each media packet, 0 turns FEC off
+    pub fn ratio(&self) -> f32 {
+        self.ratio
+    }
+
+    pub fn set_ratio(&mut self, ratio: f32) {
+        self.ratio = ratio.clamp(0.0, 1.0);
+    }
+
+    /// Makes the repair packets for a block of packets from the RtpEncoder
+    pub fn protect(&mut self, block: &[Packet]) -> Vec<Packet> {
+        let first = match block.first() {
+            Some(first) if self.ratio > 0.0 => &first.header,
+            _ => return Vec::new(),
+        };
+        let base = first.sequence_number;
+        let consecutive = block
+            .iter()
+            .enumerate()
+            .all(|(i, packet)| packet.header.sequence_number == base.wrapping_add(i as u16));
+        if !consecutive || block.len() > u16::MAX as usize {
+            return Vec::new();
+        }
+
+        let groups = ((block.len() as f32 * self.ratio).ceil() as usize).clamp(1, u8::MAX as usize);
+        let groups = groups.min(block.len());
+        (0 .. groups)
+            .map(|group| {
+                let mut recovery = Vec::new();
+                for packet in block.iter().skip(group).step_by(groups) {
+                    xor_into(&mut recovery, &recovery_data(packet));
+                }
+
+                let mut payload = BytesMut::with_capacity(REPAIR_HEADER_LEN + recovery.len());
+                payload.put_u16(base);
+                payload.put_u16(block.len() as u16);
+                payload.put_u8(groups as u8);
+                payload.put_u8(group as u8);
+                payload.put_slice(&recovery);
+
+                let sequence_number = self.sequence;
+                self.sequence = self.sequence.wrapping_add(1);
+                Packet {
+                    header: Header {
+                        version: 2,
+                        payload_type: FEC_PAYLOAD_TYPE,
+                        sequence_number,
+                        timestamp: first.timestamp,
+                        ssrc: first.ssrc,
+                        ..Default::default()
+                    },
+                    payload: payload.freeze(),
+                }
+            })
+            .collect()
+    }
+}
+
+struct Repair {
+    base: u16,
+    count: u16,
+    groups: u8,
+    group: u8,
+    ssrc: u32,
+    recovery: Bytes,
+}
+
+impl Repair {
+    fn protected(&self) -> impl Iterator<Item = u16> + '_ {
+        (self.group as u16 .. self.count)
+            .step_by(self.groups as usize)
+            .map(|i| self.base.wrapping_add(i))
+    }
+}
+
+/// Rebuilds lost media packets from the repair packets sent with them
+#[derive(Default)]
+pub struct FecDecoder {
+    media: HashMap<u16, Packet>,
+    // Sequence numbers of the media packets kept, oldest first
+    order: VecDeque<u16>,
+    repairs: VecDeque<Repair>,
+}
+
+impl FecDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Takes in a media packet, returns the packets it let us recover
+    pub fn add_media(&mut self, packet: &Packet) -> Vec<Packet> {
+        self.insert_media(packet.clone());
+        self.recover()
+    }
+
+    /// Takes in a repair packet, returns the packets it let us recover
+    pub fn add_repair(&mut self, packet: &Packet) -> Result<Vec<Packet>, FecError> {
+        let mut payload = packet.payload.clone();
+        if payload.len() < REPAIR_HEADER_LEN + RECOVERY_HEADER_LEN {
+            return Err(FecError::Truncated);
+        }
+        let repair = Repair {
+            base: payload.get_u16(),
+            count: payload.get_u16(),
+            groups: payload.get_u8(),
+            group: payload.get_u8(),
+            ssrc: packet.header.ssrc,
+            recovery: payload,
+        };
+        if repair.groups == 0 || repair.group >= repair.groups {
+            return Err(FecError::BadGroup(repair.group, repair.groups));
+        }
+
+        if self.repairs.len() == MAX_PENDING_REPAIRS {
+            self.repairs.pop_front();
+        }
+        self.repairs.push_back(repair);
+        Ok(self.recover())
+    }
+
+    fn insert_media(&mut self, packet: Packet) {
+        let sequence = packet.header.sequence_number;
+        if self.media.insert(sequence, packet).is_none() {
+            self.order.push_back(sequence);
+        }
+        while self.order.len() > MEDIA_HISTORY {
+            if let Some(sequence) = self.order.pop_front() {
+                self.media.remove(&sequence);
+            }
+        }
+    }
+
+    fn recover(&mut self) -> Vec<Packet> {
+        let mut recovered = Vec::new();
+        let mut i = 0;
+        while i < self.repairs.len() {
+            let repair = &self.repairs[i];
+            let mut missing = repair
+                .protected()
+                .filter(|sequence| !self.media.contains_key(sequence));
+            let lost = match (missing.next(), missing.next()) {
+                // Wait for the rest of the group, a packet may be sent again
+                (Some(_), Some(_)) => {
+                    i += 1;
+                    continue;
+                }
+                (Some(lost), None) => lost,
+                // Nothing to recover
+                (None, _) => {
+                    self.repairs.remove(i);
+                    continue;
+                }
+            };
+
+            let mut data = repair.recovery.to_vec();
+            for sequence in repair.protected().filter(|&sequence| sequence != lost) {
+                xor_into(&mut data, &recovery_data(&self.media[&sequence]));
+            }
+            let ssrc = repair.ssrc;
+            self.repairs.remove(i);
+            // A repair packet that doesn't match its group is dropped, what it protects may
+            // still be sent again
+            if let Some(packet) = rebuild(lost, ssrc, &data) {
+                self.insert_media(packet.clone());
+                recovered.push(packet);
+                // Which may complete another group
+                i = 0;
+            }
+        }
+        recovered
+    }
+}
+
+// What a packet contributes to the recovery data of its group
+fn recovery_data(packet: &Packet) -> Vec<u8> {
+    let mut data = Vec::with_capacity(RECOVERY_HEADER_LEN + packet.payload.len());
+    data.put_u8(packet.header.marker as u8);
+    data.put_u32(packet.header.timestamp);
+    data.put_u16(packet.payload.len() as u16);
+    data.put_slice(&packet.payload);
+    data
+}
+
+fn xor_into(dest: &mut Vec<u8>, src: &[u8]) {
+    if dest.len() < src.len() {
+        dest.resize(src.len(), 0);
+    }
+    for (dest, src) in dest.iter_mut().zip(src) {
+        *dest ^= src;
+    }
+}
+
+fn rebuild(sequence_number: u16, ssrc: u32, mut data: &[u8]) -> Option<Packet> {
+    if data.len() < RECOVERY_HEADER_LEN {
+        return None;
+    }
+    let marker = data.get_u8();
+    let timestamp = data.get_u32();
+    let len = data.get_u16() as usize;
+    if len > data.len() || marker > 1 {
+        return None;
+    }
+    Some(Packet {
+        header: Header {
+            version: 2,
+            marker: marker == 1,
+            payload_type: VP9_PAYLOAD_TYPE,
+            sequence_number,
+            timestamp,
+            ssrc,
+            ..Default::default()
+        },
+        payload: Bytes::copy_from_slice(&data[.. len]),
+    })
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum FecError {
+    #[error("repair packet is too short")]
+    Truncated,
+    #[error("repair packet is for group {0} of {1}")]
+    BadGroup(u8, u8),
+}
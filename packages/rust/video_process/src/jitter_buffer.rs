@@ -0,0 +1,402 @@
+// Holds FrameData for a display back for a moment so packets that were reordered, held up or sent
+// again can catch up, then hands out whole VP9 frames in order. Packets are grouped into frames by
+// their timestamp. A frame is complete once the packet with the VP9 descriptor's B flag, the packet
+// with the marker bit and everything in between have arrived.
+//
+// Each frame is due at its timestamp plus the transit of the fastest packet seen so far plus the
+// playout delay. The delay follows how late frames complete: it jumps up to cover a frame that
+// took longer and comes back down slowly once they arrive on time. Frames still incomplete when
+// they're due are dropped, as is everything after them up to the next keyframe since VP9 frames
+// are encoded relative to the ones before them.
+
+use crate::{
+    fec::FEC_PAYLOAD_TYPE,
+    rtp::{DecoderError, CLOCK_RATE},
+    vp9::is_keyframe,
+};
+use bytes::Bytes;
+use rtp::{codecs::vp9::Vp9Packet, packet::Packet, packetizer::Depacketizer};
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
+
+/// The least time frames are held back for
+pub const MIN_PLAYOUT_DELAY: Duration = Duration::from_millis(10);
+/// The most time frames are held back for, ones that take longer are dropped
+pub const MAX_PLAYOUT_DELAY: Duration = Duration::from_millis(200);
+/// The most frames held at once, the oldest are dropped past this
+const MAX_FRAMES: usize = 64;
+// How slowly the playout delay comes back down
+const DELAY_DECAY: i64 = 64;
+
+/// A whole VP9 frame ready to be decoded
+#[derive(Debug)]
+pub struct Frame {
+    pub timestamp: u32,
+    pub data: Vec<u8>,
+    pub keyframe: bool,
+    /// The width and height, if the VP9 descriptor had them
+    pub resolution: Option<(u16, u16)>,
+}
+
+/// What a JitterBuffer has seen since it was made, along the lines of an RTCP receiver report
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct JitterStats {
+    /// Packets the sequence numbers say should have arrived
+    pub expected: u64,
+    /// Packets that arrived, including ones too late to be played but not ones recovered from
+    /// repair packets
+    pub received: u64,
+    /// Packets that never arrived
+    pub lost: u64,
+    /// Payload bytes that arrived
+    pub received_bytes: u64,
+    /// Frames handed out
+    pub frames: u64,
+    /// Frames dropped because they were incomplete or came after one that was
+    pub dropped_frames: u64,
+    /// Interarrival jitter as in RFC 3550
+    pub jitter: Duration,
+    /// How long frames are currently held back for
+    pub playout_delay: Duration,
+}
+
+#[derive(Default)]
+struct PendingFrame {
+    // Depacketized payloads by extended sequence number
+    packets: BTreeMap<i64, Bytes>,
+    // Extended sequence numbers of the packets starting and ending the frame once they've arrived
+    first: Option<i64>,
+    last: Option<i64>,
+    resolution: Option<(u16, u16)>,
+}
+
+impl PendingFrame {
+    fn complete(&self) -> Option<(i64, i64)> {
+        let (first, last) = (self.first?, self.last?);
+        if last < first {
+            return None;
+        }
+        let count = self.packets.range(first ..= last).count() as i64;
+        if count == last - first + 1 {
+            Some((first, last))
+        } else {
+            None
+        }
+    }
+}
+
+/// Works out loss and jitter from the sequence numbers, timestamps and arrival times of FrameData
+#[derive(Default)]
+pub struct ReceiveStats {
+    // Arrival times are counted in RTP clock ticks from when the first packet arrived
+    epoch: Option<Instant>,
+    // The highest extended sequence number and timestamp so far, the next ones are extended from
+    // these
+    highest_sequence: Option<i64>,
+    highest_timestamp: Option<i64>,
+    first_sequence: Option<i64>,
+    // The smallest difference between arrival and timestamp seen, from a packet that wasn't held up
+    base_transit: Option<i64>,
+    last_transit: Option<i64>,
+    // In RTP clock ticks
+    jitter: f64,
+    received: u64,
+    received_bytes: u64,
+}
+
+impl ReceiveStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Counts a packet that arrived at the given time
+    pub fn record(&mut self, packet: &Packet, arrival: Instant) {
+        self.track(packet, arrival);
+    }
+
+    // Gives the extended sequence number and timestamp of the packet and its transit in RTP clock
+    // ticks
+    fn track(&mut self, packet: &Packet, arrival: Instant) -> (i64, i64, i64) {
+        let (sequence, timestamp, transit) = self.extend(packet, arrival);
+        self.received += 1;
+        self.received_bytes += packet.payload.len() as u64;
+
+        if let Some(last_transit) = self.last_transit {
+            self.jitter += ((transit - last_transit).abs() as f64 - self.jitter) / 16.0;
+        }
+        self.last_transit = Some(transit);
+        self.base_transit = Some(self.base_transit.map_or(transit, |base| base.min(transit)));
+        (sequence, timestamp, transit)
+    }
+
+    // Like track, except the packet isn't counted as received and its transit doesn't go towards
+    // the jitter
+    fn extend(&mut self, packet: &Packet, arrival: Instant) -> (i64, i64, i64) {
+        let sequence = extend_sequence(packet.header.sequence_number, self.highest_sequence);
+        let timestamp = extend_timestamp(packet.header.timestamp, self.highest_timestamp);
+        self.highest_sequence = self.highest_sequence.max(Some(sequence));
+        self.highest_timestamp = self.highest_timestamp.max(Some(timestamp));
+        self.first_sequence = Some(
+            self.first_sequence
+                .map_or(sequence, |first| first.min(sequence)),
+        );
+
+        let epoch = *self.epoch.get_or_insert(arrival);
+        let transit = to_ticks(arrival.saturating_duration_since(epoch)) - timestamp;
+        (sequence, timestamp, transit)
+    }
+
+    /// Packets the sequence numbers say should have arrived
+    pub fn expected(&self) -> u64 {
+        match (self.first_sequence, self.highest_sequence) {
+            (Some(first), Some(highest)) => (highest - first + 1) as u64,
+            _ => 0,
+        }
+    }
+
+    /// Packets that arrived, duplicates included
+    pub fn received(&self) -> u64 {
+        self.received
+    }
+
+    /// Packets that never arrived
+    pub fn lost(&self) -> u64 {
+        self.expected().saturating_sub(self.received)
+    }
+
+    /// Payload bytes that arrived
+    pub fn received_bytes(&self) -> u64 {
+        self.received_bytes
+    }
+
+    /// Interarrival jitter as in RFC 3550
+    pub fn jitter(&self) -> Duration {
+        from_ticks(self.jitter as i64)
+    }
+}
+
+pub struct JitterBuffer {
+    // Frames waiting to be handed out by extended timestamp
+    frames: BTreeMap<i64, PendingFrame>,
+    stats: ReceiveStats,
+    // In RTP clock ticks
+    delay: i64,
+    // The extended timestamp of the last frame handed out or dropped, anything older is too late
+    played_timestamp: Option<i64>,
+    // The extended sequence number ending the last frame handed out, the next frame must follow on
+    // from it to be decoded
+    played_sequence: Option<i64>,
+    needs_keyframe: bool,
+    played: u64,
+    dropped: u64,
+}
+
+impl Default for JitterBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl JitterBuffer {
+    pub fn new() -> Self {
+        Self {
+            frames: BTreeMap::new(),
+            stats: ReceiveStats::new(),
+            delay: to_ticks(MIN_PLAYOUT_DELAY),
+            played_timestamp: None,
+            played_sequence: None,
+            // We may have joined in the middle of the stream
+            needs_keyframe: true,
+            played: 0,
+            dropped: 0,
+        }
+    }
+
+    /// Takes in a FrameData packet that arrived at the given time. Repair packets are for the
+    /// RtpDecoder, they're ignored here.
+    pub fn push(&mut self, packet: &Packet, arrival: Instant) -> Result<(), DecoderError> {
+        self.insert(packet, arrival, false)
+    }
+
+    /// Takes in a packet the RtpDecoder recovered from repair packets. It goes into its frame like
+    /// any other but isn't counted in the stats, so they show the loss before FEC.
+    pub fn push_recovered(&mut self, packet: &Packet, now: Instant) -> Result<(), DecoderError> {
+        self.insert(packet, now, true)
+    }
+
+    fn insert(
+        &mut self,
+        packet: &Packet,
+        arrival: Instant,
+        recovered: bool,
+    ) -> Result<(), DecoderError> {
+        if packet.header.payload_type == FEC_PAYLOAD_TYPE {
+            return Ok(());
+        }
+        let mut vp9packet = Vp9Packet::default();
+        let data = vp9packet.depacketize(&packet.payload)?;
+        let (sequence, timestamp, transit) = if recovered {
+            self.stats.extend(packet, arrival)
+        } else {
+            self.stats.track(packet, arrival)
+        };
+
+        if matches!(self.played_timestamp, Some(played) if timestamp <= played) {
+            return Ok(());
+        }
+
+        let frame = self.frames.entry(timestamp).or_default();
+        if frame.packets.insert(sequence, data).is_some() {
+            return Ok(());
+        }
+        if vp9packet.b && frame.first.map_or(true, |first| sequence < first) {
+            frame.first = Some(sequence);
+            if let (true, Some(&width), Some(&height)) = (
+                vp9packet.y,
+                vp9packet.width.first(),
+                vp9packet.height.first(),
+            ) {
+                frame.resolution = Some((width, height));
+            }
+        }
+        if packet.header.marker {
+            frame.last = Some(sequence);
+        }
+        if frame.complete().is_some() {
+            let base_transit = self.stats.base_transit.unwrap_or(transit);
+            self.adapt(transit - base_transit);
+        }
+
+        while self.frames.len() > MAX_FRAMES {
+            let timestamp = self.oldest().expect("frames can't be empty");
+            self.frames.remove(&timestamp);
+            self.drop_frame(timestamp);
+        }
+        Ok(())
+    }
+
+    /// Hands out the next frame once it's due, None if there isn't one yet
+    pub fn pop(&mut self, now: Instant) -> Option<Frame> {
+        loop {
+            let timestamp = self.oldest()?;
+            if now < self.due(timestamp)? {
+                return None;
+            }
+            let frame = self.frames.remove(&timestamp)?;
+            let (first, last) = match frame.complete() {
+                Some(bounds) => bounds,
+                None => {
+                    self.drop_frame(timestamp);
+                    continue;
+                }
+            };
+
+            let data: Vec<u8> = frame
+                .packets
+                .range(first ..= last)
+                .flat_map(|(_, data)| data.iter().copied())
+                .collect();
+            let keyframe = is_keyframe(&data);
+            // A frame missing in between, even one we never saw a packet of, leaves this one
+            // nothing to be decoded against
+            if self.played_sequence != Some(first - 1) {
+                self.needs_keyframe = true;
+            }
+            self.played_timestamp = Some(timestamp);
+            self.played_sequence = Some(last);
+            if self.needs_keyframe && !keyframe {
+                self.dropped += 1;
+                continue;
+            }
+
+            self.needs_keyframe = false;
+            self.played += 1;
+            return Some(Frame {
+                timestamp: timestamp as u32,
+                data,
+                keyframe,
+                resolution: frame.resolution,
+            });
+        }
+    }
+
+    /// When the next frame is due to be handed out, if there's one waiting
+    pub fn next_due(&self) -> Option<Instant> {
+        self.oldest().and_then(|timestamp| self.due(timestamp))
+    }
+
+    /// Whether frames are being dropped until the next keyframe, in which case the Host should be
+    /// asked for one
+    pub fn needs_keyframe(&self) -> bool {
+        self.needs_keyframe
+    }
+
+    /// What arrived so far, for ReceiverReports
+    pub fn receive_stats(&self) -> &ReceiveStats {
+        &self.stats
+    }
+
+    pub fn stats(&self) -> JitterStats {
+        JitterStats {
+            expected: self.stats.expected(),
+            received: self.stats.received(),
+            lost: self.stats.lost(),
+            received_bytes: self.stats.received_bytes(),
+            frames: self.played,
+            dropped_frames: self.dropped,
+            jitter: self.stats.jitter(),
+            playout_delay: from_ticks(self.delay),
+        }
+    }
+
+    fn oldest(&self) -> Option<i64> {
+        self.frames.keys().next().copied()
+    }
+
+    // None until a packet has arrived
+    fn due(&self, timestamp: i64) -> Option<Instant> {
+        let ticks = timestamp + self.stats.base_transit? + self.delay;
+        Some(self.stats.epoch? + from_ticks(ticks))
+    }
+
+    fn drop_frame(&mut self, timestamp: i64) {
+        self.played_timestamp = self.played_timestamp.max(Some(timestamp));
+        self.needs_keyframe = true;
+        self.dropped += 1;
+    }
+
+    // Follows how late a frame completed compared to the fastest packet
+    fn adapt(&mut self, late: i64) {
+        let delay = if late > self.delay {
+            late
+        } else {
+            self.delay - (self.delay - late) / DELAY_DECAY
+        };
+        self.delay = delay.clamp(to_ticks(MIN_PLAYOUT_DELAY), to_ticks(MAX_PLAYOUT_DELAY));
+    }
+}
+
+// Sequence numbers and timestamps wrap, they're extended to the value closest to the last one
+fn extend_sequence(sequence: u16, near: Option<i64>) -> i64 {
+    match near {
+        Some(near) => near + i64::from(sequence.wrapping_sub(near as u16) as i16),
+        None => i64::from(sequence),
+    }
+}
+
+fn extend_timestamp(timestamp: u32, near: Option<i64>) -> i64 {
+    match near {
+        Some(near) => near + i64::from(timestamp.wrapping_sub(near as u32) as i32),
+        None => i64::from(timestamp),
+    }
+}
+
+fn to_ticks(duration: Duration) -> i64 {
+    (duration.as_micros() * u128::from(CLOCK_RATE) / 1_000_000) as i64
+}
+
+fn from_ticks(ticks: i64) -> Duration {
+    Duration::from_micros(ticks.max(0) as u64 * 1_000_000 / u64::from(CLOCK_RATE))
+}
@@ -1,3 +1,5 @@
 pub mod convert;
+pub mod fec;
+pub mod jitter_buffer;
 pub mod rtp;
 pub mod vp9;
@@ -5,32 +5,81 @@ use rtp::{
     packetizer::{new_packetizer, Depacketizer, Packetizer},
     sequence::WrappingSequencer,
 };
+use std::{mem, time::Instant};
 use webrtc_util::Unmarshal;
 
-const VP9_PAYLOAD_TYPE: u8 = 98;
+use crate::{
+    fec::{FecDecoder, FecError, FEC_PAYLOAD_TYPE},
+    vp9::is_keyframe,
+};
+
+pub(crate) const VP9_PAYLOAD_TYPE: u8 = 98;
+/// FrameData timestamps count in these per second
+pub const CLOCK_RATE: u32 = 90000;
+/// The most packets waited for at once, a keyframe is cheaper than sending more than this again
+pub const MAX_MISSING: usize = 64;
 
 pub struct RtpEncoder {
     packetizer: Box<dyn Packetizer>,
+    // Frames are timestamped with when they're packetized, counted from the first one
+    started: Option<Instant>,
+    last_timestamp: Option<u32>,
 }
 
 impl RtpEncoder {
     pub fn new(mtu: usize, ssrc: u32) -> Self {
         let payloader = Vp9Payloader::new();
         let sequenceizer = WrappingSequencer::new(0);
-        let packetizer =
-            new_packetizer(mtu, VP9_PAYLOAD_TYPE, ssrc, payloader, sequenceizer, 90000);
+        let packetizer = new_packetizer(
+            mtu,
+            VP9_PAYLOAD_TYPE,
+            ssrc,
+            payloader,
+            sequenceizer,
+            CLOCK_RATE,
+        );
         Self {
             packetizer: Box::new(packetizer),
+            started: None,
+            last_timestamp: None,
         }
     }
 
     pub fn process_vp9(&mut self, vp9_frame: Vec<u8>) -> Result<Vec<Packet>, rtp::Error> {
         let bytes = Bytes::from(vp9_frame);
-        self.packetizer.packetize(&bytes, 1)
+        let mut packets = self.packetizer.packetize(&bytes, 0)?;
+
+        // Frames don't come at a fixed rate, so the timestamp follows the clock for the Client to
+        // work out when to show them and how much their arrival jitters
+        let now = Instant::now();
+        let started = *self.started.get_or_insert(now);
+        let elapsed = now.duration_since(started).as_micros();
+        let mut timestamp = (elapsed * u128::from(CLOCK_RATE) / 1_000_000) as u32;
+        // Every frame needs a timestamp of its own
+        if let Some(last) = self.last_timestamp {
+            if timestamp.wrapping_sub(last) as i32 <= 0 {
+                timestamp = last.wrapping_add(1);
+            }
+        }
+        self.last_timestamp = Some(timestamp);
+        for packet in &mut packets {
+            packet.header.timestamp = timestamp;
+        }
+        Ok(packets)
     }
 }
 
-pub struct RtpDecoder {}
+pub struct RtpDecoder {
+    // The sequence number of the packet we expect next, None until the first one arrives
+    next_sequence: Option<u16>,
+    // Sequence numbers that were skipped over and haven't arrived since
+    missing: Vec<u16>,
+    // Set when packets are given up on, frames after that can't be decoded until the next keyframe
+    needs_keyframe: bool,
+    fec: FecDecoder,
+    // Packets rebuilt from repair packets that haven't been taken yet
+    recovered: Vec<Packet>,
+}
 
 impl Default for RtpDecoder {
     fn default() -> Self {
@@ -43,17 +92,117 @@ pub type Vp9PacketWrapperBecauseTheRtpCrateIsIdiotic = (Bytes, Vp9Packet);
 
 impl RtpDecoder {
     pub fn new() -> Self {
-        Self {}
+        Self {
+            next_sequence: None,
+            missing: Vec::new(),
+            // We may have joined in the middle of the stream
+            needs_keyframe: true,
+            fec: FecDecoder::new(),
+            recovered: Vec::new(),
+        }
+    }
+
+    /// Whether packets were given up on since the last keyframe, in which case the Host should be
+    /// asked for another
+    pub fn needs_keyframe(&self) -> bool {
+        self.needs_keyframe
+    }
+
+    /// The sequence numbers of the packets still being waited for, oldest first
+    pub fn missing(&self) -> &[u16] {
+        &self.missing
+    }
+
+    /// Stops waiting for the missing packets, what follows can't be decoded until a keyframe
+    pub fn give_up(&mut self) {
+        if !self.missing.is_empty() {
+            self.missing.clear();
+            self.needs_keyframe = true;
+        }
+    }
+
+    /// Takes the packets that were lost but rebuilt from repair packets since this was last called.
+    /// They've already been followed like any other packet, but still need to be decoded.
+    pub fn take_recovered(&mut self) -> Vec<Packet> {
+        mem::take(&mut self.recovered)
     }
 
+    /// Depacketizes a FrameData packet. Repair packets give None, the packets they recover are
+    /// had from take_recovered.
     pub fn decode_to_vp9(
         &mut self,
         rtp: Vec<u8>,
     ) -> Result<Option<Vp9PacketWrapperBecauseTheRtpCrateIsIdiotic>, DecoderError> {
         let pkt = Packet::unmarshal(&mut Bytes::from(rtp))?;
+        self.decode_packet(&pkt)
+    }
+
+    /// Like decode_to_vp9, for a packet that's already been unmarshalled
+    pub fn decode_packet(
+        &mut self,
+        pkt: &Packet,
+    ) -> Result<Option<Vp9PacketWrapperBecauseTheRtpCrateIsIdiotic>, DecoderError> {
+        if pkt.header.payload_type == FEC_PAYLOAD_TYPE {
+            let recovered = self.fec.add_repair(pkt)?;
+            self.recover(recovered)?;
+            return Ok(None);
+        }
+
+        let result = self.receive(pkt)?;
+        let recovered = self.fec.add_media(pkt);
+        self.recover(recovered)?;
+        Ok(Some(result))
+    }
+
+    fn recover(&mut self, packets: Vec<Packet>) -> Result<(), DecoderError> {
+        for packet in packets {
+            // Ones that arrived after all, or were given up on, aren't wanted
+            let wanted = match self.next_sequence {
+                Some(next) if packet.header.sequence_number.wrapping_sub(next) >= 0x8000 =>
+                    self.missing.contains(&packet.header.sequence_number),
+                _ => true,
+            };
+            if wanted {
+                self.receive(&packet)?;
+                self.recovered.push(packet);
+            }
+        }
+        Ok(())
+    }
+
+    // Notes which packets are missing and depacketizes
+    fn receive(
+        &mut self,
+        pkt: &Packet,
+    ) -> Result<Vp9PacketWrapperBecauseTheRtpCrateIsIdiotic, DecoderError> {
+        let sequence = pkt.header.sequence_number;
+        match self.next_sequence {
+            // Anything behind what we expect was reordered, sent again or is a duplicate
+            Some(next) if sequence.wrapping_sub(next) >= 0x8000 =>
+                self.missing.retain(|&missing| missing != sequence),
+            Some(next) => {
+                let skipped = sequence.wrapping_sub(next);
+                if self.missing.len() + usize::from(skipped) > MAX_MISSING {
+                    self.missing.clear();
+                    self.needs_keyframe = true;
+                } else {
+                    self.missing
+                        .extend((0 .. skipped).map(|i| next.wrapping_add(i)));
+                }
+                self.next_sequence = Some(sequence.wrapping_add(1));
+            }
+            None => self.next_sequence = Some(sequence.wrapping_add(1)),
+        }
+
         let mut vp9packet = Vp9Packet::default();
         let bytes = vp9packet.depacketize(&pkt.payload)?;
-        Ok(Some((bytes, vp9packet)))
+        if vp9packet.b && is_keyframe(&bytes) {
+            // Nothing from before a keyframe is needed to decode what comes after it
+            self.missing
+                .retain(|&missing| missing.wrapping_sub(sequence) < 0x8000);
+            self.needs_keyframe = false;
+        }
+        Ok((bytes, vp9packet))
     }
 }
 
@@ -63,4 +212,6 @@ pub enum DecoderError {
     PacketUnmarshal(#[from] webrtc_util::Error),
     #[error("{0}")]
     Vp9Depacketize(#[from] rtp::Error),
+    #[error("{0}")]
+    Fec(#[from] FecError),
 }
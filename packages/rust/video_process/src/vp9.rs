@@ -45,6 +45,9 @@ use vpx_sys::{
     VPX_IMG_FMT_HIGHBITDEPTH,
 };
 
+/// The bitrate an encoder starts off at, in kbit/s
+pub const DEFAULT_BITRATE: u32 = 2500;
+
 // For the next soul that is looking for documentation, see: https://developer.liveswitch.io/reference/cocoa/api/group__encoder.html, https://docs.freeswitch.org/switch__image_8h.html
 
 pub struct VP9Encoder {
@@ -56,6 +59,8 @@ pub struct VP9Encoder {
 
     raw: *mut vpx_image_t,
     pts: i64,
+    // Whether the next frame must be a keyframe, e.g. because a Client lost part of an earlier one
+    force_keyframe: bool,
 }
 
 macro_rules! vp9_call_unsafe {
@@ -81,34 +86,23 @@ impl VP9Encoder {
         let mut config = unsafe { config.assume_init() };
 
         config.g_w = width;
-        config.g_h = height; /*
-                              config.rc_target_bitrate = 0; // in kbit/s
-                             // config_->g_error_resilient = is_svc_ ? VPX_ERROR_RESILIENT_DEFAULT : 0;
-                             // Setting the time base of the codec.
-                             config.g_timebase.num = 1;
-                             config.g_timebase.den = 90000;
-                             */
+        config.g_h = height;
+        // Timestamps are in RTP clock units, which the rate control goes by
+        config.g_timebase.num = 1;
+        config.g_timebase.den = 90000;
         config.g_lag_in_frames = 0; // 0- no frame lagging
-                                    /*
-                                    // Rate control settings.
-                                    // config_->rc_dropframe_thresh = inst->VP9().frameDroppingOn ? 30 : 0;
-                                    config.rc_end_usage = VPX_CBR;
-                                    config.g_pass = VPX_RC_ONE_PASS;
-                                    config.rc_min_quantizer = 8;
-                                    config.rc_max_quantizer = 52;
-                                    config.rc_undershoot_pct = 50;
-                                    config.rc_overshoot_pct = 50;
-                                    config.rc_buf_initial_sz = 500;
-                                    config.rc_buf_optimal_sz = 600;
-                                    config.rc_buf_sz = 1000;
-                                    // Set the maximum target size of any key-frame.
-                                    // rc_max_intra_target_ = MaxIntraTarget(config_->rc_buf_optimal_sz);
-                                    // Key-frame interval is enforced manually by this wrapper.
-                                    // config.kf_mode = VPX_KF_DISABLED;
-                                    // TODO(webm:1592): work-around for libvpx issue, as it can still
-                                    // put some key-frames at will even in VPX_KF_DISABLED kf_mode.
-                                    // config_->kf_max_dist = inst->VP9().keyFrameInterval;
-                                    // config_->kf_min_dist = config_->kf_max_dist;*/
+
+        // Rate control settings.
+        config.rc_end_usage = VPX_CBR;
+        config.g_pass = VPX_RC_ONE_PASS;
+        config.rc_target_bitrate = DEFAULT_BITRATE;
+        config.rc_min_quantizer = 8;
+        config.rc_max_quantizer = 52;
+        config.rc_undershoot_pct = 50;
+        config.rc_overshoot_pct = 50;
+        config.rc_buf_initial_sz = 500;
+        config.rc_buf_optimal_sz = 600;
+        config.rc_buf_sz = 1000;
         // Determine number of threads based on the image size and #cores.
         config.g_threads = number_of_threads(width, height, num_cpus::get() as u32);
 
@@ -155,6 +149,7 @@ impl VP9Encoder {
             height,
             raw,
             pts: 0,
+            force_keyframe: false,
         })
     }
 
@@ -162,6 +157,26 @@ impl VP9Encoder {
         (self.width, self.height)
     }
 
+    /// The bitrate the encoder aims for, in kbit/s
+    pub fn bitrate(&self) -> u32 {
+        self.config.rc_target_bitrate
+    }
+
+    /// Changes the bitrate the encoder aims for from the next frame on, in kbit/s
+    pub fn set_bitrate(&mut self, kbps: u32) -> Result<(), Error> {
+        if kbps == self.config.rc_target_bitrate {
+            return Ok(());
+        }
+        self.config.rc_target_bitrate = kbps;
+        vp9_call_unsafe!(vpx_codec_enc_config_set(&mut self.encoder, &self.config));
+        Ok(())
+    }
+
+    /// Makes the next frame encoded a keyframe, so it can be decoded without the ones before it
+    pub fn request_keyframe(&mut self) {
+        self.force_keyframe = true;
+    }
+
     pub fn encode(&mut self, i420_frame: &[u8]) -> Result<Vec<Vec<u8>>, Error> {
         let img = {
             if i420_frame.is_empty() {
@@ -187,18 +202,23 @@ impl VP9Encoder {
         let target_framerate_fps = 20;
         let duration = 90000 / target_framerate_fps;
 
-        let flags = 0;
+        let flags = if self.force_keyframe {
+            VPX_EFLAG_FORCE_KF as _
+        } else {
+            0
+        };
 
         vp9_call_unsafe!(vpx_codec_encode(
             &mut self.encoder,
             raw,
             self.pts,
-            1,
+            duration as _,
             flags,
             VPX_DL_REALTIME as _,
         ));
 
         self.pts += duration;
+        self.force_keyframe = false;
 
         let mut datas = Vec::new();
 
@@ -227,6 +247,26 @@ impl VP9Encoder {
     }
 }
 
+/// Whether an encoded VP9 frame is a keyframe
+pub fn is_keyframe(frame: &[u8]) -> bool {
+    // A frame starts with its uncompressed header: frame_marker(2) profile_low_bit(1)
+    // profile_high_bit(1), a reserved bit for profile 3, show_existing_frame(1) then frame_type(1)
+    // which is 0 for keyframes
+    let byte = match frame.first() {
+        Some(&byte) => byte,
+        None => return false,
+    };
+    if byte >> 6 != 0b10 {
+        return false;
+    }
+    let profile = ((byte >> 5) & 1) | (((byte >> 4) & 1) << 1);
+    let show_existing_frame = if profile == 3 { 2 } else { 3 };
+    if (byte >> show_existing_frame) & 1 == 1 {
+        return false;
+    }
+    (byte >> (show_existing_frame - 1)) & 1 == 0
+}
+
 impl Drop for VP9Encoder {
     fn drop(&mut self) {
         unsafe {
@@ -456,32 +496,33 @@ impl Vp9DecoderWrapper {
         &mut self,
         data: Vp9PacketWrapperBecauseTheRtpCrateIsIdiotic,
     ) -> Result<Vec<Vec<u8>>, Error> {
-        let decoder = match &mut self.decoder {
-            None => {
-                if !data.1.y {
-                    // We need to initialize the decoder with the correct width and height
-                    // y indicates resolution data is present
-                    // so we must skip this packet if the decoder hasn't been initialized yet and we don't have width and height data
-                    return Ok(Vec::new());
-                }
-                self.init_decoder(data.1.width[0] as _, data.1.height[0] as _)?;
-                self.decoder.as_mut().unwrap()
-            }
-            Some(d) =>
-                if !data.1.y {
-                    d
-                } else if data.1.width[0] != d.width() as u16
-                    || data.1.height[0] != d.height() as u16
-                {
-                    // Resolution has changed, tear down and re-init a new decoder in
-                    // order to get correct sizing.
-                    self.init_decoder(data.1.width[0] as _, data.1.height[0] as _)?;
-                    self.decoder.as_mut().unwrap()
-                } else {
-                    d
-                },
+        // y indicates resolution data is present
+        let resolution = if data.1.y {
+            Some((data.1.width[0], data.1.height[0]))
+        } else {
+            None
         };
+        match self.decoder_for(resolution)? {
+            Some(decoder) => decoder.decode(&data.0),
+            None => Ok(Vec::new()),
+        }
+    }
 
-        decoder.decode(&data.0)
+    fn decoder_for(
+        &mut self,
+        resolution: Option<(u16, u16)>,
+    ) -> Result<Option<&mut Vp9Decoder>, Error> {
+        match (resolution, &self.decoder) {
+            // We need to initialize the decoder with the correct width and height, so we must skip
+            // this data if the decoder hasn't been initialized yet and we don't have width and
+            // height data
+            (None, _) => {}
+            (Some((width, height)), Some(d))
+                if width == d.width() as u16 && height == d.height() as u16 => {}
+            // Resolution has changed, tear down and re-init a new decoder in order to get correct
+            // sizing.
+            (Some((width, height)), _) => self.init_decoder(width as _, height as _)?,
+        }
+        Ok(self.decoder.as_mut())
     }
 }
@@ -1,3 +1,4 @@
+use bytes::Bytes;
 use dcv_color_primitives as dcp;
 use dcv_color_primitives::{
     convert_image,
@@ -8,10 +9,14 @@ use dcv_color_primitives::{
     PixelFormat,
 };
 use image::{GenericImageView, RgbImage};
+use rtp::packet::Packet;
+use std::time::{Duration, Instant};
 use video_process::{
     convert::convert_bgra_to_i420,
+    fec::FecEncoder,
+    jitter_buffer::{JitterBuffer, MIN_PLAYOUT_DELAY},
     rtp::{RtpDecoder, RtpEncoder},
-    vp9::{VP9Encoder, Vp9Decoder},
+    vp9::{is_keyframe, VP9Encoder, Vp9Decoder, DEFAULT_BITRATE},
 };
 use webrtc_util::Marshal;
 
@@ -174,6 +179,390 @@ pub fn rtp_decode() {
     assert!(!data.is_empty());
 }
 
+#[test]
+pub fn keyframe_request() {
+    let img = include_bytes!("img.i420");
+    let (width, height) =
+        image::load_from_memory_with_format(include_bytes!("img.png"), image::ImageFormat::Png)
+            .expect("unable to open image")
+            .dimensions();
+
+    let mut encoder = VP9Encoder::new(width, height).expect("could not construct encoder");
+    let mut encode = || -> Vec<u8> {
+        encoder
+            .encode(img)
+            .expect("could not encode frame")
+            .into_iter()
+            .flatten()
+            .collect()
+    };
+    assert!(is_keyframe(&encode()));
+    assert!(!is_keyframe(&encode()));
+    encoder.request_keyframe();
+    assert!(is_keyframe(&encode()));
+    assert!(!is_keyframe(&encode()));
+}
+
+#[test]
+pub fn encoder_bitrate() {
+    let img = include_bytes!("img.i420");
+    let (width, height) =
+        image::load_from_memory_with_format(include_bytes!("img.png"), image::ImageFormat::Png)
+            .expect("unable to open image")
+            .dimensions();
+
+    let encode = |bitrate| -> usize {
+        let mut encoder = VP9Encoder::new(width, height).expect("could not construct encoder");
+        encoder.set_bitrate(bitrate).expect("could not set bitrate");
+        assert_eq!(encoder.bitrate(), bitrate);
+        (0 .. 10)
+            .map(|_| {
+                encoder
+                    .encode(img)
+                    .expect("could not encode frame")
+                    .iter()
+                    .map(Vec::len)
+                    .sum::<usize>()
+            })
+            .sum()
+    };
+    // The encoder keeps to a lower bitrate when asked to
+    assert!(encode(DEFAULT_BITRATE / 10) < encode(DEFAULT_BITRATE));
+}
+
+fn decode(decoder: &mut RtpDecoder, packet: &Packet) -> Option<Bytes> {
+    let packet = packet.marshal().expect("could not marshal packet").to_vec();
+    decoder
+        .decode_to_vp9(packet)
+        .expect("could not decode frame")
+        .map(|(bytes, _)| bytes)
+}
+
+fn decode_packets(decoder: &mut RtpDecoder, packets: &[Packet]) {
+    for packet in packets {
+        decode(decoder, packet);
+    }
+}
+
+#[test]
+pub fn rtp_packet_loss() {
+    let keyframe = include_bytes!("img.vp9");
+    let mut rtp = RtpEncoder::new(500, 1);
+    let mut packetize = || {
+        rtp.process_vp9(keyframe.to_vec())
+            .expect("could not encode frame")
+    };
+    let mut decoder = RtpDecoder::new();
+
+    // A Client that joins in the middle of a frame has to wait for the next one
+    let packets = packetize();
+    assert!(packets.len() > 2);
+    decode_packets(&mut decoder, &packets[1 ..]);
+    assert!(decoder.needs_keyframe());
+    decode_packets(&mut decoder, &packetize());
+    assert!(!decoder.needs_keyframe());
+
+    // Packets that went missing can be sent again
+    let packets = packetize();
+    decode_packets(&mut decoder, &packets[.. 1]);
+    decode_packets(&mut decoder, &packets[2 ..]);
+    assert_eq!(decoder.missing(), [packets[1].header.sequence_number]);
+    decode_packets(&mut decoder, &packets[1 .. 2]);
+    assert!(decoder.missing().is_empty());
+    assert!(!decoder.needs_keyframe());
+
+    // Otherwise the picture is broken until the next keyframe
+    let mut packets = packetize();
+    packets.remove(1);
+    decode_packets(&mut decoder, &packets);
+    decoder.give_up();
+    assert!(decoder.needs_keyframe());
+    decode_packets(&mut decoder, &packetize());
+    assert!(!decoder.needs_keyframe());
+    assert!(decoder.missing().is_empty());
+}
+
+#[test]
+pub fn rtp_fec_recovery() {
+    let frame = include_bytes!("img.vp9");
+    let mut rtp = RtpEncoder::new(500, 1);
+    let mut fec = FecEncoder::new();
+    fec.set_ratio(0.25);
+    let mut decoder = RtpDecoder::new();
+    // Repeatable stand in for a random number generator
+    let mut seed: u32 = 0x5eed;
+    let mut random = move |n: usize| {
+        seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+        (seed >> 16) as usize % n
+    };
+
+    for round in 0 .. 20 {
+        let packets = rtp
+            .process_vp9(frame.to_vec())
+            .expect("could not encode frame");
+        let repairs = fec.protect(&packets);
+        let groups = repairs.len();
+        assert!(packets.len() > 4);
+        assert_eq!(groups, (packets.len() + 3) / 4);
+        let first = packets[0].header.sequence_number;
+
+        // Each group can make up for any one of its packets, and the groups are interleaved so a
+        // burst as long as there are groups can be made up for too
+        let lost: Vec<usize> = if round == 0 {
+            (1 .. 1 + groups).collect()
+        } else {
+            (0 .. groups)
+                .filter(|_| random(2) == 0)
+                .map(|group| {
+                    let size = (packets.len() - group + groups - 1) / groups;
+                    group + random(size) * groups
+                })
+                .collect()
+        };
+
+        let mut received = Vec::new();
+        for (i, packet) in packets.iter().enumerate() {
+            if !lost.contains(&i) {
+                let bytes = decode(&mut decoder, packet).expect("empty packet");
+                received.push((packet.header.sequence_number, bytes));
+            }
+        }
+        // The repair packets of groups that lost nothing can go missing too
+        for (group, repair) in repairs.iter().enumerate() {
+            if lost.iter().any(|i| i % groups == group) || random(2) == 0 {
+                assert!(decode(&mut decoder, repair).is_none());
+            }
+        }
+
+        let recovered = decoder.take_recovered();
+        assert_eq!(recovered.len(), lost.len());
+        let mut depacketizer = RtpDecoder::new();
+        for packet in recovered {
+            let sequence = packet.header.sequence_number;
+            let original = &packets[usize::from(sequence.wrapping_sub(first))];
+            assert_eq!(packet.header.marker, original.header.marker);
+            assert_eq!(packet.header.timestamp, original.header.timestamp);
+            assert_eq!(packet.payload, original.payload);
+            let bytes = decode(&mut depacketizer, &packet).expect("empty packet");
+            received.push((sequence, bytes));
+        }
+        assert!(decoder.missing().is_empty());
+        assert!(!decoder.needs_keyframe());
+
+        // Nothing needs to be sent again to put the frame back together
+        received.sort_by_key(|(sequence, _)| sequence.wrapping_sub(first));
+        let rebuilt: Vec<u8> = received.into_iter().flat_map(|(_, bytes)| bytes).collect();
+        assert_eq!(rebuilt, frame.to_vec());
+    }
+}
+
+const FRAME_INTERVAL: Duration = Duration::from_millis(33);
+const FRAME_TICKS: u32 = 2970;
+
+// Encodes the image over and over, starting with a keyframe and forcing one at each of keyframes
+fn encode_frames(count: usize, keyframes: &[usize]) -> Vec<Vec<u8>> {
+    let img = include_bytes!("img.i420");
+    let (width, height) =
+        image::load_from_memory_with_format(include_bytes!("img.png"), image::ImageFormat::Png)
+            .expect("unable to open image")
+            .dimensions();
+
+    let mut encoder = VP9Encoder::new(width, height).expect("could not construct encoder");
+    (0 .. count)
+        .map(|i| {
+            if keyframes.contains(&i) {
+                encoder.request_keyframe();
+            }
+            let frame: Vec<u8> = encoder
+                .encode(img)
+                .expect("could not encode frame")
+                .into_iter()
+                .flatten()
+                .collect();
+            assert!(!frame.is_empty());
+            frame
+        })
+        .collect()
+}
+
+// Packetizes frames as if they were captured FRAME_INTERVAL apart
+fn packetize_frames(frames: &[Vec<u8>]) -> Vec<Vec<Packet>> {
+    let mut rtp = RtpEncoder::new(100, 1);
+    frames
+        .iter()
+        .enumerate()
+        .map(|(i, frame)| {
+            let mut packets = rtp
+                .process_vp9(frame.clone())
+                .expect("could not encode frame");
+            for packet in &mut packets {
+                packet.header.timestamp = i as u32 * FRAME_TICKS;
+            }
+            packets
+        })
+        .collect()
+}
+
+#[test]
+pub fn jitter_buffer_reorder() {
+    let frames = encode_frames(4, &[]);
+    let packets = packetize_frames(&frames);
+    let start = Instant::now();
+    let mut buffer = JitterBuffer::new();
+
+    // The packets of each frame arrive back to front
+    for (i, frame) in packets.iter().enumerate() {
+        for packet in frame.iter().rev() {
+            buffer
+                .push(packet, start + FRAME_INTERVAL * i as u32)
+                .expect("could not depacketize");
+        }
+    }
+    assert!(buffer.pop(start).is_none());
+    assert_eq!(buffer.next_due(), Some(start + MIN_PLAYOUT_DELAY));
+
+    let (width, height) =
+        image::load_from_memory_with_format(include_bytes!("img.png"), image::ImageFormat::Png)
+            .expect("unable to open image")
+            .dimensions();
+    let mut decoder =
+        Vp9Decoder::new(width as _, height as _).expect("could not construct decoder");
+    let now = start + Duration::from_secs(1);
+    for (i, frame) in frames.iter().enumerate() {
+        let popped = buffer.pop(now).expect("frame wasn't handed out");
+        assert_eq!(popped.timestamp, i as u32 * FRAME_TICKS);
+        assert_eq!(&popped.data, frame);
+        assert_eq!(popped.keyframe, i == 0);
+        decoder
+            .decode(&popped.data)
+            .expect("could not decode frame");
+    }
+    assert!(buffer.pop(now).is_none());
+
+    let total: usize = packets.iter().map(Vec::len).sum();
+    let stats = buffer.stats();
+    assert_eq!(stats.expected, total as u64);
+    assert_eq!(stats.received, total as u64);
+    assert_eq!(stats.lost, 0);
+    assert_eq!(stats.frames, 4);
+    assert_eq!(stats.dropped_frames, 0);
+    assert_eq!(stats.jitter, Duration::ZERO);
+}
+
+#[test]
+pub fn jitter_buffer_loss() {
+    let frames = encode_frames(5, &[3]);
+    let mut packets = packetize_frames(&frames);
+    assert!(packets[0].len() > 2);
+    packets[0].remove(1);
+    let start = Instant::now();
+    let mut buffer = JitterBuffer::new();
+    for (i, frame) in packets.iter().enumerate() {
+        for packet in frame {
+            buffer
+                .push(packet, start + FRAME_INTERVAL * i as u32)
+                .expect("could not depacketize");
+        }
+    }
+
+    // The incomplete keyframe is dropped once it's due, the frames after it can't be decoded
+    assert!(buffer
+        .pop(start + FRAME_INTERVAL * 2 + MIN_PLAYOUT_DELAY)
+        .is_none());
+    assert!(buffer.needs_keyframe());
+    let stats = buffer.stats();
+    assert_eq!(stats.lost, 1);
+    assert_eq!(stats.dropped_frames, 3);
+
+    // Until the next keyframe
+    let now = start + Duration::from_secs(1);
+    let popped = buffer.pop(now).expect("frame wasn't handed out");
+    assert!(popped.keyframe);
+    assert_eq!(popped.data, frames[3]);
+    let popped = buffer.pop(now).expect("frame wasn't handed out");
+    assert_eq!(popped.data, frames[4]);
+    assert!(buffer.pop(now).is_none());
+    assert!(!buffer.needs_keyframe());
+    assert_eq!(buffer.stats().frames, 2);
+}
+
+#[test]
+pub fn jitter_buffer_recovered() {
+    let frames = encode_frames(2, &[]);
+    let packets = packetize_frames(&frames);
+    assert!(packets[0].len() > 2);
+    let start = Instant::now();
+    let mut buffer = JitterBuffer::new();
+    for (i, packet) in packets[0].iter().enumerate() {
+        if i == 1 {
+            continue;
+        }
+        buffer.push(packet, start).expect("could not depacketize");
+    }
+    for packet in &packets[1] {
+        buffer
+            .push(packet, start + FRAME_INTERVAL)
+            .expect("could not depacketize");
+    }
+
+    // A recovered packet completes its frame but still counts as lost
+    buffer
+        .push_recovered(&packets[0][1], start + FRAME_INTERVAL)
+        .expect("could not depacketize");
+    assert_eq!(buffer.stats().lost, 1);
+    let now = start + Duration::from_secs(1);
+    for frame in &frames {
+        assert_eq!(
+            &buffer.pop(now).expect("frame wasn't handed out").data,
+            frame
+        );
+    }
+    assert_eq!(buffer.stats().dropped_frames, 0);
+}
+
+#[test]
+pub fn jitter_buffer_delay() {
+    let frames = encode_frames(8, &[]);
+    let packets = packetize_frames(&frames);
+    let start = Instant::now();
+    let mut buffer = JitterBuffer::new();
+    let push = |buffer: &mut JitterBuffer, i: usize, late: Duration| {
+        for packet in &packets[i] {
+            buffer
+                .push(packet, start + FRAME_INTERVAL * i as u32 + late)
+                .expect("could not depacketize");
+        }
+    };
+
+    // A frame held up for longer than the delay pushes it up to cover it
+    push(&mut buffer, 0, Duration::ZERO);
+    push(&mut buffer, 1, Duration::ZERO);
+    assert_eq!(buffer.stats().playout_delay, MIN_PLAYOUT_DELAY);
+    assert_eq!(buffer.stats().jitter, Duration::ZERO);
+    push(&mut buffer, 3, Duration::ZERO);
+    push(&mut buffer, 2, Duration::from_millis(50));
+    assert_eq!(buffer.stats().playout_delay, Duration::from_millis(50));
+    assert!(buffer.stats().jitter > Duration::ZERO);
+
+    // And it comes back down as frames arrive on time again
+    for i in 4 .. frames.len() {
+        push(&mut buffer, i, Duration::ZERO);
+    }
+    let delay = buffer.stats().playout_delay;
+    assert!(delay < Duration::from_millis(50));
+    assert!(delay > MIN_PLAYOUT_DELAY);
+
+    // The late frame wasn't dropped
+    let now = start + Duration::from_secs(1);
+    for frame in &frames {
+        assert_eq!(
+            &buffer.pop(now).expect("frame wasn't handed out").data,
+            frame
+        );
+    }
+    assert_eq!(buffer.stats().dropped_frames, 0);
+}
+
 #[test]
 pub fn decode_test() {
     let img = include_bytes!("img.rtp.out");